@@ -0,0 +1,167 @@
+use crate::error::{Cause, Operation, Result};
+use crate::key_manager::KeyManager;
+use crate::template::write_private_file;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Parses age identities from identity file content (`AGE-SECRET-KEY-...` lines, comments
+/// allowed).
+pub(crate) fn parse_identities(
+    content: &str,
+) -> std::result::Result<Vec<Box<dyn age::Identity>>, String> {
+    age::IdentityFile::from_buffer(content.as_bytes())
+        .map_err(|e| format!("invalid age identity file: {}", e))?
+        .into_identities()
+        .map_err(|e| format!("invalid age identity: {}", e))
+}
+
+/// Encrypts `plaintext` to every `age1...` recipient and returns ASCII-armored output.
+pub(crate) fn encrypt_armored(
+    recipients: &[&str],
+    plaintext: &[u8],
+) -> std::result::Result<String, String> {
+    let recipients = recipients
+        .iter()
+        .map(|r| {
+            age::x25519::Recipient::from_str(r)
+                .map_err(|e| format!("invalid age recipient '{}': {}", r, e))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+            .map_err(|e| e.to_string())?;
+    let mut output = Vec::new();
+    let armored =
+        age::armor::ArmoredWriter::wrap_output(&mut output, age::armor::Format::AsciiArmor)
+            .map_err(|e| e.to_string())?;
+    let mut writer = encryptor.wrap_output(armored).map_err(|e| e.to_string())?;
+    std::io::Write::write_all(&mut writer, plaintext).map_err(|e| e.to_string())?;
+    writer
+        .finish()
+        .and_then(|armored| armored.finish())
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(output).map_err(|e| e.to_string())
+}
+
+/// Decrypts age data, armored or binary, with the first matching identity.
+pub(crate) fn decrypt(
+    identities: &[Box<dyn age::Identity>],
+    data: &[u8],
+) -> std::result::Result<Vec<u8>, String> {
+    let decryptor = age::Decryptor::new_buffered(age::armor::ArmoredReader::new(data))
+        .map_err(|e| e.to_string())?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i.as_ref() as &dyn age::Identity))
+        .map_err(|e| e.to_string())?;
+    let mut plaintext = Vec::new();
+    reader
+        .read_to_end(&mut plaintext)
+        .map_err(|e| e.to_string())?;
+    Ok(plaintext)
+}
+
+impl KeyManager {
+    /// Reads the key and writes its value to an ASCII-armored age file encrypted to the
+    /// given recipients (`age1...`), e.g. to hand one credential to a teammate. The file
+    /// can be decrypted with `age -d` or `import_age`.
+    pub fn export_age<P>(&mut self, path: P, recipients: &[&str]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        if recipients.is_empty() {
+            return Err(self.error(
+                Operation::Export,
+                Cause::InvalidInput("at least one age recipient is required".to_string()),
+            ));
+        }
+        let value = self.read_key()?;
+        let armored = encrypt_armored(recipients, value.as_bytes())
+            .map_err(|e| self.error(Operation::Export, Cause::InvalidInput(e)))?;
+        write_private_file(path.as_ref(), armored.as_bytes())
+            .map_err(|e| self.error(Operation::Export, e))
+    }
+
+    /// Decrypts an age file with `identity` (identity file content, i.e. one or more
+    /// `AGE-SECRET-KEY-...` lines) and stores the result as this key's value.
+    pub fn import_age<P>(&mut self, path: P, identity: &str) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let data = fs::read(path).map_err(|e| self.error(Operation::Import, e))?;
+        let identities = parse_identities(identity)
+            .map_err(|e| self.error(Operation::Import, Cause::InvalidInput(e)))?;
+        let plaintext = decrypt(&identities, &data)
+            .map_err(|e| self.error(Operation::Import, Cause::AccessDenied(e)))?;
+        let value = String::from_utf8(plaintext).map_err(|_| {
+            self.error(
+                Operation::Import,
+                Cause::Corrupt("decrypted value is not UTF-8".to_string()),
+            )
+        })?;
+        self.store(&value).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::vault::Vault;
+    use age::secrecy::ExposeSecret;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str, extension: &str) -> PathBuf {
+        let path = std::env::temp_dir().join("key_vaulter_tests").join(format!(
+            "{}-{}.{}",
+            name,
+            std::process::id(),
+            extension
+        ));
+        let _ = fs::create_dir_all(path.parent().unwrap());
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn temp_manager() -> KeyManager {
+        Vault::ephemeral_for_tests().key("token")
+    }
+
+    #[test]
+    fn test_export_and_import_age() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let mut source = temp_manager();
+        source.store("s3cr3t").unwrap();
+        let file = temp_path("age_export", "age");
+        source.export_age(&file, &[recipient.as_str()]).unwrap();
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(content.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+        assert!(!content.contains("s3cr3t"));
+
+        let mut target = temp_manager();
+        target
+            .import_age(&file, identity.to_string().expose_secret())
+            .unwrap();
+        assert_eq!(target.read_key().unwrap(), "s3cr3t");
+    }
+
+    #[test]
+    fn test_import_with_wrong_identity() {
+        let recipient = age::x25519::Identity::generate().to_public().to_string();
+        let mut source = temp_manager();
+        source.store("s3cr3t").unwrap();
+        let file = temp_path("age_wrong", "age");
+        source.export_age(&file, &[recipient.as_str()]).unwrap();
+
+        let other = age::x25519::Identity::generate();
+        let error = temp_manager()
+            .import_age(&file, other.to_string().expose_secret())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AccessDenied);
+        assert!(temp_manager()
+            .export_age(&file, &["not-a-recipient"])
+            .is_err());
+    }
+}
@@ -0,0 +1,1066 @@
+use crate::backend::Backend;
+use crate::error::{is_locked, StoreLocked};
+use crate::file_store::platform_error;
+use crate::hooks;
+use crate::key_manager::KeyManager;
+use crate::lock_events;
+use keyring::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// One request per line, JSON encoded.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    Ping,
+    Get {
+        system: String,
+        key: String,
+    },
+    Set {
+        system: String,
+        key: String,
+        value: String,
+    },
+    Delete {
+        system: String,
+        key: String,
+    },
+    /// The salted digest of the value, which stays in the agent.
+    Digest {
+        system: String,
+        key: String,
+        salt: Vec<u8>,
+    },
+}
+
+/// One response line per request.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Response {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<String>,
+    /// `not_found`, `denied` or `failed`; absent on success.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl Response {
+    fn success(value: Option<String>) -> Self {
+        Response {
+            value,
+            ..Response::default()
+        }
+    }
+
+    fn failure(error: &keyring::Error) -> Self {
+        let code = match error {
+            keyring::Error::NoEntry => "not_found",
+            _ if is_locked(error) => "locked",
+            keyring::Error::NoStorageAccess(_) => "denied",
+            _ => "failed",
+        };
+        Response {
+            value: None,
+            error: Some(code.to_string()),
+            message: Some(error.to_string()),
+        }
+    }
+
+    fn into_result(self) -> Result<Option<String>> {
+        match self.error.as_deref() {
+            None => Ok(self.value),
+            Some("not_found") => Err(keyring::Error::NoEntry),
+            Some(code) => {
+                let message = self.message.unwrap_or_else(|| code.to_string());
+                match code {
+                    "locked" => Err(keyring::Error::NoStorageAccess(Box::new(StoreLocked(
+                        message,
+                    )))),
+                    "denied" => Err(keyring::Error::NoStorageAccess(Box::new(AgentError(
+                        message,
+                    )))),
+                    _ => Err(platform_error(AgentError(message))),
+                }
+            }
+        }
+    }
+}
+
+/// A failure reported by the agent.
+#[derive(Debug)]
+struct AgentError(String);
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "agent: {}", self.0)
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+/// The process on the other end of a connection.
+struct Peer {
+    /// Who the peer runs as, when that isn't the current user.
+    other_user: Option<String>,
+    /// Its executable, where the platform reports it.
+    exe: Option<PathBuf>,
+}
+
+/// A key an executable may use, `"*"` standing for every key of the system.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct AclEntry {
+    system: String,
+    key: String,
+}
+
+/// Which keys each client executable may read, store or delete through the agent (see
+/// `Agent::with_acl`), so a random process of the same user can't ask for every secret.
+///
+/// Clients are identified by the executable of the connecting process, found from the
+/// socket's peer credentials on Linux and Android and from the pipe's client process on
+/// Windows. Elsewhere (macOS, the BSDs) the executable can't be identified, so every
+/// request is denied once an ACL is set. Saved as JSON, e.g.
+/// `{"/usr/bin/backup": [{"system": "s3", "key": "*"}]}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AgentAcl(BTreeMap<PathBuf, Vec<AclEntry>>);
+
+impl AgentAcl {
+    /// Creates an ACL allowing nothing.
+    pub fn new() -> Self {
+        AgentAcl::default()
+    }
+
+    /// Reads an ACL saved as JSON.
+    pub fn load<P>(path: P) -> io::Result<AgentAcl>
+    where
+        P: AsRef<Path>,
+    {
+        let json = fs::read_to_string(path)?;
+        let acl: AgentAcl = serde_json::from_str(&json).map_err(io::Error::other)?;
+        Ok(AgentAcl(
+            acl.0
+                .into_iter()
+                .map(|(exe, entries)| (canonical(exe), entries))
+                .collect(),
+        ))
+    }
+
+    /// Lets the executable at `exe` use `key` of `system`; a `key` of `"*"` allows every key
+    /// of the system.
+    pub fn allow<P>(mut self, exe: P, system: &str, key: &str) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.0
+            .entry(canonical(exe.into()))
+            .or_default()
+            .push(AclEntry {
+                system: system.to_string(),
+                key: key.to_string(),
+            });
+        self
+    }
+
+    /// Whether the executable at `exe` may use `key` of `system`.
+    pub fn permits(&self, exe: &Path, system: &str, key: &str) -> bool {
+        self.0.get(exe).is_some_and(|entries| {
+            entries
+                .iter()
+                .any(|entry| entry.system == system && (entry.key == "*" || entry.key == key))
+        })
+    }
+}
+
+/// `path` with its symlinks resolved, to compare with the path the kernel reports; kept as
+/// is if it doesn't exist (yet).
+fn canonical(path: PathBuf) -> PathBuf {
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+/// Values read or stored through the agent, and when a client last asked for one.
+struct Cache {
+    values: HashMap<(String, String), Zeroizing<String>>,
+    last_access: Instant,
+}
+
+/// A long-running process holding unlocked secrets in memory and serving them to other
+/// processes of the same user over a Unix domain socket, or a named pipe on Windows.
+///
+/// Values are read from the backend once (triggering at most one unlock prompt) and then
+/// answered from memory. Connections from other users are refused, and with
+/// `with_acl`, requests for keys the client executable isn't allowed.
+pub struct Agent {
+    listener: imp::Listener,
+    socket_path: PathBuf,
+    backend: Backend,
+    cache: Mutex<Cache>,
+    acl: Option<AgentAcl>,
+    idle_timeout: Option<Duration>,
+}
+
+impl Agent {
+    /// Default socket location: `key_vaulter/agent.sock` in `$XDG_RUNTIME_DIR`, or in a
+    /// per-user directory under the temporary directory. On Windows, the pipe
+    /// `\\.\pipe\key_vaulter-<user>-agent`.
+    pub fn default_socket_path() -> PathBuf {
+        imp::default_socket_path()
+    }
+
+    /// Binds the socket at `socket_path`, readable only by the current user, replacing a
+    /// stale socket left by a previous agent.
+    ///
+    /// A missing directory is created readable only by the current user. An existing one
+    /// is left as it is, and refused when it belongs to another user or others can write
+    /// to it, since they could replace the socket. On Windows, `socket_path` names a pipe
+    /// which only the current user, on this machine, may open.
+    pub fn bind<P>(socket_path: P, backend: Backend) -> io::Result<Agent>
+    where
+        P: AsRef<Path>,
+    {
+        let socket_path = socket_path.as_ref().to_path_buf();
+        let listener = imp::Listener::bind(&socket_path)?;
+        Ok(Agent {
+            listener,
+            socket_path,
+            backend,
+            cache: Mutex::new(Cache {
+                values: HashMap::new(),
+                last_access: Instant::now(),
+            }),
+            acl: None,
+            idle_timeout: None,
+        })
+    }
+
+    /// Serves each key only to the executables `acl` allows it to.
+    pub fn with_acl(mut self, acl: AgentAcl) -> Self {
+        self.acl = Some(acl);
+        self
+    }
+
+    /// Zeroizes the cached values once no client has asked for a key for `timeout`, like a
+    /// password manager locking itself: the next request reads the backend again, which
+    /// may ask to unlock the keychain.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Path of the bound socket.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Serves connections until the listener fails, one thread per connection.
+    pub fn serve(self) -> io::Result<()> {
+        let agent = Arc::new(self);
+        let weak = Arc::downgrade(&agent);
+        lock_events::register_cache(move || match weak.upgrade() {
+            Some(agent) => {
+                agent.cache().values.clear();
+                true
+            }
+            None => false,
+        });
+        if let Some(timeout) = agent.idle_timeout {
+            let weak = Arc::downgrade(&agent);
+            // Checks a few times per timeout, so values don't outlive it by much.
+            let interval = (timeout / 4).clamp(Duration::from_millis(10), Duration::from_secs(15));
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                let Some(agent) = weak.upgrade() else {
+                    break;
+                };
+                agent.lock_if_idle(&mut agent.cache());
+            });
+        }
+        loop {
+            let stream = agent.listener.accept()?;
+            let agent = Arc::clone(&agent);
+            thread::spawn(move || {
+                if let Err(e) = agent.handle(stream) {
+                    hooks::warn(&format!("agent connection failed: {}", e));
+                }
+            });
+        }
+    }
+
+    fn handle(&self, stream: imp::Stream) -> io::Result<()> {
+        let peer = imp::peer(&stream)?;
+        let mut writer = stream.try_clone()?;
+        if let Some(user) = peer.other_user {
+            let denied = keyring::Error::NoStorageAccess(Box::new(AgentError(format!(
+                "connections from {} are not allowed",
+                user
+            ))));
+            return write_line(&mut writer, &Response::failure(&denied));
+        }
+        let exe = peer.exe;
+        for line in BufReader::new(stream).lines() {
+            let response = match serde_json::from_str::<Request>(&line?) {
+                Ok(request) => match self.check_acl(&request, exe.as_deref()) {
+                    Ok(()) => self.answer(request),
+                    Err(denied) => Response::failure(&denied),
+                },
+                Err(e) => Response::failure(&platform_error(e)),
+            };
+            write_line(&mut writer, &response)?;
+        }
+        Ok(())
+    }
+
+    /// Fails with `NoStorageAccess` when an ACL is set and doesn't allow the client's
+    /// executable to use the requested key.
+    fn check_acl(&self, request: &Request, exe: Option<&Path>) -> Result<()> {
+        let Some(acl) = &self.acl else {
+            return Ok(());
+        };
+        let (system, key) = match request {
+            Request::Ping => return Ok(()),
+            Request::Get { system, key }
+            | Request::Set { system, key, .. }
+            | Request::Delete { system, key }
+            | Request::Digest { system, key, .. } => (system, key),
+        };
+        let reason = match exe {
+            Some(exe) if acl.permits(exe, system, key) => return Ok(()),
+            Some(exe) => format!("{} may not use '{}/{}'", exe.display(), system, key),
+            None => "the client executable could not be identified".to_string(),
+        };
+        Err(keyring::Error::NoStorageAccess(Box::new(AgentError(
+            reason,
+        ))))
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Zeroizes the cached values when the idle timeout has passed since the last access.
+    fn lock_if_idle(&self, cache: &mut Cache) {
+        let Some(timeout) = self.idle_timeout else {
+            return;
+        };
+        if !cache.values.is_empty() && cache.last_access.elapsed() >= timeout {
+            cache.values.clear();
+            hooks::debug("agent locked after being idle");
+        }
+    }
+
+    /// The value of `system/key`, read from the backend into `values` if not cached yet.
+    fn cached_value<'a>(
+        backend: &Backend,
+        values: &'a mut HashMap<(String, String), Zeroizing<String>>,
+        system: String,
+        key: String,
+    ) -> Result<&'a Zeroizing<String>> {
+        match values.entry((system, key)) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let (system, key) = entry.key();
+                let value = backend.get(system, key)?;
+                Ok(entry.insert(Zeroizing::new(value)))
+            }
+        }
+    }
+
+    fn answer(&self, request: Request) -> Response {
+        let mut cache = self.cache();
+        self.lock_if_idle(&mut cache);
+        if !matches!(request, Request::Ping) {
+            cache.last_access = Instant::now();
+        }
+        let values = &mut cache.values;
+        let result = match request {
+            Request::Ping => Ok(None),
+            Request::Get { system, key } => Agent::cached_value(&self.backend, values, system, key)
+                .map(|value| Some(value.to_string())),
+            Request::Digest { system, key, salt } => {
+                Agent::cached_value(&self.backend, values, system, key)
+                    .map(|value| Some(KeyManager::digest_of(&salt, value)))
+            }
+            Request::Set { system, key, value } => {
+                self.backend.set(&system, &key, &value).map(|()| {
+                    values.insert((system, key), Zeroizing::new(value));
+                    None
+                })
+            }
+            Request::Delete { system, key } => {
+                values.remove(&(system.clone(), key.clone()));
+                self.backend.delete(&system, &key).map(|()| None)
+            }
+        };
+        match result {
+            Ok(value) => Response::success(value),
+            Err(e) => Response::failure(&e),
+        }
+    }
+}
+
+fn write_line<W: Write, T: Serialize>(writer: &mut W, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message).map_err(io::Error::other)?;
+    line.push(b'\n');
+    writer.write_all(&line)
+}
+
+/// Backend client talking to a running [`Agent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentClient {
+    socket_path: PathBuf,
+}
+
+impl AgentClient {
+    /// Connects to the agent listening at `socket_path` (see `Agent::default_socket_path`).
+    pub fn new(socket_path: impl Into<PathBuf>) -> Self {
+        AgentClient {
+            socket_path: socket_path.into(),
+        }
+    }
+
+    /// Path of the agent socket.
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    fn request(&self, request: &Request) -> Result<Option<String>> {
+        let mut stream = imp::connect(&self.socket_path).map_err(platform_error)?;
+        write_line(&mut stream, request).map_err(platform_error)?;
+        let mut line = String::new();
+        BufReader::new(stream)
+            .read_line(&mut line)
+            .map_err(platform_error)?;
+        let response: Response = serde_json::from_str(&line).map_err(platform_error)?;
+        response.into_result()
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        self.request(&Request::Get {
+            system: system_name.to_string(),
+            key: key_name.to_string(),
+        })
+        .map(Option::unwrap_or_default)
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        self.request(&Request::Set {
+            system: system_name.to_string(),
+            key: key_name.to_string(),
+            value: value.to_string(),
+        })
+        .map(|_| ())
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        self.request(&Request::Delete {
+            system: system_name.to_string(),
+            key: key_name.to_string(),
+        })
+        .map(|_| ())
+    }
+
+    pub(crate) fn digest(&self, system_name: &str, key_name: &str, salt: &[u8]) -> Result<String> {
+        self.request(&Request::Digest {
+            system: system_name.to_string(),
+            key: key_name.to_string(),
+            salt: salt.to_vec(),
+        })
+        .map(Option::unwrap_or_default)
+    }
+
+    pub(crate) fn health_check(&self) -> Result<()> {
+        self.request(&Request::Ping).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::Peer;
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+
+    pub(super) type Stream = UnixStream;
+
+    pub(super) fn default_socket_path() -> PathBuf {
+        match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir).join("key_vaulter"),
+            None => std::env::temp_dir().join(format!("key_vaulter-{}", current_uid())),
+        }
+        .join("agent.sock")
+    }
+
+    /// A Unix socket readable only by the current user.
+    pub(super) struct Listener(UnixListener);
+
+    impl Listener {
+        /// See `Agent::bind`.
+        pub(super) fn bind(socket_path: &Path) -> io::Result<Listener> {
+            if let Some(parent) = socket_path.parent() {
+                if parent.exists() {
+                    check_socket_dir(parent)?;
+                } else {
+                    fs::DirBuilder::new()
+                        .recursive(true)
+                        .mode(0o700)
+                        .create(parent)?;
+                }
+            }
+            if UnixStream::connect(socket_path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "an agent is already listening on this socket",
+                ));
+            }
+            let _ = fs::remove_file(socket_path);
+            let listener = UnixListener::bind(socket_path)?;
+            fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+            Ok(Listener(listener))
+        }
+
+        pub(super) fn accept(&self) -> io::Result<Stream> {
+            self.0.accept().map(|(stream, _)| stream)
+        }
+    }
+
+    pub(super) fn connect(socket_path: &Path) -> io::Result<Stream> {
+        UnixStream::connect(socket_path)
+    }
+
+    pub(super) fn peer(stream: &Stream) -> io::Result<Peer> {
+        let (uid, pid) = peer_credentials(stream)?;
+        Ok(Peer {
+            other_user: (uid != current_uid()).then(|| format!("uid {}", uid)),
+            exe: pid.and_then(process_exe),
+        })
+    }
+
+    /// User id and, where the platform reports it, process id of the process on the other
+    /// end of `stream`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_credentials(stream: &UnixStream) -> io::Result<(u32, Option<u32>)> {
+        let mut credentials = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: the buffer and its length describe a valid `ucred` for SO_PEERCRED.
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut credentials as *mut libc::ucred as *mut libc::c_void,
+                &mut length,
+            )
+        };
+        if result == 0 {
+            Ok((credentials.uid, u32::try_from(credentials.pid).ok()))
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// User id of the process on the other end of `stream`; its process id isn't available.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn peer_credentials(stream: &UnixStream) -> io::Result<(u32, Option<u32>)> {
+        let (mut uid, mut gid) = (0, 0);
+        // SAFETY: both pointers are valid for writes.
+        let result = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+        if result == 0 {
+            Ok((uid, None))
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Executable of the process `pid`, as the kernel reports it (symlinks resolved).
+    fn process_exe(pid: u32) -> Option<PathBuf> {
+        if cfg!(any(target_os = "linux", target_os = "android")) {
+            fs::read_link(format!("/proc/{}/exe", pid)).ok()
+        } else {
+            None
+        }
+    }
+
+    fn current_uid() -> u32 {
+        // SAFETY: geteuid has no preconditions.
+        unsafe { libc::geteuid() }
+    }
+
+    /// Fails unless `dir` belongs to the current user and only they can write to it.
+    fn check_socket_dir(dir: &Path) -> io::Result<()> {
+        let metadata = fs::metadata(dir)?;
+        if metadata.uid() != current_uid() || metadata.permissions().mode() & 0o022 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} must belong to the current user and not be writable by others",
+                    dir.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::{canonical, Peer};
+    use crate::file_store::current_user;
+    use std::ffi::{OsStr, OsString};
+    use std::fs::{self, File};
+    use std::io;
+    use std::iter::once;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use windows_sys::core::PWSTR;
+    use windows_sys::Win32::Foundation::{
+        LocalFree, ERROR_ACCESS_DENIED, ERROR_NO_DATA, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED,
+        HANDLE, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW,
+        SDDL_REVISION_1,
+    };
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY,
+        TOKEN_USER,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, GetNamedPipeClientProcessId, PIPE_READMODE_BYTE,
+        PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, OpenProcess, OpenProcessToken, QueryFullProcessImageNameW,
+        PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    /// How long a client waits while every instance of the pipe is busy.
+    const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub(super) type Stream = File;
+
+    pub(super) fn default_socket_path() -> PathBuf {
+        PathBuf::from(format!(r"\\.\pipe\key_vaulter-{}-agent", current_user()))
+    }
+
+    /// A named pipe only the current user may open, and only from this machine.
+    pub(super) struct Listener {
+        name: Vec<u16>,
+        descriptor: PrivateDescriptor,
+        /// The instance of the pipe the next client connects to.
+        next: Mutex<OwnedHandle>,
+    }
+
+    impl Listener {
+        /// See `Agent::bind`.
+        pub(super) fn bind(socket_path: &Path) -> io::Result<Listener> {
+            let name = wide(socket_path.as_os_str());
+            let descriptor = PrivateDescriptor::new()?;
+            let first = create_instance(&name, &descriptor, true).map_err(|e| {
+                if e.raw_os_error() == Some(ERROR_ACCESS_DENIED as i32) {
+                    io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        "an agent is already listening on this pipe",
+                    )
+                } else {
+                    e
+                }
+            })?;
+            Ok(Listener {
+                name,
+                descriptor,
+                next: Mutex::new(first),
+            })
+        }
+
+        /// Waits for a client on the current instance, then creates the one the next client
+        /// connects to.
+        pub(super) fn accept(&self) -> io::Result<Stream> {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                // SAFETY: `next` is a pipe instance opened without overlapped I/O.
+                if unsafe { ConnectNamedPipe(next.as_raw_handle(), ptr::null_mut()) } != 0 {
+                    break;
+                }
+                let error = io::Error::last_os_error();
+                match error.raw_os_error().map(|code| code as u32) {
+                    // The client connected before `ConnectNamedPipe` was called.
+                    Some(ERROR_PIPE_CONNECTED) => break,
+                    // The client already left: start over on a fresh instance.
+                    Some(ERROR_NO_DATA) => {
+                        *next = create_instance(&self.name, &self.descriptor, false)?
+                    }
+                    _ => return Err(error),
+                }
+            }
+            let instance = create_instance(&self.name, &self.descriptor, false)?;
+            Ok(File::from(std::mem::replace(&mut *next, instance)))
+        }
+    }
+
+    /// Opens the pipe, retrying for a while when every instance is busy.
+    pub(super) fn connect(socket_path: &Path) -> io::Result<Stream> {
+        let deadline = Instant::now() + BUSY_TIMEOUT;
+        loop {
+            match fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(socket_path)
+            {
+                Err(e)
+                    if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32)
+                        && Instant::now() < deadline =>
+                {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// The pipe only lets the current user in, so only the client's executable is looked up.
+    pub(super) fn peer(stream: &Stream) -> io::Result<Peer> {
+        let mut pid = 0;
+        // SAFETY: `stream` is a connected pipe instance and `pid` is valid for writes.
+        if unsafe { GetNamedPipeClientProcessId(stream.as_raw_handle(), &mut pid) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Peer {
+            other_user: None,
+            exe: process_exe(pid),
+        })
+    }
+
+    /// Executable of the process `pid`, canonicalized like the paths of an `AgentAcl`.
+    fn process_exe(pid: u32) -> Option<PathBuf> {
+        // SAFETY: OpenProcess has no preconditions; the handle is closed when dropped.
+        let process =
+            owned(unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) }).ok()?;
+        let mut buffer = vec![0u16; 32_768];
+        let mut length = buffer.len() as u32;
+        // SAFETY: `buffer` holds `length` UTF-16 units.
+        let result = unsafe {
+            QueryFullProcessImageNameW(
+                process.as_raw_handle(),
+                PROCESS_NAME_WIN32,
+                buffer.as_mut_ptr(),
+                &mut length,
+            )
+        };
+        if result == 0 {
+            return None;
+        }
+        let exe = OsString::from_wide(&buffer[..length as usize]);
+        Some(canonical(PathBuf::from(exe)))
+    }
+
+    fn create_instance(
+        name: &[u16],
+        descriptor: &PrivateDescriptor,
+        first: bool,
+    ) -> io::Result<OwnedHandle> {
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: descriptor.0,
+            bInheritHandle: 0,
+        };
+        let mut open_mode = PIPE_ACCESS_DUPLEX;
+        if first {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+        // SAFETY: `name` is NUL-terminated and `attributes` outlives the call.
+        owned(unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                &attributes,
+            )
+        })
+    }
+
+    /// A security descriptor granting access to the current user only.
+    struct PrivateDescriptor(PSECURITY_DESCRIPTOR);
+
+    // SAFETY: the descriptor is only read once created, and freed once.
+    unsafe impl Send for PrivateDescriptor {}
+    unsafe impl Sync for PrivateDescriptor {}
+
+    impl PrivateDescriptor {
+        fn new() -> io::Result<Self> {
+            let sddl = wide(OsStr::new(&format!("D:P(A;;GA;;;{})", current_user_sid()?)));
+            let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+            // SAFETY: `sddl` is NUL-terminated; the descriptor is freed on drop.
+            let result = unsafe {
+                ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                    sddl.as_ptr(),
+                    SDDL_REVISION_1,
+                    &mut descriptor,
+                    ptr::null_mut(),
+                )
+            };
+            if result == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(PrivateDescriptor(descriptor))
+        }
+    }
+
+    impl Drop for PrivateDescriptor {
+        fn drop(&mut self) {
+            // SAFETY: allocated by ConvertStringSecurityDescriptorToSecurityDescriptorW.
+            unsafe { LocalFree(self.0) };
+        }
+    }
+
+    /// The SID of the user running this process, e.g. `S-1-5-21-...-1001`.
+    fn current_user_sid() -> io::Result<String> {
+        let mut token: HANDLE = ptr::null_mut();
+        // SAFETY: the pseudo handle of the current process is always valid.
+        if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let token = owned(token)?;
+        let mut length = 0;
+        // SAFETY: only asks for the size of the information.
+        unsafe {
+            GetTokenInformation(
+                token.as_raw_handle(),
+                TokenUser,
+                ptr::null_mut(),
+                0,
+                &mut length,
+            )
+        };
+        // Allocated as u64s so the TOKEN_USER at its start is aligned.
+        let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+        // SAFETY: `buffer` holds at least `length` bytes.
+        let result = unsafe {
+            GetTokenInformation(
+                token.as_raw_handle(),
+                TokenUser,
+                buffer.as_mut_ptr().cast(),
+                length,
+                &mut length,
+            )
+        };
+        if result == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the call filled `buffer` with a TOKEN_USER.
+        let user = unsafe { &*buffer.as_ptr().cast::<TOKEN_USER>() };
+        let mut text: PWSTR = ptr::null_mut();
+        // SAFETY: the SID points into `buffer`, alive until the end of the function.
+        if unsafe { ConvertSidToStringSidW(user.User.Sid, &mut text) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `text` is a NUL-terminated string allocated by Windows, freed here.
+        let sid = unsafe {
+            let length = (0..).take_while(|&i| *text.add(i) != 0).count();
+            let sid = OsString::from_wide(std::slice::from_raw_parts(text, length));
+            LocalFree(text.cast());
+            sid
+        };
+        Ok(sid.to_string_lossy().into_owned())
+    }
+
+    /// Takes ownership of a handle returned by a Windows call, failing when it is invalid.
+    fn owned(handle: HANDLE) -> io::Result<OwnedHandle> {
+        if handle.is_null() || handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: a valid handle nothing else owns.
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle) })
+    }
+
+    fn wide(text: &OsStr) -> Vec<u16> {
+        text.encode_wide().chain(once(0)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_store::EncryptedFileStore;
+
+    fn start_agent(name: &str) -> AgentClient {
+        start_agent_with(name, |agent| agent)
+    }
+
+    fn store_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join("key_vaulter_tests").join(format!(
+            "{}-{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn store_backend(name: &str) -> Backend {
+        Backend::EncryptedFile(
+            EncryptedFileStore::new(store_path(name))
+                .with_machine_secret(crate::machine::test_secret_path()),
+        )
+    }
+
+    #[cfg(unix)]
+    fn test_socket(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("{}-{}", name, std::process::id()))
+            .join("agent.sock")
+    }
+
+    #[cfg(windows)]
+    fn test_socket(name: &str) -> PathBuf {
+        PathBuf::from(format!(
+            r"\\.\pipe\key_vaulter_tests-{}-{}",
+            name,
+            std::process::id()
+        ))
+    }
+
+    /// Starts an agent on a fresh store, set up by `configure`.
+    fn start_agent_with<F>(name: &str, configure: F) -> AgentClient
+    where
+        F: FnOnce(Agent) -> Agent,
+    {
+        let _ = fs::remove_file(store_path(name));
+        let socket = test_socket(name);
+        let agent = configure(Agent::bind(&socket, store_backend(name)).unwrap());
+        thread::spawn(move || agent.serve());
+        AgentClient::new(socket)
+    }
+
+    #[test]
+    fn test_client_roundtrip() {
+        let client = start_agent("agent_roundtrip");
+        client.health_check().unwrap();
+        client.set("system", "key", "secret").unwrap();
+        assert_eq!(client.get("system", "key").unwrap(), "secret");
+        assert_eq!(
+            client.digest("system", "key", b"salt").unwrap(),
+            KeyManager::digest_of(b"salt", "secret")
+        );
+        client.delete("system", "key").unwrap();
+        assert!(matches!(
+            client.get("system", "key"),
+            Err(keyring::Error::NoEntry)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_socket_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let client = start_agent("agent_private");
+        let mode = fs::metadata(client.socket_path())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o077, 0);
+        assert!(Agent::bind(client.socket_path(), Backend::Keyring).is_err());
+
+        let shared = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("agent_shared-{}", std::process::id()));
+        fs::create_dir_all(&shared).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+        let error = Agent::bind(shared.join("agent.sock"), Backend::Keyring)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        let mode = fs::metadata(&shared).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o777);
+        let _ = fs::remove_dir(&shared);
+    }
+
+    #[cfg(any(target_os = "linux", windows))]
+    #[test]
+    fn test_acl_limits_keys_per_executable() {
+        let me = std::env::current_exe().unwrap();
+        let acl =
+            AgentAcl::new()
+                .allow(&me, "system", "allowed")
+                .allow("/usr/bin/other", "system", "*");
+        let client = start_agent_with("agent_acl", |agent| agent.with_acl(acl));
+        client.set("system", "allowed", "secret").unwrap();
+        assert_eq!(client.get("system", "allowed").unwrap(), "secret");
+        assert!(matches!(
+            client.get("system", "forbidden"),
+            Err(keyring::Error::NoStorageAccess(_))
+        ));
+        client.health_check().unwrap();
+    }
+
+    #[test]
+    fn test_idle_agent_forgets_values() {
+        let client = start_agent_with("agent_idle", |agent| {
+            agent.with_idle_timeout(Duration::from_millis(50))
+        });
+        client.set("system", "key", "cached").unwrap();
+        // Changed behind the agent's back: only visible once the cache is dropped.
+        store_backend("agent_idle")
+            .set("system", "key", "rotated")
+            .unwrap();
+        assert_eq!(client.get("system", "key").unwrap(), "cached");
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(client.get("system", "key").unwrap(), "rotated");
+    }
+
+    #[test]
+    fn test_failures_keep_their_kind_across_the_socket() {
+        use crate::error::{Error, ErrorKind, Operation};
+
+        let kind_of = |error: keyring::Error| {
+            let error = Response::failure(&error).into_result().unwrap_err();
+            Error::new("system", "key", Operation::Read, "agent", error).kind()
+        };
+        let denied = keyring::Error::NoStorageAccess(Box::new(AgentError(
+            "/usr/bin/clockd may not use 'system/key'".to_string(),
+        )));
+        assert_eq!(kind_of(denied), ErrorKind::AccessDenied);
+        let locked =
+            keyring::Error::NoStorageAccess(Box::new(StoreLocked("the vault is locked".into())));
+        assert_eq!(kind_of(locked), ErrorKind::Locked);
+        assert_eq!(kind_of(keyring::Error::NoEntry), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_unreachable_agent() {
+        let client = AgentClient::new("/nonexistent/key_vaulter/agent.sock");
+        assert!(matches!(
+            client.health_check(),
+            Err(keyring::Error::PlatformFailure(_))
+        ));
+    }
+}
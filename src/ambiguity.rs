@@ -0,0 +1,116 @@
+use keyring::credential::Credential;
+
+/// What a manager does when several platform credentials match its system and key,
+/// which some keyrings (e.g. the Secret Service) allow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AmbiguityStrategy {
+    /// Fail with an error of kind `ErrorKind::Ambiguous`, whose message lists the matching
+    /// credentials (`Error::keyring_error` gives access to them).
+    #[default]
+    Fail,
+    /// Use the first credential the platform returned.
+    First,
+    /// List the credentials through the prompter and ask which one to use.
+    Prompt,
+}
+
+/// Describes each candidate credential for the chooser, one numbered line each.
+pub(crate) fn describe(credentials: &[Box<Credential>]) -> String {
+    credentials
+        .iter()
+        .enumerate()
+        .map(|(index, credential)| format!("{}) {:?}\n", index + 1, credential))
+        .collect()
+}
+
+/// Parses the answer of the chooser into an index of `credentials`.
+pub(crate) fn parse_choice(input: &str, count: usize) -> Option<usize> {
+    input
+        .parse::<usize>()
+        .ok()
+        .filter(|number| (1..=count).contains(number))
+        .map(|number| number - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Backend;
+    use crate::error::ErrorKind;
+    use crate::store::SecretStore;
+    use crate::test_utils::ScriptedPrompter;
+    use crate::vault::Vault;
+    use keyring::credential::CredentialApi;
+    use std::any::Any;
+    use std::fmt;
+
+    /// A credential holding a fixed password, like one of two duplicate keychain items.
+    struct Duplicate(&'static str);
+
+    impl CredentialApi for Duplicate {
+        fn set_password(&self, _: &str) -> keyring::Result<()> {
+            Ok(())
+        }
+
+        fn set_secret(&self, _: &[u8]) -> keyring::Result<()> {
+            Ok(())
+        }
+
+        fn get_password(&self) -> keyring::Result<String> {
+            Ok(self.0.to_string())
+        }
+
+        fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+            Ok(self.0.as_bytes().to_vec())
+        }
+
+        fn delete_credential(&self) -> keyring::Result<()> {
+            Ok(())
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn debug_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "item holding {}", self.0)
+        }
+    }
+
+    struct AmbiguousStore;
+
+    impl SecretStore for AmbiguousStore {
+        fn get(&self, _: &str, _: &str) -> keyring::Result<String> {
+            Err(keyring::Error::Ambiguous(vec![
+                Box::new(Duplicate("old")),
+                Box::new(Duplicate("new")),
+            ]))
+        }
+
+        fn set(&self, _: &str, _: &str, _: &str) -> keyring::Result<()> {
+            Ok(())
+        }
+
+        fn delete(&self, _: &str, _: &str) -> keyring::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ambiguity_strategies() {
+        let vault = Vault::new("ambiguity_service").with_backend(Backend::custom(AmbiguousStore));
+        let error = vault.key("token").read_key().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Ambiguous);
+        assert!(error.to_string().contains("item holding new"));
+
+        let vault = vault.with_ambiguity_strategy(AmbiguityStrategy::First);
+        assert_eq!(vault.key("token").read_key().unwrap(), "old");
+
+        let prompter = ScriptedPrompter::new(["3", "2"]);
+        let vault = vault
+            .with_ambiguity_strategy(AmbiguityStrategy::Prompt)
+            .with_prompter(prompter.clone());
+        assert_eq!(vault.key("token").read_key().unwrap(), "new");
+        assert!(prompter.prompts()[0].contains("1) item holding old\n2) item holding new\n"));
+    }
+}
@@ -0,0 +1,387 @@
+//! An Android backend (feature `android`), reached through JNI.
+//!
+//! Android Keystore holds keys rather than arbitrary secrets, so values are encrypted with
+//! AES-GCM under a key generated inside the Keystore (it never leaves it, and is
+//! hardware-backed where the device allows), and the ciphertexts are kept in a private
+//! `SharedPreferences` file of the app.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use jni::objects::{GlobalRef, JByteArray, JObject, JObjectArray, JString, JValue};
+use jni::{JNIEnv, JavaVM};
+use keyring::Result;
+use std::fmt;
+use std::sync::Arc;
+
+/// Alias of the AES key in the Android Keystore.
+const KEY_ALIAS: &str = "key_vaulter";
+/// Name of the `SharedPreferences` file holding the ciphertexts.
+const PREFERENCES: &str = "key_vaulter_secrets";
+/// Separates the system name from the key name in preference keys.
+const SEPARATOR: char = '\u{1f}';
+
+const PURPOSE_ENCRYPT_DECRYPT: i32 = 1 | 2;
+const ENCRYPT_MODE: i32 = 1;
+const DECRYPT_MODE: i32 = 2;
+const TAG_BITS: i32 = 128;
+const MODE_PRIVATE: i32 = 0;
+
+const BUILDER: &str = "android/security/keystore/KeyGenParameterSpec$Builder";
+const EDITOR: &str = "Landroid/content/SharedPreferences$Editor;";
+
+/// A store encrypting values with a key kept in the Android Keystore.
+///
+/// Clones share the same JVM and context.
+#[derive(Clone)]
+pub struct AndroidKeystore {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    vm: JavaVM,
+    context: GlobalRef,
+}
+
+impl fmt::Debug for AndroidKeystore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AndroidKeystore").finish_non_exhaustive()
+    }
+}
+
+/// Two stores are equal when one is a clone of the other.
+impl PartialEq for AndroidKeystore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for AndroidKeystore {}
+
+impl AndroidKeystore {
+    /// Creates a store for the app owning `context` (an `android.content.Context`, e.g. the
+    /// activity or application passed to the native library).
+    pub fn new(vm: JavaVM, context: &JObject) -> Result<AndroidKeystore> {
+        let context = vm
+            .attach_current_thread()
+            .and_then(|env| env.new_global_ref(context))
+            .map_err(failure)?;
+        Ok(AndroidKeystore {
+            inner: Arc::new(Inner { vm, context }),
+        })
+    }
+
+    /// Runs `f` on this thread's JNI environment, in a local frame so the references it
+    /// creates are freed. A pending Java exception is cleared and returned as the error.
+    fn with_env<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut JNIEnv, &JObject) -> jni::errors::Result<T>,
+    {
+        let mut env = self.inner.vm.attach_current_thread().map_err(failure)?;
+        let context = self.inner.context.as_obj();
+        let result = env.with_local_frame(16, |env| f(env, context));
+        if env.exception_check().unwrap_or(false) {
+            let _ = env.exception_describe();
+            let _ = env.exception_clear();
+        }
+        result.map_err(failure)
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        let stored = self.with_env(|env, context| {
+            let preferences = preferences(env, context)?;
+            let name = env.new_string(entry_name(system_name, key_name))?;
+            let value = env
+                .call_method(
+                    &preferences,
+                    "getString",
+                    "(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;",
+                    &[(&name).into(), (&JObject::null()).into()],
+                )?
+                .l()?;
+            if value.is_null() {
+                return Ok(None);
+            }
+            let record = read_string(env, value)?;
+            let Ok(record) = BASE64.decode(record) else {
+                return Ok(Some(Err(keyring::Error::BadEncoding(Vec::new()))));
+            };
+            if record.len() < 12 {
+                return Ok(Some(Err(keyring::Error::BadEncoding(record))));
+            }
+            let (iv, sealed) = record.split_at(12);
+            let cipher = cipher(env, DECRYPT_MODE, Some(iv))?;
+            let bytes = final_bytes(env, &cipher, sealed)?;
+            Ok(Some(
+                String::from_utf8(bytes).map_err(|e| keyring::Error::BadEncoding(e.into_bytes())),
+            ))
+        })?;
+        stored.ok_or(keyring::Error::NoEntry)?
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        let committed = self.with_env(|env, context| {
+            let cipher = cipher(env, ENCRYPT_MODE, None)?;
+            let iv = env.call_method(&cipher, "getIV", "()[B", &[])?.l()?;
+            let mut record = env.convert_byte_array(JByteArray::from(iv))?;
+            record.extend(final_bytes(env, &cipher, value.as_bytes())?);
+
+            let preferences = preferences(env, context)?;
+            let editor = env
+                .call_method(&preferences, "edit", format!("(){}", EDITOR), &[])?
+                .l()?;
+            let name = env.new_string(entry_name(system_name, key_name))?;
+            let record = env.new_string(BASE64.encode(record))?;
+            env.call_method(
+                &editor,
+                "putString",
+                format!("(Ljava/lang/String;Ljava/lang/String;){}", EDITOR),
+                &[(&name).into(), (&record).into()],
+            )?;
+            env.call_method(&editor, "commit", "()Z", &[])?.z()
+        })?;
+        commit_result(committed)
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        let committed = self.with_env(|env, context| {
+            let preferences = preferences(env, context)?;
+            let name = env.new_string(entry_name(system_name, key_name))?;
+            let name: JValue = (&name).into();
+            if !env
+                .call_method(&preferences, "contains", "(Ljava/lang/String;)Z", &[name])?
+                .z()?
+            {
+                return Ok(None);
+            }
+            let editor = env
+                .call_method(&preferences, "edit", format!("(){}", EDITOR), &[])?
+                .l()?;
+            env.call_method(
+                &editor,
+                "remove",
+                format!("(Ljava/lang/String;){}", EDITOR),
+                &[name],
+            )?;
+            env.call_method(&editor, "commit", "()Z", &[])?
+                .z()
+                .map(Some)
+        })?;
+        commit_result(committed.ok_or(keyring::Error::NoEntry)?)
+    }
+
+    pub(crate) fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        let names = self.with_env(|env, context| {
+            let preferences = preferences(env, context)?;
+            let all = env
+                .call_method(&preferences, "getAll", "()Ljava/util/Map;", &[])?
+                .l()?;
+            let keys = env
+                .call_method(&all, "keySet", "()Ljava/util/Set;", &[])?
+                .l()?;
+            let keys = JObjectArray::from(
+                env.call_method(&keys, "toArray", "()[Ljava/lang/Object;", &[])?
+                    .l()?,
+            );
+            let mut names = Vec::new();
+            for index in 0..env.get_array_length(&keys)? {
+                let name = env.get_object_array_element(&keys, index)?;
+                names.push(read_string(env, name)?);
+            }
+            Ok(names)
+        })?;
+        let prefix = entry_name(system_name, "");
+        let mut keys: Vec<String> = names
+            .iter()
+            .filter_map(|name| name.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Checks that the Keystore key can be loaded or created.
+    pub(crate) fn health_check(&self) -> Result<()> {
+        self.with_env(|env, _| secret_key(env).map(|_| ()))
+    }
+}
+
+fn entry_name(system_name: &str, key_name: &str) -> String {
+    format!("{}{}{}", system_name, SEPARATOR, key_name)
+}
+
+fn commit_result(committed: bool) -> Result<()> {
+    if committed {
+        Ok(())
+    } else {
+        Err(keyring::Error::PlatformFailure(
+            "SharedPreferences commit failed".into(),
+        ))
+    }
+}
+
+fn read_string(env: &mut JNIEnv, value: JObject) -> jni::errors::Result<String> {
+    let value = JString::from(value);
+    let text = env.get_string(&value)?.into();
+    Ok(text)
+}
+
+fn preferences<'local>(
+    env: &mut JNIEnv<'local>,
+    context: &JObject,
+) -> jni::errors::Result<JObject<'local>> {
+    let name = env.new_string(PREFERENCES)?;
+    env.call_method(
+        context,
+        "getSharedPreferences",
+        "(Ljava/lang/String;I)Landroid/content/SharedPreferences;",
+        &[(&name).into(), JValue::Int(MODE_PRIVATE)],
+    )?
+    .l()
+}
+
+/// Loads the AES key from the Android Keystore, generating it on first use.
+fn secret_key<'local>(env: &mut JNIEnv<'local>) -> jni::errors::Result<JObject<'local>> {
+    let provider = env.new_string("AndroidKeyStore")?;
+    let alias = env.new_string(KEY_ALIAS)?;
+    let keystore = env
+        .call_static_method(
+            "java/security/KeyStore",
+            "getInstance",
+            "(Ljava/lang/String;)Ljava/security/KeyStore;",
+            &[(&provider).into()],
+        )?
+        .l()?;
+    env.call_method(
+        &keystore,
+        "load",
+        "(Ljava/security/KeyStore$LoadStoreParameter;)V",
+        &[(&JObject::null()).into()],
+    )?;
+    let key = env
+        .call_method(
+            &keystore,
+            "getKey",
+            "(Ljava/lang/String;[C)Ljava/security/Key;",
+            &[(&alias).into(), (&JObject::null()).into()],
+        )?
+        .l()?;
+    if !key.is_null() {
+        return Ok(key);
+    }
+
+    let builder_type = format!("L{};", BUILDER);
+    let builder = env.new_object(
+        BUILDER,
+        "(Ljava/lang/String;I)V",
+        &[(&alias).into(), JValue::Int(PURPOSE_ENCRYPT_DECRYPT)],
+    )?;
+    for (method, value) in [
+        ("setBlockModes", "GCM"),
+        ("setEncryptionPaddings", "NoPadding"),
+    ] {
+        let value = env.new_string(value)?;
+        let values = env.new_object_array(1, "java/lang/String", &value)?;
+        env.call_method(
+            &builder,
+            method,
+            format!("([Ljava/lang/String;){}", builder_type),
+            &[(&values).into()],
+        )?;
+    }
+    env.call_method(
+        &builder,
+        "setKeySize",
+        format!("(I){}", builder_type),
+        &[JValue::Int(256)],
+    )?;
+    let spec = env
+        .call_method(
+            &builder,
+            "build",
+            "()Landroid/security/keystore/KeyGenParameterSpec;",
+            &[],
+        )?
+        .l()?;
+    let algorithm = env.new_string("AES")?;
+    let generator = env
+        .call_static_method(
+            "javax/crypto/KeyGenerator",
+            "getInstance",
+            "(Ljava/lang/String;Ljava/lang/String;)Ljavax/crypto/KeyGenerator;",
+            &[(&algorithm).into(), (&provider).into()],
+        )?
+        .l()?;
+    env.call_method(
+        &generator,
+        "init",
+        "(Ljava/security/spec/AlgorithmParameterSpec;)V",
+        &[(&spec).into()],
+    )?;
+    env.call_method(&generator, "generateKey", "()Ljavax/crypto/SecretKey;", &[])?
+        .l()
+}
+
+/// Returns an `AES/GCM/NoPadding` cipher initialized with the Keystore key. Encryption lets
+/// the Keystore pick the IV (it refuses caller-provided ones); decryption takes `iv`.
+fn cipher<'local>(
+    env: &mut JNIEnv<'local>,
+    mode: i32,
+    iv: Option<&[u8]>,
+) -> jni::errors::Result<JObject<'local>> {
+    let key = secret_key(env)?;
+    let transformation = env.new_string("AES/GCM/NoPadding")?;
+    let cipher = env
+        .call_static_method(
+            "javax/crypto/Cipher",
+            "getInstance",
+            "(Ljava/lang/String;)Ljavax/crypto/Cipher;",
+            &[(&transformation).into()],
+        )?
+        .l()?;
+    match iv {
+        None => env.call_method(
+            &cipher,
+            "init",
+            "(ILjava/security/Key;)V",
+            &[JValue::Int(mode), (&key).into()],
+        )?,
+        Some(iv) => {
+            let iv = env.byte_array_from_slice(iv)?;
+            let spec = env.new_object(
+                "javax/crypto/spec/GCMParameterSpec",
+                "(I[B)V",
+                &[JValue::Int(TAG_BITS), (&iv).into()],
+            )?;
+            env.call_method(
+                &cipher,
+                "init",
+                "(ILjava/security/Key;Ljava/security/spec/AlgorithmParameterSpec;)V",
+                &[JValue::Int(mode), (&key).into(), (&spec).into()],
+            )?
+        }
+    };
+    Ok(cipher)
+}
+
+fn final_bytes(env: &mut JNIEnv, cipher: &JObject, input: &[u8]) -> jni::errors::Result<Vec<u8>> {
+    let input = env.byte_array_from_slice(input)?;
+    let output = env
+        .call_method(cipher, "doFinal", "([B)[B", &[(&input).into()])?
+        .l()?;
+    env.convert_byte_array(JByteArray::from(output))
+}
+
+fn failure(error: jni::errors::Error) -> keyring::Error {
+    keyring::Error::PlatformFailure(Box::new(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_names() {
+        assert_eq!(entry_name("app", "token"), "app\u{1f}token");
+        assert!(commit_result(true).is_ok());
+        assert!(commit_result(false).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::file_store::KdfParams;
+use crate::hooks::ReadRequest;
+use crate::prompt::{Prompter, SharedPrompter, TerminalPrompter};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use std::fmt;
+use std::sync::Arc;
+
+/// Decides whether a read of a key set up with `KeyManager::with_approver` may proceed,
+/// e.g. by asking a teammate through a remote service or a prompt on another device.
+/// Every approver of a key must approve; return `false` to deny the read. Implemented
+/// for closures.
+pub trait Approver: Send + Sync {
+    fn approve(&self, request: &ReadRequest) -> bool;
+}
+
+impl<F> Approver for F
+where
+    F: Fn(&ReadRequest) -> bool + Send + Sync,
+{
+    fn approve(&self, request: &ReadRequest) -> bool {
+        self(request)
+    }
+}
+
+/// A two-person (or more) gate: asks each approver for their passphrase through the
+/// prompter and approves only if every one matches its digest (see `digest`).
+///
+/// Keep the digests where whoever can read the key can't replace them.
+#[derive(Clone)]
+pub struct PassphraseApprover {
+    approvers: Vec<(String, String)>,
+    prompter: SharedPrompter,
+}
+
+impl fmt::Debug for PassphraseApprover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self
+            .approvers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        f.debug_struct("PassphraseApprover")
+            .field("approvers", &names)
+            .finish()
+    }
+}
+
+impl Default for PassphraseApprover {
+    fn default() -> Self {
+        PassphraseApprover::new()
+    }
+}
+
+impl PassphraseApprover {
+    /// Creates a gate without approvers, prompting on the terminal.
+    pub fn new() -> Self {
+        PassphraseApprover {
+            approvers: Vec::new(),
+            prompter: SharedPrompter(Arc::new(TerminalPrompter::new())),
+        }
+    }
+
+    /// Creates a gate requiring both `first` and `second`, each a name and the `digest`
+    /// of that person's passphrase.
+    pub fn two_person(first: (&str, &str), second: (&str, &str)) -> Self {
+        PassphraseApprover::new()
+            .with_approver(first.0, first.1)
+            .with_approver(second.0, second.1)
+    }
+
+    /// Requires the passphrase of `name`, whose `digest` is given.
+    pub fn with_approver(mut self, name: &str, digest: &str) -> Self {
+        self.approvers.push((name.to_string(), digest.to_string()));
+        self
+    }
+
+    /// Asks for the passphrases through `prompter` instead of the terminal.
+    pub fn with_prompter<P>(mut self, prompter: P) -> Self
+    where
+        P: Prompter + 'static,
+    {
+        self.prompter = SharedPrompter(Arc::new(prompter));
+        self
+    }
+
+    /// The digest of `passphrase` to configure with `with_approver`, in the form
+    /// `scrypt:<log_n>:<r>:<p>$<salt>$<hash>`: the passphrase stretched with scrypt and a
+    /// random salt, both base64-encoded. Each call picks a new salt.
+    pub fn digest(passphrase: &str) -> String {
+        let params = KdfParams::DEFAULT;
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let hash = params
+            .derive(passphrase, &salt)
+            .expect("the default scrypt parameters are valid");
+        format!(
+            "{}${}${}",
+            params,
+            BASE64.encode(salt),
+            BASE64.encode(hash.as_slice())
+        )
+    }
+
+    /// Whether `passphrase` matches `digest`, comparing the hashes in constant time. A
+    /// malformed digest matches nothing.
+    fn matches(passphrase: &str, digest: &str) -> bool {
+        let mut parts = digest.split('$');
+        let (Some(params), Some(salt), Some(expected), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return false;
+        };
+        let (Some(params), Ok(salt), Ok(expected)) = (
+            KdfParams::parse(params),
+            BASE64.decode(salt),
+            BASE64.decode(expected),
+        ) else {
+            return false;
+        };
+        match params.derive(passphrase, &salt) {
+            Ok(hash) => {
+                hash.len() == expected.len()
+                    && hash
+                        .iter()
+                        .zip(&expected)
+                        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+                        == 0
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl Approver for PassphraseApprover {
+    /// Stops at the first wrong passphrase; a gate without approvers denies every read.
+    fn approve(&self, request: &ReadRequest) -> bool {
+        !self.approvers.is_empty()
+            && self.approvers.iter().all(|(name, digest)| {
+                let message = format!(
+                    "Passphrase of {} to approve reading '{}/{}': ",
+                    name, request.system, request.key
+                );
+                match self.prompter.0.prompt(&message) {
+                    Ok(passphrase) => PassphraseApprover::matches(&passphrase, digest),
+                    Err(_) => false,
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_utils::ScriptedPrompter;
+    use crate::vault::Vault;
+
+    #[test]
+    fn test_two_person_approval() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("root_ca").store("private key").unwrap();
+        let gate = |answers: [&str; 2]| {
+            PassphraseApprover::two_person(
+                ("ana", &PassphraseApprover::digest("ana's passphrase")),
+                ("bob", &PassphraseApprover::digest("bob's passphrase")),
+            )
+            .with_prompter(ScriptedPrompter::new(answers))
+        };
+
+        let approved = gate(["ana's passphrase", "bob's passphrase"]);
+        let mut manager = vault.key("root_ca").with_approver(approved);
+        assert_eq!(manager.read_key().unwrap(), "private key");
+
+        let denied = gate(["ana's passphrase", "ana's passphrase"]);
+        let mut manager = vault.key("root_ca").with_approver(denied);
+        assert_eq!(
+            manager.read_key().unwrap_err().kind(),
+            ErrorKind::AccessDenied
+        );
+
+        let mut manager = vault
+            .key("root_ca")
+            .with_approver(|request: &ReadRequest| request.key == "root_ca")
+            .with_approver(|_: &ReadRequest| false);
+        assert!(manager.read_key().is_err());
+    }
+
+    #[test]
+    fn test_digests_are_salted() {
+        let digest = PassphraseApprover::digest("passphrase");
+        assert!(digest.starts_with("scrypt:"));
+        assert_ne!(digest, PassphraseApprover::digest("passphrase"));
+        assert!(PassphraseApprover::matches("passphrase", &digest));
+        assert!(!PassphraseApprover::matches("Passphrase", &digest));
+        assert!(!PassphraseApprover::matches("passphrase", "not a digest"));
+    }
+}
@@ -0,0 +1,167 @@
+use crate::batch::BatchOutcome;
+use crate::error::{Cause, Operation, Result};
+use crate::key_manager::{DeleteOutcome, KeyManager, StoreOutcome};
+use crate::vault::Vault;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use zeroize::Zeroizing;
+
+/// An async front end for a [`Vault`] (feature `async`).
+///
+/// Backends are blocking, so every operation runs on tokio's blocking thread pool and
+/// never stalls the async executor.
+#[derive(Debug, Clone)]
+pub struct AsyncVault {
+    vault: Vault,
+}
+
+impl AsyncVault {
+    pub fn new(vault: Vault) -> Self {
+        AsyncVault { vault }
+    }
+
+    /// The wrapped vault.
+    pub fn vault(&self) -> &Vault {
+        &self.vault
+    }
+
+    /// See `KeyManager::read_key`.
+    pub async fn read(&self, key_name: &str) -> Result<String> {
+        blocking(&self.vault, key_name, Operation::Read, |mut key| {
+            key.read_key()
+        })
+        .await
+    }
+
+    /// See `KeyManager::store`.
+    pub async fn store(&self, key_name: &str, value: &str) -> Result<StoreOutcome> {
+        let value = Zeroizing::new(value.to_string());
+        blocking(&self.vault, key_name, Operation::Store, move |mut key| {
+            key.store(&value)
+        })
+        .await
+    }
+
+    /// See `KeyManager::delete`.
+    pub async fn delete(&self, key_name: &str) -> Result<DeleteOutcome> {
+        blocking(&self.vault, key_name, Operation::Delete, |mut key| {
+            key.delete()
+        })
+        .await
+    }
+
+    /// Reads `keys` concurrently, at most `max_concurrency` at a time, and returns every
+    /// result in input order. Cuts the cold start of services loading many secrets from a
+    /// remote backend.
+    pub async fn join_read(&self, keys: &[&str], max_concurrency: usize) -> BatchOutcome<String> {
+        let requests = keys.iter().map(|key| (self.vault.clone(), key.to_string()));
+        let results = join_read(requests, max_concurrency).await;
+        BatchOutcome::from_results(
+            keys.iter()
+                .map(|key| key.to_string())
+                .zip(results)
+                .collect(),
+        )
+    }
+}
+
+/// Reads `(vault, key)` pairs concurrently, at most `max_concurrency` at a time, across
+/// any mix of vaults and backends. Results are returned in input order.
+pub async fn join_read<I>(requests: I, max_concurrency: usize) -> Vec<Result<String>>
+where
+    I: IntoIterator<Item = (Vault, String)>,
+{
+    let requests: Vec<(Vault, String)> = requests.into_iter().collect();
+    let permits = Arc::new(Semaphore::new(max_concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, (vault, key_name)) in requests.iter().cloned().enumerate() {
+        let permits = Arc::clone(&permits);
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let result =
+                blocking(&vault, &key_name, Operation::Read, |mut key| key.read_key()).await;
+            (index, result)
+        });
+    }
+    let mut results: Vec<Option<Result<String>>> = requests.iter().map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, result)) = joined {
+            results[index] = Some(result);
+        }
+    }
+    results
+        .into_iter()
+        .zip(&requests)
+        .map(|(result, (vault, key_name))| {
+            result.unwrap_or_else(|| {
+                Err(vault.key(key_name).error(
+                    Operation::Read,
+                    Cause::Platform("read task was aborted".into()),
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Runs `task` on a manager for `key_name` on tokio's blocking thread pool.
+async fn blocking<T, F>(vault: &Vault, key_name: &str, operation: Operation, task: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(KeyManager) -> Result<T> + Send + 'static,
+{
+    let manager = vault.key(key_name);
+    match tokio::task::spawn_blocking(move || task(manager)).await {
+        Ok(result) => result,
+        Err(e) => Err(vault
+            .key(key_name)
+            .error(operation, Cause::Platform(Box::new(e)))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[tokio::test]
+    async fn test_join_read() {
+        let vault = AsyncVault::new(Vault::ephemeral_for_tests());
+        for key in ["a", "b", "c"] {
+            vault.store(key, &key.to_uppercase()).await.unwrap();
+        }
+        let outcome = vault.join_read(&["c", "missing", "a", "b"], 2).await;
+        let values: Vec<_> = outcome
+            .results()
+            .iter()
+            .map(|(key, result)| (key.as_str(), result.as_ref().ok().cloned()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("c", Some("C".to_string())),
+                ("missing", None),
+                ("a", Some("A".to_string())),
+                ("b", Some("B".to_string())),
+            ]
+        );
+        assert_eq!(
+            outcome.get("missing").unwrap().as_ref().unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+
+        let other = Vault::ephemeral_for_tests();
+        other.key("x").store("X").unwrap();
+        let results = join_read(
+            [
+                (vault.vault().clone(), "a".to_string()),
+                (other, "x".to_string()),
+            ],
+            4,
+        )
+        .await;
+        assert_eq!(results[1].as_ref().unwrap(), "X");
+        vault.delete("a").await.unwrap();
+        assert!(vault.read("a").await.is_err());
+    }
+}
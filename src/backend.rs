@@ -0,0 +1,499 @@
+#[cfg(feature = "agent")]
+use crate::agent::AgentClient;
+#[cfg(feature = "android")]
+use crate::android_store::AndroidKeystore;
+use crate::file_store::EncryptedFileStore;
+use crate::hooks;
+use crate::key_manager::KeyManager;
+use crate::memory_store::MemoryStore;
+use crate::offline::OfflineStore;
+#[cfg(feature = "redb")]
+use crate::redb_store::RedbStore;
+#[cfg(feature = "remote")]
+use crate::remote::RemoteStore;
+use crate::secret_service;
+#[cfg(feature = "sqlite")]
+use crate::sqlite_store::SqliteStore;
+use crate::store::CustomStore;
+#[cfg(feature = "wasm")]
+use crate::web_store::WebStore;
+use keyring::{Entry, Result};
+use zeroize::Zeroizing;
+
+/// Service name used when probing a backend without touching real entries.
+const PROBE_SYSTEM: &str = "key_vaulter";
+const PROBE_KEY: &str = "__key_vaulter_health_check__";
+
+/// Describes what a storage backend is able to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether the backend can enumerate the keys it holds.
+    pub supports_listing: bool,
+    /// Largest value (in bytes) the backend accepts, if it has a known limit.
+    pub max_value_size: Option<usize>,
+    /// Whether stored values survive a process restart.
+    pub persistent: bool,
+    /// Whether the store may need to be unlocked (e.g. by a user prompt) before use.
+    pub requires_unlock: bool,
+}
+
+/// Summary of a backend returned by `KeyManager::backend_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendInfo {
+    pub name: &'static str,
+    pub capabilities: Capabilities,
+    /// `None` when the health check passed, otherwise a description of the failure.
+    pub health_error: Option<String>,
+}
+
+impl BackendInfo {
+    /// Returns true when the backend answered the health check.
+    pub fn is_healthy(&self) -> bool {
+        self.health_error.is_none()
+    }
+}
+
+/// The storage backend a key is read from and written to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The operating system keyring (Credential Manager, Keychain or Secret Service).
+    #[default]
+    Keyring,
+    /// An encrypted JSON file, used where no OS keyring is available.
+    EncryptedFile(EncryptedFileStore),
+    /// Values kept in process memory only, e.g. for tests.
+    Memory(MemoryStore),
+    /// An encrypted single-file redb database, in pure Rust.
+    #[cfg(feature = "redb")]
+    Redb(RedbStore),
+    /// An encrypted SQLite database, which can also be queried by tag and date.
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteStore),
+    /// A shared secrets server reached over HTTP(S).
+    #[cfg(feature = "remote")]
+    Remote(RemoteStore),
+    /// A running `key_vaulter agent` reached over its Unix socket or named pipe.
+    #[cfg(feature = "agent")]
+    Agent(AgentClient),
+    /// Values encrypted with a key held by the Android Keystore, through JNI.
+    #[cfg(feature = "android")]
+    Android(AndroidKeystore),
+    /// The browser's IndexedDB, encrypted with Web Crypto, in WASM builds.
+    #[cfg(feature = "wasm")]
+    Web(WebStore),
+    /// Another backend, usually `Remote`, with an encrypted local cache serving reads and
+    /// queueing writes while it is unreachable.
+    Offline(OfflineStore),
+    /// Any [`SecretStore`](crate::store::SecretStore), e.g. a mock in unit tests.
+    Custom(CustomStore),
+}
+
+impl Backend {
+    /// Picks the OS keyring when it answers the health check, otherwise falls back to an
+    /// encrypted file at [`EncryptedFileStore::default_path`].
+    ///
+    /// This covers WSL, minimal containers and SSH sessions without D-Bus. The fallback is
+    /// reported through the warning hook (see [`hooks::set_warning_hook`]). Returns `None`
+    /// when neither is usable, e.g. the keyring is missing and the data directory is not
+    /// writable.
+    pub fn auto() -> Option<Backend> {
+        Backend::first_healthy([
+            Backend::Keyring,
+            Backend::EncryptedFile(EncryptedFileStore::new(EncryptedFileStore::default_path())),
+        ])
+    }
+
+    /// Returns the first candidate that passes its health check, warning about each one
+    /// that is skipped.
+    pub fn first_healthy<I>(candidates: I) -> Option<Backend>
+    where
+        I: IntoIterator<Item = Backend>,
+    {
+        for candidate in candidates {
+            match candidate.health_check() {
+                Ok(()) => {
+                    hooks::debug(&format!("using backend '{}'", candidate.name()));
+                    return Some(candidate);
+                }
+                Err(e) => hooks::warn(&format!(
+                    "backend '{}' is unavailable ({}), trying the next one",
+                    candidate.name(),
+                    e
+                )),
+            }
+        }
+        None
+    }
+
+    /// Short, stable name of the backend.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Keyring => "keyring",
+            Backend::EncryptedFile(_) => "encrypted-file",
+            Backend::Memory(_) => "memory",
+            #[cfg(feature = "redb")]
+            Backend::Redb(_) => "redb",
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(_) => "sqlite",
+            #[cfg(feature = "remote")]
+            Backend::Remote(_) => "remote",
+            #[cfg(feature = "android")]
+            Backend::Android(_) => "android",
+            #[cfg(feature = "wasm")]
+            Backend::Web(_) => "web",
+            #[cfg(feature = "agent")]
+            Backend::Agent(_) => "agent",
+            Backend::Offline(_) => "offline",
+            Backend::Custom(store) => store.inner().name(),
+        }
+    }
+
+    /// Names the store behind the backend, e.g. `encrypted-file:/path/to/file`: backends of
+    /// the same kind get different identities when they hold different entries, as far as
+    /// the process can tell.
+    pub(crate) fn identity(&self) -> String {
+        match self {
+            Backend::EncryptedFile(store) => format!("encrypted-file:{}", store.path().display()),
+            Backend::Memory(store) => format!("memory:{:x}", store.id()),
+            #[cfg(feature = "redb")]
+            Backend::Redb(store) => format!("redb:{}", store.path().display()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => match store.path() {
+                Some(path) => format!("sqlite:{}", path.display()),
+                None => "sqlite".to_string(),
+            },
+            #[cfg(feature = "remote")]
+            Backend::Remote(store) => format!("remote:{}", store.base_url()),
+            #[cfg(feature = "agent")]
+            Backend::Agent(client) => format!("agent:{}", client.socket_path().display()),
+            Backend::Offline(store) => format!("offline:{}", store.primary().identity()),
+            _ => self.name().to_string(),
+        }
+    }
+
+    /// Returns the capabilities of the backend on the current platform.
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            Backend::Keyring => Capabilities {
+                // The Secret Service can be searched by attribute.
+                supports_listing: secret_service::AVAILABLE,
+                // Windows limits a credential blob to 5 * 512 bytes.
+                max_value_size: if cfg!(target_os = "windows") {
+                    Some(2560)
+                } else {
+                    None
+                },
+                persistent: true,
+                requires_unlock: !cfg!(target_os = "windows"),
+            },
+            Backend::EncryptedFile(_) => Capabilities {
+                supports_listing: true,
+                max_value_size: None,
+                persistent: true,
+                requires_unlock: false,
+            },
+            Backend::Memory(_) => Capabilities {
+                supports_listing: true,
+                max_value_size: None,
+                persistent: false,
+                requires_unlock: false,
+            },
+            #[cfg(feature = "redb")]
+            Backend::Redb(_) => Capabilities {
+                supports_listing: true,
+                max_value_size: None,
+                persistent: true,
+                requires_unlock: false,
+            },
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => Capabilities {
+                supports_listing: true,
+                max_value_size: None,
+                persistent: store.path().is_some(),
+                requires_unlock: false,
+            },
+            #[cfg(feature = "remote")]
+            Backend::Remote(_) => Capabilities {
+                supports_listing: true,
+                max_value_size: None,
+                persistent: true,
+                requires_unlock: false,
+            },
+            #[cfg(feature = "android")]
+            Backend::Android(_) => Capabilities {
+                supports_listing: true,
+                max_value_size: None,
+                persistent: true,
+                requires_unlock: false,
+            },
+            #[cfg(feature = "wasm")]
+            Backend::Web(_) => Capabilities {
+                supports_listing: true,
+                max_value_size: None,
+                persistent: true,
+                requires_unlock: false,
+            },
+            #[cfg(feature = "agent")]
+            Backend::Agent(_) => Capabilities {
+                supports_listing: false,
+                max_value_size: None,
+                persistent: true,
+                requires_unlock: false,
+            },
+            Backend::Offline(store) => store.capabilities(),
+            Backend::Custom(store) => store.inner().capabilities(),
+        }
+    }
+
+    /// Checks that the backend is reachable without reading or writing any real entry.
+    ///
+    /// A missing probe entry counts as healthy; any other failure is returned.
+    pub fn health_check(&self) -> Result<()> {
+        match self {
+            Backend::Keyring => {
+                let entry = Entry::new(PROBE_SYSTEM, PROBE_KEY)?;
+                match entry.get_password() {
+                    Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+                    Err(e) => Err(e),
+                }
+            }
+            Backend::EncryptedFile(store) => store.health_check(),
+            Backend::Memory(_) => Ok(()),
+            #[cfg(feature = "redb")]
+            Backend::Redb(store) => store.health_check(),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.health_check(),
+            #[cfg(feature = "android")]
+            Backend::Android(store) => store.health_check(),
+            #[cfg(feature = "wasm")]
+            Backend::Web(_) => Ok(()),
+            #[cfg(feature = "remote")]
+            Backend::Remote(store) => store.health_check(),
+            #[cfg(feature = "agent")]
+            Backend::Agent(client) => client.health_check(),
+            Backend::Offline(store) => store.health_check(),
+            Backend::Custom(store) => store.inner().health_check(),
+        }
+    }
+
+    /// Runs the health check and collects everything into a `BackendInfo`.
+    pub fn info(&self) -> BackendInfo {
+        BackendInfo {
+            name: self.name(),
+            capabilities: self.capabilities(),
+            health_error: self.health_check().err().map(|e| e.to_string()),
+        }
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        match self {
+            Backend::Keyring => Entry::new(system_name, key_name)?.get_password(),
+            Backend::EncryptedFile(store) => store.get(system_name, key_name),
+            Backend::Memory(store) => store.get(system_name, key_name),
+            #[cfg(feature = "redb")]
+            Backend::Redb(store) => store.get(system_name, key_name),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.get(system_name, key_name),
+            #[cfg(feature = "remote")]
+            Backend::Remote(store) => store.get(system_name, key_name),
+            #[cfg(feature = "android")]
+            Backend::Android(store) => store.get(system_name, key_name),
+            #[cfg(feature = "wasm")]
+            Backend::Web(store) => store.get(system_name, key_name),
+            #[cfg(feature = "agent")]
+            Backend::Agent(client) => client.get(system_name, key_name),
+            Backend::Offline(store) => store.get(system_name, key_name),
+            Backend::Custom(store) => store.inner().get(system_name, key_name),
+        }
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        match self {
+            Backend::Keyring => Entry::new(system_name, key_name)?.set_password(value),
+            Backend::EncryptedFile(store) => store.set(system_name, key_name, value),
+            Backend::Memory(store) => store.set(system_name, key_name, value),
+            #[cfg(feature = "redb")]
+            Backend::Redb(store) => store.set(system_name, key_name, value),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.set(system_name, key_name, value),
+            #[cfg(feature = "remote")]
+            Backend::Remote(store) => store.set(system_name, key_name, value),
+            #[cfg(feature = "android")]
+            Backend::Android(store) => store.set(system_name, key_name, value),
+            #[cfg(feature = "wasm")]
+            Backend::Web(store) => store.set(system_name, key_name, value),
+            #[cfg(feature = "agent")]
+            Backend::Agent(client) => client.set(system_name, key_name, value),
+            Backend::Offline(store) => store.set(system_name, key_name, value),
+            Backend::Custom(store) => store.inner().set(system_name, key_name, value),
+        }
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        match self {
+            Backend::Keyring => Entry::new(system_name, key_name)?.delete_credential(),
+            Backend::EncryptedFile(store) => store.delete(system_name, key_name),
+            Backend::Memory(store) => store.delete(system_name, key_name),
+            #[cfg(feature = "redb")]
+            Backend::Redb(store) => store.delete(system_name, key_name),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.delete(system_name, key_name),
+            #[cfg(feature = "remote")]
+            Backend::Remote(store) => store.delete(system_name, key_name),
+            #[cfg(feature = "android")]
+            Backend::Android(store) => store.delete(system_name, key_name),
+            #[cfg(feature = "wasm")]
+            Backend::Web(store) => store.delete(system_name, key_name),
+            #[cfg(feature = "agent")]
+            Backend::Agent(client) => client.delete(system_name, key_name),
+            Backend::Offline(store) => store.delete(system_name, key_name),
+            Backend::Custom(store) => store.inner().delete(system_name, key_name),
+        }
+    }
+
+    /// Lists the key names stored for `system_name`. Fails on backends without
+    /// `supports_listing`.
+    pub fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        match self {
+            Backend::Keyring if secret_service::AVAILABLE => {
+                secret_service::find_keys(system_name, &[])
+            }
+            Backend::EncryptedFile(store) => store.list(system_name),
+            Backend::Memory(store) => store.list(system_name),
+            #[cfg(feature = "redb")]
+            Backend::Redb(store) => store.list(system_name),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => store.list(system_name),
+            #[cfg(feature = "remote")]
+            Backend::Remote(store) => store.list(system_name),
+            #[cfg(feature = "android")]
+            Backend::Android(store) => store.list(system_name),
+            #[cfg(feature = "wasm")]
+            Backend::Web(store) => store.list(system_name),
+            Backend::Offline(store) => store.list(system_name),
+            Backend::Custom(store) => store.inner().list(system_name),
+            _ => Err(listing_unsupported(self.name())),
+        }
+    }
+
+    /// The salted digest of a value (see `KeyManager::digest_of`), computed by the agent on
+    /// the `Agent` backend so the value doesn't leave it.
+    pub(crate) fn digest(&self, system_name: &str, key_name: &str, salt: &[u8]) -> Result<String> {
+        match self {
+            #[cfg(feature = "agent")]
+            Backend::Agent(client) => client.digest(system_name, key_name, salt),
+            _ => {
+                let value = Zeroizing::new(self.get(system_name, key_name)?);
+                Ok(KeyManager::digest_of(salt, &value))
+            }
+        }
+    }
+
+    /// When `key_name` was last written, in seconds since the Unix epoch, for backends
+    /// recording it (SQLite stores); `None` elsewhere or when the key doesn't exist.
+    pub fn modified(&self, system_name: &str, key_name: &str) -> Result<Option<u64>> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite(store) => Ok(store
+                .entries(system_name)?
+                .into_iter()
+                .find(|entry| entry.key_name == key_name)
+                .map(|entry| entry.modified)),
+            _ => {
+                let _ = (system_name, key_name);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Lists the key names of `system_name` stored with the attribute `name` set to
+    /// `value` (see `KeyManager::with_attribute`). Only the `Keyring` backend on the
+    /// Secret Service (Linux and BSD) supports it.
+    pub fn find_by_attribute(
+        &self,
+        system_name: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<Vec<String>> {
+        match self {
+            Backend::Keyring if secret_service::AVAILABLE => {
+                secret_service::find_keys(system_name, &[(name, value)])
+            }
+            _ => Err(keyring::Error::PlatformFailure(
+                format!(
+                    "backend '{}' does not support attribute search",
+                    self.name()
+                )
+                .into(),
+            )),
+        }
+    }
+}
+
+/// The error returned by `list` on backends that can't enumerate their entries.
+pub(crate) fn listing_unsupported(backend: &str) -> keyring::Error {
+    keyring::Error::PlatformFailure(
+        format!("backend '{}' does not support listing", backend).into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyring_capabilities() {
+        let caps = Backend::Keyring.capabilities();
+        assert!(caps.persistent);
+        assert_eq!(caps.supports_listing, secret_service::AVAILABLE);
+    }
+
+    #[test]
+    fn test_backend_info() {
+        let info = Backend::default().info();
+        assert_eq!(info.name, "keyring");
+        assert_eq!(info.capabilities, Backend::Keyring.capabilities());
+        assert_eq!(info.is_healthy(), Backend::Keyring.health_check().is_ok());
+    }
+
+    #[test]
+    fn test_first_healthy_skips_unavailable_backends() {
+        let file = Backend::EncryptedFile(EncryptedFileStore::temp_for_tests("first_healthy"));
+        let broken = Backend::EncryptedFile(EncryptedFileStore::new(
+            "/dev/null/not-a-directory/vault.json",
+        ));
+        let selected = Backend::first_healthy([broken, file.clone()]);
+        assert_eq!(selected, Some(file));
+    }
+
+    #[test]
+    fn test_first_healthy_returns_none_when_all_fail() {
+        use crate::store::SecretStore;
+
+        /// A store whose health check always fails, like a locked-out keyring.
+        struct Unreachable;
+
+        impl SecretStore for Unreachable {
+            fn get(&self, _: &str, _: &str) -> Result<String> {
+                Err(keyring::Error::NoEntry)
+            }
+
+            fn set(&self, _: &str, _: &str, _: &str) -> Result<()> {
+                Ok(())
+            }
+
+            fn delete(&self, _: &str, _: &str) -> Result<()> {
+                Ok(())
+            }
+
+            fn health_check(&self) -> Result<()> {
+                Err(keyring::Error::NoStorageAccess("unreachable".into()))
+            }
+        }
+
+        let memory = Backend::Memory(MemoryStore::new());
+        let selected = Backend::first_healthy([Backend::custom(Unreachable), memory.clone()]);
+        assert_eq!(selected, Some(memory));
+        assert_eq!(Backend::first_healthy([Backend::custom(Unreachable)]), None);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::error::{Error, Operation, Result};
+use crate::key_manager::KeyManager;
+use crate::prompt::{Prompter, SharedPrompter};
+use crate::struct_key_manager::StructKeyManager;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// Handle of a `read_or_request_key` running on a background thread, returned by
+/// `KeyManager::read_or_request_in_background`.
+///
+/// A GUI polls `try_result` from its event loop and calls `cancel` (or drops the handle)
+/// when the user closes the window. A terminal prompt already waiting for a line can't be
+/// interrupted: its thread stays blocked on stdin until the next line, which is then
+/// discarded. Nothing answered after the cancellation is stored.
+#[derive(Debug)]
+pub struct PromptHandle<T> {
+    cancelled: Arc<AtomicBool>,
+    result: Receiver<Result<T>>,
+    system_name: String,
+    key_name: String,
+    backend: &'static str,
+}
+
+impl<T> PromptHandle<T> {
+    /// Aborts the pending prompt: the result becomes an `ErrorKind::Io` error with
+    /// `io::ErrorKind::Interrupted`, unless the value was already read or stored.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// The result if the read or prompt has finished or was cancelled, without blocking.
+    pub fn try_result(&self) -> Option<Result<T>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) if self.is_cancelled() => Some(Err(self.cancelled_error())),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(
+                self.error(io::Error::other("the prompt thread panicked"))
+            )),
+        }
+    }
+
+    /// Blocks until the read or prompt finishes; returns at once if it was cancelled.
+    pub fn wait(self) -> Result<T> {
+        if self.is_cancelled() {
+            return self
+                .try_result()
+                .unwrap_or_else(|| Err(self.cancelled_error()));
+        }
+        self.result
+            .recv()
+            .unwrap_or_else(|_| Err(self.error(io::Error::other("the prompt thread panicked"))))
+    }
+
+    fn cancelled_error(&self) -> Error {
+        self.error(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "the prompt was cancelled",
+        ))
+    }
+
+    fn error(&self, cause: io::Error) -> Error {
+        Error::new(
+            &self.system_name,
+            &self.key_name,
+            Operation::Prompt,
+            self.backend,
+            cause,
+        )
+    }
+}
+
+impl<T> Drop for PromptHandle<T> {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Fails every prompt asked, or answered, after the cancellation.
+struct CancellablePrompter {
+    inner: SharedPrompter,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellablePrompter {
+    fn check(&self) -> io::Result<()> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "the prompt was cancelled",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Prompter for CancellablePrompter {
+    fn prompt(&self, message: &str) -> io::Result<String> {
+        self.prompt_with_history(message, &[])
+    }
+
+    fn prompt_with_history(&self, message: &str, history: &[String]) -> io::Result<String> {
+        self.check()?;
+        let answer = self.inner.0.prompt_with_history(message, history)?;
+        self.check()?;
+        Ok(answer)
+    }
+}
+
+/// A handle for `manager`, the prompter to run its read with, and where to send the
+/// result.
+fn start<T>(manager: &KeyManager) -> (PromptHandle<T>, CancellablePrompter, Sender<Result<T>>) {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let prompter = CancellablePrompter {
+        inner: manager.prompter().clone(),
+        cancelled: Arc::clone(&cancelled),
+    };
+    let (sender, result) = mpsc::channel();
+    let handle = PromptHandle {
+        cancelled,
+        result,
+        system_name: manager.system_name.clone(),
+        key_name: manager.key_name.clone(),
+        backend: manager.backend().name(),
+    };
+    (handle, prompter, sender)
+}
+
+impl KeyManager {
+    /// Runs `read_or_request_key` on a background thread, so a GUI thread isn't blocked
+    /// while the user is prompted and can abort the prompt with `PromptHandle::cancel`.
+    pub fn read_or_request_in_background(self) -> PromptHandle<String> {
+        let (handle, prompter, sender) = start(&self);
+        thread::spawn(move || {
+            let _ = sender.send(self.with_prompter(prompter).read_or_request_key());
+        });
+        handle
+    }
+}
+
+impl<T> StructKeyManager<T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Default + Send + 'static,
+{
+    /// Runs `read_or_request_key` on a background thread (see
+    /// `KeyManager::read_or_request_in_background`).
+    pub fn read_or_request_in_background(self, force: bool) -> PromptHandle<T> {
+        let (handle, prompter, sender) = start(self.inner());
+        thread::spawn(move || {
+            let _ = sender.send(self.with_prompter(prompter).read_or_request_key(force));
+        });
+        handle
+    }
+}
+
+#[cfg(all(test, feature = "interactive"))]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_utils::{assert_not_stored, assert_stored, ScriptedPrompter};
+    use crate::vault::Vault;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Answers once `release` receives, and records when the prompting thread is done.
+    struct GatedPrompter {
+        release: Mutex<Receiver<()>>,
+        done: Arc<AtomicBool>,
+    }
+
+    impl Prompter for GatedPrompter {
+        fn prompt(&self, _message: &str) -> io::Result<String> {
+            let _ = self.release.lock().unwrap().recv();
+            Ok("too late".to_string())
+        }
+    }
+
+    impl Drop for GatedPrompter {
+        fn drop(&mut self) {
+            self.done.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_background_prompt() {
+        let vault = Vault::ephemeral_for_tests().with_prompter(ScriptedPrompter::new(["s3cret"]));
+        let handle = vault.key("api_token").read_or_request_in_background();
+        assert_eq!(handle.wait().unwrap(), "s3cret");
+        assert_stored(&vault, "api_token", "s3cret");
+    }
+
+    #[test]
+    fn test_cancel_background_prompt() {
+        let (release, released) = mpsc::channel();
+        let done = Arc::new(AtomicBool::new(false));
+        let vault = Vault::ephemeral_for_tests();
+        let handle = vault
+            .clone()
+            .with_prompter(GatedPrompter {
+                release: Mutex::new(released),
+                done: Arc::clone(&done),
+            })
+            .key("api_token")
+            .read_or_request_in_background();
+        assert!(handle.try_result().is_none());
+
+        handle.cancel();
+        let error = handle.try_result().unwrap().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Io);
+        assert_eq!(error.operation(), Operation::Prompt);
+
+        release.send(()).unwrap();
+        for _ in 0..200 {
+            if done.load(Ordering::SeqCst) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(done.load(Ordering::SeqCst));
+        assert_not_stored(&vault, "api_token");
+    }
+}
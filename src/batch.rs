@@ -0,0 +1,150 @@
+use crate::error::Result;
+use crate::vault::Vault;
+use std::collections::BTreeMap;
+
+/// What a batch operation does after a key fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnError {
+    /// Skip the remaining keys.
+    #[default]
+    Stop,
+    /// Carry on with the remaining keys.
+    Continue,
+}
+
+/// Per-key results of `Vault::read_many` or `Vault::store_many`, in input order.
+#[derive(Debug)]
+pub struct BatchOutcome<T> {
+    results: Vec<(String, Result<T>)>,
+    skipped: Vec<String>,
+}
+
+impl<T> BatchOutcome<T> {
+    fn run<I, A, F>(items: I, on_error: OnError, mut operation: F) -> Self
+    where
+        I: IntoIterator<Item = (String, A)>,
+        F: FnMut(&str, A) -> Result<T>,
+    {
+        let mut outcome = BatchOutcome {
+            results: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let mut stopped = false;
+        for (key, argument) in items {
+            if stopped {
+                outcome.skipped.push(key);
+                continue;
+            }
+            let result = operation(&key, argument);
+            stopped = result.is_err() && on_error == OnError::Stop;
+            outcome.results.push((key, result));
+        }
+        outcome
+    }
+
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    pub(crate) fn from_results(results: Vec<(String, Result<T>)>) -> Self {
+        BatchOutcome {
+            results,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// The result of every key that was attempted.
+    pub fn results(&self) -> &[(String, Result<T>)] {
+        &self.results
+    }
+
+    /// The result for `key_name`, or `None` if it was skipped or not part of the batch.
+    pub fn get(&self, key_name: &str) -> Option<&Result<T>> {
+        self.results
+            .iter()
+            .find(|(key, _)| key == key_name)
+            .map(|(_, result)| result)
+    }
+
+    /// Keys not attempted because an earlier key failed with `OnError::Stop`.
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Returns true when every key was attempted and succeeded.
+    pub fn is_success(&self) -> bool {
+        self.skipped.is_empty() && self.results.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// The values by key, or the first error.
+    pub fn into_result(self) -> Result<BTreeMap<String, T>> {
+        self.results
+            .into_iter()
+            .map(|(key, result)| result.map(|value| (key, value)))
+            .collect()
+    }
+}
+
+impl Vault {
+    /// Reads several keys in one call, e.g. every credential a service needs at startup.
+    ///
+    /// ```no_run
+    /// use key_vaulter::batch::OnError;
+    /// use key_vaulter::Vault;
+    ///
+    /// let vault = Vault::new("my_service");
+    /// let credentials = vault
+    ///     .read_many(&["db_password", "api_token"], OnError::Stop)
+    ///     .into_result()?;
+    /// # Ok::<(), key_vaulter::Error>(())
+    /// ```
+    pub fn read_many(&self, keys: &[&str], on_error: OnError) -> BatchOutcome<String> {
+        let items = keys.iter().map(|key| (key.to_string(), ()));
+        BatchOutcome::run(items, on_error, |key, ()| self.key(key).read_key())
+    }
+
+    /// Stores several `(key, value)` pairs in one call, in iteration order. Keys already
+    /// written stay written when a later key fails.
+    pub fn store_many<I, K, V>(&self, entries: I, on_error: OnError) -> BatchOutcome<()>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let items = entries
+            .into_iter()
+            .map(|(key, value)| (key.as_ref().to_string(), value));
+        BatchOutcome::run(items, on_error, |key, value| {
+            self.key(key).store(value.as_ref()).map(drop)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_read_and_store_many() {
+        let vault = Vault::ephemeral_for_tests();
+        let entries = BTreeMap::from([("a", "1"), ("b", "2")]);
+        assert!(vault.store_many(&entries, OnError::Stop).is_success());
+
+        let outcome = vault.read_many(&["a", "missing", "b"], OnError::Continue);
+        assert_eq!(outcome.results().len(), 3);
+        assert_eq!(
+            outcome.get("missing").unwrap().as_ref().unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(outcome.get("b").unwrap().as_ref().unwrap(), "2");
+        assert!(!outcome.is_success());
+
+        let outcome = vault.read_many(&["a", "missing", "b"], OnError::Stop);
+        assert_eq!(outcome.skipped(), ["b"]);
+        assert!(outcome.into_result().is_err());
+
+        let values = vault
+            .read_many(&["a", "b"], OnError::Stop)
+            .into_result()
+            .unwrap();
+        assert_eq!(values["a"], "1");
+    }
+}
@@ -0,0 +1,77 @@
+use serde_json::Value;
+
+/// Serializes `value` as canonical JSON: no whitespace, object keys sorted by their UTF-8
+/// bytes and numbers in a fixed format (shortest round-trip form, `-0.0` written as `0.0`).
+///
+/// The same value always produces the same bytes, regardless of field declaration order or
+/// of whether serde_json's `preserve_order` feature is enabled elsewhere in the build.
+pub fn to_canonical_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if n.is_f64() && f == 0.0 => out.push_str("0.0"),
+            _ => out.push_str(&n.to_string()),
+        },
+        Value::String(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            let mut entries: Vec<(&String, &Value)> = fields.iter().collect();
+            entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(s: &str, out: &mut String) {
+    // Serializing a plain string cannot fail.
+    out.push_str(&serde_json::to_string(s).unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_keys_are_sorted_recursively() {
+        let value = json!({"b": 1, "a": {"z": true, "y": [2, "x"]}});
+        assert_eq!(
+            to_canonical_string(&value),
+            r#"{"a":{"y":[2,"x"],"z":true},"b":1}"#
+        );
+    }
+
+    #[test]
+    fn test_number_formatting() {
+        let value = json!({"neg_zero": -0.0, "float": 1.5, "whole": 2.0, "int": -3});
+        assert_eq!(
+            to_canonical_string(&value),
+            r#"{"float":1.5,"int":-3,"neg_zero":0.0,"whole":2.0}"#
+        );
+    }
+}
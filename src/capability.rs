@@ -0,0 +1,142 @@
+use crate::error::Result;
+use crate::key_manager::{KeyManager, StoreOutcome};
+use crate::struct_key_manager::StructKeyManager;
+use crate::vault::Vault;
+use crate::watch::{KeyChange, Watcher};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A handle that can read a key but never overwrite or delete it.
+///
+/// Hand it to the parts of an application that only consume a credential.
+#[derive(Debug)]
+pub struct ReadOnlyKeyManager {
+    inner: KeyManager,
+}
+
+impl ReadOnlyKeyManager {
+    pub fn system_name(&self) -> &str {
+        &self.inner.system_name
+    }
+
+    pub fn key_name(&self) -> &str {
+        &self.inner.key_name
+    }
+
+    /// See `KeyManager::read_key`.
+    pub fn read_key(&mut self) -> Result<String> {
+        self.inner.read_key()
+    }
+
+    /// See `KeyManager::watch`.
+    pub fn watch<F>(&self, interval: Duration, callback: F) -> Watcher
+    where
+        F: FnMut(KeyChange) + Send + 'static,
+    {
+        self.inner.watch(interval, callback)
+    }
+}
+
+/// A handle that can store a key but neither read nor delete it, e.g. for the component
+/// that receives rotated credentials.
+#[derive(Debug)]
+pub struct WriteOnlyKeyManager {
+    inner: KeyManager,
+}
+
+impl WriteOnlyKeyManager {
+    pub fn system_name(&self) -> &str {
+        &self.inner.system_name
+    }
+
+    pub fn key_name(&self) -> &str {
+        &self.inner.key_name
+    }
+
+    /// See `KeyManager::store`. The cached value is dropped right away.
+    pub fn store(&mut self, value: &str) -> Result<StoreOutcome> {
+        let result = self.inner.store(value);
+        self.inner.key_value = None;
+        result
+    }
+
+    /// See `KeyManager::store`. The cached value is dropped right away.
+    #[deprecated(since = "0.2.0", note = "use `store`, which returns a `StoreOutcome`")]
+    pub fn store_key(&mut self, value: &str) -> Result<()> {
+        self.store(value).map(drop)
+    }
+}
+
+/// A handle that can read a struct but never overwrite or delete it.
+#[derive(Debug)]
+pub struct ReadOnlyStructKeyManager<T> {
+    inner: StructKeyManager<T>,
+}
+
+impl<T> ReadOnlyStructKeyManager<T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Default,
+{
+    /// See `StructKeyManager::read_key`.
+    pub fn read_key(&mut self) -> Result<T> {
+        self.inner.read_key()
+    }
+
+    /// See `StructKeyManager::dump_redacted`.
+    pub fn dump_redacted(&mut self) -> Result<String> {
+        self.inner.dump_redacted()
+    }
+}
+
+impl KeyManager {
+    /// Gives up write access: the returned handle can only read.
+    pub fn into_read_only(self) -> ReadOnlyKeyManager {
+        ReadOnlyKeyManager { inner: self }
+    }
+
+    /// Gives up read access: the returned handle can only store.
+    pub fn into_write_only(mut self) -> WriteOnlyKeyManager {
+        self.key_value = None;
+        WriteOnlyKeyManager { inner: self }
+    }
+}
+
+impl<T> StructKeyManager<T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Default,
+{
+    /// Gives up write access: the returned handle can only read.
+    pub fn into_read_only(self) -> ReadOnlyStructKeyManager<T> {
+        ReadOnlyStructKeyManager { inner: self }
+    }
+}
+
+impl Vault {
+    /// Same as `key(key_name).into_read_only()`.
+    pub fn read_only_key(&self, key_name: &str) -> ReadOnlyKeyManager {
+        self.key(key_name).into_read_only()
+    }
+
+    /// Same as `key(key_name).into_write_only()`.
+    pub fn write_only_key(&self, key_name: &str) -> WriteOnlyKeyManager {
+        self.key(key_name).into_write_only()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_handles() {
+        let vault = Vault::ephemeral_for_tests();
+
+        let mut writer = vault.write_only_key("token");
+        writer.store("rotated").unwrap();
+        assert!(format!("{:?}", writer).contains("key_value: None"));
+
+        let mut reader = vault.read_only_key("token");
+        assert_eq!(reader.key_name(), "token");
+        assert_eq!(reader.read_key().unwrap(), "rotated");
+    }
+}
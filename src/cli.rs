@@ -0,0 +1,124 @@
+use crate::error::{Cause, ErrorKind, Operation, Result};
+use crate::hooks;
+use crate::vault::Vault;
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+
+/// Resolves the argument `id` of a parsed clap command, falling back to the vault:
+///
+/// 1. a value given on the command line or through the argument's `env` variable;
+/// 2. the value stored under `key_name` in `vault`;
+/// 3. the argument's `default_value`, if it has one;
+/// 4. otherwise the user is prompted and the answer is stored in the vault (without the
+///    `interactive` feature, the `ErrorKind::NotFound` error is returned instead).
+///
+/// ```no_run
+/// use clap::{Arg, Command};
+/// use key_vaulter::{cli, Vault};
+///
+/// let matches = Command::new("deploy")
+///     .arg(Arg::new("token").long("token").env("DEPLOY_TOKEN"))
+///     .get_matches();
+/// let token = cli::resolve_arg(&matches, "token", &Vault::new("deploy"), "api_token")?;
+/// # Ok::<(), key_vaulter::Error>(())
+/// ```
+///
+/// The argument must take a `String` value. Values given on the command line are never
+/// written to the vault.
+pub fn resolve_arg(
+    matches: &ArgMatches,
+    id: &str,
+    vault: &Vault,
+    key_name: &str,
+) -> Result<String> {
+    let mut manager = vault.key(key_name);
+    let value = matches.try_get_one::<String>(id).map_err(|e| {
+        manager.error(
+            Operation::Read,
+            Cause::InvalidInput(format!("argument '{}': {}", id, e)),
+        )
+    })?;
+    let default = match (value, matches.value_source(id)) {
+        (Some(value), Some(source @ (ValueSource::CommandLine | ValueSource::EnvVariable))) => {
+            let source = match source {
+                ValueSource::CommandLine => "the command line",
+                _ => "its environment variable",
+            };
+            hooks::debug(&format!("argument '{}' taken from {}", id, source));
+            return Ok(value.clone());
+        }
+        (value, _) => value,
+    };
+    match manager.read_key() {
+        Err(e) if e.kind() == ErrorKind::NotFound => match default {
+            Some(default) => {
+                hooks::debug(&format!(
+                    "argument '{}' not in the vault, using its default",
+                    id
+                ));
+                Ok(default.clone())
+            }
+            #[cfg(feature = "interactive")]
+            None => manager.request_key(),
+            #[cfg(not(feature = "interactive"))]
+            None => Err(e),
+        },
+        result => result,
+    }
+}
+
+/// The same fallback for values parsed with clap's derive API, e.g. an
+/// `#[arg(long, env = "DEPLOY_TOKEN")] token: Option<String>` field:
+/// `args.token.or_from_vault(&vault, "api_token")`.
+pub trait VaultFallback {
+    /// Returns the value if present, else the stored key, else prompts and stores it.
+    fn or_from_vault(self, vault: &Vault, key_name: &str) -> Result<String>;
+}
+
+impl VaultFallback for Option<String> {
+    fn or_from_vault(self, vault: &Vault, key_name: &str) -> Result<String> {
+        match self {
+            Some(value) => Ok(value),
+            None => vault.key(key_name).read_or_request_key(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, Command};
+
+    #[test]
+    fn test_resolve_arg_precedence() {
+        let vault = Vault::ephemeral_for_tests();
+        let command = Command::new("app")
+            .arg(Arg::new("token").long("token"))
+            .arg(Arg::new("region").long("region").default_value("eu"));
+        let parse = |args: &[&str]| command.clone().try_get_matches_from(args).unwrap();
+
+        let matches = parse(&["app", "--token", "from-cli"]);
+        assert_eq!(
+            resolve_arg(&matches, "token", &vault, "token").unwrap(),
+            "from-cli"
+        );
+        assert_eq!(
+            resolve_arg(&matches, "region", &vault, "region").unwrap(),
+            "eu"
+        );
+
+        vault.key("token").store("from-vault").unwrap();
+        vault.key("region").store("us").unwrap();
+        let matches = parse(&["app"]);
+        assert_eq!(
+            resolve_arg(&matches, "token", &vault, "token").unwrap(),
+            "from-vault"
+        );
+        assert_eq!(
+            resolve_arg(&matches, "region", &vault, "region").unwrap(),
+            "us"
+        );
+        assert_eq!(None.or_from_vault(&vault, "token").unwrap(), "from-vault");
+        assert!(resolve_arg(&matches, "unknown", &vault, "token").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use crate::error::{Cause, Operation, Result};
+use crate::key_manager::KeyManager;
+use arboard::Clipboard;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+impl KeyManager {
+    /// Reads the key, puts its value on the system clipboard and clears the clipboard again
+    /// after `timeout`, unless something else was copied in the meantime.
+    ///
+    /// Join the returned handle before the process exits: clearing happens on that thread,
+    /// and on X11/Wayland the clipboard content is served by the copying process.
+    pub fn copy_to_clipboard(&mut self, timeout: Duration) -> Result<JoinHandle<()>> {
+        let value = self.read_key()?;
+        let mut clipboard = Clipboard::new()
+            .map_err(|e| self.error(Operation::Export, Cause::Platform(Box::new(e))))?;
+        clipboard
+            .set_text(value.clone())
+            .map_err(|e| self.error(Operation::Export, Cause::Platform(Box::new(e))))?;
+        Ok(thread::spawn(move || {
+            thread::sleep(timeout);
+            let unchanged = clipboard
+                .get_text()
+                .map(|current| current == value)
+                .unwrap_or(false);
+            if unchanged {
+                let _ = clipboard.clear();
+            }
+        }))
+    }
+}
@@ -0,0 +1,282 @@
+use crate::backend::Backend;
+use crate::error::{ErrorKind, Result};
+use crate::prompt::Prompter;
+use crate::struct_key_manager::StructKeyManager;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A list of structs kept in a single entry, e.g. several saved server profiles.
+///
+/// The list is stored as a JSON array and read back on every call. A missing entry
+/// behaves like an empty list, and removing the last item deletes the entry.
+pub struct CollectionKeyManager<T> {
+    inner: StructKeyManager<Vec<T>>,
+}
+
+impl<T> fmt::Debug for CollectionKeyManager<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CollectionKeyManager")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<T> CollectionKeyManager<T>
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    /// Creates a manager for the list stored under `key_name`.
+    pub fn new(system_name: &str, key_name: &str) -> Self {
+        CollectionKeyManager {
+            inner: StructKeyManager::new(system_name, key_name),
+        }
+    }
+
+    /// Uses the given backend instead of the default OS keyring.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.inner = self.inner.with_backend(backend);
+        self
+    }
+
+    /// Asks for selections and new items through `prompter` instead of the terminal.
+    pub fn with_prompter<P>(mut self, prompter: P) -> Self
+    where
+        P: Prompter + 'static,
+    {
+        self.inner = self.inner.with_prompter(prompter);
+        self
+    }
+
+    fn load(&mut self) -> Result<Vec<T>> {
+        match self.inner.read_key() {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            result => result,
+        }
+    }
+
+    fn save(&mut self, items: &Vec<T>) -> Result<()> {
+        if items.is_empty() {
+            self.inner.delete().map(drop)
+        } else {
+            self.inner.store(items).map(drop)
+        }
+    }
+
+    /// Appends `item` to the list.
+    pub fn add(&mut self, item: T) -> Result<()> {
+        let _lock = self.inner.inner().lock();
+        let mut items = self.load()?;
+        items.push(item);
+        self.save(&items)
+    }
+
+    /// Removes every item matching `predicate` and returns them.
+    pub fn remove<P>(&mut self, mut predicate: P) -> Result<Vec<T>>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let _lock = self.inner.inner().lock();
+        let (removed, kept): (Vec<T>, Vec<T>) =
+            self.load()?.into_iter().partition(|item| predicate(item));
+        if !removed.is_empty() {
+            self.save(&kept)?;
+        }
+        Ok(removed)
+    }
+
+    /// Iterates over the stored items, in insertion order.
+    pub fn iter(&mut self) -> Result<std::vec::IntoIter<T>> {
+        Ok(self.load()?.into_iter())
+    }
+
+    /// Lists the items, each described by `label`, and asks for the number of one of them.
+    /// Returns `None` when the list is empty. Asks again on an invalid number.
+    pub fn pick<F>(&mut self, label: F) -> Result<Option<T>>
+    where
+        F: Fn(&T) -> String,
+    {
+        let mut items = self.load()?;
+        if items.is_empty() {
+            return Ok(None);
+        }
+        let message = format!(
+            "{}Select one [1-{}]: ",
+            listing(&items, &label),
+            items.len()
+        );
+        loop {
+            let input = self.inner.prompt(&message)?;
+            match parse_choice(&input, items.len()) {
+                Some(Choice::Pick(index)) => return Ok(Some(items.swap_remove(index))),
+                _ => self
+                    .inner
+                    .notify_user(&format!("Invalid selection '{}'.", input)),
+            }
+        }
+    }
+
+    /// A profile picker: lists the items, each described by `label`, and lets the user
+    /// pick one by number, add a new one (`a`, prompting for each field) or delete one
+    /// (`d <number>`). Returns the picked or added item, or `None` when the user cancels
+    /// with `q`.
+    pub fn select<F>(&mut self, label: F) -> Result<Option<T>>
+    where
+        T: Default,
+        F: Fn(&T) -> String,
+    {
+        loop {
+            let mut items = self.load()?;
+            let message = if items.is_empty() {
+                "No entries stored.\n'a' to add, 'q' to cancel: ".to_string()
+            } else {
+                format!(
+                    "{}Select [1-{}], 'a' to add, 'd <number>' to delete, 'q' to cancel: ",
+                    listing(&items, &label),
+                    items.len()
+                )
+            };
+            let input = self.inner.prompt(&message)?;
+            match parse_choice(&input, items.len()) {
+                Some(Choice::Pick(index)) => return Ok(Some(items.swap_remove(index))),
+                Some(Choice::Add) => {
+                    let item: T = self.inner.prompt_struct(None)?;
+                    items.push(item);
+                    self.save(&items)?;
+                    return Ok(items.pop());
+                }
+                Some(Choice::Delete(index)) => {
+                    items.remove(index);
+                    self.save(&items)?;
+                }
+                Some(Choice::Cancel) => return Ok(None),
+                None => self
+                    .inner
+                    .notify_user(&format!("Invalid selection '{}'.", input)),
+            }
+        }
+    }
+}
+
+/// One numbered line per item.
+fn listing<T, F>(items: &[T], label: &F) -> String
+where
+    F: Fn(&T) -> String,
+{
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| format!("{}) {}\n", index + 1, label(item)))
+        .collect()
+}
+
+enum Choice {
+    Pick(usize),
+    Add,
+    Delete(usize),
+    Cancel,
+}
+
+/// Parses an answer of the picker; item numbers start at 1.
+fn parse_choice(input: &str, len: usize) -> Option<Choice> {
+    let index = |number: &str| {
+        number
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=len).contains(number))
+            .map(|number| number - 1)
+    };
+    match input.to_lowercase().as_str() {
+        "a" | "add" => Some(Choice::Add),
+        "q" | "quit" => Some(Choice::Cancel),
+        answer => match answer.strip_prefix('d') {
+            Some(number) => index(number).map(Choice::Delete),
+            None => index(answer).map(Choice::Pick),
+        },
+    }
+}
+
+impl Vault {
+    /// Returns a manager for a list of structs stored under `key_name` in this vault.
+    pub fn collection<T>(&self, key_name: &str) -> CollectionKeyManager<T>
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        CollectionKeyManager {
+            inner: self.structured(key_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_not_stored, ScriptedPrompter};
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    struct Profile {
+        name: String,
+        host: String,
+    }
+
+    fn profile(name: &str) -> Profile {
+        Profile {
+            name: name.to_string(),
+            host: format!("{}.example.com", name),
+        }
+    }
+
+    #[test]
+    fn test_add_remove_and_iter() {
+        let vault = Vault::ephemeral_for_tests();
+        let mut profiles = vault.collection::<Profile>("profiles");
+        assert_eq!(profiles.iter().unwrap().count(), 0);
+        profiles.add(profile("staging")).unwrap();
+        profiles.add(profile("prod")).unwrap();
+        let names: Vec<_> = profiles.iter().unwrap().map(|p| p.name).collect();
+        assert_eq!(names, vec!["staging", "prod"]);
+
+        let removed = profiles.remove(|p| p.name == "staging").unwrap();
+        assert_eq!(removed, vec![profile("staging")]);
+        assert!(profiles.remove(|p| p.name == "staging").unwrap().is_empty());
+        profiles.remove(|_| true).unwrap();
+        assert_not_stored(&vault, "profiles");
+    }
+
+    #[test]
+    fn test_pick() {
+        let vault = Vault::ephemeral_for_tests();
+        let prompter = ScriptedPrompter::new(["7", "2"]);
+        let mut profiles = vault
+            .collection::<Profile>("profiles")
+            .with_prompter(prompter.clone());
+        assert_eq!(profiles.pick(|p| p.name.clone()).unwrap(), None);
+        profiles.add(profile("staging")).unwrap();
+        profiles.add(profile("prod")).unwrap();
+        assert_eq!(
+            profiles.pick(|p| p.name.clone()).unwrap(),
+            Some(profile("prod"))
+        );
+        assert!(prompter.prompts()[0].starts_with("1) staging\n2) prod\n"));
+        assert!(prompter.is_exhausted());
+    }
+
+    #[test]
+    fn test_select_adds_and_deletes() {
+        let vault = Vault::ephemeral_for_tests();
+        let prompter = ScriptedPrompter::new(["a", "dev.local", "dev", "x", "d 1", "q"]);
+        let mut profiles = vault
+            .collection::<Profile>("profiles")
+            .with_prompter(prompter.clone());
+        profiles.add(profile("prod")).unwrap();
+
+        let added = profiles.select(|p| p.name.clone()).unwrap().unwrap();
+        assert_eq!(added.host, "dev.local");
+        assert_eq!(profiles.select(|p| p.name.clone()).unwrap(), None);
+        let names: Vec<_> = profiles.iter().unwrap().map(|p| p.name).collect();
+        assert_eq!(names, vec!["dev"]);
+        assert!(prompter.is_exhausted());
+        assert_eq!(prompter.notices(), vec!["Invalid selection 'x'."]);
+    }
+}
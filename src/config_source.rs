@@ -0,0 +1,164 @@
+use crate::error::ErrorKind;
+use crate::vault::Vault;
+use config::{ConfigError, Map, Source, Value, ValueKind};
+
+/// A [`config`] source reading one key of a vault, so stored credentials can take part in
+/// layered configuration:
+///
+/// ```no_run
+/// use key_vaulter::config_source::KeyringSource;
+/// use key_vaulter::Vault;
+///
+/// let settings = config::Config::builder()
+///     .set_default("port", 8080)?
+///     .add_source(config::Environment::with_prefix("APP"))
+///     .add_source(KeyringSource::new(Vault::new("my_service"), "settings"))
+///     .build()?;
+/// # Ok::<(), config::ConfigError>(())
+/// ```
+///
+/// A struct stored as JSON contributes its fields at the top level; any other value is
+/// exposed under the key name. A missing key contributes nothing unless the source is
+/// required.
+#[derive(Debug, Clone)]
+pub struct KeyringSource {
+    vault: Vault,
+    key_name: String,
+    required: bool,
+}
+
+impl KeyringSource {
+    /// Reads `key_name` from `vault`.
+    pub fn new(vault: Vault, key_name: &str) -> Self {
+        KeyringSource {
+            vault,
+            key_name: key_name.to_string(),
+            required: false,
+        }
+    }
+
+    /// Fails the build of the configuration when the key is missing.
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    fn origin(&self) -> String {
+        format!(
+            "{}:{}/{}",
+            self.vault.backend().name(),
+            self.vault.system_name(),
+            self.key_name
+        )
+    }
+}
+
+impl Vault {
+    /// Same as `KeyringSource::new(self.clone(), key_name)`.
+    pub fn config_source(&self, key_name: &str) -> KeyringSource {
+        KeyringSource::new(self.clone(), key_name)
+    }
+}
+
+fn to_config_value(origin: &String, value: serde_json::Value) -> Value {
+    let kind = match value {
+        serde_json::Value::Null => ValueKind::Nil,
+        serde_json::Value::Bool(b) => ValueKind::Boolean(b),
+        serde_json::Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => ValueKind::I64(i),
+            (None, Some(u)) => ValueKind::U64(u),
+            _ => ValueKind::Float(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => ValueKind::String(s),
+        serde_json::Value::Array(items) => ValueKind::Array(
+            items
+                .into_iter()
+                .map(|item| to_config_value(origin, item))
+                .collect(),
+        ),
+        serde_json::Value::Object(fields) => ValueKind::Table(
+            fields
+                .into_iter()
+                .map(|(name, field)| (name, to_config_value(origin, field)))
+                .collect(),
+        ),
+    };
+    Value::new(Some(origin), kind)
+}
+
+impl Source for KeyringSource {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let raw = match self.vault.key(&self.key_name).read_key() {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == ErrorKind::NotFound && !self.required => return Ok(Map::new()),
+            Err(e) => return Err(ConfigError::Foreign(Box::new(e))),
+        };
+        let origin = self.origin();
+        match serde_json::from_str(&raw) {
+            Ok(serde_json::Value::Object(fields)) => Ok(fields
+                .into_iter()
+                .map(|(name, field)| (name, to_config_value(&origin, field)))
+                .collect()),
+            _ => {
+                let mut map = Map::new();
+                map.insert(
+                    self.key_name.clone(),
+                    Value::new(Some(&origin), ValueKind::String(raw)),
+                );
+                Ok(map)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Settings {
+        host: String,
+        port: u16,
+        password: String,
+    }
+
+    #[test]
+    fn test_keyring_layer_overrides_defaults() {
+        let vault = Vault::ephemeral_for_tests();
+        vault
+            .key("settings")
+            .store(r#"{"port": 5433, "password": "s3cret"}"#)
+            .unwrap();
+
+        let settings: Settings = config::Config::builder()
+            .set_default("host", "localhost")
+            .unwrap()
+            .set_default("port", 5432)
+            .unwrap()
+            .add_source(vault.config_source("settings"))
+            .add_source(vault.config_source("missing"))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                host: "localhost".to_string(),
+                port: 5433,
+                password: "s3cret".to_string(),
+            }
+        );
+
+        let required = vault.config_source("missing").with_required(true);
+        assert!(config::Config::builder()
+            .add_source(required)
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A limit on one field of a stored struct, set with `StructKeyManager::with_range` or
+/// `with_length`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Constraint {
+    /// Inclusive bounds of a numeric field.
+    Range { min: f64, max: f64 },
+    /// Inclusive bounds of the length of a string field, in characters.
+    Length { min: usize, max: usize },
+}
+
+impl Constraint {
+    /// Checks the value of `field`, returning a message like `port must be 1–65535`.
+    pub(crate) fn check(&self, field: &str, value: &Value) -> Result<(), String> {
+        match *self {
+            Constraint::Range { min, max } => match value.as_f64() {
+                Some(number) if (min..=max).contains(&number) => Ok(()),
+                _ => Err(format!("{} must be {}–{}", field, min, max)),
+            },
+            Constraint::Length { min, max } => match value.as_str() {
+                Some(text) if (min..=max).contains(&text.chars().count()) => Ok(()),
+                _ => Err(format!("{} must be {}–{} characters long", field, min, max)),
+            },
+        }
+    }
+}
+
+/// Checks every constrained field of `value`, addressed by dotted path. Missing and `null`
+/// fields are skipped.
+pub(crate) fn check_all(
+    constraints: &BTreeMap<String, Constraint>,
+    value: &Value,
+) -> Result<(), String> {
+    for (path, constraint) in constraints {
+        let field = path
+            .split('.')
+            .try_fold(value, |value, segment| value.get(segment));
+        match field {
+            None | Some(Value::Null) => {}
+            Some(field) => constraint.check(path, field)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_constraints() {
+        let constraints = BTreeMap::from([
+            (
+                "port".to_string(),
+                Constraint::Range {
+                    min: 1.0,
+                    max: 65535.0,
+                },
+            ),
+            ("db.user".to_string(), Constraint::Length { min: 3, max: 8 }),
+        ]);
+        assert!(check_all(&constraints, &json!({"port": 443, "db": {"user": "admin"}})).is_ok());
+        assert_eq!(
+            check_all(&constraints, &json!({"port": 0})).unwrap_err(),
+            "port must be 1–65535"
+        );
+        assert_eq!(
+            check_all(&constraints, &json!({"port": 80, "db": {"user": "al"}})).unwrap_err(),
+            "db.user must be 3–8 characters long"
+        );
+    }
+}
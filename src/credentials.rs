@@ -0,0 +1,151 @@
+#[cfg(feature = "interactive")]
+use crate::error::ErrorKind;
+use crate::error::{Cause, Operation, Result};
+use crate::key_manager::{DeleteOutcome, StoreOutcome};
+use crate::redact::Redacted;
+use crate::vault::Vault;
+
+/// A user name and its password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Redacted<String>,
+}
+
+/// Stores logins the way the platform keyring models them: the service is the system
+/// name, the account is the user name and the secret is the password alone, not a JSON
+/// document. Mail clients, OS password dialogs and other programs reading the same
+/// service see ordinary entries.
+///
+/// Finding the stored account without knowing the user name (`read`, `usernames`) needs a
+/// backend that supports listing, which the keyring does on the Secret Service only; on
+/// macOS and Windows, keep the user name in the app's configuration and use `password`.
+#[derive(Debug, Clone)]
+pub struct CredentialsManager {
+    vault: Vault,
+}
+
+impl CredentialsManager {
+    /// Creates a manager for the accounts of `system_name` in the OS keyring.
+    pub fn new(system_name: &str) -> Self {
+        Vault::new(system_name).credentials()
+    }
+
+    /// Stores `password` as the secret of the account `username`.
+    pub fn store(&self, username: &str, password: &str) -> Result<StoreOutcome> {
+        self.vault.key(username).store(password)
+    }
+
+    /// The password of the account `username`.
+    pub fn password(&self, username: &str) -> Result<String> {
+        self.vault.key(username).read_key()
+    }
+
+    /// The user names of the stored accounts.
+    pub fn usernames(&self) -> Result<Vec<String>> {
+        let mut usernames = self
+            .vault
+            .backend()
+            .list(self.vault.system_name())
+            .map_err(|e| self.vault.error(Operation::Read, e))?;
+        usernames.sort();
+        Ok(usernames)
+    }
+
+    /// The credentials of the only stored account. Fails with `ErrorKind::NotFound` when
+    /// there is none and `ErrorKind::InvalidInput` when there are several.
+    pub fn read(&self) -> Result<Credentials> {
+        let usernames = self.usernames()?;
+        let username = match usernames.as_slice() {
+            [] => return Err(self.vault.error(Operation::Read, keyring::Error::NoEntry)),
+            [username] => username,
+            _ => {
+                return Err(self.vault.error(
+                    Operation::Read,
+                    Cause::InvalidInput(format!(
+                        "several accounts are stored ({}); read one by user name",
+                        usernames.join(", ")
+                    )),
+                ))
+            }
+        };
+        Ok(Credentials {
+            username: username.clone(),
+            password: self.password(username)?.into(),
+        })
+    }
+
+    /// Asks for a user name and password through the prompter and stores them.
+    #[cfg(feature = "interactive")]
+    pub fn request(&self) -> Result<Credentials> {
+        let system_name = self.vault.system_name();
+        let manager = self.vault.key("");
+        let username = manager.prompt(&format!("User name for {}: ", system_name))?;
+        let password = manager.prompt(&format!("Password for {}@{}: ", username, system_name))?;
+        self.store(&username, &password)?;
+        Ok(Credentials {
+            username,
+            password: password.into(),
+        })
+    }
+
+    /// Returns the stored credentials, prompting for them if no account is stored.
+    #[cfg(feature = "interactive")]
+    pub fn read_or_request(&self) -> Result<Credentials> {
+        match self.read() {
+            Err(e) if e.kind() == ErrorKind::NotFound => self.request(),
+            result => result,
+        }
+    }
+
+    /// Same as `read`: without the `interactive` feature, nothing is prompted.
+    #[cfg(not(feature = "interactive"))]
+    pub fn read_or_request(&self) -> Result<Credentials> {
+        self.read()
+    }
+
+    /// Deletes the account `username`.
+    pub fn delete(&self, username: &str) -> Result<DeleteOutcome> {
+        self.vault.key(username).delete()
+    }
+}
+
+impl Vault {
+    /// Returns a manager for user name and password pairs stored as native keyring
+    /// accounts of this vault's system.
+    pub fn credentials(&self) -> CredentialsManager {
+        CredentialsManager {
+            vault: self.clone(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "interactive"))]
+mod tests {
+    use super::*;
+    use crate::test_utils::ScriptedPrompter;
+
+    #[test]
+    fn test_credentials() {
+        let vault = Vault::ephemeral_for_tests()
+            .with_prompter(ScriptedPrompter::new(["ana@example.com", "s3cret"]));
+        let credentials = vault.credentials();
+        let stored = credentials.read_or_request().unwrap();
+        assert_eq!(stored.username, "ana@example.com");
+        assert_eq!(vault.key("ana@example.com").read_key().unwrap(), "s3cret");
+        assert_eq!(credentials.read().unwrap(), stored);
+        assert_eq!(format!("{:?}", stored.password), "***");
+
+        credentials.store("bob", "hunter2").unwrap();
+        assert_eq!(
+            credentials.usernames().unwrap(),
+            vec!["ana@example.com", "bob"]
+        );
+        assert_eq!(
+            credentials.read().unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        credentials.delete("bob").unwrap();
+        assert_eq!(credentials.password("ana@example.com").unwrap(), "s3cret");
+    }
+}
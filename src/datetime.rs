@@ -0,0 +1,198 @@
+//! Parsing of date and date-time fields typed at the prompt (features `chrono` or `time`).
+//!
+//! Accepted dates: `2024-03-15`, `2024/03/15`, `15.03.2024`. Accepted date-times:
+//! RFC 3339 (`2024-03-15T10:30:00+01:00`), `2024-03-15 10:30[:00]` and
+//! `2024-03-15T10:30[:00]` in UTC, or a bare date at midnight UTC. Values are stored as
+//! `2024-03-15` and `2024-03-15T09:30:00Z`, which `chrono::NaiveDate`,
+//! `chrono::DateTime<Utc>`, `time::Date` and `time::OffsetDateTime` (with
+//! `time::serde::rfc3339`) all deserialize.
+
+/// The shape of a date field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateKind {
+    /// A calendar date, stored as `YYYY-MM-DD`.
+    Date,
+    /// A point in time, stored as RFC 3339 in UTC.
+    DateTime,
+}
+
+impl DateKind {
+    /// Recognizes the default value of a date field, e.g. `1970-01-01` for
+    /// `NaiveDate::default()`.
+    pub(crate) fn detect(value: &str) -> Option<DateKind> {
+        if parse_iso_date(value).is_some() {
+            Some(DateKind::Date)
+        } else if parse_rfc3339(value).is_some() {
+            Some(DateKind::DateTime)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            DateKind::Date => "a date like 2024-03-15",
+            DateKind::DateTime => "a date and time like 2024-03-15 10:30 or RFC 3339",
+        }
+    }
+
+    /// Parses `input` in any accepted format and returns the value to store.
+    pub(crate) fn parse(&self, input: &str) -> Option<String> {
+        let input = input.trim();
+        match self {
+            DateKind::Date => parse_date(input),
+            DateKind::DateTime => parse_rfc3339(input)
+                .or_else(|| parse_naive_datetime(input))
+                .or_else(|| parse_date(input).map(|date| format!("{}T00:00:00Z", date))),
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod imp {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+
+    const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%Y/%m/%d", "%d.%m.%Y"];
+    const DATETIME_FORMATS: [&str; 4] = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ];
+
+    pub(super) fn parse_iso_date(input: &str) -> Option<String> {
+        NaiveDate::parse_from_str(input, DATE_FORMATS[0])
+            .ok()
+            .map(|date| date.format(DATE_FORMATS[0]).to_string())
+    }
+
+    pub(super) fn parse_date(input: &str) -> Option<String> {
+        DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(input, format).ok())
+            .map(|date| date.format(DATE_FORMATS[0]).to_string())
+    }
+
+    pub(super) fn parse_rfc3339(input: &str) -> Option<String> {
+        DateTime::parse_from_rfc3339(input)
+            .ok()
+            .map(|value| format_utc(value.with_timezone(&Utc)))
+    }
+
+    pub(super) fn parse_naive_datetime(input: &str) -> Option<String> {
+        DATETIME_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+            .map(|value| format_utc(value.and_utc()))
+    }
+
+    fn format_utc(value: DateTime<Utc>) -> String {
+        value.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+}
+
+#[cfg(all(feature = "time", not(feature = "chrono")))]
+mod imp {
+    use time::format_description::well_known::Rfc3339;
+    use time::format_description::BorrowedFormatItem;
+    use time::macros::format_description;
+    use time::{Date, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+    const ISO_DATE: &[BorrowedFormatItem<'_>] = format_description!("[year]-[month]-[day]");
+    const DATE_FORMATS: [&[BorrowedFormatItem<'_>]; 3] = [
+        ISO_DATE,
+        format_description!("[year]/[month]/[day]"),
+        format_description!("[day].[month].[year]"),
+    ];
+    const DATETIME_FORMATS: [&[BorrowedFormatItem<'_>]; 4] = [
+        format_description!("[year]-[month]-[day] [hour]:[minute]:[second]"),
+        format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]"),
+        format_description!("[year]-[month]-[day] [hour]:[minute]"),
+        format_description!("[year]-[month]-[day]T[hour]:[minute]"),
+    ];
+
+    pub(super) fn parse_iso_date(input: &str) -> Option<String> {
+        Date::parse(input, ISO_DATE).ok()?.format(ISO_DATE).ok()
+    }
+
+    pub(super) fn parse_date(input: &str) -> Option<String> {
+        DATE_FORMATS
+            .iter()
+            .find_map(|format| Date::parse(input, format).ok())?
+            .format(ISO_DATE)
+            .ok()
+    }
+
+    pub(super) fn parse_rfc3339(input: &str) -> Option<String> {
+        format_utc(OffsetDateTime::parse(input, &Rfc3339).ok()?)
+    }
+
+    pub(super) fn parse_naive_datetime(input: &str) -> Option<String> {
+        let value = DATETIME_FORMATS
+            .iter()
+            .find_map(|format| PrimitiveDateTime::parse(input, format).ok())?;
+        format_utc(value.assume_utc())
+    }
+
+    fn format_utc(value: OffsetDateTime) -> Option<String> {
+        value.to_offset(UtcOffset::UTC).format(&Rfc3339).ok()
+    }
+}
+
+/// Without `chrono` or `time`, no field is treated as a date.
+#[cfg(not(any(feature = "chrono", feature = "time")))]
+mod imp {
+    pub(super) fn parse_iso_date(_input: &str) -> Option<String> {
+        None
+    }
+
+    pub(super) fn parse_date(_input: &str) -> Option<String> {
+        None
+    }
+
+    pub(super) fn parse_rfc3339(_input: &str) -> Option<String> {
+        None
+    }
+
+    pub(super) fn parse_naive_datetime(_input: &str) -> Option<String> {
+        None
+    }
+}
+
+use imp::{parse_date, parse_iso_date, parse_naive_datetime, parse_rfc3339};
+
+#[cfg(all(test, any(feature = "chrono", feature = "time")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_common_formats() {
+        assert_eq!(DateKind::detect("1970-01-01"), Some(DateKind::Date));
+        assert_eq!(
+            DateKind::detect("1970-01-01T00:00:00Z"),
+            Some(DateKind::DateTime)
+        );
+        assert_eq!(DateKind::detect("admin"), None);
+
+        for input in ["2024-03-15", "2024/03/15", "15.03.2024"] {
+            assert_eq!(DateKind::Date.parse(input).unwrap(), "2024-03-15");
+        }
+        assert_eq!(DateKind::Date.parse("2024-02-30"), None);
+
+        for input in [
+            "2024-03-15T10:30:00+01:00",
+            "2024-03-15 09:30",
+            "2024-03-15T09:30:00",
+        ] {
+            assert_eq!(
+                DateKind::DateTime.parse(input).unwrap(),
+                "2024-03-15T09:30:00Z"
+            );
+        }
+        assert_eq!(
+            DateKind::DateTime.parse("2024-03-15").unwrap(),
+            "2024-03-15T00:00:00Z"
+        );
+        assert_eq!(DateKind::DateTime.parse("tomorrow"), None);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::error::{Operation, Result};
+use crate::redact::REDACTED;
+use crate::template::write_private_file;
+use crate::vault::Vault;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Options for `Vault::export_dotenv`.
+#[derive(Debug, Clone)]
+pub struct DotenvOptions {
+    redact_secrets: bool,
+    secret_fields: BTreeSet<String>,
+    placeholder: String,
+}
+
+impl Default for DotenvOptions {
+    fn default() -> Self {
+        DotenvOptions {
+            redact_secrets: false,
+            secret_fields: BTreeSet::new(),
+            placeholder: REDACTED.to_string(),
+        }
+    }
+}
+
+impl DotenvOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the placeholder instead of the real value for secret fields.
+    pub fn redact_secrets(mut self, redact: bool) -> Self {
+        self.redact_secrets = redact;
+        self
+    }
+
+    /// Marks a whole key (`"key"`) or one of its fields (`"key:field"`, dotted for nested
+    /// fields) as secret.
+    pub fn secret(mut self, reference: &str) -> Self {
+        self.secret_fields.insert(reference.to_string());
+        self
+    }
+
+    /// Text written for redacted values (default `***`).
+    pub fn placeholder(mut self, placeholder: &str) -> Self {
+        self.placeholder = placeholder.to_string();
+        self
+    }
+
+    fn is_secret(&self, key_name: &str, field_path: &str) -> bool {
+        self.secret_fields.contains(key_name)
+            || (!field_path.is_empty()
+                && self
+                    .secret_fields
+                    .contains(&format!("{}:{}", key_name, field_path)))
+    }
+}
+
+/// Turns a key or field name into an environment variable name segment: uppercase ASCII
+/// letters, digits and `_`.
+pub(crate) fn env_segment(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Flattens a JSON value into `(variable, field path, value)` triples, joining nested
+/// object fields with `__`. Strings are written as-is, other scalars and arrays as JSON.
+pub(crate) fn flatten_env(
+    variable: &str,
+    path: &str,
+    value: &Value,
+    out: &mut Vec<(String, String, String)>,
+) {
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields {
+                let field_path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                let field_variable = format!("{}__{}", variable, env_segment(name));
+                flatten_env(&field_variable, &field_path, field, out);
+            }
+        }
+        Value::String(s) => out.push((variable.to_string(), path.to_string(), s.clone())),
+        other => out.push((variable.to_string(), path.to_string(), other.to_string())),
+    }
+}
+
+/// Quotes a value for a `.env` file when it contains anything beyond a plain word.
+fn quote_dotenv(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.:/@+,".contains(c));
+    if plain {
+        return value.to_string();
+    }
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('$', "\\$");
+    format!("\"{}\"", escaped)
+}
+
+impl Vault {
+    /// Renders the given keys as `.env` content.
+    ///
+    /// Plain values become `KEY=value`; values holding a JSON object (as stored by
+    /// `StructKeyManager`) are flattened to `KEY__FIELD=value`, with `__` between nesting
+    /// levels.
+    pub fn dotenv_string<I, K>(&self, keys: I, options: &DotenvOptions) -> Result<String>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let mut output = String::new();
+        for key_name in keys {
+            let key_name = key_name.as_ref();
+            let raw = self.key(key_name).read_key()?;
+            let mut entries = Vec::new();
+            match serde_json::from_str::<Value>(&raw) {
+                Ok(value @ Value::Object(_)) => {
+                    flatten_env(&env_segment(key_name), "", &value, &mut entries)
+                }
+                _ => entries.push((env_segment(key_name), String::new(), raw)),
+            }
+            for (variable, field_path, value) in entries {
+                let value = if options.redact_secrets && options.is_secret(key_name, &field_path) {
+                    options.placeholder.clone()
+                } else {
+                    value
+                };
+                output.push_str(&format!("{}={}\n", variable, quote_dotenv(&value)));
+            }
+        }
+        Ok(output)
+    }
+
+    /// Writes the given keys to a `.env` file at `path` (mode `0600` on Unix).
+    ///
+    /// See `dotenv_string` for the format. Useful for bootstrapping local development
+    /// environments; use `DotenvOptions::redact_secrets` to write placeholders instead of
+    /// real secrets.
+    pub fn export_dotenv<P, I, K>(&self, path: P, keys: I, options: &DotenvOptions) -> Result<()>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let content = self.dotenv_string(keys, options)?;
+        write_private_file(path.as_ref(), content.as_bytes())
+            .map_err(|e| self.error(Operation::Export, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dotenv_flattening_and_redaction() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("api-token").store("abc").unwrap();
+        vault
+            .key("db")
+            .store(r#"{"host":"localhost","login":{"user":"john","password":"p w"}}"#)
+            .unwrap();
+        let options = DotenvOptions::new()
+            .redact_secrets(true)
+            .secret("db:login.password")
+            .placeholder("CHANGE_ME");
+        let content = vault.dotenv_string(["api-token", "db"], &options).unwrap();
+        assert_eq!(
+            content,
+            "API_TOKEN=abc\nDB__HOST=localhost\nDB__LOGIN__PASSWORD=CHANGE_ME\nDB__LOGIN__USER=john\n"
+        );
+        let content = vault.dotenv_string(["db"], &DotenvOptions::new()).unwrap();
+        assert!(content.contains("DB__LOGIN__PASSWORD=\"p w\"\n"));
+    }
+}
@@ -0,0 +1,221 @@
+//! Drafts of prompted answers kept on disk, so a setup that crashed before storing them
+//! can resume on the next launch (see `KeyManager::with_draft_persistence`).
+
+use crate::file_store::EncryptedFileStore;
+use crate::hooks;
+use crate::timestamp::now_unix;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// One entry of the drafts file.
+#[derive(Serialize, Deserialize)]
+struct Draft {
+    value: String,
+    expires_at: u64,
+}
+
+/// Where drafts are persisted and for how long: an `EncryptedFileStore`, one entry per
+/// system and key, by default `drafts.json` in a directory private to the user (see
+/// [`default_path`]). Without a passphrase it is bound to the machine and the per-user
+/// secret of `machine::machine_key`, so other users can't decrypt the drafts.
+///
+/// The directory of the store is created with mode `0700`, and drafts are neither written
+/// nor read while it belongs to another user or others can access it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DraftStore {
+    store: EncryptedFileStore,
+    ttl: Duration,
+}
+
+/// `$XDG_RUNTIME_DIR/key_vaulter/drafts.json` when the variable is set, otherwise
+/// `drafts.json` next to [`EncryptedFileStore::default_path`].
+fn default_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("key_vaulter").join("drafts.json"),
+        None => EncryptedFileStore::default_path().with_file_name("drafts.json"),
+    }
+}
+
+/// Creates `dir` with mode `0700` if missing, and fails unless it belongs to the current
+/// user and nobody else can access it.
+#[cfg(unix)]
+fn ensure_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    let metadata = std::fs::metadata(dir)?;
+    // SAFETY: geteuid has no preconditions.
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} must belong to the current user and be private to them (mode 0700)",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn ensure_private_dir(dir: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dir)
+}
+
+impl DraftStore {
+    /// Drafts kept at the default path (see [`default_path`]).
+    pub(crate) fn new(ttl: Duration) -> Self {
+        DraftStore::in_store(EncryptedFileStore::new(default_path()), ttl)
+    }
+
+    /// Drafts kept in `store`.
+    pub(crate) fn in_store(store: EncryptedFileStore, ttl: Duration) -> Self {
+        DraftStore { store, ttl }
+    }
+
+    /// The store, once its directory is checked to be private.
+    fn checked_store(&self) -> io::Result<&EncryptedFileStore> {
+        if let Some(dir) = self.store.path().parent() {
+            ensure_private_dir(dir)?;
+        }
+        Ok(&self.store)
+    }
+
+    /// Persists `value`; failing to do so only warns, the draft is still kept in memory.
+    pub(crate) fn save(&self, system_name: &str, key_name: &str, value: &str) {
+        let draft = Draft {
+            value: value.to_string(),
+            expires_at: now_unix().saturating_add(self.ttl.as_secs()),
+        };
+        let json = Zeroizing::new(serde_json::to_string(&draft).unwrap_or_default());
+        let stored = self
+            .checked_store()
+            .map_err(|e| e.to_string())
+            .and_then(|store| {
+                store
+                    .set(system_name, key_name, &json)
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = stored {
+            hooks::warn(&format!("could not persist the draft: {}", e));
+        }
+    }
+
+    /// The persisted draft, unless missing, unreadable or expired (expired ones are removed).
+    pub(crate) fn load(&self, system_name: &str, key_name: &str) -> Option<Zeroizing<String>> {
+        let store = self.checked_store().ok()?;
+        let json = Zeroizing::new(store.get(system_name, key_name).ok()?);
+        match serde_json::from_str::<Draft>(&json) {
+            Ok(draft) if draft.expires_at > now_unix() => Some(Zeroizing::new(draft.value)),
+            Ok(_) => {
+                self.remove(system_name, key_name);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub(crate) fn remove(&self, system_name: &str, key_name: &str) {
+        let Ok(store) = self.checked_store() else {
+            return;
+        };
+        match store.delete(system_name, key_name) {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => hooks::warn(&format!("could not remove the draft: {}", e)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "interactive"))]
+mod tests {
+    use crate::backend::Backend;
+    use crate::file_store::EncryptedFileStore;
+    use crate::key_manager::StoreOutcome;
+    use crate::test_utils::ScriptedPrompter;
+    use crate::vault::Vault;
+    use std::time::Duration;
+
+    #[test]
+    fn test_persisted_draft_survives_a_restart() {
+        let secret = crate::machine::test_secret_path();
+        // A file where the store's directory should be makes every store fail.
+        let blocker = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("draft-{}", std::process::id()));
+        std::fs::create_dir_all(blocker.parent().unwrap()).unwrap();
+        std::fs::write(&blocker, "").unwrap();
+        let drafts = blocker.with_file_name(format!("drafts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&drafts);
+        let vault = Vault::ephemeral_for_tests()
+            .with_backend(Backend::EncryptedFile(
+                EncryptedFileStore::new(blocker.join("vault.json")).with_machine_secret(&secret),
+            ))
+            .with_draft_store(
+                EncryptedFileStore::new(drafts.join("drafts.json")).with_machine_secret(&secret),
+                Duration::from_secs(600),
+            );
+        let mut manager = vault
+            .key("token")
+            .with_prompter(ScriptedPrompter::new(["typed_value"]));
+        assert!(manager.request_key().is_err());
+        drop(manager);
+
+        let mut restarted = vault.key("token");
+        assert!(restarted.has_draft());
+        assert!(!vault.key("other").has_draft());
+        std::fs::remove_file(&blocker).unwrap();
+        assert_eq!(restarted.retry_store().unwrap(), StoreOutcome::Created);
+        assert!(!vault.key("token").has_draft());
+        assert_eq!(restarted.read_key().unwrap(), "typed_value");
+
+        let mut discarded = vault
+            .key("other")
+            .with_draft_persistence(Duration::from_secs(600));
+        discarded.keep_draft("abandoned");
+        discarded.discard_draft();
+        assert!(!vault.key("other").has_draft());
+        let mut expired = vault.key("other").with_draft_persistence(Duration::ZERO);
+        expired.keep_draft("stale");
+        assert!(!vault.key("other").has_draft());
+        let _ = std::fs::remove_dir_all(&blocker);
+        let _ = std::fs::remove_dir_all(&drafts);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_drafts_need_a_private_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let secret = crate::machine::test_secret_path();
+        let dir = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("drafts-shared-{}", std::process::id()));
+        let drafts = super::DraftStore::in_store(
+            EncryptedFileStore::new(dir.join("drafts.json")).with_machine_secret(&secret),
+            Duration::from_secs(600),
+        );
+        drafts.save("system", "token", "typed_value");
+        assert_eq!(
+            std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777,
+            0o700
+        );
+        assert_eq!(
+            drafts.load("system", "token").unwrap().as_str(),
+            "typed_value"
+        );
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(drafts.load("system", "token").is_none());
+        drafts.save("system", "other", "typed_value");
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(drafts.load("system", "other").is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,433 @@
+use crate::policy::PolicyViolation;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Result type returned by the managers in this crate.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The operation that was being performed when an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Read,
+    Store,
+    Delete,
+    Prompt,
+    Validate,
+    Export,
+    Import,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Read => "read",
+            Operation::Store => "store",
+            Operation::Delete => "delete",
+            Operation::Prompt => "prompt for",
+            Operation::Validate => "validate",
+            Operation::Export => "export",
+            Operation::Import => "import",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Broad category of an [`Error`], stable across releases so applications can branch on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// No value is stored for the key.
+    NotFound,
+    /// The platform refused access to the store.
+    AccessDenied,
+    /// The store is locked and has to be unlocked first.
+    Locked,
+    /// The stored value could not be decoded.
+    Corrupt,
+    /// The backend could not be reached.
+    Unavailable,
+    /// A value or attribute was rejected (too long, wrong type, ...).
+    InvalidInput,
+    /// Reading from or writing to the terminal or a file failed.
+    Io,
+    /// A system or key name was rejected.
+    InvalidName,
+    /// A value was rejected by a store policy.
+    PolicyViolation,
+    /// Too many reads of the key in a short time (see `rate_limit::RateLimiter`).
+    RateLimited,
+    /// Several platform credentials match the key (see `AmbiguityStrategy`).
+    Ambiguous,
+    /// The stored value doesn't match the pin set with `KeyManager::with_pin`.
+    UnexpectedChange,
+    /// A value past its expiry, like a JWT (see `jwt::JwtManager`).
+    Expired,
+    /// Any other failure.
+    Other,
+}
+
+impl ErrorKind {
+    /// Stable numeric code, suitable for process exit codes.
+    pub fn code(&self) -> u16 {
+        match self {
+            ErrorKind::NotFound => 1,
+            ErrorKind::AccessDenied => 2,
+            ErrorKind::Locked => 3,
+            ErrorKind::Corrupt => 4,
+            ErrorKind::Unavailable => 5,
+            ErrorKind::InvalidInput => 6,
+            ErrorKind::Io => 7,
+            ErrorKind::InvalidName => 8,
+            ErrorKind::PolicyViolation => 9,
+            ErrorKind::RateLimited => 10,
+            ErrorKind::Ambiguous => 11,
+            ErrorKind::UnexpectedChange => 12,
+            ErrorKind::Expired => 13,
+            ErrorKind::Other => 99,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorKind::NotFound => "not found",
+            ErrorKind::AccessDenied => "access denied",
+            ErrorKind::Locked => "locked",
+            ErrorKind::Corrupt => "corrupt data",
+            ErrorKind::Unavailable => "backend unavailable",
+            ErrorKind::InvalidInput => "invalid input",
+            ErrorKind::Io => "I/O error",
+            ErrorKind::InvalidName => "invalid name",
+            ErrorKind::PolicyViolation => "policy violation",
+            ErrorKind::RateLimited => "rate limited",
+            ErrorKind::Ambiguous => "ambiguous credential",
+            ErrorKind::UnexpectedChange => "unexpected change",
+            ErrorKind::Expired => "expired",
+            ErrorKind::Other => "other error",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Marks stored data that a backend found unreadable, so it is reported as
+/// [`ErrorKind::Corrupt`].
+#[derive(Debug)]
+pub(crate) struct CorruptData(pub(crate) String);
+
+impl fmt::Display for CorruptData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CorruptData {}
+
+/// Marks a backend that refuses access until it is unlocked, so it is reported as
+/// [`ErrorKind::Locked`] rather than [`ErrorKind::AccessDenied`].
+#[derive(Debug)]
+pub(crate) struct StoreLocked(pub(crate) String);
+
+impl fmt::Display for StoreLocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for StoreLocked {}
+
+/// Whether `error` is a `NoStorageAccess` because the backend is locked: a
+/// [`StoreLocked`], or a locked Secret Service collection or item.
+pub(crate) fn is_locked(error: &keyring::Error) -> bool {
+    let keyring::Error::NoStorageAccess(e) = error else {
+        return false;
+    };
+    #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+    if matches!(
+        e.downcast_ref::<dbus_secret_service::Error>(),
+        Some(dbus_secret_service::Error::Locked)
+    ) {
+        return true;
+    }
+    e.is::<StoreLocked>()
+}
+
+fn keyring_error_kind(error: &keyring::Error) -> ErrorKind {
+    match error {
+        keyring::Error::NoEntry => ErrorKind::NotFound,
+        keyring::Error::NoStorageAccess(_) if is_locked(error) => ErrorKind::Locked,
+        keyring::Error::NoStorageAccess(_) => ErrorKind::AccessDenied,
+        keyring::Error::BadEncoding(_) => ErrorKind::Corrupt,
+        keyring::Error::TooLong(_, _) | keyring::Error::Invalid(_, _) => ErrorKind::InvalidInput,
+        keyring::Error::PlatformFailure(e) => {
+            if e.is::<CorruptData>() || e.is::<serde_json::Error>() {
+                ErrorKind::Corrupt
+            } else if e.is::<std::io::Error>() {
+                ErrorKind::Io
+            } else {
+                ErrorKind::Unavailable
+            }
+        }
+        keyring::Error::Ambiguous(_) => ErrorKind::Ambiguous,
+        _ => ErrorKind::Other,
+    }
+}
+
+/// The underlying failure wrapped by an [`Error`].
+#[derive(Debug)]
+pub(crate) enum Cause {
+    Keyring(keyring::Error),
+    Serialization(serde_json::Error),
+    Io(std::io::Error),
+    InvalidName(String),
+    FieldNotFound(String),
+    InvalidInput(String),
+    PolicyViolation(PolicyViolation),
+    RateLimited(String),
+    Corrupt(String),
+    AccessDenied(String),
+    UnexpectedChange(String),
+    Expired(String),
+    Platform(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<keyring::Error> for Cause {
+    fn from(error: keyring::Error) -> Self {
+        Cause::Keyring(error)
+    }
+}
+
+impl From<serde_json::Error> for Cause {
+    fn from(error: serde_json::Error) -> Self {
+        Cause::Serialization(error)
+    }
+}
+
+impl From<PolicyViolation> for Cause {
+    fn from(violation: PolicyViolation) -> Self {
+        Cause::PolicyViolation(violation)
+    }
+}
+
+impl From<std::io::Error> for Cause {
+    fn from(error: std::io::Error) -> Self {
+        Cause::Io(error)
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::Keyring(e) => write!(f, "{}", e),
+            Cause::Serialization(e) => write!(f, "invalid stored data: {}", e),
+            Cause::Io(e) => write!(f, "I/O error: {}", e),
+            Cause::InvalidName(reason) => write!(f, "invalid name: {}", reason),
+            Cause::FieldNotFound(field) => write!(f, "field '{}' not found", field),
+            Cause::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+            Cause::PolicyViolation(violation) => write!(f, "{}", violation),
+            Cause::RateLimited(reason) => write!(f, "rate limited: {}", reason),
+            Cause::Corrupt(reason) => write!(f, "corrupt data: {}", reason),
+            Cause::AccessDenied(reason) => write!(f, "access denied: {}", reason),
+            Cause::UnexpectedChange(reason) => write!(f, "unexpected change: {}", reason),
+            Cause::Expired(reason) => write!(f, "expired: {}", reason),
+            Cause::Platform(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// An error carrying the system, key, operation and backend that failed.
+#[derive(Debug)]
+pub struct Error {
+    system: String,
+    key: String,
+    operation: Operation,
+    backend: &'static str,
+    cause: Cause,
+}
+
+impl Error {
+    pub(crate) fn new<C>(
+        system: &str,
+        key: &str,
+        operation: Operation,
+        backend: &'static str,
+        cause: C,
+    ) -> Self
+    where
+        C: Into<Cause>,
+    {
+        Error {
+            system: system.to_string(),
+            key: key.to_string(),
+            operation,
+            backend,
+            cause: cause.into(),
+        }
+    }
+
+    /// The system (service) name of the key involved.
+    pub fn system(&self) -> &str {
+        &self.system
+    }
+
+    /// The name of the key involved, or an empty string for operations spanning a whole
+    /// system.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The operation that failed.
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    /// The name of the backend the operation ran against (see `Backend::name`).
+    pub fn backend(&self) -> &'static str {
+        self.backend
+    }
+
+    /// The category of the failure.
+    pub fn kind(&self) -> ErrorKind {
+        match &self.cause {
+            Cause::Keyring(e) => keyring_error_kind(e),
+            Cause::Serialization(_) if self.operation == Operation::Read => ErrorKind::Corrupt,
+            Cause::Serialization(_) => ErrorKind::InvalidInput,
+            Cause::Io(_) => ErrorKind::Io,
+            Cause::InvalidName(_) => ErrorKind::InvalidName,
+            Cause::FieldNotFound(_) => ErrorKind::NotFound,
+            Cause::InvalidInput(_) => ErrorKind::InvalidInput,
+            Cause::PolicyViolation(_) => ErrorKind::PolicyViolation,
+            Cause::RateLimited(_) => ErrorKind::RateLimited,
+            Cause::Corrupt(_) => ErrorKind::Corrupt,
+            Cause::AccessDenied(_) => ErrorKind::AccessDenied,
+            Cause::UnexpectedChange(_) => ErrorKind::UnexpectedChange,
+            Cause::Expired(_) => ErrorKind::Expired,
+            Cause::Platform(_) => ErrorKind::Unavailable,
+        }
+    }
+
+    /// Shortcut for `self.kind().code()`.
+    pub fn code(&self) -> u16 {
+        self.kind().code()
+    }
+
+    /// The keyring error behind this failure, if it came from a backend.
+    pub fn keyring_error(&self) -> Option<&keyring::Error> {
+        match &self.cause {
+            Cause::Keyring(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(
+                f,
+                "failed to {} keys of system '{}' using backend '{}': {}",
+                self.operation, self.system, self.backend, self.cause
+            )
+        } else {
+            write!(
+                f,
+                "failed to {} key '{}' of system '{}' using backend '{}': {}",
+                self.operation, self.key, self.system, self.backend, self.cause
+            )
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.cause {
+            Cause::Keyring(e) => Some(e),
+            Cause::Serialization(e) => Some(e),
+            Cause::Io(e) => Some(e),
+            Cause::PolicyViolation(e) => Some(e),
+            Cause::InvalidName(_)
+            | Cause::InvalidInput(_)
+            | Cause::FieldNotFound(_)
+            | Cause::RateLimited(_)
+            | Cause::Corrupt(_)
+            | Cause::AccessDenied(_)
+            | Cause::UnexpectedChange(_)
+            | Cause::Expired(_) => None,
+            Cause::Platform(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_context() {
+        let error = Error::new(
+            "my_system",
+            "my_key",
+            Operation::Read,
+            "keyring",
+            keyring::Error::NoEntry,
+        );
+        assert_eq!(error.system(), "my_system");
+        assert_eq!(error.key(), "my_key");
+        assert_eq!(error.operation(), Operation::Read);
+        assert_eq!(error.backend(), "keyring");
+        assert!(matches!(
+            error.keyring_error(),
+            Some(keyring::Error::NoEntry)
+        ));
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(error.code(), 1);
+        assert!(error.to_string().starts_with(
+            "failed to read key 'my_key' of system 'my_system' using backend 'keyring'"
+        ));
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let kind_of =
+            |cause: keyring::Error| Error::new("s", "k", Operation::Read, "keyring", cause).kind();
+        assert_eq!(
+            kind_of(keyring::Error::NoStorageAccess(Box::new(StoreLocked(
+                "denied".to_string()
+            )))),
+            ErrorKind::Locked
+        );
+        #[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+        assert_eq!(
+            kind_of(keyring::Error::NoStorageAccess(Box::new(
+                dbus_secret_service::Error::Locked
+            ))),
+            ErrorKind::Locked
+        );
+        assert_eq!(
+            kind_of(keyring::Error::NoStorageAccess("denied".into())),
+            ErrorKind::AccessDenied
+        );
+        // Only the type of the inner error counts, not its message.
+        assert_eq!(
+            kind_of(keyring::Error::NoStorageAccess(
+                "/usr/bin/clockd may not use 'system/key'".into()
+            )),
+            ErrorKind::AccessDenied
+        );
+        assert_eq!(
+            kind_of(keyring::Error::PlatformFailure(Box::new(CorruptData(
+                "bad".to_string()
+            )))),
+            ErrorKind::Corrupt
+        );
+        assert_eq!(
+            kind_of(keyring::Error::PlatformFailure("no dbus".into())),
+            ErrorKind::Unavailable
+        );
+        let json_error = serde_json::from_str::<u32>("x").unwrap_err();
+        let error = Error::new("s", "k", Operation::Read, "keyring", json_error);
+        assert_eq!(error.kind(), ErrorKind::Corrupt);
+    }
+}
@@ -0,0 +1,258 @@
+//! Key escrow for organization recovery (feature `age`): with `with_escrow`, every value
+//! stored is also encrypted to the organization's recovery key and appended to an escrow
+//! file, so IT can recover credentials when an employee leaves.
+
+use crate::age_file::{decrypt, encrypt_armored, parse_identities};
+use crate::error::{Cause, ErrorKind, Operation, Result};
+use crate::file_store::platform_error;
+use crate::key_manager::KeyManager;
+use crate::naming::internal_key;
+use crate::timestamp::now_unix;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// Serializes appends from the threads of this process, so lines never interleave.
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// Where stored values are escrowed: an age recipient (`age1...`) held by the
+/// organization and the file the encrypted copies are appended to.
+///
+/// Escrow is opt-in per vault or manager, and visible: each escrowed key gets a
+/// `<key_name>#escrow` entry naming the recipient and the file (see
+/// `KeyManager::escrow_status`). Only the holder of the recovery identity can decrypt
+/// the file, with `Escrow::recover`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escrow {
+    recipient: String,
+    path: PathBuf,
+}
+
+/// One line of the escrow file.
+#[derive(Serialize, Deserialize)]
+struct Deposit {
+    system: String,
+    key: String,
+    at: u64,
+    /// The value, ASCII-armored age encrypted to the recipient.
+    value: String,
+}
+
+/// What the `<key_name>#escrow` entry of an escrowed key says.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EscrowStatus {
+    pub recipient: String,
+    pub path: PathBuf,
+    /// Last deposit, in seconds since the Unix epoch.
+    pub at: u64,
+}
+
+/// A value recovered from an escrow file by `Escrow::recover`.
+#[derive(Clone)]
+pub struct RecoveredValue {
+    pub system: String,
+    pub key: String,
+    /// When the value was stored, in seconds since the Unix epoch.
+    pub at: u64,
+    pub value: Zeroizing<String>,
+}
+
+impl fmt::Debug for RecoveredValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecoveredValue")
+            .field("system", &self.system)
+            .field("key", &self.key)
+            .field("at", &self.at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Escrow {
+    pub fn new(recipient: &str, path: impl Into<PathBuf>) -> Self {
+        Escrow {
+            recipient: recipient.to_string(),
+            path: path.into(),
+        }
+    }
+
+    pub fn recipient(&self) -> &str {
+        &self.recipient
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decrypts every deposit of the escrow file at `path` with the organization's
+    /// `identity` (identity file content, `AGE-SECRET-KEY-...` lines), oldest first. The
+    /// latest deposit of a key is its last stored value.
+    pub fn recover<P>(path: P, identity: &str) -> io::Result<Vec<RecoveredValue>>
+    where
+        P: AsRef<Path>,
+    {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let identities = parse_identities(identity).map_err(invalid)?;
+        let content = fs::read_to_string(path)?;
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let deposit: Deposit =
+                    serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+                let plaintext = decrypt(&identities, deposit.value.as_bytes()).map_err(invalid)?;
+                let value = String::from_utf8(plaintext)
+                    .map_err(|_| invalid("escrowed value is not UTF-8".to_string()))?;
+                Ok(RecoveredValue {
+                    system: deposit.system,
+                    key: deposit.key,
+                    at: deposit.at,
+                    value: Zeroizing::new(value),
+                })
+            })
+            .collect()
+    }
+
+    /// Appends `value`, encrypted to the recipient, to the escrow file and records the
+    /// deposit in the `#escrow` entry of `manager`'s key.
+    pub(crate) fn deposit(&self, manager: &KeyManager, value: &str) -> Result<()> {
+        let armored = encrypt_armored(&[&self.recipient], value.as_bytes())
+            .map_err(|e| manager.error(Operation::Store, Cause::InvalidInput(e)))?;
+        let at = now_unix();
+        let deposit = Deposit {
+            system: manager.system_name.clone(),
+            key: manager.key_name.clone(),
+            at,
+            value: armored,
+        };
+        let line =
+            serde_json::to_string(&deposit).map_err(|e| manager.error(Operation::Store, e))?;
+        self.append(&line)
+            .map_err(|e| manager.error(Operation::Store, e))?;
+        let status = EscrowStatus {
+            recipient: self.recipient.clone(),
+            path: self.path.clone(),
+            at,
+        };
+        let json =
+            serde_json::to_string(&status).map_err(|e| manager.error(Operation::Store, e))?;
+        status_manager(manager).store(&json).map(drop)
+    }
+
+    fn append(&self, line: &str) -> keyring::Result<()> {
+        let _guard = APPEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(platform_error)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path).map_err(platform_error)?;
+        file.write_all(format!("{}\n", line).as_bytes())
+            .map_err(platform_error)
+    }
+}
+
+fn status_manager(manager: &KeyManager) -> KeyManager {
+    let key_name = internal_key(&manager.key_name, "escrow");
+    manager.sibling(&key_name, manager.backend().clone())
+}
+
+impl KeyManager {
+    /// Where the value of this key was last escrowed, `None` if it never was.
+    pub fn escrow_status(&self) -> Result<Option<EscrowStatus>> {
+        let mut manager = status_manager(self);
+        match manager.read_key() {
+            Ok(json) => serde_json::from_str(&json)
+                .map(Some)
+                .map_err(|e| manager.error(Operation::Read, Cause::Corrupt(e.to_string()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Backend;
+    use crate::file_store::EncryptedFileStore;
+    use crate::test_utils::{assert_not_stored, assert_stored};
+    use crate::vault::Vault;
+    use age::secrecy::ExposeSecret;
+
+    #[test]
+    fn test_escrowed_values_are_recoverable() {
+        let identity = age::x25519::Identity::generate();
+        let path = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("escrow-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let escrow = Escrow::new(&identity.to_public().to_string(), &path);
+        let vault = Vault::ephemeral_for_tests();
+        let escrowed = vault.clone().with_escrow(escrow.clone());
+        escrowed.key("db_password").store("first").unwrap();
+        escrowed.key("db_password").store("second").unwrap();
+        vault.key("not_escrowed").store("private").unwrap();
+
+        let status = vault.key("db_password").escrow_status().unwrap().unwrap();
+        assert_eq!(status.recipient, escrow.recipient());
+        assert_eq!(status.path, path);
+        assert_eq!(vault.key("not_escrowed").escrow_status().unwrap(), None);
+
+        let recovered = Escrow::recover(&path, identity.to_string().expose_secret()).unwrap();
+        let values: Vec<(&str, &str)> = recovered
+            .iter()
+            .map(|deposit| (deposit.key.as_str(), deposit.value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            [("db_password", "first"), ("db_password", "second")]
+        );
+        let other = age::x25519::Identity::generate();
+        assert!(Escrow::recover(&path, other.to_string().expose_secret()).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_failed_deposit_keeps_the_previous_value() {
+        let path = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("escrow-failed-{}.jsonl", std::process::id()));
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("db_password").store("first").unwrap();
+        let escrowed = vault
+            .clone()
+            .with_escrow(Escrow::new("age1notarecipient", &path));
+        assert!(escrowed.key("db_password").store("second").is_err());
+        assert!(escrowed.key("api_token").store("secret").is_err());
+        assert_stored(&vault, "db_password", "first");
+        assert_not_stored(&vault, "api_token");
+    }
+
+    #[test]
+    fn test_failed_store_is_not_escrowed() {
+        let identity = age::x25519::Identity::generate();
+        let dir = std::env::temp_dir().join("key_vaulter_tests");
+        let path = dir.join(format!("escrow-unstored-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        // A file where the store's directory should be makes every store fail.
+        let blocker = dir.join(format!("escrow-blocker-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&blocker, "").unwrap();
+        let vault = Vault::ephemeral_for_tests()
+            .with_backend(Backend::EncryptedFile(EncryptedFileStore::new(
+                blocker.join("vault.json"),
+            )))
+            .with_escrow(Escrow::new(&identity.to_public().to_string(), &path));
+        assert!(vault.key("db_password").store("secret").is_err());
+        assert!(!path.exists());
+    }
+}
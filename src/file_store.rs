@@ -0,0 +1,693 @@
+use crate::error::CorruptData;
+use crate::lock_events;
+use crate::machine::{machine_id, machine_key_with_secret, user_secret_path};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use keyring::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use zeroize::Zeroizing;
+
+const KDF_INFO: &[u8] = b"key_vaulter encrypted file store v1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Parameters of the scrypt derivation that stretches a passphrase, recorded next to the
+/// salt so they can be raised for new files without breaking existing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct KdfParams {
+    log_n: u8,
+    r: u32,
+    p: u32,
+}
+
+impl KdfParams {
+    /// What new files are written with: 32 MiB of memory per derivation.
+    pub(crate) const DEFAULT: KdfParams = KdfParams {
+        log_n: 15,
+        r: 8,
+        p: 1,
+    };
+
+    /// Parses the `scrypt:<log_n>:<r>:<p>` form written by `Display`.
+    pub(crate) fn parse(text: &str) -> Option<KdfParams> {
+        let mut parts = text.strip_prefix("scrypt:")?.split(':');
+        let params = KdfParams {
+            log_n: parts.next()?.parse().ok()?,
+            r: parts.next()?.parse().ok()?,
+            p: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some(params)
+    }
+
+    /// The passphrase stretched with scrypt. Derivations are cached, as every read and
+    /// write of a store derives its key again, until `forget_derived_keys`.
+    fn stretch(&self, passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+        let id = self.cache_id(passphrase, salt);
+        let mut cache = derived_keys();
+        if let Some(key) = cache.get(&id) {
+            return Ok(key.clone());
+        }
+        let key = self.derive(passphrase, salt)?;
+        cache.insert(id, key.clone());
+        Ok(key)
+    }
+
+    /// The passphrase stretched with scrypt, bypassing the cache.
+    pub(crate) fn derive(&self, passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, 32)
+            .map_err(|_| corrupt("invalid key derivation parameters"))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+            .map_err(|_| corrupt("key derivation failed"))?;
+        Ok(key)
+    }
+
+    /// The entry of a derivation in the cache, so the passphrase itself is not kept.
+    fn cache_id(&self, passphrase: &str, salt: &[u8]) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(self.to_string())
+            .chain_update((salt.len() as u64).to_be_bytes())
+            .chain_update(salt)
+            .chain_update(passphrase.as_bytes())
+            .finalize()
+            .into()
+    }
+}
+
+type DerivedKeys = HashMap<[u8; 32], Zeroizing<[u8; 32]>>;
+
+/// The keys stretched from passphrases, registered with `lock_events::clear_caches`.
+fn derived_keys() -> MutexGuard<'static, DerivedKeys> {
+    static CACHE: OnceLock<Mutex<DerivedKeys>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| {
+            lock_events::register_cache(|| {
+                forget_derived_keys();
+                true
+            });
+            Mutex::default()
+        })
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Zeroizes the keys stretched from passphrases, so the next access derives them again.
+pub(crate) fn forget_derived_keys() {
+    derived_keys().clear();
+}
+
+impl fmt::Display for KdfParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "scrypt:{}:{}:{}", self.log_n, self.r, self.p)
+    }
+}
+
+/// What the key of a store is derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Protection {
+    /// The passphrase set with `with_passphrase`.
+    Passphrase,
+    /// The machine and the user (see `machine::machine_key`).
+    Machine,
+    /// The user name alone, on platforms without a machine identifier.
+    User,
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Protection::Passphrase => "a passphrase",
+            Protection::Machine => "the machine key",
+            Protection::User => "the user name",
+        })
+    }
+}
+
+/// On-disk layout: a random salt plus encrypted values grouped by system name.
+#[derive(Serialize, Deserialize)]
+struct StoreFile {
+    salt: String,
+    /// How a passphrase is stretched.
+    kdf: KdfParams,
+    protection: Protection,
+    entries: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// A single JSON file holding values encrypted with XChaCha20-Poly1305.
+///
+/// The encryption key is derived from the passphrase and a random salt stored in the file
+/// with scrypt, whose parameters are stored too, then HKDF-SHA256. When no passphrase is
+/// set, stores are bound to the machine (see [`machine_key`](crate::machine::machine_key)),
+/// so a copied file can't be opened on another host; on platforms without a machine
+/// identifier they use the current user name, which only protects against casual
+/// inspection. Without a passphrase, anything running as the same user can open the store.
+///
+/// The protection is recorded in the file, and a file whose protection differs from what
+/// this store would create (e.g. one that needs a passphrase when none is set) is refused
+/// rather than written with a weaker key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    passphrase: Option<Zeroizing<String>>,
+    /// Where the per-user secret of machine-bound files is kept, if not at
+    /// `machine::user_secret_path()`.
+    machine_secret: Option<PathBuf>,
+}
+
+impl fmt::Debug for EncryptedFileStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileStore")
+            .field("path", &self.path)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "***"))
+            .field("machine_secret", &self.machine_secret)
+            .finish()
+    }
+}
+
+pub(crate) fn platform_error<E>(error: E) -> keyring::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    keyring::Error::PlatformFailure(Box::new(error))
+}
+
+pub(crate) fn corrupt(message: &str) -> keyring::Error {
+    keyring::Error::PlatformFailure(Box::new(CorruptData(message.to_string())))
+}
+
+/// Derives an XChaCha20-Poly1305 key from the passphrase (or, when none is set, from the
+/// current user name), the salt and a context string. The passphrase is first stretched
+/// with scrypt using `kdf`; HKDF-SHA256 then derives the key for `info`.
+pub(crate) fn derive_cipher(
+    passphrase: Option<&str>,
+    salt: &[u8],
+    info: &[u8],
+    kdf: KdfParams,
+) -> Result<XChaCha20Poly1305> {
+    let secret: Zeroizing<Vec<u8>> = match passphrase {
+        Some(passphrase) => Zeroizing::new(kdf.stretch(passphrase, salt)?.to_vec()),
+        None => Zeroizing::new(format!("key_vaulter:{}", current_user()).into_bytes()),
+    };
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(salt), &secret)
+        .expand(info, key.as_mut())
+        .map_err(|_| corrupt("key derivation failed"))?;
+    Ok(XChaCha20Poly1305::new(key.as_ref().into()))
+}
+
+/// Binds a ciphertext to its system and key so entries can't be swapped around.
+fn associated_data(system_name: &str, key_name: &str) -> Vec<u8> {
+    format!("{}\0{}", system_name, key_name).into_bytes()
+}
+
+/// Encrypts the value of an entry into a random nonce followed by the ciphertext.
+pub(crate) fn seal_entry(
+    cipher: &XChaCha20Poly1305,
+    system_name: &str,
+    key_name: &str,
+    value: &str,
+) -> Result<Vec<u8>> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let aad = associated_data(system_name, key_name);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: value.as_bytes(),
+                aad: &aad,
+            },
+        )
+        .map_err(|_| corrupt("failed to encrypt entry"))?;
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+/// Decrypts what `seal_entry` produced; `store` names the store in error messages.
+pub(crate) fn open_entry(
+    cipher: &XChaCha20Poly1305,
+    system_name: &str,
+    key_name: &str,
+    data: &[u8],
+    store: &str,
+) -> Result<String> {
+    if data.len() < NONCE_LEN {
+        return Err(corrupt(&format!("truncated entry in {}", store)));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let aad = associated_data(system_name, key_name);
+    let plaintext = cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| {
+            keyring::Error::NoStorageAccess("failed to decrypt entry (wrong passphrase?)".into())
+        })?;
+    String::from_utf8(plaintext).map_err(|e| keyring::Error::BadEncoding(e.into_bytes()))
+}
+
+/// A file next to `path`, named after it with `suffix` appended.
+pub(crate) fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn open_private(path: &Path, options: &mut fs::OpenOptions) -> Result<fs::File> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path).map_err(platform_error)
+}
+
+/// Takes an exclusive lock on the file at `path`, created (with its directory) if needed,
+/// and held by threads and processes alike until the returned file is dropped.
+pub(crate) fn lock_file(path: &Path) -> Result<fs::File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(platform_error)?;
+    }
+    let file = open_private(
+        path,
+        fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true),
+    )?;
+    file.lock().map_err(platform_error)?;
+    Ok(file)
+}
+
+/// Name of the current OS user, or an empty string when unknown.
+pub(crate) fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+impl EncryptedFileStore {
+    /// Creates a store backed by the file at `path`. The file is created on first write.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        EncryptedFileStore {
+            path: path.into(),
+            passphrase: None,
+            machine_secret: None,
+        }
+    }
+
+    /// Derives the encryption key from the given passphrase.
+    pub fn with_passphrase(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(Zeroizing::new(passphrase.to_string()));
+        self
+    }
+
+    /// Keeps the per-user secret that binds the store to the machine (see
+    /// [`machine_key`](crate::machine::machine_key)) at `path` rather than at
+    /// `machine::user_secret_path()`. Irrelevant with a passphrase; a file written with
+    /// one secret can't be opened with another.
+    pub fn with_machine_secret(mut self, path: impl Into<PathBuf>) -> Self {
+        self.machine_secret = Some(path.into());
+        self
+    }
+
+    /// A machine-bound store in the temporary directory, named after `name` and the test
+    /// process, whose per-user secret is kept there too rather than in the user's data
+    /// directory.
+    #[cfg(test)]
+    pub(crate) fn temp_for_tests(name: &str) -> Self {
+        let path = std::env::temp_dir().join("key_vaulter_tests").join(format!(
+            "{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        EncryptedFileStore::new(path).with_machine_secret(crate::machine::test_secret_path())
+    }
+
+    /// Default location: `key_vaulter/vault.json` inside the platform's per-user data directory.
+    pub fn default_path() -> PathBuf {
+        let base = if cfg!(target_os = "windows") {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join("Library/Application Support"))
+        } else {
+            std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+                })
+        };
+        base.unwrap_or_else(std::env::temp_dir)
+            .join("key_vaulter")
+            .join("vault.json")
+    }
+
+    /// Path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What new files are protected with, and what existing ones must be.
+    fn protection(&self) -> Protection {
+        if self.passphrase.is_some() {
+            Protection::Passphrase
+        } else if machine_id().is_some() {
+            Protection::Machine
+        } else {
+            Protection::User
+        }
+    }
+
+    fn load(&self) -> Result<StoreFile> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => {
+                let file: StoreFile = serde_json::from_str(&content).map_err(platform_error)?;
+                if file.protection != self.protection() {
+                    return Err(keyring::Error::NoStorageAccess(
+                        format!(
+                            "the store is protected by {}, not {}",
+                            file.protection,
+                            self.protection()
+                        )
+                        .into(),
+                    ));
+                }
+                Ok(file)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                Ok(StoreFile {
+                    salt: BASE64.encode(salt),
+                    kdf: KdfParams::DEFAULT,
+                    protection: self.protection(),
+                    entries: BTreeMap::new(),
+                })
+            }
+            Err(e) => Err(platform_error(e)),
+        }
+    }
+
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        sibling(&self.path, suffix)
+    }
+
+    /// Takes an exclusive lock on the `.lock` sibling of the store, held until the returned
+    /// file is dropped, so concurrent writers (threads or processes) don't lose each
+    /// other's changes between `load` and `save`.
+    fn lock(&self) -> Result<fs::File> {
+        lock_file(&self.sibling(".lock"))
+    }
+
+    fn save(&self, file: &StoreFile) -> Result<()> {
+        static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(platform_error)?;
+        }
+        let content = serde_json::to_string_pretty(file).map_err(platform_error)?;
+        // Write to a sibling file first so a crash never leaves a truncated store behind.
+        // Its name is unique, and only the owner can read it.
+        let tmp_path = self.sibling(&format!(
+            ".{}-{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = open_private(
+            &tmp_path,
+            fs::OpenOptions::new().create_new(true).write(true),
+        )
+        .and_then(|mut tmp| {
+            tmp.write_all(content.as_bytes())
+                .and_then(|()| tmp.sync_all())
+                .map_err(platform_error)
+        })
+        .and_then(|()| fs::rename(&tmp_path, &self.path).map_err(platform_error));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        written
+    }
+
+    fn cipher(&self, file: &StoreFile) -> Result<XChaCha20Poly1305> {
+        let salt = BASE64
+            .decode(&file.salt)
+            .map_err(|_| corrupt("invalid salt in encrypted file store"))?;
+        if file.protection == Protection::Machine {
+            let secret_path = match &self.machine_secret {
+                Some(path) => path.clone(),
+                None => user_secret_path(),
+            };
+            let key = machine_key_with_secret(&secret_path, &salt, KDF_INFO).ok_or(
+                keyring::Error::NoStorageAccess(
+                    "the store is bound to a machine whose identifier or secret is unavailable"
+                        .into(),
+                ),
+            )?;
+            return Ok(XChaCha20Poly1305::new(key.as_ref().into()));
+        }
+        derive_cipher(
+            self.passphrase.as_deref().map(String::as_str),
+            &salt,
+            KDF_INFO,
+            file.kdf,
+        )
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        let file = self.load()?;
+        let encoded = file
+            .entries
+            .get(system_name)
+            .and_then(|keys| keys.get(key_name))
+            .ok_or(keyring::Error::NoEntry)?;
+        let data = BASE64
+            .decode(encoded)
+            .map_err(|_| corrupt("invalid entry encoding in encrypted file store"))?;
+        open_entry(
+            &self.cipher(&file)?,
+            system_name,
+            key_name,
+            &data,
+            "encrypted file store",
+        )
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut file = self.load()?;
+        let data = seal_entry(&self.cipher(&file)?, system_name, key_name, value)?;
+        file.entries
+            .entry(system_name.to_string())
+            .or_default()
+            .insert(key_name.to_string(), BASE64.encode(data));
+        self.save(&file)
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut file = self.load()?;
+        let keys = file
+            .entries
+            .get_mut(system_name)
+            .ok_or(keyring::Error::NoEntry)?;
+        keys.remove(key_name).ok_or(keyring::Error::NoEntry)?;
+        if keys.is_empty() {
+            file.entries.remove(system_name);
+        }
+        self.save(&file)
+    }
+
+    /// Lists the keys stored for `system_name`.
+    pub fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        let file = self.load()?;
+        Ok(file
+            .entries
+            .get(system_name)
+            .map(|keys| keys.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    /// Checks that the passphrase opens the store by decrypting one of its entries. An
+    /// empty or missing store accepts any passphrase.
+    pub(crate) fn verify_passphrase(&self) -> Result<()> {
+        let file = self.load()?;
+        let first = file
+            .entries
+            .iter()
+            .find_map(|(system, keys)| keys.keys().next().map(|key| (system, key)));
+        match first {
+            Some((system, key)) => self.get(system, key).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the file can be read (when present) and its directory created.
+    pub(crate) fn health_check(&self) -> Result<()> {
+        let file = self.load()?;
+        self.cipher(&file)?;
+        match self.path.parent() {
+            Some(parent) => fs::create_dir_all(parent).map_err(platform_error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{Error, ErrorKind, Operation};
+
+    fn temp_store(name: &str) -> EncryptedFileStore {
+        let path = std::env::temp_dir().join("key_vaulter_tests").join(format!(
+            "{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        EncryptedFileStore::new(path).with_passphrase("test passphrase")
+    }
+
+    #[test]
+    fn test_store_read_and_delete() {
+        let store = temp_store("file_store_roundtrip");
+        store.set("system", "key", "secret").unwrap();
+        assert_eq!(store.get("system", "key").unwrap(), "secret");
+        assert_eq!(store.list("system").unwrap(), vec!["key".to_string()]);
+        store.delete("system", "key").unwrap();
+        assert!(matches!(
+            store.get("system", "key"),
+            Err(keyring::Error::NoEntry)
+        ));
+        let _ = fs::remove_file(store.path());
+    }
+
+    #[test]
+    fn test_clear_caches_forgets_derived_keys() {
+        let params = KdfParams {
+            log_n: 10,
+            r: 8,
+            p: 1,
+        };
+        let key = params.stretch("derived keys test", b"salt").unwrap();
+        let id = params.cache_id("derived keys test", b"salt");
+        lock_events::clear_caches();
+        assert!(!derived_keys().contains_key(&id));
+        assert_eq!(params.stretch("derived keys test", b"salt").unwrap(), key);
+    }
+
+    #[test]
+    fn test_value_is_not_stored_in_plaintext() {
+        let store = temp_store("file_store_plaintext");
+        store.set("system", "key", "very-secret-value").unwrap();
+        let content = fs::read_to_string(store.path()).unwrap();
+        assert!(!content.contains("very-secret-value"));
+        let _ = fs::remove_file(store.path());
+    }
+
+    #[test]
+    fn test_default_protection_is_machine_bound() {
+        let store = EncryptedFileStore::temp_for_tests("file_store_machine");
+        store.set("system", "key", "secret").unwrap();
+        assert_eq!(store.get("system", "key").unwrap(), "secret");
+        let expected = if machine_id().is_some() {
+            Protection::Machine
+        } else {
+            Protection::User
+        };
+        assert_eq!(store.load().unwrap().protection, expected);
+        let _ = fs::remove_file(store.path());
+    }
+
+    #[test]
+    fn test_store_with_another_protection_is_refused() {
+        let store = temp_store("file_store_protection");
+        store.set("system", "key", "secret").unwrap();
+        let unprotected = EncryptedFileStore::new(store.path());
+        assert!(unprotected.get("system", "key").is_err());
+        assert!(unprotected.set("system", "other", "value").is_err());
+
+        // Downgrading the recorded protection doesn't make new entries use a weaker key.
+        let content = fs::read_to_string(store.path()).unwrap();
+        let mut json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        json["protection"] = "user".into();
+        fs::write(store.path(), json.to_string()).unwrap();
+        assert!(store.set("system", "other", "value").is_err());
+        json.as_object_mut().unwrap().remove("protection");
+        fs::write(store.path(), json.to_string()).unwrap();
+        assert!(store.set("system", "other", "value").is_err());
+        let _ = fs::remove_file(store.path());
+    }
+
+    #[test]
+    fn test_wrong_passphrase_fails() {
+        let store = temp_store("file_store_passphrase");
+        store.set("system", "key", "secret").unwrap();
+        let other = EncryptedFileStore::new(store.path()).with_passphrase("other");
+        assert!(other.get("system", "key").is_err());
+        let _ = fs::remove_file(store.path());
+    }
+
+    #[test]
+    fn test_concurrent_writes_are_all_kept() {
+        let store = temp_store("file_store_concurrent");
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let store = store.clone();
+                std::thread::spawn(move || store.set("system", &format!("key{}", i), "value"))
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+        assert_eq!(store.list("system").unwrap().len(), 8);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(store.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let _ = fs::remove_file(store.path());
+    }
+
+    #[test]
+    fn test_passphrase_is_stretched_with_recorded_params() {
+        let store = temp_store("file_store_kdf");
+        store.set("system", "key", "secret").unwrap();
+        let content = fs::read_to_string(store.path()).unwrap();
+        let file: StoreFile = serde_json::from_str(&content).unwrap();
+        assert_eq!(file.kdf, KdfParams::DEFAULT);
+        assert_eq!(
+            KdfParams::parse(&KdfParams::DEFAULT.to_string()),
+            Some(file.kdf)
+        );
+
+        // A file without the parameters is rejected rather than keyed by HKDF alone.
+        let mut json: serde_json::Value = serde_json::from_str(&content).unwrap();
+        json.as_object_mut().unwrap().remove("kdf");
+        fs::write(store.path(), json.to_string()).unwrap();
+        let error = store.get("system", "key").unwrap_err();
+        let error = Error::new("system", "key", Operation::Read, "encrypted-file", error);
+        assert_eq!(error.kind(), ErrorKind::Corrupt);
+        assert!(store.set("system", "other", "value").is_err());
+        let _ = fs::remove_file(store.path());
+    }
+}
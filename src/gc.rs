@@ -0,0 +1,167 @@
+use crate::error::{Operation, Result};
+use crate::hooks;
+use crate::jwt::token_expiry;
+use crate::naming::{is_internal_key, is_vault_entry};
+use crate::rotation::previous_expiry;
+use crate::timestamp::now_unix;
+use crate::vault::Vault;
+use std::time::Duration;
+
+/// Why `Vault::gc` considers an entry dead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcReason {
+    /// The value is a JSON Web Token past its `exp` claim.
+    TokenExpired,
+    /// The entry is a value replaced by `KeyManager::rotate_key` whose grace period ended.
+    GracePeriodEnded,
+}
+
+/// An entry removed, or to be removed, by `Vault::gc`. Never carries the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcEntry {
+    pub key: String,
+    pub reason: GcReason,
+    /// When the entry expired, in seconds since the Unix epoch.
+    pub expired_at: u64,
+}
+
+impl Vault {
+    /// Deletes the entries of this vault that expired more than `retention` ago, and
+    /// returns them in key order, so long-lived machines don't keep years of dead tokens.
+    ///
+    /// An entry is expired when its value is a JWT past its `exp` claim, or when it is
+    /// the previous value kept by `KeyManager::rotate_key` after its grace period; other
+    /// entries the crate keeps for itself are left alone. Values
+    /// are read straight from the backend to check them, without approvers or prompts;
+    /// deletes go through the vault's journal and hooks. Each entry is checked again while
+    /// holding its key (see `KeyManager::lock`), so a value refreshed meanwhile by another
+    /// thread is kept. Requires a backend that supports listing.
+    pub fn gc(&self, retention: Duration) -> Result<Vec<GcEntry>> {
+        let cutoff = cutoff(retention);
+        let mut removed = Vec::new();
+        for candidate in self.gc_dry_run(retention)? {
+            let mut manager = self.key(&candidate.key);
+            let _lock = manager.lock();
+            if let Some(entry) = self.expired_entry(candidate.key, cutoff)? {
+                manager.delete()?;
+                removed.push(entry);
+            }
+        }
+        if !removed.is_empty() {
+            hooks::debug(&format!("removed {} expired entries", removed.len()));
+        }
+        Ok(removed)
+    }
+
+    /// Lists what `gc` would delete, without deleting anything.
+    pub fn gc_dry_run(&self, retention: Duration) -> Result<Vec<GcEntry>> {
+        let cutoff = cutoff(retention);
+        let mut keys = self
+            .backend()
+            .list(self.system_name())
+            .map_err(|e| self.error(Operation::Read, e))?;
+        keys.retain(|key| !is_vault_entry(key));
+        keys.sort();
+        let mut expired = Vec::new();
+        for key in keys {
+            expired.extend(self.expired_entry(key, cutoff)?);
+        }
+        Ok(expired)
+    }
+
+    /// The entry of `key` if its current value expired before `cutoff`.
+    fn expired_entry(&self, key: String, cutoff: u64) -> Result<Option<GcEntry>> {
+        let value = match self.backend().get(self.system_name(), &key) {
+            Ok(value) => value,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(self.key(&key).error(Operation::Read, e)),
+        };
+        let found = match previous_expiry(&key, &value) {
+            Some(expired_at) => Some((GcReason::GracePeriodEnded, expired_at)),
+            None if is_internal_key(&key) => None,
+            None => token_expiry(&value).map(|exp| (GcReason::TokenExpired, exp)),
+        };
+        Ok(found
+            .filter(|(_, at)| *at < cutoff)
+            .map(|(reason, expired_at)| GcEntry {
+                key,
+                reason,
+                expired_at,
+            }))
+    }
+}
+
+fn cutoff(retention: Duration) -> u64 {
+    now_unix().saturating_sub(retention.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_not_stored, assert_stored};
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+    use base64::Engine;
+
+    const DAY: u64 = 86_400;
+
+    fn token(exp: u64) -> String {
+        let claims = BASE64_URL.encode(format!(r#"{{"exp":{}}}"#, exp));
+        format!("eyJhbGciOiJIUzI1NiJ9.{}.c2lnbmF0dXJl", claims)
+    }
+
+    #[test]
+    fn test_gc_removes_long_expired_entries() {
+        let vault = Vault::ephemeral_for_tests();
+        let now = now_unix();
+        let old_token = token(now - 40 * DAY);
+        vault.key("old_token").store(&old_token).unwrap();
+        vault.key("recent_token").store(&token(now - DAY)).unwrap();
+        vault.key("valid_token").store(&token(now + DAY)).unwrap();
+        vault.key("password").store("hunter2").unwrap();
+        vault
+            .key("api_key#previous")
+            .store(&format!(
+                r#"{{"value":"old","expires_at":{}}}"#,
+                now - 10 * DAY
+            ))
+            .unwrap();
+
+        let retention = Duration::from_secs(7 * DAY);
+        let preview = vault.gc_dry_run(retention).unwrap();
+        assert_eq!(
+            preview
+                .iter()
+                .map(|entry| (entry.key.as_str(), entry.reason))
+                .collect::<Vec<_>>(),
+            vec![
+                ("api_key#previous", GcReason::GracePeriodEnded),
+                ("old_token", GcReason::TokenExpired),
+            ]
+        );
+        assert_stored(&vault, "old_token", &old_token);
+
+        assert_eq!(vault.gc(retention).unwrap(), preview);
+        assert_not_stored(&vault, "old_token");
+        assert_not_stored(&vault, "api_key#previous");
+        assert_eq!(vault.gc_dry_run(Duration::ZERO).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_gc_keeps_a_value_refreshed_meanwhile() {
+        let vault = Vault::ephemeral_for_tests();
+        let now = now_unix();
+        let mut manager = vault.key("session_token");
+        manager.store(&token(now - 40 * DAY)).unwrap();
+        let fresh = token(now + DAY);
+
+        let lock = manager.lock();
+        std::thread::scope(|scope| {
+            let gc = scope.spawn(|| vault.gc(Duration::from_secs(7 * DAY)).unwrap());
+            std::thread::sleep(Duration::from_millis(100));
+            manager.store(&fresh).unwrap();
+            drop(lock);
+            assert!(gc.join().unwrap().is_empty());
+        });
+        assert_stored(&vault, "session_token", &fresh);
+    }
+}
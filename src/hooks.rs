@@ -0,0 +1,460 @@
+use crate::error::Operation;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+type WarningHook = Box<dyn Fn(&str) + Send + Sync>;
+
+static WARNING_HOOK: RwLock<Option<WarningHook>> = RwLock::new(None);
+
+/// Installs a hook that receives the crate's warnings (e.g. a backend fallback).
+///
+/// Without a hook, warnings are printed to stderr.
+pub fn set_warning_hook<F>(hook: F)
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    if let Ok(mut slot) = WARNING_HOOK.write() {
+        *slot = Some(Box::new(hook));
+    }
+}
+
+/// Removes the warning hook, restoring the default stderr output.
+pub fn clear_warning_hook() {
+    if let Ok(mut slot) = WARNING_HOOK.write() {
+        *slot = None;
+    }
+}
+
+pub(crate) fn warn(message: &str) {
+    match WARNING_HOOK.read() {
+        Ok(slot) => match slot.as_ref() {
+            Some(hook) => hook(message),
+            None => eprintln!("key_vaulter warning: {}", message),
+        },
+        Err(_) => eprintln!("key_vaulter warning: {}", message),
+    }
+}
+
+/// Reports which source answered a read, or why the next one is tried, through the `log`
+/// crate at debug level (feature `log`). Messages name keys and sources, never values.
+pub(crate) fn debug(message: &str) {
+    #[cfg(feature = "log")]
+    log::debug!(target: "key_vaulter", "{}", message);
+    #[cfg(not(feature = "log"))]
+    let _ = message;
+}
+
+/// What happened to a key, as reported to change hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ChangeKind {
+    Stored,
+    Deleted,
+    /// A new value replaced the old one through `KeyManager::rotate_key` or
+    /// `Vault::rotate_due`.
+    Rotated,
+}
+
+impl ChangeKind {
+    /// Short, stable name (`"stored"`, `"deleted"`, `"rotated"`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Stored => "stored",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Rotated => "rotated",
+        }
+    }
+}
+
+/// A successful change of a stored key. Never carries the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub system: String,
+    pub key: String,
+    /// Name of the backend that was written (see `Backend::name`).
+    pub backend: &'static str,
+    pub at: SystemTime,
+}
+
+impl ChangeEvent {
+    pub(crate) fn new(kind: ChangeKind, system: &str, key: &str, backend: &'static str) -> Self {
+        ChangeEvent {
+            kind,
+            system: system.to_string(),
+            key: key.to_string(),
+            backend,
+            at: SystemTime::now(),
+        }
+    }
+
+    /// The operation that produced the change.
+    pub fn operation(&self) -> Operation {
+        match self.kind {
+            ChangeKind::Stored | ChangeKind::Rotated => Operation::Store,
+            ChangeKind::Deleted => Operation::Delete,
+        }
+    }
+}
+
+/// Receives an event after every successful store, delete or rotation, e.g. to alert the
+/// team when a production credential changes. Entries the crate keeps next to a key (see
+/// `naming::is_internal_key`) don't produce events. Implemented for closures.
+///
+/// Hooks run synchronously on the thread that made the change; hand slow work (network
+/// calls) off to another thread.
+pub trait ChangeHook: Send + Sync {
+    fn on_change(&self, event: &ChangeEvent);
+}
+
+impl<F> ChangeHook for F
+where
+    F: Fn(&ChangeEvent) + Send + Sync,
+{
+    fn on_change(&self, event: &ChangeEvent) {
+        self(event)
+    }
+}
+
+static CHANGE_HOOKS: RwLock<Vec<Arc<dyn ChangeHook>>> = RwLock::new(Vec::new());
+
+/// Registers a hook notified of every successful store and delete.
+pub fn add_change_hook<H>(hook: H)
+where
+    H: ChangeHook + 'static,
+{
+    if let Ok(mut hooks) = CHANGE_HOOKS.write() {
+        hooks.push(Arc::new(hook));
+    }
+}
+
+/// Removes every change hook.
+pub fn clear_change_hooks() {
+    if let Ok(mut hooks) = CHANGE_HOOKS.write() {
+        hooks.clear();
+    }
+}
+
+pub(crate) fn notify_change(event: ChangeEvent) {
+    // Clone the list so hooks may register other hooks without deadlocking.
+    let hooks = match CHANGE_HOOKS.read() {
+        Ok(hooks) => hooks.clone(),
+        Err(_) => return,
+    };
+    for hook in hooks {
+        hook.on_change(&event);
+    }
+}
+
+/// A step of an interactive prompt, as reported to prompt hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PromptEventKind {
+    /// The question was shown; `attempt` counts the re-entries.
+    Asked,
+    /// The answer was refused and the question is asked again.
+    Rejected,
+    /// The answer was taken.
+    Accepted,
+}
+
+impl PromptEventKind {
+    /// Short, stable name (`"asked"`, `"rejected"`, `"accepted"`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptEventKind::Asked => "asked",
+            PromptEventKind::Rejected => "rejected",
+            PromptEventKind::Accepted => "accepted",
+        }
+    }
+}
+
+/// A step of `request_key` and similar prompts, to see where users struggle in a setup
+/// flow. Never carries the answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptEvent {
+    pub kind: PromptEventKind,
+    pub system: String,
+    pub key: String,
+    /// Dotted path of the struct field asked, `None` for a plain key.
+    pub field: Option<String>,
+    /// 1 for the first time the question is asked, 2 after the first rejection, ...
+    pub attempt: u32,
+    /// Why a `Rejected` answer was refused, e.g. `Expected a number`.
+    pub reason: Option<String>,
+    pub at: SystemTime,
+}
+
+impl PromptEvent {
+    pub(crate) fn new(
+        kind: PromptEventKind,
+        system: &str,
+        key: &str,
+        field: Option<&str>,
+        attempt: u32,
+    ) -> Self {
+        PromptEvent {
+            kind,
+            system: system.to_string(),
+            key: key.to_string(),
+            field: field.map(str::to_string),
+            attempt,
+            reason: None,
+            at: SystemTime::now(),
+        }
+    }
+
+    pub(crate) fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+}
+
+/// Receives the steps of every interactive prompt. Implemented for closures.
+///
+/// Hooks run synchronously on the prompting thread.
+pub trait PromptHook: Send + Sync {
+    fn on_prompt(&self, event: &PromptEvent);
+}
+
+impl<F> PromptHook for F
+where
+    F: Fn(&PromptEvent) + Send + Sync,
+{
+    fn on_prompt(&self, event: &PromptEvent) {
+        self(event)
+    }
+}
+
+static PROMPT_HOOKS: RwLock<Vec<Arc<dyn PromptHook>>> = RwLock::new(Vec::new());
+
+/// Registers a hook notified of every question asked, answer rejected and answer
+/// accepted at a prompt.
+pub fn add_prompt_hook<H>(hook: H)
+where
+    H: PromptHook + 'static,
+{
+    if let Ok(mut hooks) = PROMPT_HOOKS.write() {
+        hooks.push(Arc::new(hook));
+    }
+}
+
+/// Removes every prompt hook.
+pub fn clear_prompt_hooks() {
+    if let Ok(mut hooks) = PROMPT_HOOKS.write() {
+        hooks.clear();
+    }
+}
+
+pub(crate) fn notify_prompt(event: PromptEvent) {
+    let hooks = match PROMPT_HOOKS.read() {
+        Ok(hooks) => hooks.clone(),
+        Err(_) => return,
+    };
+    for hook in hooks {
+        hook.on_prompt(&event);
+    }
+}
+
+/// A read of a key marked with `with_read_confirmation`, waiting for approval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadRequest {
+    pub system: String,
+    pub key: String,
+    /// Name of the backend that would be read (see `Backend::name`).
+    pub backend: &'static str,
+}
+
+type ReadApprover = Box<dyn Fn(&ReadRequest) -> bool + Send + Sync>;
+
+static READ_APPROVER: RwLock<Option<ReadApprover>> = RwLock::new(None);
+
+/// Installs a hook deciding whether a confirmed read may proceed, e.g. a GUI dialog or a
+/// hardware-token touch. Return `false` to deny it.
+///
+/// Without an approver, the user is asked on the terminal.
+pub fn set_read_approver<F>(approver: F)
+where
+    F: Fn(&ReadRequest) -> bool + Send + Sync + 'static,
+{
+    if let Ok(mut slot) = READ_APPROVER.write() {
+        *slot = Some(Box::new(approver));
+    }
+}
+
+/// Removes the read approver, restoring the terminal confirmation.
+pub fn clear_read_approver() {
+    if let Ok(mut slot) = READ_APPROVER.write() {
+        *slot = None;
+    }
+}
+
+pub(crate) fn approve_read(request: &ReadRequest) -> bool {
+    if let Ok(slot) = READ_APPROVER.read() {
+        if let Some(approver) = slot.as_ref() {
+            return approver(request);
+        }
+    }
+    confirm_on_terminal(request)
+}
+
+/// Asks `Allow reading 'system/key'? [y/N]`; anything but yes (or a failing terminal)
+/// denies the read.
+#[cfg(feature = "interactive")]
+fn confirm_on_terminal(request: &ReadRequest) -> bool {
+    use std::io::{self, Write};
+
+    eprint!(
+        "Allow reading '{}/{}' from {}? [y/N] ",
+        request.system, request.key, request.backend
+    );
+    if io::stderr().flush().is_err() {
+        return false;
+    }
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(_) => matches!(input.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+/// Without the `interactive` feature, reads without an approver are denied.
+#[cfg(not(feature = "interactive"))]
+fn confirm_on_terminal(_request: &ReadRequest) -> bool {
+    false
+}
+
+/// A change hook posting each event as JSON to an HTTP(S) endpoint (e.g. a chat
+/// webhook), from a background thread:
+///
+/// `{"event": "stored", "system": "...", "key": "...", "backend": "...", "timestamp": 1717236000}`
+///
+/// Delivery failures go to the warning hook.
+#[cfg(feature = "remote")]
+#[derive(Debug, Clone)]
+pub struct WebhookHook {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(feature = "remote")]
+impl WebhookHook {
+    /// Posts events to `url`.
+    pub fn new(url: &str) -> Self {
+        WebhookHook {
+            url: url.to_string(),
+            client: reqwest::blocking::Client::new(),
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+impl ChangeHook for WebhookHook {
+    fn on_change(&self, event: &ChangeEvent) {
+        let body = serde_json::json!({
+            "event": event.kind.as_str(),
+            "system": event.system,
+            "key": event.key,
+            "backend": event.backend,
+            "timestamp": event
+                .at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        });
+        let (url, client) = (self.url.clone(), self.client.clone());
+        std::thread::spawn(move || {
+            let result = client
+                .post(&url)
+                .json(&body)
+                .send()
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                warn(&format!("change webhook failed: {}", e));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_manager::DeleteOutcome;
+    use crate::vault::Vault;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_change_hooks_receive_store_and_delete() {
+        let vault = Vault::ephemeral_for_tests();
+        let system = vault.system_name().to_string();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        add_change_hook(move |event: &ChangeEvent| {
+            if event.system == system {
+                sink.lock().unwrap().push((event.kind, event.key.clone()));
+            }
+        });
+        let mut manager = vault.key("token");
+        manager.store("secret").unwrap();
+        manager.delete().unwrap();
+        assert_eq!(manager.delete().unwrap(), DeleteOutcome::NotFound);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (ChangeKind::Stored, "token".to_string()),
+                (ChangeKind::Deleted, "token".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_prompt_hooks_report_reentered_fields() {
+        use crate::test_utils::ScriptedPrompter;
+        use crate::vault::Vault;
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Default)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+
+        let vault = Vault::ephemeral_for_tests()
+            .with_prompter(ScriptedPrompter::new(["db", "fifty", "5432"]));
+        let system = vault.system_name().to_string();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        add_prompt_hook(move |event: &PromptEvent| {
+            if event.system == system {
+                sink.lock().unwrap().push((
+                    event.kind,
+                    event.field.clone().unwrap_or_default(),
+                    event.attempt,
+                    event.reason.clone(),
+                ));
+            }
+        });
+        vault.structured::<Server>("server").request_key().unwrap();
+
+        let field = |kind, name: &str, attempt, reason: Option<&str>| {
+            (kind, name.to_string(), attempt, reason.map(str::to_string))
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                field(PromptEventKind::Asked, "host", 1, None),
+                field(PromptEventKind::Accepted, "host", 1, None),
+                field(PromptEventKind::Asked, "port", 1, None),
+                field(
+                    PromptEventKind::Rejected,
+                    "port",
+                    1,
+                    Some("Expected a number")
+                ),
+                field(PromptEventKind::Asked, "port", 2, None),
+                field(PromptEventKind::Accepted, "port", 2, None),
+            ]
+        );
+    }
+}
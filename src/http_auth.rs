@@ -0,0 +1,240 @@
+use crate::error::{Cause, Operation, Result};
+use crate::redact::REDACTED;
+use crate::vault::Vault;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use http::Extensions;
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// Credentials for HTTP basic authentication, stored as a struct
+/// (`{"username": ..., "password": ...}`).
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsernamePassword {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for UsernamePassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UsernamePassword")
+            .field("username", &self.username)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scheme {
+    Bearer,
+    Basic,
+}
+
+type Refresh = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+/// A [`reqwest_middleware`] middleware adding the `Authorization` header from a stored
+/// credential to every request:
+///
+/// ```no_run
+/// use key_vaulter::http_auth::VaultAuth;
+/// use key_vaulter::Vault;
+///
+/// let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+///     .with(VaultAuth::bearer(&Vault::new("github"), "api_token"))
+///     .build();
+/// ```
+///
+/// The credential is read on every request, so a rotated token is picked up right away.
+/// Reads go through the vault's (blocking) backend.
+#[derive(Clone)]
+pub struct VaultAuth {
+    vault: Vault,
+    key_name: String,
+    scheme: Scheme,
+    refresh: Option<Refresh>,
+}
+
+impl fmt::Debug for VaultAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VaultAuth")
+            .field("vault", &self.vault)
+            .field("key_name", &self.key_name)
+            .field("scheme", &self.scheme)
+            .field("refresh", &self.refresh.is_some())
+            .finish()
+    }
+}
+
+impl VaultAuth {
+    /// Sends the value of `key_name` as `Authorization: Bearer <token>`.
+    pub fn bearer(vault: &Vault, key_name: &str) -> Self {
+        VaultAuth::new(vault, key_name, Scheme::Bearer)
+    }
+
+    /// Sends the [`UsernamePassword`] stored under `key_name` as basic authentication.
+    pub fn basic(vault: &Vault, key_name: &str) -> Self {
+        VaultAuth::new(vault, key_name, Scheme::Basic)
+    }
+
+    fn new(vault: &Vault, key_name: &str, scheme: Scheme) -> Self {
+        VaultAuth {
+            vault: vault.clone(),
+            key_name: key_name.to_string(),
+            scheme,
+            refresh: None,
+        }
+    }
+
+    /// Calls `refresh` when the server answers `401 Unauthorized` to a bearer token, e.g.
+    /// to run an OAuth refresh-token grant. The new token is stored under the key and the
+    /// request is sent once more (requests with streaming bodies are not retried).
+    pub fn with_refresh<F>(mut self, refresh: F) -> Self
+    where
+        F: Fn() -> Result<String> + Send + Sync + 'static,
+    {
+        self.refresh = Some(Arc::new(refresh));
+        self
+    }
+
+    fn header(&self) -> Result<HeaderValue> {
+        let value = match self.scheme {
+            Scheme::Bearer => format!("Bearer {}", self.vault.key(&self.key_name).read_key()?),
+            Scheme::Basic => {
+                let credentials: UsernamePassword =
+                    self.vault.structured(&self.key_name).read_key()?;
+                let pair = format!("{}:{}", credentials.username, credentials.password);
+                format!("Basic {}", BASE64.encode(pair))
+            }
+        };
+        let mut header = HeaderValue::from_str(&value).map_err(|e| {
+            self.vault.key(&self.key_name).error(
+                Operation::Read,
+                Cause::InvalidInput(format!("not a valid header value: {}", e)),
+            )
+        })?;
+        header.set_sensitive(true);
+        Ok(header)
+    }
+
+    fn authorize(&self, request: &mut Request) -> reqwest_middleware::Result<()> {
+        let header = self
+            .header()
+            .map_err(reqwest_middleware::Error::middleware)?;
+        request.headers_mut().insert(AUTHORIZATION, header);
+        Ok(())
+    }
+
+    /// Runs the refresh callback and stores the new token.
+    fn refresh(&self, refresh: &Refresh) -> Result<()> {
+        let token = refresh()?;
+        self.vault.key(&self.key_name).store(&token).map(drop)
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for VaultAuth {
+    async fn handle(
+        &self,
+        mut request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        self.authorize(&mut request)?;
+        let (Some(refresh), Some(mut retry)) = (&self.refresh, request.try_clone()) else {
+            return next.run(request, extensions).await;
+        };
+        let response = next.clone().run(request, extensions).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        self.refresh(refresh)
+            .map_err(reqwest_middleware::Error::middleware)?;
+        self.authorize(&mut retry)?;
+        next.run(retry, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// Answers 200 to requests carrying `expected` as Authorization header, 401 otherwise.
+    fn serve(requests: usize, expected: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut authorized = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    authorized |= line.trim().eq_ignore_ascii_case(expected);
+                }
+                let status = if authorized {
+                    "200 OK"
+                } else {
+                    "401 Unauthorized"
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_expired_bearer_token_is_refreshed() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("token").store("expired").unwrap();
+        let refreshes = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&refreshes);
+        let auth = VaultAuth::bearer(&vault, "token").with_refresh(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok("fresh".to_string())
+        });
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(auth)
+            .build();
+        let url = serve(3, "authorization: Bearer fresh");
+
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 200);
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 200);
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+        assert_eq!(vault.key("token").read_key().unwrap(), "fresh");
+    }
+
+    #[tokio::test]
+    async fn test_basic_credentials() {
+        let vault = Vault::ephemeral_for_tests();
+        vault
+            .structured("login")
+            .store(&UsernamePassword {
+                username: "alice".to_string(),
+                password: "s3cret".to_string(),
+            })
+            .unwrap();
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(VaultAuth::basic(&vault, "login"))
+            .build();
+        // base64("alice:s3cret")
+        let url = serve(1, "authorization: Basic YWxpY2U6czNjcmV0");
+        assert_eq!(client.get(&url).send().await.unwrap().status(), 200);
+    }
+}
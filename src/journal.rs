@@ -0,0 +1,412 @@
+use crate::error::{ErrorKind, Operation};
+use crate::file_store::{
+    corrupt, current_user, derive_cipher, lock_file, platform_error, sibling, KdfParams,
+};
+use crate::timestamp::{format_rfc3339, now_unix};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use keyring::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const KDF_INFO: &[u8] = b"key_vaulter journal v1";
+const HEADER: &str = "key_vaulter-journal v1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Serializes appends within the process, so sequence numbers stay unique; the `.lock`
+/// sibling of the journal does it across processes.
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// One recorded operation. Never contains the value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Unix timestamp (seconds) of the operation.
+    pub at: u64,
+    /// OS user that ran the operation.
+    pub user: String,
+    pub operation: Operation,
+    pub system: String,
+    pub key: String,
+    pub backend: String,
+    /// `None` when the operation succeeded.
+    pub error: Option<ErrorKind>,
+}
+
+impl JournalEntry {
+    pub(crate) fn new(
+        operation: Operation,
+        system: &str,
+        key: &str,
+        backend: &str,
+        error: Option<ErrorKind>,
+    ) -> Self {
+        JournalEntry {
+            at: now_unix(),
+            user: current_user(),
+            operation,
+            system: system.to_string(),
+            key: key.to_string(),
+            backend: backend.to_string(),
+            error,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// `2024-06-01T10:00:00Z alice store my_system/token ok`
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}/{} ",
+            format_rfc3339(self.at),
+            self.user,
+            self.operation,
+            self.system,
+            self.key
+        )?;
+        match self.error {
+            None => f.write_str("ok"),
+            Some(kind) => write!(f, "failed ({})", kind),
+        }
+    }
+}
+
+/// An append-only, encrypted log of the operations run through managers that have it
+/// attached (see `Vault::with_journal`).
+///
+/// The first line of the file holds a random salt and the key derivation parameters;
+/// every following line is one entry encrypted with XChaCha20-Poly1305 and bound to its
+/// position, so entries can't be removed from the middle or reordered without `entries`
+/// failing. The key is derived the same way as for `EncryptedFileStore`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Journal {
+    path: PathBuf,
+    passphrase: Option<String>,
+}
+
+/// The salt and key derivation parameters following `HEADER` on the first line.
+fn header_fields(first: Option<&str>) -> Result<String> {
+    first
+        .and_then(|header| header.strip_prefix(HEADER))
+        .map(|fields| fields.trim().to_string())
+        .ok_or_else(|| corrupt("missing journal header"))
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Journal")
+            .field("path", &self.path)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl Journal {
+    /// Creates a journal written to `path`. The file is created on first append.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Journal {
+            path: path.into(),
+            passphrase: None,
+        }
+    }
+
+    /// Derives the encryption key from the given passphrase.
+    pub fn with_passphrase(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    /// Path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The cipher for the header's `<salt> <kdf>`.
+    fn cipher(&self, header: &str) -> Result<XChaCha20Poly1305> {
+        let mut fields = header.split_whitespace();
+        let salt = fields
+            .next()
+            .and_then(|salt| BASE64.decode(salt).ok())
+            .ok_or_else(|| corrupt("invalid salt in journal"))?;
+        let kdf = fields
+            .next()
+            .and_then(KdfParams::parse)
+            .ok_or_else(|| corrupt("missing or invalid key derivation parameters in journal"))?;
+        if fields.next().is_some() {
+            return Err(corrupt("invalid journal header"));
+        }
+        derive_cipher(self.passphrase.as_deref(), &salt, KDF_INFO, kdf)
+    }
+
+    /// Reads the header and the encrypted lines of the file, if it exists.
+    fn load(&self) -> Result<Option<(String, Vec<String>)>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(platform_error(e)),
+        };
+        let mut lines = content.lines();
+        let fields = header_fields(lines.next())?;
+        Ok(Some((fields, lines.map(str::to_string).collect())))
+    }
+
+    /// The sidecar file keeping the number of entries, so appends don't read the journal.
+    fn count_path(&self) -> PathBuf {
+        sibling(&self.path, ".count")
+    }
+
+    /// The header fields and the number of entries of an existing journal of `len` bytes.
+    /// The count comes from the sidecar when it was written for that length, and from
+    /// reading the whole journal otherwise, e.g. after a crash between both writes.
+    fn tail(&self, len: u64) -> Result<(String, usize)> {
+        let counted = fs::read_to_string(self.count_path())
+            .ok()
+            .and_then(|sidecar| {
+                let (count, counted_len) = sidecar.trim().split_once(' ')?;
+                (counted_len.parse::<u64>().ok()? == len).then(|| count.parse().ok())?
+            });
+        match counted {
+            Some(count) => {
+                let mut first = String::new();
+                BufReader::new(fs::File::open(&self.path).map_err(platform_error)?)
+                    .read_line(&mut first)
+                    .map_err(platform_error)?;
+                Ok((header_fields(Some(&first))?, count))
+            }
+            None => {
+                let (fields, lines) = self
+                    .load()?
+                    .ok_or_else(|| corrupt("missing journal header"))?;
+                Ok((fields, lines.len()))
+            }
+        }
+    }
+
+    pub(crate) fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let _guard = APPEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // Held until the entry is written, so two processes can't both take the same
+        // sequence number or both write a header.
+        let _lock = lock_file(&sibling(&self.path, ".lock"))?;
+        let len = match fs::metadata(&self.path) {
+            Ok(metadata) => Some(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(platform_error(e)),
+        };
+        let (fields, sequence, header) = match len {
+            Some(len) => {
+                let (fields, sequence) = self.tail(len)?;
+                (fields, sequence, None)
+            }
+            None => {
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let fields = format!("{} {}", BASE64.encode(salt), KdfParams::DEFAULT);
+                let header = format!("{} {}\n", HEADER, fields);
+                (fields, 0, Some(header))
+            }
+        };
+        let plaintext = serde_json::to_vec(entry).map_err(platform_error)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher(&fields)?
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: sequence.to_string().as_bytes(),
+                },
+            )
+            .map_err(|_| corrupt("failed to encrypt journal entry"))?;
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(platform_error)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&self.path).map_err(platform_error)?;
+        let line = format!("{}{}\n", header.unwrap_or_default(), BASE64.encode(data));
+        file.write_all(line.as_bytes()).map_err(platform_error)?;
+        // A stale or missing sidecar only costs a full read on the next append.
+        if let Ok(metadata) = file.metadata() {
+            let _ = fs::write(
+                self.count_path(),
+                format!("{} {}\n", sequence + 1, metadata.len()),
+            );
+        }
+        Ok(())
+    }
+
+    /// Decrypts and returns every entry, oldest first.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        let Some((fields, lines)) = self.load()? else {
+            return Ok(Vec::new());
+        };
+        let cipher = self.cipher(&fields)?;
+        lines
+            .iter()
+            .enumerate()
+            .map(|(sequence, line)| {
+                let data = BASE64
+                    .decode(line)
+                    .map_err(|_| corrupt("invalid journal entry encoding"))?;
+                if data.len() < NONCE_LEN {
+                    return Err(corrupt("truncated journal entry"));
+                }
+                let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+                let plaintext = cipher
+                    .decrypt(
+                        XNonce::from_slice(nonce),
+                        Payload {
+                            msg: ciphertext,
+                            aad: sequence.to_string().as_bytes(),
+                        },
+                    )
+                    .map_err(|_| {
+                        keyring::Error::NoStorageAccess(
+                            "failed to decrypt journal entry (wrong passphrase or tampered journal)"
+                                .into(),
+                        )
+                    })?;
+                serde_json::from_slice(&plaintext).map_err(platform_error)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::Vault;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join("key_vaulter_tests").join(format!(
+            "{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_vault_operations_are_journaled() {
+        let journal = Journal::new(temp_path("journal.log")).with_passphrase("audit");
+        let vault = Vault::ephemeral_for_tests().with_journal(journal.clone());
+        let mut key = vault.key("token");
+        key.store("secret").unwrap();
+        key.read_key().unwrap();
+        key.delete().unwrap();
+        assert!(key.read_key().is_err());
+
+        let entries = vault.journal().unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.operation, e.key.as_str(), e.error))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Operation::Store, "token", None),
+                (Operation::Read, "token", None),
+                (Operation::Delete, "token", None),
+                (Operation::Read, "token", Some(ErrorKind::NotFound)),
+            ]
+        );
+        let content = fs::read_to_string(journal.path()).unwrap();
+        assert!(!content.contains("token"));
+    }
+
+    #[test]
+    fn test_reordered_entries_are_rejected() {
+        let journal = Journal::new(temp_path("journal-tamper.log"));
+        for key in ["a", "b"] {
+            journal
+                .append(&JournalEntry::new(
+                    Operation::Store,
+                    "s",
+                    key,
+                    "keyring",
+                    None,
+                ))
+                .unwrap();
+        }
+        let content = fs::read_to_string(journal.path()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        fs::write(
+            journal.path(),
+            format!("{}\n{}\n{}\n", lines[0], lines[2], lines[1]),
+        )
+        .unwrap();
+        assert!(journal.entries().is_err());
+    }
+
+    #[test]
+    fn test_header_without_kdf_params_is_rejected() {
+        let journal = Journal::new(temp_path("journal-kdf.log")).with_passphrase("audit");
+        journal
+            .append(&JournalEntry::new(
+                Operation::Store,
+                "s",
+                "a",
+                "keyring",
+                None,
+            ))
+            .unwrap();
+        let content = fs::read_to_string(journal.path()).unwrap();
+        let (header, rest) = content.split_once('\n').unwrap();
+        let salt_only = header.rsplit_once(' ').unwrap().0;
+        fs::write(journal.path(), format!("{}\n{}", salt_only, rest)).unwrap();
+        let error = journal.entries().unwrap_err();
+        assert!(error.to_string().contains("key derivation parameters"));
+    }
+
+    #[test]
+    fn test_append_recounts_without_a_matching_sidecar() {
+        let journal = Journal::new(temp_path("journal-count.log"));
+        let _ = fs::remove_file(journal.count_path());
+        let append = |key: &str| {
+            journal
+                .append(&JournalEntry::new(
+                    Operation::Store,
+                    "s",
+                    key,
+                    "keyring",
+                    None,
+                ))
+                .unwrap()
+        };
+        append("a");
+        append("b");
+        assert!(fs::read_to_string(journal.count_path())
+            .unwrap()
+            .starts_with("2 "));
+        fs::write(journal.count_path(), "7 0\n").unwrap();
+        append("c");
+        let keys: Vec<_> = journal
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+    }
+}
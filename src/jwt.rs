@@ -0,0 +1,283 @@
+use crate::backend::Backend;
+use crate::error::{Cause, Error, Operation, Result};
+use crate::key_manager::{DeleteOutcome, KeyManager, StoreOutcome};
+use crate::timestamp::now_unix;
+use crate::vault::Vault;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// Fetches a new token when the stored one has expired; it receives the expired token.
+type Refresh = Arc<
+    dyn Fn(&str) -> std::result::Result<String, Box<dyn std::error::Error + Send + Sync>>
+        + Send
+        + Sync,
+>;
+
+/// Keeps a JSON Web Token and checks its `exp` claim on every read.
+///
+/// An expired token fails with `ErrorKind::Expired`, unless a refresh callback is set with
+/// `with_refresh`, in which case the new token it returns is stored and returned instead.
+/// Tokens without `exp` never expire.
+///
+/// The signature is not checked: the claims are only decoded, which is enough to know
+/// when to refresh a token the app received from its issuer. With the `jwt-verify`
+/// feature, `with_verification` checks it on every read and store.
+pub struct JwtManager {
+    key_manager: KeyManager,
+    leeway: u64,
+    refresh: Option<Refresh>,
+    #[cfg(feature = "jwt-verify")]
+    verification: Option<(jsonwebtoken::DecodingKey, jsonwebtoken::Validation)>,
+}
+
+impl fmt::Debug for JwtManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtManager")
+            .field("key_manager", &self.key_manager)
+            .field("leeway", &self.leeway)
+            .field("refresh", &self.refresh.is_some())
+            .finish()
+    }
+}
+
+impl JwtManager {
+    /// Creates a manager for the token stored under `key_name`.
+    pub fn new(system_name: &str, key_name: &str) -> Self {
+        JwtManager::from_key_manager(KeyManager::new(system_name, key_name))
+    }
+
+    pub(crate) fn from_key_manager(key_manager: KeyManager) -> Self {
+        JwtManager {
+            key_manager,
+            leeway: 0,
+            refresh: None,
+            #[cfg(feature = "jwt-verify")]
+            verification: None,
+        }
+    }
+
+    /// Uses the given backend instead of the default OS keyring.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.key_manager = self.key_manager.with_backend(backend);
+        self
+    }
+
+    /// Treats tokens as expired `seconds` before their `exp`, so a token doesn't expire
+    /// between the read and the request using it.
+    pub fn with_leeway(mut self, seconds: u64) -> Self {
+        self.leeway = seconds;
+        self
+    }
+
+    /// Calls `refresh` with the expired token when `read_token` finds it expired, and
+    /// stores the token it returns. Its errors fail the read with `ErrorKind::Unavailable`.
+    pub fn with_refresh<F>(mut self, refresh: F) -> Self
+    where
+        F: Fn(&str) -> std::result::Result<String, Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.refresh = Some(Arc::new(refresh));
+        self
+    }
+
+    /// Checks the token's signature and claims with `key` on every read and store
+    /// (feature `jwt-verify`). Expiry is still handled by this manager, so `validation`'s
+    /// `exp` check is turned off; tokens that fail verification are `ErrorKind::Corrupt`
+    /// on read and `ErrorKind::InvalidInput` on store.
+    #[cfg(feature = "jwt-verify")]
+    pub fn with_verification(
+        mut self,
+        key: jsonwebtoken::DecodingKey,
+        mut validation: jsonwebtoken::Validation,
+    ) -> Self {
+        validation.validate_exp = false;
+        validation.required_spec_claims.remove("exp");
+        self.verification = Some((key, validation));
+        self
+    }
+
+    /// Returns the stored token, refreshed first if it has expired and a refresh callback
+    /// is set.
+    pub fn read_token(&mut self) -> Result<String> {
+        let token = self.key_manager.read_key()?;
+        let claims = self.claims_of(&token, Operation::Read)?;
+        if !self.is_expired(&claims) {
+            return Ok(token);
+        }
+        let Some(refresh) = self.refresh.clone() else {
+            return Err(self.error(
+                Operation::Read,
+                Cause::Expired(format!(
+                    "the token expired at {}",
+                    expiry(&claims).unwrap_or(0)
+                )),
+            ));
+        };
+        let token = refresh(&token).map_err(|e| self.error(Operation::Read, Cause::Platform(e)))?;
+        self.store_token(&token)?;
+        Ok(token)
+    }
+
+    /// Stores `token` after checking that it is a JWT with readable claims.
+    pub fn store_token(&mut self, token: &str) -> Result<StoreOutcome> {
+        self.claims_of(token, Operation::Store)?;
+        self.key_manager.store(token)
+    }
+
+    /// The claims of the stored token, decoded without checking its expiry (nor its
+    /// signature, unless `with_verification` is set).
+    pub fn claims(&mut self) -> Result<Value> {
+        let token = self.key_manager.read_key()?;
+        self.claims_of(&token, Operation::Read)
+    }
+
+    /// The `exp` claim of the stored token, in seconds since the Unix epoch.
+    pub fn expires_at(&mut self) -> Result<Option<u64>> {
+        Ok(expiry(&self.claims()?))
+    }
+
+    /// Deletes the stored token.
+    pub fn delete_token(&mut self) -> Result<DeleteOutcome> {
+        self.key_manager.delete()
+    }
+
+    fn is_expired(&self, claims: &Value) -> bool {
+        expiry(claims).is_some_and(|exp| now_unix().saturating_add(self.leeway) >= exp)
+    }
+
+    /// Decodes the payload of `token`; a value that isn't a JWT is `ErrorKind::Corrupt` on
+    /// read and `ErrorKind::InvalidInput` on store.
+    fn claims_of(&self, token: &str, operation: Operation) -> Result<Value> {
+        let invalid = |reason: String| match operation {
+            Operation::Read => self.error(operation, Cause::Corrupt(reason)),
+            _ => self.error(operation, Cause::InvalidInput(reason)),
+        };
+        #[cfg(feature = "jwt-verify")]
+        if let Some((key, validation)) = &self.verification {
+            return jsonwebtoken::decode::<Value>(token, key, validation)
+                .map(|data| data.claims)
+                .map_err(|e| invalid(format!("the token failed verification: {}", e)));
+        }
+        decode_claims(token).map_err(invalid)
+    }
+
+    fn error(&self, operation: Operation, cause: Cause) -> Error {
+        self.key_manager.error(operation, cause)
+    }
+}
+
+/// Decodes the payload of `token` without checking its signature.
+fn decode_claims(token: &str) -> std::result::Result<Value, String> {
+    let mut parts = token.trim().split('.');
+    let payload = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(payload), Some(_), None) => payload,
+        _ => return Err("not a JWT".to_string()),
+    };
+    let payload = BASE64_URL
+        .decode(payload)
+        .map_err(|e| format!("bad JWT payload: {}", e))?;
+    match serde_json::from_slice(&payload) {
+        Ok(claims @ Value::Object(_)) => Ok(claims),
+        Ok(_) => Err("JWT claims are not an object".to_string()),
+        Err(e) => Err(format!("bad JWT claims: {}", e)),
+    }
+}
+
+/// The `exp` claim of `value` if it is a JWT, for `Vault::gc`.
+pub(crate) fn token_expiry(value: &str) -> Option<u64> {
+    decode_claims(value).ok().as_ref().and_then(expiry)
+}
+
+/// The `exp` claim, accepting the fractional seconds some issuers write.
+fn expiry(claims: &Value) -> Option<u64> {
+    claims
+        .get("exp")
+        .and_then(Value::as_f64)
+        .map(|exp| exp as u64)
+}
+
+impl Vault {
+    /// Returns a manager for a JSON Web Token stored under `key_name` in this vault.
+    pub fn jwt(&self, key_name: &str) -> JwtManager {
+        JwtManager::from_key_manager(self.key(key_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    fn token(exp: u64) -> String {
+        let claims = BASE64_URL.encode(format!(r#"{{"sub":"ana","exp":{}}}"#, exp));
+        format!("eyJhbGciOiJIUzI1NiJ9.{}.c2lnbmF0dXJl", claims)
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let vault = Vault::ephemeral_for_tests();
+        let mut manager = vault.jwt("access_token");
+        assert_eq!(
+            manager.store_token("not-a-jwt").unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+
+        let valid = token(now_unix() + 3600);
+        manager.store_token(&valid).unwrap();
+        assert_eq!(manager.read_token().unwrap(), valid);
+        assert_eq!(manager.claims().unwrap()["sub"], "ana");
+        let mut early = vault.jwt("access_token").with_leeway(7200);
+        assert_eq!(early.read_token().unwrap_err().kind(), ErrorKind::Expired);
+
+        manager.store_token(&token(now_unix() - 1)).unwrap();
+        assert_eq!(manager.read_token().unwrap_err().kind(), ErrorKind::Expired);
+    }
+
+    #[test]
+    fn test_refresh_expired_token() {
+        let vault = Vault::ephemeral_for_tests();
+        let expired = token(now_unix() - 60);
+        let fresh = token(now_unix() + 3600);
+        vault.jwt("access_token").store_token(&expired).unwrap();
+
+        let expected = expired.clone();
+        let new_token = fresh.clone();
+        let mut manager = vault.jwt("access_token").with_refresh(move |old| {
+            assert_eq!(old, expected);
+            Ok(new_token.clone())
+        });
+        assert_eq!(manager.read_token().unwrap(), fresh);
+        assert_eq!(vault.key("access_token").read_key().unwrap(), fresh);
+    }
+
+    #[cfg(feature = "jwt-verify")]
+    #[test]
+    fn test_verified_token() {
+        use jsonwebtoken::{encode, DecodingKey, EncodingKey, Header, Validation};
+
+        let claims = serde_json::json!({"sub": "ana", "exp": now_unix() + 3600});
+        let signed = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"issuer secret"),
+        )
+        .unwrap();
+        let vault = Vault::ephemeral_for_tests();
+        let verified = |secret: &[u8]| {
+            vault
+                .jwt("id_token")
+                .with_verification(DecodingKey::from_secret(secret), Validation::default())
+        };
+        verified(b"issuer secret").store_token(&signed).unwrap();
+        assert_eq!(verified(b"issuer secret").read_token().unwrap(), signed);
+        assert_eq!(
+            verified(b"other secret").read_token().unwrap_err().kind(),
+            ErrorKind::Corrupt
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use crate::error::{Cause, Operation, Result};
+use crate::struct_key_manager::StructKeyManager;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Metadata of a Kubernetes object; only the fields relevant to secrets are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectMeta {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+/// A Kubernetes `v1/Secret` manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KubernetesSecret {
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    #[serde(rename = "type", default = "opaque")]
+    pub secret_type: String,
+    /// Base64-encoded values.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub data: BTreeMap<String, String>,
+    /// Plain-text values; they take precedence over `data` like in the API server.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub string_data: BTreeMap<String, String>,
+}
+
+fn opaque() -> String {
+    "Opaque".to_string()
+}
+
+impl KubernetesSecret {
+    /// Creates an empty `Opaque` secret.
+    pub fn new(name: &str, namespace: Option<&str>) -> Self {
+        KubernetesSecret {
+            api_version: "v1".to_string(),
+            kind: "Secret".to_string(),
+            metadata: ObjectMeta {
+                name: name.to_string(),
+                namespace: namespace.map(str::to_string),
+            },
+            secret_type: opaque(),
+            data: BTreeMap::new(),
+            string_data: BTreeMap::new(),
+        }
+    }
+
+    /// Parses a manifest in YAML (JSON is valid YAML too).
+    pub fn from_yaml(manifest: &str) -> std::result::Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(manifest)
+    }
+
+    pub fn to_yaml(&self) -> std::result::Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    pub fn to_json(&self) -> std::result::Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Returns every value in plain text, merging `data` (decoded) and `stringData`.
+    pub fn decoded(&self) -> std::result::Result<BTreeMap<String, String>, String> {
+        let mut values = BTreeMap::new();
+        for (name, encoded) in &self.data {
+            let bytes = BASE64
+                .decode(encoded.trim())
+                .map_err(|e| format!("invalid base64 in data '{}': {}", name, e))?;
+            let text = String::from_utf8(bytes)
+                .map_err(|_| format!("data '{}' is not valid UTF-8", name))?;
+            values.insert(name.clone(), text);
+        }
+        values.extend(self.string_data.clone());
+        Ok(values)
+    }
+}
+
+/// Converts `raw` text into a JSON value of the same type as `template` (usually the
+/// matching field of `T::default()`), or returns `None` when it doesn't fit.
+///
+/// `null` templates (e.g. `Option` fields) accept JSON and fall back to a string.
+fn coerce_like(template: &Value, raw: &str) -> Option<Value> {
+    match template {
+        Value::String(_) => Some(Value::String(raw.to_string())),
+        Value::Bool(_) => match raw.trim().to_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        Value::Number(n) if n.is_f64() => raw
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        Value::Number(_) => {
+            let raw = raw.trim();
+            raw.parse::<i64>()
+                .map(Value::from)
+                .or_else(|_| raw.parse::<u64>().map(Value::from))
+                .ok()
+        }
+        Value::Null => {
+            Some(serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())))
+        }
+        Value::Array(_) | Value::Object(_) => serde_json::from_str(raw).ok(),
+    }
+}
+
+impl<T> StructKeyManager<T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Default,
+{
+    /// Reads the struct and exports its top-level fields as the base64 `data` of a Secret.
+    ///
+    /// String fields are stored as-is, other values as JSON text.
+    pub fn to_k8s_secret(
+        &mut self,
+        name: &str,
+        namespace: Option<&str>,
+    ) -> Result<KubernetesSecret> {
+        let value = self.read_key()?;
+        let value = serde_json::to_value(&value).map_err(|e| self.error(Operation::Export, e))?;
+        let Value::Object(fields) = value else {
+            return Err(self.error(
+                Operation::Export,
+                Cause::InvalidInput("only structs with named fields can be exported".to_string()),
+            ));
+        };
+        let mut secret = KubernetesSecret::new(name, namespace);
+        for (field, field_value) in fields {
+            let text = match field_value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            secret.data.insert(field, BASE64.encode(text));
+        }
+        Ok(secret)
+    }
+
+    /// Imports the values of a Secret manifest into this key and stores the result.
+    ///
+    /// Each entry fills the field of the same name, converted to the field's type as seen
+    /// in `T::default()`. Fields missing from the manifest keep their default value.
+    pub fn import_k8s_secret(&mut self, secret: &KubernetesSecret) -> Result<T> {
+        let values = secret
+            .decoded()
+            .map_err(|reason| self.error(Operation::Store, Cause::InvalidInput(reason)))?;
+        let mut value =
+            serde_json::to_value(T::default()).map_err(|e| self.error(Operation::Store, e))?;
+        if let Value::Object(fields) = &mut value {
+            for (field, text) in values {
+                let converted = match fields.get(&field) {
+                    Some(template) => coerce_like(template, &text).ok_or_else(|| {
+                        self.error(
+                            Operation::Store,
+                            Cause::InvalidInput(format!(
+                                "value of '{}' does not match the field type",
+                                field
+                            )),
+                        )
+                    })?,
+                    None => Value::String(text),
+                };
+                fields.insert(field, converted);
+            }
+        }
+        let value: T =
+            serde_json::from_value(value).map_err(|e| self.error(Operation::Store, e))?;
+        self.store(&value)?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MANIFEST: &str = r#"
+apiVersion: v1
+kind: Secret
+metadata:
+  name: db-credentials
+  namespace: prod
+type: Opaque
+data:
+  username: am9obg==
+  port: NTQzMg==
+stringData:
+  password: hunter2
+"#;
+
+    #[test]
+    fn test_parse_and_decode_manifest() {
+        let secret = KubernetesSecret::from_yaml(MANIFEST).unwrap();
+        assert_eq!(secret.metadata.name, "db-credentials");
+        assert_eq!(secret.metadata.namespace.as_deref(), Some("prod"));
+        let values = secret.decoded().unwrap();
+        assert_eq!(values["username"], "john");
+        assert_eq!(values["port"], "5432");
+        assert_eq!(values["password"], "hunter2");
+    }
+
+    #[test]
+    fn test_coerce_like() {
+        assert_eq!(coerce_like(&json!(""), "abc"), Some(json!("abc")));
+        assert_eq!(coerce_like(&json!(0), " 42 "), Some(json!(42)));
+        assert_eq!(coerce_like(&json!(0), "4.2"), None);
+        assert_eq!(coerce_like(&json!(0.0), "4.2"), Some(json!(4.2)));
+        assert_eq!(coerce_like(&json!(false), "TRUE"), Some(json!(true)));
+        assert_eq!(coerce_like(&json!(null), "7"), Some(json!(7)));
+        assert_eq!(coerce_like(&json!(null), "text"), Some(json!("text")));
+        assert_eq!(coerce_like(&json!([]), "[1,2]"), Some(json!([1, 2])));
+    }
+
+    #[test]
+    fn test_yaml_roundtrip() {
+        let mut secret = KubernetesSecret::new("token", None);
+        secret
+            .data
+            .insert("token".to_string(), BASE64.encode("abc"));
+        let yaml = secret.to_yaml().unwrap();
+        assert!(yaml.contains("apiVersion: v1"));
+        assert!(!yaml.contains("namespace"));
+        assert_eq!(KubernetesSecret::from_yaml(&yaml).unwrap(), secret);
+        let json = secret.to_json().unwrap();
+        assert_eq!(KubernetesSecret::from_yaml(&json).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_invalid_base64_is_rejected() {
+        let mut secret = KubernetesSecret::new("bad", None);
+        secret.data.insert("x".to_string(), "***".to_string());
+        assert!(secret.decoded().is_err());
+    }
+}
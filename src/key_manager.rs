@@ -1,12 +1,128 @@
-use keyring::{Entry, Result};
+use crate::ambiguity::{self, AmbiguityStrategy};
+use crate::approval::Approver;
+use crate::backend::{Backend, BackendInfo};
+use crate::draft::DraftStore;
+use crate::error::{Cause, Error, ErrorKind, Operation, Result};
+#[cfg(feature = "age")]
+use crate::escrow::Escrow;
+use crate::file_store::EncryptedFileStore;
+use crate::hooks::{self, ChangeEvent, ChangeKind, PromptEvent, PromptEventKind, ReadRequest};
+use crate::journal::{Journal, JournalEntry};
+use crate::macos_options::MacOsOptions;
+use crate::naming::{current_app_name, is_internal_key, sanitize_name, validate_name};
+use crate::policy::{Policies, StorePolicy};
+use crate::prompt::{Prompter, SharedPrompter, TerminalPrompter};
+use crate::rate_limit::RateLimiter;
+use crate::redact::REDACTED;
+use crate::registry::{KeyLock, KeySlot, PromptTurn};
+use crate::secret_service;
+use crate::timestamp::{format_rfc3339, now_unix};
+use crate::windows_options::WindowsOptions;
+use hkdf::Hkdf;
+use keyring::credential::Credential;
+use keyring::Entry;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 #[allow(unused_imports)]
 use std::env;
-use std::io::{self, Write};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Prefix hashed with the salt and the value by `digest_of`, so digests are specific to
+/// this use.
+const DIGEST_CONTEXT: &[u8] = b"key_vaulter value digest v1:";
+
+/// Prefix of the HKDF info of subkeys, followed by their context.
+const SUBKEY_INFO: &[u8] = b"key_vaulter subkey v1:";
 
 pub struct KeyManager {
     pub system_name: String,
     pub key_name: String,
     pub key_value: Option<String>,
+    backend: Backend,
+    journal: Option<Journal>,
+    policies: Policies,
+    confirm_reads: bool,
+    approvers: Vec<Arc<dyn Approver>>,
+    rate_limiter: Option<RateLimiter>,
+    prompter: SharedPrompter,
+    ambiguity: AmbiguityStrategy,
+    windows: Option<WindowsOptions>,
+    macos: Option<MacOsOptions>,
+    attributes: BTreeMap<String, String>,
+    description: Option<Description>,
+    /// Expected `pin_of` the stored value, set with `with_pin`.
+    pin: Option<String>,
+    /// Whether stores are recorded for `Vault::due_for_rotation`.
+    track_rotation: bool,
+    #[cfg(feature = "age")]
+    escrow: Option<Escrow>,
+    /// Shared with every manager of the same key, see `lock` and `cached_value`.
+    slot: Arc<KeySlot>,
+    /// The answer of a `request_key` that failed to store it, for `retry_store`.
+    draft: Option<Zeroizing<String>>,
+    /// Where drafts are also persisted, set with `with_draft_persistence`.
+    draft_store: Option<DraftStore>,
+}
+
+/// What `KeyManager::store` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOutcome {
+    /// The key didn't exist.
+    Created,
+    /// The key existed, or whether it did couldn't be checked, and its value was replaced.
+    ///
+    /// The outcome follows what this process last saw of the key (see `cached_value`), so
+    /// a key deleted meanwhile by another process is reported as updated; the backend is
+    /// only asked when no manager of the key read, stored or deleted it yet.
+    Updated,
+}
+
+/// What `KeyManager::delete` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteOutcome {
+    Deleted,
+    /// There was nothing to delete.
+    NotFound,
+}
+
+/// What `with_description` or `with_owner_description` attach to the entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Description {
+    Text(String),
+    /// "Stored by <app> via key_vaulter on <date>", dated when storing.
+    Owner,
+}
+
+/// Never prints the cached value, only whether one is present.
+impl fmt::Debug for KeyManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("KeyManager");
+        debug
+            .field("system_name", &self.system_name)
+            .field("key_name", &self.key_name)
+            .field("key_value", &self.key_value.as_ref().map(|_| REDACTED))
+            .field("backend", &self.backend)
+            .field("journal", &self.journal)
+            .field("policies", &self.policies)
+            .field("confirm_reads", &self.confirm_reads)
+            .field("approvers", &self.approvers.len())
+            .field("rate_limiter", &self.rate_limiter)
+            .field("ambiguity", &self.ambiguity)
+            .field("windows", &self.windows)
+            .field("macos", &self.macos)
+            .field("attributes", &self.attributes)
+            .field("description", &self.description)
+            .field("pin", &self.pin)
+            .field("track_rotation", &self.track_rotation)
+            .field("draft", &self.draft.as_ref().map(|_| REDACTED))
+            .field("draft_store", &self.draft_store);
+        #[cfg(feature = "age")]
+        debug.field("escrow", &self.escrow);
+        debug.finish()
+    }
 }
 
 impl KeyManager {
@@ -16,78 +132,1005 @@ impl KeyManager {
             system_name: system_name.to_string(),
             key_name: key_name.to_string(),
             key_value: None,
+            backend: Backend::default(),
+            journal: None,
+            policies: Policies::default(),
+            confirm_reads: false,
+            approvers: Vec::new(),
+            rate_limiter: None,
+            prompter: SharedPrompter(Arc::new(TerminalPrompter::new())),
+            ambiguity: AmbiguityStrategy::default(),
+            windows: None,
+            macos: None,
+            attributes: BTreeMap::new(),
+            description: None,
+            pin: None,
+            track_rotation: false,
+            #[cfg(feature = "age")]
+            escrow: None,
+            slot: KeySlot::acquire(&Backend::default(), system_name, key_name),
+            draft: None,
+            draft_store: None,
         }
     }
 
+    /// Creates a new instance of KeyManager after checking that both names are portable.
+    ///
+    /// Returns an error of kind `ErrorKind::InvalidName` for empty, overly long or
+    /// otherwise unusable names (see `naming::validate_name`).
+    pub fn try_new(system_name: &str, key_name: &str) -> Result<Self> {
+        let manager = KeyManager::new(system_name, key_name);
+        for name in [system_name, key_name] {
+            validate_name(name)
+                .map_err(|reason| manager.error(Operation::Validate, Cause::InvalidName(reason)))?;
+        }
+        Ok(manager)
+    }
+
+    /// Creates a new instance of KeyManager with both names passed through
+    /// `naming::sanitize_name`, so the entry has the same portable name on every OS.
+    pub fn new_sanitized(system_name: &str, key_name: &str) -> Self {
+        KeyManager::new(&sanitize_name(system_name), &sanitize_name(key_name))
+    }
+
+    /// Creates a KeyManager whose system name is derived from the running executable (see
+    /// `naming::current_app_name`), so modules don't have to repeat the same string.
+    pub fn for_current_app(key_name: &str) -> Self {
+        KeyManager::new(&current_app_name(), key_name)
+    }
+
+    /// Uses the given backend instead of the default OS keyring.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.slot = KeySlot::acquire(&backend, &self.system_name, &self.key_name);
+        self.backend = backend;
+        self
+    }
+
+    /// Records every read, store and delete in `journal`.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Escrows every value stored for organization recovery (see `escrow::Escrow`). A
+    /// value that can't be escrowed isn't stored (feature `age`).
+    #[cfg(feature = "age")]
+    pub fn with_escrow(mut self, escrow: Escrow) -> Self {
+        self.escrow = Some(escrow);
+        self
+    }
+
+    /// Checks every value against `policy` before storing it. Policies are checked in the
+    /// order they were added; the first violation fails `store`.
+    pub fn with_policy<P>(mut self, policy: P) -> Self
+    where
+        P: StorePolicy + 'static,
+    {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Requires an explicit approval before every `read_key`, for keys such as code-signing
+    /// material that must never be read silently. The approval comes from the hook set with
+    /// `hooks::set_read_approver`, or else from a `[y/N]` question on the terminal; a denied
+    /// read fails with `ErrorKind::AccessDenied`.
+    pub fn with_read_confirmation(mut self) -> Self {
+        self.confirm_reads = true;
+        self
+    }
+
+    /// Requires `approver` to approve every `read_key`, in addition to the other approvers
+    /// and `with_read_confirmation`, e.g. a `approval::PassphraseApprover` asking two
+    /// people for their passphrases. A denied read fails with `ErrorKind::AccessDenied`.
+    pub fn with_approver<A>(mut self, approver: A) -> Self
+    where
+        A: Approver + 'static,
+    {
+        self.approvers.push(Arc::new(approver));
+        self
+    }
+
+    /// Pins the key to the value whose `pin_of` is `pin`, kept by the app in its own
+    /// configuration. `read_key` then fails with `ErrorKind::UnexpectedChange` when the
+    /// stored value differs, e.g. because another program replaced it, so the app can
+    /// re-verify the credential before using it. `store` moves the pin to the new
+    /// value; save `current_pin` afterwards.
+    pub fn with_pin(mut self, pin: &str) -> Self {
+        self.pin = Some(pin.to_string());
+        self
+    }
+
+    /// The pin of `value`: its SHA-256 as lowercase hex. It reveals nothing useful about
+    /// a random secret, but don't pin short, guessable values.
+    pub fn pin_of(value: &str) -> String {
+        Sha256::digest(value.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// The pin of the stored value, to save in the app's configuration once the value has
+    /// been verified.
+    pub fn current_pin(&mut self) -> Result<String> {
+        let pin = self.pin.take();
+        let result = self.read_key();
+        self.pin = pin;
+        result.map(|value| KeyManager::pin_of(&value))
+    }
+
+    /// A salted SHA-256 of `value` as lowercase hex: what `value_digest` returns when
+    /// `value` is stored, e.g. to compute an expected fingerprint.
+    pub fn digest_of(salt: &[u8], value: &str) -> String {
+        Sha256::new()
+            .chain_update(DIGEST_CONTEXT)
+            .chain_update((salt.len() as u64).to_be_bytes())
+            .chain_update(salt)
+            .chain_update(value.as_bytes())
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// The salted digest of the stored value (see `digest_of`), so monitoring code can
+    /// tell that a credential changed, or matches an expected fingerprint, without handling
+    /// the value. On the `Agent` backend the agent computes it and the value never reaches
+    /// this process; elsewhere the value is hashed and zeroized right away. Counts as a
+    /// read: read confirmation, approvers and the rate limiter apply and it is recorded in
+    /// the journal, but pins aren't checked, as the value may not be seen.
+    pub fn value_digest(&self, salt: &[u8]) -> Result<String> {
+        let result = self.check_read_allowed().and_then(|()| {
+            self.backend
+                .digest(&self.system_name, &self.key_name, salt)
+                .map_err(|e| self.error(Operation::Read, e))
+        });
+        self.record(Operation::Read, &result);
+        result
+    }
+
+    /// Asks for the confirmation and approvals a read needs and counts it against the rate
+    /// limiter; fails with `ErrorKind::AccessDenied` or `RateLimited`.
+    fn check_read_allowed(&self) -> Result<()> {
+        if self.confirm_reads && !self.approve_read() {
+            return Err(self.error(
+                Operation::Read,
+                Cause::AccessDenied("read was not confirmed".to_string()),
+            ));
+        }
+        if !self.approvers.is_empty() && !self.approve_by_approvers() {
+            return Err(self.error(
+                Operation::Read,
+                Cause::AccessDenied("read was not approved by every approver".to_string()),
+            ));
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            if let Err(reason) = limiter.check(&self.system_name, &self.key_name) {
+                return Err(self.error(Operation::Read, Cause::RateLimited(reason)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails with `ErrorKind::UnexpectedChange` when a pin is set and `value` doesn't
+    /// match it.
+    fn check_pin(&self, value: &str) -> Result<()> {
+        match &self.pin {
+            Some(pin) if *pin != KeyManager::pin_of(value) => Err(self.error(
+                Operation::Read,
+                Cause::UnexpectedChange("the value differs from its pin".to_string()),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts every `read_key` against `limiter`; reads over the limit fail with
+    /// `ErrorKind::RateLimited`.
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Asks for values through `prompter` instead of the terminal.
+    pub fn with_prompter<P>(mut self, prompter: P) -> Self
+    where
+        P: Prompter + 'static,
+    {
+        self.prompter = SharedPrompter(Arc::new(prompter));
+        self
+    }
+
+    /// Sets how to handle several platform credentials matching this key (see
+    /// `AmbiguityStrategy`). By default such operations fail with `ErrorKind::Ambiguous`.
+    pub fn with_ambiguity_strategy(mut self, strategy: AmbiguityStrategy) -> Self {
+        self.ambiguity = strategy;
+        self
+    }
+
+    /// Applies `options` to the Windows credential after every successful store, e.g. a
+    /// description shown in Credential Manager or local-only persistence. Ignored on other
+    /// platforms and by backends other than `Backend::Keyring`.
+    pub fn with_windows_options(mut self, options: WindowsOptions) -> Self {
+        self.windows = Some(options);
+        self
+    }
+
+    /// Applies `options` to the macOS keychain item after every successful store, e.g. a
+    /// label shown in Keychain Access or the applications allowed to read it. Ignored on
+    /// other platforms and by backends other than `Backend::Keyring`.
+    pub fn with_macos_options(mut self, options: MacOsOptions) -> Self {
+        self.macos = Some(options);
+        self
+    }
+
+    /// Attaches the attribute `name` with `value` to the Secret Service item after every
+    /// successful store, so it can be found with `Vault::find_by_attribute`. Ignored on
+    /// other platforms and by backends other than `Backend::Keyring`. Storing fails with
+    /// `ErrorKind::InvalidInput` for the attributes keyring identifies items with
+    /// (`target`, `service` and `username`).
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.attributes.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Attaches `description` to the keyring entry after every successful store, so users
+    /// inspecting their keychain know what the entry is: the comment of Windows
+    /// credentials and macOS keychain items (unless their options set one), and the label
+    /// of Secret Service items. Ignored by backends other than `Backend::Keyring`.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(Description::Text(description.to_string()));
+        self
+    }
+
+    /// Same as `with_description`, with `"Stored by <app> via key_vaulter on <date>"`, the
+    /// app being `naming::current_app_name` and the date that of the store.
+    pub fn with_owner_description(mut self) -> Self {
+        self.description = Some(Description::Owner);
+        self
+    }
+
+    /// The text of the description, if any.
+    fn description(&self) -> Option<String> {
+        match self.description.as_ref()? {
+            Description::Text(text) => Some(text.clone()),
+            Description::Owner => Some(format!(
+                "Stored by {} via key_vaulter on {}",
+                current_app_name(),
+                &format_rfc3339(now_unix())[..10]
+            )),
+        }
+    }
+
+    pub(crate) fn with_shared_prompter(mut self, prompter: SharedPrompter) -> Self {
+        self.prompter = prompter;
+        self
+    }
+
+    /// Shows `message` through the prompter and returns the trimmed answer.
+    pub(crate) fn prompt(&self, message: &str) -> Result<String> {
+        self.prompt_with_history(message, &[])
+    }
+
+    /// Shows `message` through the prompter without expecting an answer.
+    pub(crate) fn notify_user(&self, message: &str) {
+        self.prompter.0.notify(message);
+    }
+
+    /// Reports a step of a prompt for `field` (`None` for the key itself) to the prompt
+    /// hooks.
+    pub(crate) fn notify_prompt(
+        &self,
+        kind: PromptEventKind,
+        field: Option<&str>,
+        attempt: u32,
+        reason: Option<&str>,
+    ) {
+        let event = PromptEvent::new(kind, &self.system_name, &self.key_name, field, attempt);
+        hooks::notify_prompt(match reason {
+            Some(reason) => event.with_reason(reason),
+            None => event,
+        });
+    }
+
+    /// Records every store for `Vault::due_for_rotation` (see `Vault::with_rotation_policy`).
+    pub(crate) fn with_rotation_tracking(mut self) -> Self {
+        self.track_rotation = true;
+        self
+    }
+
+    pub(crate) fn prompter(&self) -> &SharedPrompter {
+        &self.prompter
+    }
+
+    /// Same as `prompt`, passing the earlier answers to the prompter.
+    pub(crate) fn prompt_with_history(&self, message: &str, history: &[String]) -> Result<String> {
+        self.prompter
+            .0
+            .prompt_with_history(message, history)
+            .map(|input| input.trim().to_string())
+            .map_err(|e| self.error(Operation::Prompt, e))
+    }
+
+    pub(crate) fn with_policies(mut self, policies: &Policies) -> Self {
+        self.policies.extend(policies);
+        self
+    }
+
+    /// A manager for another key of the same system on `backend`, sharing this manager's
+    /// journal, prompter and ambiguity strategy.
+    pub(crate) fn sibling(&self, key_name: &str, backend: Backend) -> KeyManager {
+        KeyManager {
+            journal: self.journal.clone(),
+            prompter: self.prompter.clone(),
+            ambiguity: self.ambiguity,
+            ..KeyManager::new(&self.system_name, key_name).with_backend(backend)
+        }
+    }
+
+    /// Returns the backend this manager reads from and writes to.
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    /// The slot of this key, taken again if `system_name` or `key_name` were changed.
+    fn slot(&self) -> Arc<KeySlot> {
+        if self
+            .slot
+            .is_for(&self.backend, &self.system_name, &self.key_name)
+        {
+            Arc::clone(&self.slot)
+        } else {
+            KeySlot::acquire(&self.backend, &self.system_name, &self.key_name)
+        }
+    }
+
+    /// The last value read or stored by any manager of this key in this process (same
+    /// backend, system and key name), `None` if none was or it was deleted since. Unlike
+    /// `key_value`, which only reflects this manager's own calls, it is never stale
+    /// because of another manager. Changes made by other processes are not seen. Cleared by
+    /// `lock_events::clear_caches`.
+    pub fn cached_value(&self) -> Option<String> {
+        self.slot().value()
+    }
+
+    /// How many turns to prompt for this key were taken in this process, see `prompt_turn`.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub(crate) fn prompt_rounds(&self) -> u64 {
+        self.slot().prompt_rounds()
+    }
+
+    /// Waits until no other thread prompts for this key or holds it (see `lock`), so the
+    /// same credential is never asked for twice in parallel. Take `prompt_rounds` before
+    /// reading the key: if the turn is `stored_since` then, another thread prompted
+    /// meanwhile and the key should be read again instead. Call `stored` on the turn once
+    /// the answer is stored.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub(crate) fn prompt_turn(&self) -> PromptTurn {
+        self.slot().prompt_turn()
+    }
+
+    /// Holds the key until the returned guard is dropped: stores and deletes of the key
+    /// from other threads, through any manager of this process, wait for it. Use it around
+    /// read-modify-write sequences so concurrent updates aren't lost. The holding thread
+    /// can still store, delete and lock again.
+    pub fn lock(&self) -> KeyLock {
+        self.slot().lock()
+    }
+
+    /// Probes the backend and reports its name, capabilities and health.
+    ///
+    /// Useful to warn the user before the first real operation fails.
+    pub fn backend_info(&self) -> BackendInfo {
+        self.backend.info()
+    }
+
+    /// Returns the `keyring::Entry` behind this key, for keyring features this crate
+    /// doesn't wrap (platform attributes, ambiguous credentials, ...).
+    ///
+    /// Only the `Keyring` backend has entries; other backends return an error of kind
+    /// `ErrorKind::InvalidInput`. Operations on the entry bypass journals, policies, hooks
+    /// and rate limits.
+    pub fn entry(&self) -> Result<Entry> {
+        match self.backend {
+            Backend::Keyring => Entry::new(&self.system_name, &self.key_name)
+                .map_err(|e| self.error(Operation::Read, e)),
+            _ => Err(self.error(
+                Operation::Read,
+                Cause::InvalidInput(format!(
+                    "backend '{}' has no keyring entry",
+                    self.backend.name()
+                )),
+            )),
+        }
+    }
+
+    /// Wraps a failure with this manager's system, key and backend.
+    pub(crate) fn error<C>(&self, operation: Operation, cause: C) -> Error
+    where
+        C: Into<Cause>,
+    {
+        Error::new(
+            &self.system_name,
+            &self.key_name,
+            operation,
+            self.backend.name(),
+            cause,
+        )
+    }
+
     /// Reads the value of a key from the keyring or environment variable (if feature `use_env_credentials` is enabled).
     ///
     /// Priority of key lookup:
     /// 1. **Environment Variable**: If the feature `use_env_credentials` is enabled, it will first try to read the key from the environment variables.
     /// 2. **Keyring**: If the key is not in the environment variables, it will then try to read it from the keyring.
+    ///
+    /// With `with_read_confirmation` or `with_approver`, the read must be approved first; with
+    /// `with_rate_limit`, it counts against the limit.
     pub fn read_key(&mut self) -> Result<String> {
+        if let Err(e) = self.check_read_allowed() {
+            let result: Result<String> = Err(e);
+            self.record(Operation::Read, &result);
+            return result;
+        }
+
         // Se a feature `use_env_credentials` estiver habilitada, tente ler da variável de ambiente
         #[cfg(feature = "use_env_credentials")]
         {
             // println!("Feature `use_env_credentials` is enabled.");
             if let Ok(env_value) = env::var(&self.key_name) {
+                hooks::debug(&format!(
+                    "'{}/{}' read from environment variable {}",
+                    self.system_name, self.key_name, self.key_name
+                ));
+                self.check_pin(&env_value)?;
                 self.key_value = Some(env_value.clone());
                 return Ok(env_value);
             }
+            hooks::debug(&format!(
+                "'{}/{}' not in environment variable {}, trying backend '{}'",
+                self.system_name,
+                self.key_name,
+                self.key_name,
+                self.backend.name()
+            ));
         }
 
         // Se não estiver na variável de ambiente, lê do keyring
-        let entry = Entry::new(&self.system_name, &self.key_name)?;
-        let password = entry.get_password()?;
+        let result = self.resolve(
+            Operation::Read,
+            self.backend.get(&self.system_name, &self.key_name),
+            |credential| credential.get_password(),
+        );
+        if matches!(&result, Err(e) if e.kind() == ErrorKind::NotFound) {
+            self.slot().set_value(None);
+        }
+        let result = result.and_then(|password| self.check_pin(&password).map(|()| password));
+        self.record(Operation::Read, &result);
+        let password = result?;
+        hooks::debug(&format!(
+            "'{}/{}' read from backend '{}'",
+            self.system_name,
+            self.key_name,
+            self.backend.name()
+        ));
+        self.slot().set_value(Some(&password));
         self.key_value = Some(password.clone());
         Ok(password)
     }
 
+    /// Reads the stored value straight from the backend, without approvers, confirmation,
+    /// rate limit, pin check or journal entry: for a value the caller already read through
+    /// `read_key`, e.g. to show as the current answer of a prompt.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub(crate) fn read_stored(&self) -> Result<String> {
+        self.resolve(
+            Operation::Read,
+            self.backend.get(&self.system_name, &self.key_name),
+            |credential| credential.get_password(),
+        )
+    }
+
+    /// Derives a 32-byte key for `context` (e.g. `"db-encryption"` or `"cookie-signing"`)
+    /// from the stored value with HKDF-SHA256, so an application storing one root secret
+    /// can derive all its internal keys from it. The same value and context always give
+    /// the same key, and different contexts give unrelated keys.
+    ///
+    /// The root is read like with `read_key`, confirmations and rate limits included.
+    pub fn derive_subkey(&mut self, context: &str) -> Result<Zeroizing<[u8; 32]>> {
+        let root = Zeroizing::new(self.read_key()?);
+        if root.is_empty() {
+            return Err(self.error(
+                Operation::Read,
+                Cause::InvalidInput("cannot derive subkeys from an empty value".to_string()),
+            ));
+        }
+        let info = [SUBKEY_INFO, context.as_bytes()].concat();
+        let mut subkey = Zeroizing::new([0u8; 32]);
+        Hkdf::<Sha256>::new(None, root.as_bytes())
+            .expand(&info, subkey.as_mut())
+            .map_err(|_| {
+                self.error(
+                    Operation::Read,
+                    Cause::Corrupt("key derivation failed".into()),
+                )
+            })?;
+        Ok(subkey)
+    }
+
     /// Reads the value of the key, and if it does not exist, prompts the user and saves the new key value in the keyring.
+    ///
+    /// Only a missing key is prompted for: other errors, e.g. a refused read confirmation
+    /// or a pin mismatch, are returned unchanged and the stored value is kept.
+    ///
+    /// When several threads call it for the same missing key, only one prompts; the others
+    /// wait for it and read what it stored, or prompt in turn if it stored nothing. The key
+    /// is held (see `lock`) while prompting.
+    #[cfg(feature = "interactive")]
     pub fn read_or_request_key(&mut self) -> Result<String> {
+        let rounds = self.prompt_rounds();
         match self.read_key() {
-            Ok(value) => Ok(value),
-            Err(_) => {
-                println!("The key was not found.");
-                self.request_key()
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let turn = self.prompt_turn();
+                if turn.stored_since(rounds) {
+                    return self.read_key();
+                }
+                hooks::debug(&format!(
+                    "'{}/{}' not read ({}), prompting",
+                    self.system_name, self.key_name, e
+                ));
+                self.notify_user("The key was not found.");
+                let result = self.request_key();
+                if result.is_ok() {
+                    turn.stored();
+                }
+                result
             }
+            result => result,
         }
     }
 
-    /// Prompts the user and saves the new key value in the keyring.
+    /// Same as `read_key`: without the `interactive` feature, nothing is prompted.
+    #[cfg(not(feature = "interactive"))]
+    pub fn read_or_request_key(&mut self) -> Result<String> {
+        self.read_key()
+    }
+
+    /// Prompts the user (see `with_prompter`) and saves the new key value in the keyring.
+    /// If storing fails, the answer is kept in memory and `retry_store` stores it without
+    /// asking again.
+    #[cfg(feature = "interactive")]
     pub fn request_key(&mut self) -> Result<String> {
-        println!("Please enter the value for key {}:", self.key_name);
-        let mut input = String::new();
-        io::stdout()
-            .flush()
-            .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
-        io::stdin()
-            .read_line(&mut input)
-            .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
-        let input = input.trim().to_string();
-        self.store_key(&input)?;
+        self.notify_prompt(PromptEventKind::Asked, None, 1, None);
+        let input = self.prompt(&format!(
+            "Please enter the value for key {}: ",
+            self.key_name
+        ))?;
+        self.keep_draft(&input);
+        if let Err(e) = self.store(&input) {
+            self.notify_prompt(PromptEventKind::Rejected, None, 1, Some(&e.to_string()));
+            return Err(e);
+        }
+        self.discard_draft();
+        self.notify_prompt(PromptEventKind::Accepted, None, 1, None);
         Ok(input)
     }
 
+    /// Also keeps the answers of `request_key` until they are stored, for at most `ttl`,
+    /// in an encrypted file private to the user (`$XDG_RUNTIME_DIR/key_vaulter/drafts.json`,
+    /// or `drafts.json` next to `EncryptedFileStore::default_path`), so a setup that
+    /// crashed before storing them can `retry_store` them on the next launch. Bound to the
+    /// machine like an `EncryptedFileStore` without passphrase.
+    pub fn with_draft_persistence(mut self, ttl: Duration) -> Self {
+        self.draft_store = Some(DraftStore::new(ttl));
+        self
+    }
+
+    /// Like `with_draft_persistence`, with the drafts kept in `store`. Its directory is
+    /// created with mode `0700` and must stay private to the user.
+    pub fn with_draft_store(mut self, store: EncryptedFileStore, ttl: Duration) -> Self {
+        self.draft_store = Some(DraftStore::in_store(store, ttl));
+        self
+    }
+
+    pub(crate) fn with_drafts(mut self, drafts: DraftStore) -> Self {
+        self.draft_store = Some(drafts);
+        self
+    }
+
+    /// Whether `request_key` kept an answer it didn't store, in memory or, with
+    /// `with_draft_persistence`, from an earlier run.
+    pub fn has_draft(&self) -> bool {
+        self.draft.is_some() || self.persisted_draft().is_some()
+    }
+
+    /// Stores the answer kept by a `request_key` that failed to store it, e.g. once the
+    /// keyring is unlocked. The answer is kept until it is stored; fails with
+    /// `ErrorKind::InvalidInput` if there is none.
+    pub fn retry_store(&mut self) -> Result<StoreOutcome> {
+        let Some(draft) = self.take_draft() else {
+            return Err(self.error(
+                Operation::Store,
+                Cause::InvalidInput("no answer to store".to_string()),
+            ));
+        };
+        let result = self.store(&draft);
+        match result {
+            Ok(_) => self.discard_draft(),
+            Err(_) => self.draft = Some(draft),
+        }
+        result
+    }
+
+    /// Forgets the answer kept by `request_key`, including its persisted copy.
+    pub fn discard_draft(&mut self) {
+        self.draft = None;
+        if let Some(store) = &self.draft_store {
+            store.remove(&self.system_name, &self.key_name);
+        }
+    }
+
+    /// Keeps `value` as the draft, persisted if enabled, until `discard_draft`.
+    pub(crate) fn keep_draft(&mut self, value: &str) {
+        if let Some(store) = &self.draft_store {
+            store.save(&self.system_name, &self.key_name, value);
+        }
+        self.draft = Some(Zeroizing::new(value.to_string()));
+    }
+
+    /// Takes the draft kept in memory, or else the persisted one.
+    pub(crate) fn take_draft(&mut self) -> Option<Zeroizing<String>> {
+        self.draft.take().or_else(|| self.persisted_draft())
+    }
+
+    fn persisted_draft(&self) -> Option<Zeroizing<String>> {
+        self.draft_store
+            .as_ref()
+            .and_then(|store| store.load(&self.system_name, &self.key_name))
+    }
+
+    /// Stores the key value in the keyring, and tells whether the key was created or
+    /// updated.
+    pub fn store(&mut self, value: &str) -> Result<StoreOutcome> {
+        self.store_as(value, ChangeKind::Stored)
+    }
+
+    /// `store`, reported to change hooks as `kind`.
+    pub(crate) fn store_as(&mut self, value: &str, kind: ChangeKind) -> Result<StoreOutcome> {
+        let _lock = self.lock();
+        let mut outcome = StoreOutcome::Updated;
+        let result = match self.policies.check(value) {
+            Ok(()) if self.has_reserved_attribute() => Err(self.error(
+                Operation::Store,
+                Cause::InvalidInput(format!(
+                    "attributes {} are reserved by keyring",
+                    secret_service::RESERVED_ATTRIBUTES.join(", ")
+                )),
+            )),
+            Ok(()) => {
+                let existed = self.slot().is_stored().unwrap_or_else(|| {
+                    !matches!(
+                        self.backend.get(&self.system_name, &self.key_name),
+                        Err(keyring::Error::NoEntry)
+                    )
+                });
+                if !existed {
+                    outcome = StoreOutcome::Created;
+                }
+                self.set_entry(value)
+            }
+            Err(violation) => Err(self.error(Operation::Store, violation)),
+        };
+        let result = result.and_then(|()| self.apply_platform_options());
+        self.record(Operation::Store, &result);
+        result?;
+        if self.pin.is_some() {
+            self.pin = Some(KeyManager::pin_of(value));
+        }
+        if self.track_rotation {
+            self.record_rotation();
+        }
+        self.slot().set_value(Some(value));
+        self.key_value = Some(value.to_string());
+        self.notify(kind);
+        Ok(outcome)
+    }
+
     /// Stores the key value in the keyring.
+    #[deprecated(since = "0.2.0", note = "use `store`, which returns a `StoreOutcome`")]
     pub fn store_key(&mut self, value: &str) -> Result<()> {
-        let entry = Entry::new(&self.system_name, &self.key_name)?;
-        entry.set_password(value)?;
-        self.key_value = Some(value.to_string());
-        Ok(())
+        self.store(value).map(drop)
     }
 
-    /// Deletes the key value from the keyring.
+    /// Deletes the key value from the keyring. Deleting a missing key is not an error.
+    pub fn delete(&mut self) -> Result<DeleteOutcome> {
+        match self.delete_entry() {
+            Ok(()) => Ok(DeleteOutcome::Deleted),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(DeleteOutcome::NotFound),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Deletes the key value from the keyring; fails with `ErrorKind::NotFound` if it is
+    /// missing.
+    #[deprecated(
+        since = "0.2.0",
+        note = "use `delete`, which returns a `DeleteOutcome`"
+    )]
     pub fn delete_key(&mut self) -> Result<()> {
-        let entry = Entry::new(&self.system_name, &self.key_name)?;
-        entry.delete_credential()?;
+        self.delete_entry()
+    }
+
+    fn delete_entry(&mut self) -> Result<()> {
+        let _lock = self.lock();
+        let result = self.resolve(
+            Operation::Delete,
+            self.backend.delete(&self.system_name, &self.key_name),
+            |credential| credential.delete_credential(),
+        );
+        self.record(Operation::Delete, &result);
+        result?;
+        self.slot().set_value(None);
         self.key_value = None;
+        self.notify(ChangeKind::Deleted);
+        Ok(())
+    }
+
+    /// Sets the entry to `value`, then escrows it (see `with_escrow`). When the deposit
+    /// fails, the previous value is put back, or the entry deleted, so no value is stored
+    /// without being escrowed; a deposit that failed after appending to the escrow file
+    /// leaves that record behind.
+    fn set_entry(&self, value: &str) -> Result<()> {
+        #[cfg(feature = "age")]
+        let previous = match &self.escrow {
+            Some(_) => Some(
+                match self.resolve(
+                    Operation::Store,
+                    self.backend.get(&self.system_name, &self.key_name),
+                    |credential| credential.get_password(),
+                ) {
+                    Ok(previous) => Some(Zeroizing::new(previous)),
+                    Err(e) if e.kind() == ErrorKind::NotFound => None,
+                    Err(e) => return Err(e),
+                },
+            ),
+            None => None,
+        };
+        self.resolve(
+            Operation::Store,
+            self.backend.set(&self.system_name, &self.key_name, value),
+            |credential| credential.set_password(value),
+        )?;
+        #[cfg(feature = "age")]
+        if let (Some(escrow), Some(previous)) = (&self.escrow, previous) {
+            if let Err(e) = escrow.deposit(self, value) {
+                let restored = match &previous {
+                    Some(previous) => self
+                        .backend
+                        .set(&self.system_name, &self.key_name, previous),
+                    None => match self.backend.delete(&self.system_name, &self.key_name) {
+                        Err(keyring::Error::NoEntry) => Ok(()),
+                        result => result,
+                    },
+                };
+                if let Err(restore_error) = restored {
+                    hooks::warn(&format!(
+                        "failed to restore key '{}' of system '{}' after a failed escrow: {}",
+                        self.key_name, self.system_name, restore_error
+                    ));
+                }
+                return Err(e);
+            }
+        }
         Ok(())
     }
+
+    fn has_reserved_attribute(&self) -> bool {
+        secret_service::RESERVED_ATTRIBUTES
+            .iter()
+            .any(|name| self.attributes.contains_key(*name))
+    }
+
+    /// Applies the platform-specific options to the credential that was just stored.
+    fn apply_platform_options(&self) -> Result<()> {
+        if !matches!(self.backend, Backend::Keyring) {
+            return Ok(());
+        }
+        let description = self.description();
+        let windows = match (&self.windows, &description) {
+            (Some(options), Some(text)) if options.comment().is_none() => {
+                Some(options.clone().with_comment(text))
+            }
+            (Some(options), _) => Some(options.clone()),
+            (None, Some(text)) => Some(WindowsOptions::new().with_comment(text)),
+            (None, None) => None,
+        };
+        if let Some(options) = windows.filter(|_| cfg!(windows)) {
+            options
+                .apply(&self.entry()?)
+                .map_err(|e| self.error(Operation::Store, e))?;
+        }
+        let macos = match (&self.macos, &description) {
+            (Some(options), Some(text)) if options.comment().is_none() => {
+                Some(options.clone().with_comment(text))
+            }
+            (Some(options), _) => Some(options.clone()),
+            (None, Some(text)) => Some(MacOsOptions::new().with_comment(text)),
+            (None, None) => None,
+        };
+        if let Some(options) = macos.filter(|_| cfg!(target_os = "macos")) {
+            options
+                .apply(&self.entry()?)
+                .map_err(|e| self.error(Operation::Store, e))?;
+        }
+        if let (true, Some(text)) = (secret_service::AVAILABLE, &description) {
+            let label = format!("{}/{} - {}", self.system_name, self.key_name, text);
+            secret_service::set_label(&self.system_name, &self.key_name, &label)
+                .map_err(|e| self.error(Operation::Store, e))?;
+        }
+        if secret_service::AVAILABLE && !self.attributes.is_empty() {
+            let attributes: HashMap<&str, &str> = self
+                .attributes
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            self.entry()?
+                .update_attributes(&attributes)
+                .map_err(|e| self.error(Operation::Store, e))?;
+        }
+        Ok(())
+    }
+
+    /// Wraps the result of a backend call, applying the ambiguity strategy: when several
+    /// credentials matched, `retry` runs on the chosen one.
+    fn resolve<R, F>(&self, operation: Operation, result: keyring::Result<R>, retry: F) -> Result<R>
+    where
+        F: FnOnce(&Credential) -> keyring::Result<R>,
+    {
+        let credentials = match result {
+            Err(keyring::Error::Ambiguous(credentials))
+                if self.ambiguity != AmbiguityStrategy::Fail && !credentials.is_empty() =>
+            {
+                credentials
+            }
+            result => return result.map_err(|e| self.error(operation, e)),
+        };
+        let index = match self.ambiguity {
+            AmbiguityStrategy::Prompt => self.choose_credential(&credentials)?,
+            _ => 0,
+        };
+        retry(credentials[index].as_ref()).map_err(|e| self.error(operation, e))
+    }
+
+    /// Asks through the prompter which of several matching credentials to use.
+    fn choose_credential(&self, credentials: &[Box<Credential>]) -> Result<usize> {
+        let message = format!(
+            "Several credentials match key '{}' of system '{}':\n{}Select one [1-{}]: ",
+            self.key_name,
+            self.system_name,
+            ambiguity::describe(credentials),
+            credentials.len()
+        );
+        loop {
+            let input = self.prompt(&message)?;
+            match ambiguity::parse_choice(&input, credentials.len()) {
+                Some(index) => return Ok(index),
+                None => self.notify_user(&format!("Invalid selection '{}'.", input)),
+            }
+        }
+    }
+
+    /// Appends the outcome of an operation to the journal, if one is attached. A failing
+    /// journal is reported through the warning hook and doesn't fail the operation.
+    fn record<T>(&self, operation: Operation, result: &Result<T>) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        let entry = JournalEntry::new(
+            operation,
+            &self.system_name,
+            &self.key_name,
+            self.backend.name(),
+            result.as_ref().err().map(Error::kind),
+        );
+        if let Err(e) = journal.append(&entry) {
+            hooks::warn(&format!("failed to write journal entry: {}", e));
+        }
+    }
+
+    /// A copy of this manager reading the same key through the same confirmation,
+    /// approvers, rate limiter, pin and journal, e.g. for the thread of `watch`.
+    pub(crate) fn reader(&self) -> KeyManager {
+        KeyManager {
+            system_name: self.system_name.clone(),
+            key_name: self.key_name.clone(),
+            key_value: None,
+            backend: self.backend.clone(),
+            journal: self.journal.clone(),
+            policies: self.policies.clone(),
+            confirm_reads: self.confirm_reads,
+            approvers: self.approvers.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            prompter: self.prompter.clone(),
+            ambiguity: self.ambiguity,
+            windows: self.windows.clone(),
+            macos: self.macos.clone(),
+            attributes: self.attributes.clone(),
+            description: self.description.clone(),
+            pin: self.pin.clone(),
+            track_rotation: self.track_rotation,
+            #[cfg(feature = "age")]
+            escrow: self.escrow.clone(),
+            slot: self.slot(),
+            draft: None,
+            draft_store: None,
+        }
+    }
+
+    fn approve_read(&self) -> bool {
+        hooks::approve_read(&self.read_request())
+    }
+
+    fn approve_by_approvers(&self) -> bool {
+        let request = self.read_request();
+        self.approvers
+            .iter()
+            .all(|approver| approver.approve(&request))
+    }
+
+    fn read_request(&self) -> ReadRequest {
+        ReadRequest {
+            system: self.system_name.clone(),
+            key: self.key_name.clone(),
+            backend: self.backend.name(),
+        }
+    }
+
+    fn notify(&self, kind: ChangeKind) {
+        if is_internal_key(&self.key_name) {
+            return;
+        }
+        hooks::notify_change(ChangeEvent::new(
+            kind,
+            &self.system_name,
+            &self.key_name,
+            self.backend.name(),
+        ));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ErrorKind;
+    use crate::vault::Vault;
+
+    #[test]
+    fn test_derive_subkey() {
+        let vault = Vault::ephemeral_for_tests();
+        let mut manager = vault.key("root");
+        manager.store("root-secret").unwrap();
+        let cookies = manager.derive_subkey("cookie-signing").unwrap();
+        assert_eq!(cookies, manager.derive_subkey("cookie-signing").unwrap());
+        assert_ne!(cookies, manager.derive_subkey("db-encryption").unwrap());
+
+        manager.store("").unwrap();
+        let error = manager.derive_subkey("cookie-signing").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_owner_description() {
+        let manager = KeyManager::new("key_manager_service", "described");
+        assert_eq!(manager.description(), None);
+        let description = manager.with_owner_description().description().unwrap();
+        assert!(description.starts_with(&format!(
+            "Stored by {} via key_vaulter on ",
+            current_app_name()
+        )));
+        assert_eq!(
+            description.rsplit(' ').next().unwrap().len(),
+            "2024-06-01".len()
+        );
+    }
 
     #[test]
     fn test_key_manager_new() {
@@ -95,49 +1138,260 @@ mod tests {
         let manager = KeyManager::new("key_manager_service", test_key_name);
         assert_eq!(manager.key_name, test_key_name);
         assert!(manager.key_value.is_none());
+        assert_eq!(manager.backend(), &Backend::Keyring);
+    }
+
+    #[test]
+    fn test_debug_hides_key_value() {
+        let mut manager = KeyManager::new("key_manager_service", "test_key1");
+        manager.key_value = Some("super-secret".to_string());
+        let debug = format!("{:?}", manager);
+        assert!(!debug.contains("super-secret"));
+        assert!(debug.contains("***"));
+    }
+
+    #[test]
+    fn test_try_new_validates_names() {
+        assert!(KeyManager::try_new("key_manager_service", "test_key1").is_ok());
+        let error = KeyManager::try_new("key_manager_service", "")
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidName);
+        assert_eq!(error.operation(), Operation::Validate);
+    }
+
+    #[test]
+    fn test_pinned_key() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("pinned").store("token-1").unwrap();
+        let pin = vault.key("pinned").current_pin().unwrap();
+        assert_eq!(pin, KeyManager::pin_of("token-1"));
+
+        let mut manager = vault.key("pinned").with_pin(&pin);
+        assert_eq!(manager.read_key().unwrap(), "token-1");
+        vault.key("pinned").store("token-2").unwrap();
+        let error = manager.read_key().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedChange);
+
+        manager.store("token-3").unwrap();
+        assert_eq!(manager.read_key().unwrap(), "token-3");
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_read_or_request_key_refuses_tampered_values() {
+        let prompter = crate::test_utils::ScriptedPrompter::new(["typed_value"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        vault.key("pinned").store("token-1").unwrap();
+        let mut manager = vault.key("pinned").with_pin(&KeyManager::pin_of("token-1"));
+        vault.key("pinned").store("tampered").unwrap();
+
+        let error = manager.read_or_request_key().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedChange);
+        assert!(prompter.prompts().is_empty());
+        assert_eq!(vault.key("pinned").read_key().unwrap(), "tampered");
+        // Still pinned to the original value.
+        let error = manager.read_key().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedChange);
+    }
+
+    #[test]
+    fn test_value_digest() {
+        let vault = Vault::ephemeral_for_tests();
+        let manager = vault.key("monitored");
+        assert_eq!(
+            manager.value_digest(b"salt").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        vault.key("monitored").store("token-1").unwrap();
+        let digest = manager.value_digest(b"salt").unwrap();
+        assert_eq!(digest, KeyManager::digest_of(b"salt", "token-1"));
+        assert_ne!(digest, manager.value_digest(b"pepper").unwrap());
+
+        vault.key("monitored").store("token-2").unwrap();
+        assert_ne!(manager.value_digest(b"salt").unwrap(), digest);
+
+        let gated = vault
+            .key("monitored")
+            .with_approver(|_: &crate::hooks::ReadRequest| false)
+            .value_digest(b"salt");
+        assert_eq!(gated.unwrap_err().kind(), ErrorKind::AccessDenied);
+        let limited = vault
+            .key("monitored")
+            .with_rate_limit(crate::rate_limit::RateLimiter::per_minute(1));
+        limited.value_digest(b"salt").unwrap();
+        assert_eq!(
+            limited.value_digest(b"salt").unwrap_err().kind(),
+            ErrorKind::RateLimited
+        );
+    }
+
+    #[test]
+    fn test_new_sanitized() {
+        let manager = KeyManager::new_sanitized("my service", "key");
+        assert!(KeyManager::try_new(&manager.system_name, &manager.key_name).is_ok());
+        assert_eq!(manager.key_name, "key");
+    }
+
+    #[test]
+    fn test_backend_info() {
+        let manager = KeyManager::new("key_manager_service", "test_key1");
+        let info = manager.backend_info();
+        assert_eq!(info.name, "keyring");
+        assert!(info.capabilities.persistent);
+    }
+
+    #[test]
+    fn test_entry() {
+        let manager = KeyManager::new("key_manager_service", "entry_key");
+        assert!(manager.entry().is_ok());
+        let error = Vault::ephemeral_for_tests()
+            .key("entry_key")
+            .entry()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_read_confirmation() {
+        let vault = Vault::ephemeral_for_tests();
+        /// Removes the process-wide approver even if an assertion fails.
+        struct ClearApprover;
+        impl Drop for ClearApprover {
+            fn drop(&mut self) {
+                hooks::clear_read_approver();
+            }
+        }
+
+        hooks::set_read_approver(|request: &ReadRequest| request.key != "denied_key");
+        let _clear = ClearApprover;
+        for (key, approved) in [("signing_key", true), ("denied_key", false)] {
+            let mut manager = vault.key(key).with_read_confirmation();
+            manager.store("secret").unwrap();
+            match manager.read_key() {
+                Ok(value) => assert!(approved && value == "secret"),
+                Err(e) => assert!(!approved && e.kind() == ErrorKind::AccessDenied),
+            }
+        }
     }
 
     #[test]
     fn test_store_and_read_key() {
-        let mut manager = KeyManager::new("key_manager_service", "test_key2");
+        let mut manager = Vault::ephemeral_for_tests().key("test_key2");
         let test_value = "test_value";
         match manager.read_key() {
             Ok(_) => {
-                manager.delete_key().unwrap();
+                manager.delete().unwrap();
             }
             Err(e) => {
-                assert_eq!(
-                    format!("{:?}", e),
-                    "Error(PlatformFailure(Other(\"No such file or directory (os error 2)\")))"
-                );
+                assert_eq!(e.operation(), Operation::Read);
+                assert_eq!(e.key(), "test_key2");
+                assert_eq!(e.backend(), "memory");
             }
         }
-        manager.store_key(test_value).unwrap();
+        manager.store(test_value).unwrap();
         let read_value = manager.read_key().unwrap();
         assert_eq!(read_value, test_value);
     }
 
+    #[test]
+    fn test_attributes() {
+        let vault = Vault::ephemeral_for_tests();
+        let mut manager = vault.key("token").with_attribute("env", "prod");
+        manager.store("secret").unwrap();
+        let error = vault.find_by_attribute("env", "prod").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("does not support attribute search"));
+
+        let mut manager = manager.with_attribute("service", "other");
+        let error = manager.store("secret").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "interactive")]
     #[test]
     fn test_read_or_request_key() {
-        let mut manager = KeyManager::new("key_manager_service", "test_key3");
-        match manager.read_key() {
-            Ok(value) => {
-                assert_eq!(manager.read_or_request_key().unwrap(), value);
-            }
-            Err(_) => {
-                // Test input is not automated in this example.
-                // To test this function, you would need to simulate stdin input.
-            }
-        }
+        let prompter = crate::test_utils::ScriptedPrompter::new(["typed_value"]);
+        let mut manager = Vault::ephemeral_for_tests()
+            .key("test_key3")
+            .with_prompter(prompter.clone());
+        assert_eq!(manager.read_or_request_key().unwrap(), "typed_value");
+        // The stored value is read back without prompting again.
+        assert_eq!(manager.read_or_request_key().unwrap(), "typed_value");
+        assert_eq!(prompter.prompts().len(), 1);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_retry_store_keeps_the_answer() {
+        use crate::file_store::EncryptedFileStore;
+        // A file where the store's directory should be makes every store fail.
+        let blocker = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("retry-{}", std::process::id()));
+        std::fs::create_dir_all(blocker.parent().unwrap()).unwrap();
+        std::fs::write(&blocker, "").unwrap();
+        let store = EncryptedFileStore::new(blocker.join("vault.json"))
+            .with_machine_secret(crate::machine::test_secret_path());
+        let prompter = crate::test_utils::ScriptedPrompter::new(["typed_value"]);
+        let mut manager = Vault::ephemeral_for_tests()
+            .with_backend(Backend::EncryptedFile(store))
+            .key("token")
+            .with_prompter(prompter.clone());
+        assert!(manager.retry_store().is_err());
+        assert!(manager.request_key().is_err());
+        assert!(manager.has_draft());
+        assert!(manager.retry_store().is_err());
+        assert!(manager.has_draft());
+
+        std::fs::remove_file(&blocker).unwrap();
+        assert_eq!(manager.retry_store().unwrap(), StoreOutcome::Created);
+        assert!(!manager.has_draft());
+        assert_eq!(manager.read_key().unwrap(), "typed_value");
+        assert!(prompter.is_exhausted());
+        let _ = std::fs::remove_dir_all(&blocker);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_read_or_request_key_keeps_denied_values() {
+        let prompter = crate::test_utils::ScriptedPrompter::new(["replacement"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        vault.key("token").store("secret").unwrap();
+        let mut manager = vault.key("token").with_approver(|_: &ReadRequest| false);
+        let error = manager.read_or_request_key().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AccessDenied);
+        assert!(prompter.prompts().is_empty());
+        assert_eq!(vault.key("token").read_key().unwrap(), "secret");
     }
 
     #[test]
     fn test_delete_key() {
-        let mut manager = KeyManager::new("key_manager_service", "test_key4");
-        manager.store_key("test_value").unwrap();
-        manager.delete_key().unwrap();
+        let mut manager = Vault::ephemeral_for_tests().key("test_key4");
+        manager.store("test_value").unwrap();
+        manager.delete().unwrap();
         let result = manager.read_key();
-        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_store_and_delete_outcomes() {
+        let mut manager = Vault::ephemeral_for_tests().key("test_key5");
+        assert_eq!(manager.store("first").unwrap(), StoreOutcome::Created);
+        assert_eq!(manager.store("second").unwrap(), StoreOutcome::Updated);
+        assert_eq!(manager.delete().unwrap(), DeleteOutcome::Deleted);
+        assert_eq!(manager.delete().unwrap(), DeleteOutcome::NotFound);
+        assert_eq!(manager.store("again").unwrap(), StoreOutcome::Created);
+        manager.delete().unwrap();
+
+        manager.store_key("shim").unwrap();
+        manager.delete_key().unwrap();
+        assert_eq!(
+            manager.delete_key().unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
     }
 
     #[cfg(feature = "use_env_credentials")]
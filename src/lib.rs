@@ -1,2 +1,89 @@
+#[cfg(feature = "age")]
+pub mod age_file;
+#[cfg(feature = "agent")]
+pub mod agent;
+pub mod ambiguity;
+#[cfg(feature = "android")]
+pub mod android_store;
+pub mod approval;
+#[cfg(feature = "async")]
+pub mod async_vault;
+pub mod backend;
+pub mod background;
+pub mod batch;
+pub mod canonical;
+pub mod capability;
+#[cfg(feature = "clap")]
+pub mod cli;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod collection_key_manager;
+#[cfg(feature = "config-integration")]
+pub mod config_source;
+mod constraint;
+pub mod credentials;
+pub mod datetime;
+pub mod dotenv;
+mod draft;
+pub mod error;
+#[cfg(feature = "age")]
+pub mod escrow;
+pub mod file_store;
+pub mod gc;
+pub mod hooks;
+#[cfg(feature = "middleware")]
+pub mod http_auth;
+pub mod journal;
+pub mod jwt;
+#[cfg(feature = "k8s")]
+pub mod k8s;
 pub mod key_manager;
+pub mod lock_events;
+pub mod machine;
+pub mod macos_options;
+pub mod map_key_manager;
+pub mod memory_store;
+pub mod naming;
+pub mod number;
+pub mod offline;
+pub mod orphans;
+pub mod policy;
+pub mod prompt;
+#[cfg(feature = "qr")]
+pub mod qr;
+pub mod rate_limit;
+pub mod redact;
+#[cfg(feature = "redb")]
+pub mod redb_store;
+pub mod registry;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod rotation;
+#[cfg(feature = "schemars")]
+mod schema;
+pub mod scope;
+mod secret_service;
+pub mod session;
+pub mod snapshot;
+#[cfg(feature = "sops")]
+pub mod sops;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod store;
 pub mod struct_key_manager;
+pub mod sync;
+pub mod template;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+mod timestamp;
+pub mod transaction;
+pub mod vault;
+pub mod watch;
+#[cfg(feature = "wasm")]
+pub mod web_store;
+pub mod windows_options;
+#[cfg(feature = "interactive")]
+pub mod wizard;
+
+pub use error::{Error, ErrorKind, Operation, Result};
+pub use vault::Vault;
@@ -0,0 +1,230 @@
+//! Clearing secrets cached in memory when the machine locks or goes to sleep, so they
+//! don't linger in RAM while a laptop is unattended.
+//!
+//! The caches of this crate (the agent's values, `RemoteStore::with_cache_ttl`) register
+//! themselves here; apps add their own sessions with `add_lock_hook`. `clear_caches` runs
+//! everything. On Linux, `watch_lock_events` (feature `lock-events`) calls it on the
+//! logind and screensaver signals; on macOS and Windows, call it from the app's own
+//! handler of the OS notification (`NSWorkspace` sleep and screen lock notifications,
+//! `WM_WTSSESSION_CHANGE` or `WM_POWERBROADCAST`).
+
+use crate::hooks;
+use std::sync::{Arc, RwLock};
+
+/// Clears one cache; returns `false` once the cache is gone, to be unregistered.
+type Purger = Box<dyn Fn() -> bool + Send + Sync>;
+
+static CACHES: RwLock<Vec<Purger>> = RwLock::new(Vec::new());
+
+static LOCK_HOOKS: RwLock<Vec<Arc<dyn Fn() + Send + Sync>>> = RwLock::new(Vec::new());
+
+/// Registers a cache cleared by `clear_caches`. `purge` holds a weak reference to the
+/// cache and returns `false` when it has been dropped.
+pub(crate) fn register_cache<F>(purge: F)
+where
+    F: Fn() -> bool + Send + Sync + 'static,
+{
+    if let Ok(mut caches) = CACHES.write() {
+        caches.push(Box::new(purge));
+    }
+}
+
+/// Registers a hook run by `clear_caches`, e.g. to drop an app's `UnlockedVault` so the
+/// user has to unlock it again.
+pub fn add_lock_hook<F>(hook: F)
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    if let Ok(mut hooks) = LOCK_HOOKS.write() {
+        hooks.push(Arc::new(hook));
+    }
+}
+
+/// Removes every lock hook.
+pub fn clear_lock_hooks() {
+    if let Ok(mut hooks) = LOCK_HOOKS.write() {
+        hooks.clear();
+    }
+}
+
+/// Empties every cache of this crate and runs the lock hooks. Call it from your own
+/// integration with the OS, or let `watch_lock_events` do it.
+pub fn clear_caches() {
+    if let Ok(mut caches) = CACHES.write() {
+        purge(&mut caches);
+    }
+    // Clone the list so hooks may register other hooks without deadlocking.
+    let lock_hooks = match LOCK_HOOKS.read() {
+        Ok(lock_hooks) => lock_hooks.clone(),
+        Err(_) => return,
+    };
+    for hook in lock_hooks {
+        hook();
+    }
+    hooks::debug("in-memory caches cleared");
+}
+
+/// Clears every cache of `caches`, forgetting the dropped ones.
+fn purge(caches: &mut Vec<Purger>) {
+    caches.retain(|purge| purge());
+}
+
+/// Handle of the subscription started by `watch_lock_events`; dropping it unsubscribes.
+#[cfg(feature = "lock-events")]
+#[derive(Debug)]
+pub struct LockWatcher {
+    stop: Option<std::sync::mpsc::Sender<()>>,
+}
+
+#[cfg(feature = "lock-events")]
+impl LockWatcher {
+    /// Stops listening to the OS notifications.
+    pub fn stop(self) {}
+}
+
+#[cfg(feature = "lock-events")]
+impl Drop for LockWatcher {
+    fn drop(&mut self) {
+        self.stop.take();
+    }
+}
+
+/// Calls `clear_caches` whenever the machine is about to sleep or the session locks
+/// (feature `lock-events`). Linux only.
+///
+/// Listens on D-Bus to logind's `PrepareForSleep` and session `Lock` signals and to the
+/// desktop screensaver turning on. Every other platform, macOS and Windows included,
+/// fails with `io::ErrorKind::Unsupported`: call `clear_caches` from the app's own
+/// handler of the OS notification.
+#[cfg(feature = "lock-events")]
+pub fn watch_lock_events() -> std::io::Result<LockWatcher> {
+    platform::watch()
+}
+
+#[cfg(all(feature = "lock-events", target_os = "linux"))]
+mod platform {
+    use super::{clear_caches, LockWatcher};
+    use crate::hooks;
+    use dbus::blocking::Connection;
+    use dbus::message::MatchRule;
+    use std::io;
+    use std::sync::mpsc::{self, TryRecvError};
+    use std::thread;
+    use std::time::Duration;
+
+    fn subscribe(system: &Connection, session: Option<&Connection>) -> Result<(), dbus::Error> {
+        system.add_match(
+            MatchRule::new_signal("org.freedesktop.login1.Manager", "PrepareForSleep"),
+            |(sleeping,): (bool,), _, _| {
+                if sleeping {
+                    clear_caches();
+                }
+                true
+            },
+        )?;
+        system.add_match(
+            MatchRule::new_signal("org.freedesktop.login1.Session", "Lock"),
+            |(): (), _, _| {
+                clear_caches();
+                true
+            },
+        )?;
+        if let Some(session) = session {
+            for interface in ["org.freedesktop.ScreenSaver", "org.gnome.ScreenSaver"] {
+                session.add_match(
+                    MatchRule::new_signal(interface, "ActiveChanged"),
+                    |(active,): (bool,), _, _| {
+                        if active {
+                            clear_caches();
+                        }
+                        true
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn watch() -> io::Result<LockWatcher> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let (ready, started) = mpsc::channel::<io::Result<()>>();
+        thread::spawn(move || {
+            let connections = Connection::new_system().and_then(|system| {
+                // Headless machines have no session bus: sleep events still work.
+                let session = Connection::new_session().ok();
+                subscribe(&system, session.as_ref())?;
+                Ok((system, session))
+            });
+            let (system, session) = match connections {
+                Ok(connections) => {
+                    let _ = ready.send(Ok(()));
+                    connections
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(io::Error::other(e)));
+                    return;
+                }
+            };
+            while let Err(TryRecvError::Empty) = stopped.try_recv() {
+                let mut result = system.process(Duration::from_millis(250));
+                if let (Ok(_), Some(session)) = (&result, &session) {
+                    result = session.process(Duration::from_millis(250));
+                }
+                if let Err(e) = result {
+                    hooks::warn(&format!("stopped watching lock events: {}", e));
+                    return;
+                }
+            }
+        });
+        started
+            .recv()
+            .unwrap_or_else(|_| Err(io::Error::other("the lock event thread panicked")))?;
+        Ok(LockWatcher { stop: Some(stop) })
+    }
+}
+
+#[cfg(all(feature = "lock-events", not(target_os = "linux")))]
+mod platform {
+    use super::LockWatcher;
+    use std::io;
+
+    pub(super) fn watch() -> io::Result<LockWatcher> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "lock events are only watched on Linux; call clear_caches from the app",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Mutex, Weak};
+
+    // Works on a local list: clearing the global one would empty the caches of agents
+    // started by other tests.
+    #[test]
+    fn test_purge_forgets_dropped_caches() {
+        let cache = Arc::new(Mutex::new(vec!["secret"]));
+        let weak: Weak<Mutex<Vec<&str>>> = Arc::downgrade(&cache);
+        let purges = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&purges);
+        let mut caches: Vec<Purger> = vec![Box::new(move || match weak.upgrade() {
+            Some(cache) => {
+                cache.lock().unwrap().clear();
+                counter.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        })];
+
+        purge(&mut caches);
+        assert!(cache.lock().unwrap().is_empty());
+        drop(cache);
+        purge(&mut caches);
+        assert!(caches.is_empty());
+        purge(&mut caches);
+        assert_eq!(purges.load(Ordering::SeqCst), 1);
+    }
+}
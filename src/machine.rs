@@ -0,0 +1,213 @@
+//! Keys bound to the machine they were derived on.
+//!
+//! The secret mixes a stable identifier of the OS installation and the current user name
+//! with a random per-user secret kept in a file only that user can read, so a file
+//! encrypted with it can't be opened on another host by simply copying it, nor by other
+//! users of the machine, to whom the identifier and the user name are no secret. Anything
+//! running as the same user can still read the secret file.
+
+use crate::file_store::{current_user, EncryptedFileStore};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use zeroize::Zeroizing;
+
+/// Returns a stable identifier of this OS installation, or `None` when the platform doesn't
+/// expose one:
+///
+/// - Linux: `/etc/machine-id` (or the D-Bus copy in `/var/lib/dbus/machine-id`);
+/// - BSD: `/etc/hostid`;
+/// - macOS: the `IOPlatformUUID` reported by `ioreg`;
+/// - Windows: the `MachineGuid` of `HKLM\SOFTWARE\Microsoft\Cryptography`.
+///
+/// The identifier changes when the OS is reinstalled. It is looked up once per process.
+pub fn machine_id() -> Option<String> {
+    static MACHINE_ID: OnceLock<Option<String>> = OnceLock::new();
+    MACHINE_ID
+        .get_or_init(|| {
+            imp::machine_id()
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+        })
+        .clone()
+}
+
+/// Where the per-user secret is kept by default: `machine.secret` next to
+/// [`EncryptedFileStore::default_path`]. Stores can keep it elsewhere (see
+/// `EncryptedFileStore::with_machine_secret`).
+pub fn user_secret_path() -> PathBuf {
+    EncryptedFileStore::default_path().with_file_name("machine.secret")
+}
+
+/// Where the crate's tests keep the per-user secret of their machine-bound stores, rather
+/// than in the user's data directory.
+#[cfg(test)]
+pub(crate) fn test_secret_path() -> PathBuf {
+    std::env::temp_dir()
+        .join("key_vaulter_tests")
+        .join("machine.secret")
+}
+
+/// The random per-user secret at `path`, created with mode `0600` on first use and read
+/// once per process. Returns `None` when it can't be created or read, or (on Unix) when
+/// the file is readable by other users.
+fn user_secret(path: &Path) -> Option<Zeroizing<Vec<u8>>> {
+    static SECRETS: OnceLock<Mutex<HashMap<PathBuf, Zeroizing<Vec<u8>>>>> = OnceLock::new();
+    let secrets = SECRETS.get_or_init(Mutex::default);
+    if let Some(secret) = secrets.lock().unwrap_or_else(|e| e.into_inner()).get(path) {
+        return Some(secret.clone());
+    }
+    if !path.exists() {
+        let parent = path.parent()?;
+        fs::create_dir_all(parent).ok()?;
+        let mut secret = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(secret.as_mut());
+        // Written in full under a unique name, then linked into place, so a concurrent
+        // process either publishes its secret first or reads the complete one.
+        let tmp = path.with_file_name(format!("machine.secret.{}.tmp", std::process::id()));
+        let mut options = fs::OpenOptions::new();
+        options.create_new(true).write(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let written = options
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(secret.as_ref()))
+            .and_then(|()| fs::hard_link(&tmp, path));
+        let _ = fs::remove_file(&tmp);
+        if written.is_err() && !path.exists() {
+            return None;
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(path).ok()?.permissions().mode() & 0o077 != 0 {
+            crate::hooks::warn(&format!(
+                "ignoring {}: it is readable by other users",
+                path.display()
+            ));
+            return None;
+        }
+    }
+    let secret = Zeroizing::new(fs::read(path).ok()?);
+    if secret.len() < 32 {
+        return None;
+    }
+    Some(
+        secrets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(path.to_path_buf())
+            .or_insert(secret)
+            .clone(),
+    )
+}
+
+/// Derives a 32-byte key bound to this machine and the current user with HKDF-SHA256, from
+/// [`machine_id`], the per-user secret at [`user_secret_path`], `salt` (random and stored
+/// next to the data) and the context `info`. Returns `None` when the identifier or the
+/// secret is unavailable.
+///
+/// Deleting the secret file makes every key derived before unrecoverable.
+pub fn machine_key(salt: &[u8], info: &[u8]) -> Option<Zeroizing<[u8; 32]>> {
+    machine_key_with_secret(&user_secret_path(), salt, info)
+}
+
+/// Like [`machine_key`], with the per-user secret kept at `secret_path`.
+pub fn machine_key_with_secret(
+    secret_path: &Path,
+    salt: &[u8],
+    info: &[u8],
+) -> Option<Zeroizing<[u8; 32]>> {
+    let mut secret =
+        Zeroizing::new(format!("key_vaulter:{}:{}:", current_user(), machine_id()?).into_bytes());
+    secret.extend_from_slice(&user_secret(secret_path)?);
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(salt), &secret)
+        .expand(info, key.as_mut())
+        .ok()?;
+    Some(key)
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod imp {
+    pub(super) fn machine_id() -> Option<String> {
+        ["/etc/machine-id", "/var/lib/dbus/machine-id", "/etc/hostid"]
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::process::Command;
+
+    pub(super) fn machine_id() -> Option<String> {
+        let output = Command::new("ioreg")
+            .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+            .output()
+            .ok()?;
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find(|line| line.contains("\"IOPlatformUUID\""))
+            .and_then(|line| line.rsplit('"').nth(1).map(str::to_string))
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::process::Command;
+
+    pub(super) fn machine_id() -> Option<String> {
+        let output = Command::new("reg")
+            .args([
+                "query",
+                r"HKLM\SOFTWARE\Microsoft\Cryptography",
+                "/v",
+                "MachineGuid",
+            ])
+            .output()
+            .ok()?;
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find(|line| line.contains("MachineGuid"))
+            .and_then(|line| line.split_whitespace().last().map(str::to_string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machine_key_is_deterministic() {
+        if machine_id().is_none() {
+            return;
+        }
+        let path = test_secret_path();
+        let key = machine_key_with_secret(&path, b"salt", b"info").unwrap();
+        assert_eq!(
+            key,
+            machine_key_with_secret(&path, b"salt", b"info").unwrap()
+        );
+        assert_ne!(
+            key,
+            machine_key_with_secret(&path, b"other salt", b"info").unwrap()
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
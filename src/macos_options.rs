@@ -0,0 +1,278 @@
+use keyring::Entry;
+use std::path::PathBuf;
+
+/// Which applications may read a keychain item without a confirmation dialog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacOsAccess {
+    /// The running executable and the applications at these paths.
+    TrustedApplications(Vec<PathBuf>),
+    /// Every application, like "Allow all applications to access this item" in Keychain
+    /// Access.
+    AlwaysAllow,
+}
+
+/// macOS keychain options applied to every value a manager stores in the OS keyring (see
+/// `KeyManager::with_macos_options`). Ignored on other platforms and backends.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacOsOptions {
+    label: Option<String>,
+    comment: Option<String>,
+    description: Option<String>,
+    access: Option<MacOsAccess>,
+}
+
+impl MacOsOptions {
+    pub fn new() -> Self {
+        MacOsOptions::default()
+    }
+
+    /// Sets the name of the item in Keychain Access, which defaults to the system name.
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// Sets the comment shown in the item's details.
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Sets the "Kind" column, `application password` by default.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Replaces the item's access control list.
+    pub fn with_access(mut self, access: MacOsAccess) -> Self {
+        self.access = Some(access);
+        self
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn access(&self) -> Option<&MacOsAccess> {
+        self.access.as_ref()
+    }
+
+    /// Updates the keychain item behind `entry`, which must have just been stored.
+    #[cfg(target_os = "macos")]
+    pub(crate) fn apply(&self, entry: &Entry) -> keyring::Result<()> {
+        match entry
+            .get_credential()
+            .downcast_ref::<keyring::macos::MacCredential>()
+        {
+            Some(credential) => imp::update(&credential.service, &credential.account, self),
+            None => Ok(()),
+        }
+    }
+
+    /// Updates the keychain item behind `entry`, which must have just been stored.
+    #[cfg(not(target_os = "macos"))]
+    pub(crate) fn apply(&self, _entry: &Entry) -> keyring::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use super::{MacOsAccess, MacOsOptions};
+    use core_foundation::array::{CFArray, CFArrayRef};
+    use core_foundation::base::{CFType, CFTypeRef, OSStatus, TCFType};
+    use core_foundation::dictionary::CFDictionary;
+    use core_foundation::string::{CFString, CFStringRef};
+    use security_framework_sys::item::{
+        kSecAttrAccount, kSecAttrComment, kSecAttrDescription, kSecAttrLabel, kSecAttrService,
+        kSecClass, kSecClassGenericPassword,
+    };
+    use security_framework_sys::keychain_item::SecItemUpdate;
+    use std::ffi::{c_void, CString};
+    use std::os::raw::c_char;
+    use std::os::unix::ffi::OsStrExt;
+    use std::ptr;
+
+    type OpaqueRef = *mut c_void;
+
+    // Legacy keychain access control, not covered by `security-framework-sys`.
+    #[link(name = "Security", kind = "framework")]
+    extern "C" {
+        static kSecAttrAccess: CFStringRef;
+        static kSecACLAuthorizationDecrypt: CFStringRef;
+        fn SecTrustedApplicationCreateFromPath(
+            path: *const c_char,
+            app: *mut OpaqueRef,
+        ) -> OSStatus;
+        fn SecAccessCreate(
+            descriptor: CFStringRef,
+            trusted_list: CFArrayRef,
+            access: *mut OpaqueRef,
+        ) -> OSStatus;
+        fn SecAccessCopyMatchingACLList(access: OpaqueRef, tag: CFTypeRef) -> CFArrayRef;
+        fn SecACLCopyContents(
+            acl: OpaqueRef,
+            applications: *mut CFArrayRef,
+            description: *mut CFStringRef,
+            prompt_selector: *mut u16,
+        ) -> OSStatus;
+        fn SecACLSetContents(
+            acl: OpaqueRef,
+            applications: CFArrayRef,
+            description: CFStringRef,
+            prompt_selector: u16,
+        ) -> OSStatus;
+    }
+
+    /// Updates the generic password item of `service` and `account` with `options`.
+    pub(super) fn update(
+        service: &str,
+        account: &str,
+        options: &MacOsOptions,
+    ) -> keyring::Result<()> {
+        // SAFETY: the `kSec*` constants are static CFStrings owned by the framework.
+        let key = |name: CFStringRef| unsafe { CFString::wrap_under_get_rule(name) };
+        let text = |value: &str| CFString::new(value).as_CFType();
+
+        let mut attributes = Vec::new();
+        unsafe {
+            if let Some(label) = &options.label {
+                attributes.push((key(kSecAttrLabel), text(label)));
+            }
+            if let Some(comment) = &options.comment {
+                attributes.push((key(kSecAttrComment), text(comment)));
+            }
+            if let Some(description) = &options.description {
+                attributes.push((key(kSecAttrDescription), text(description)));
+            }
+            if let Some(access) = &options.access {
+                let descriptor = options.label.as_deref().unwrap_or(service);
+                attributes.push((key(kSecAttrAccess), create_access(descriptor, access)?));
+            }
+        }
+        if attributes.is_empty() {
+            return Ok(());
+        }
+
+        let query = CFDictionary::from_CFType_pairs(&[
+            (
+                key(unsafe { kSecClass }),
+                key(unsafe { kSecClassGenericPassword }).as_CFType(),
+            ),
+            (key(unsafe { kSecAttrService }), text(service)),
+            (key(unsafe { kSecAttrAccount }), text(account)),
+        ]);
+        let attributes = CFDictionary::from_CFType_pairs(&attributes);
+        // SAFETY: both dictionaries are valid for the duration of the call.
+        check(unsafe {
+            SecItemUpdate(
+                query.as_concrete_TypeRef(),
+                attributes.as_concrete_TypeRef(),
+            )
+        })
+    }
+
+    /// Creates a `SecAccess` named `descriptor` granting `access`.
+    unsafe fn create_access(descriptor: &str, access: &MacOsAccess) -> keyring::Result<CFType> {
+        let descriptor = CFString::new(descriptor);
+        let mut created: OpaqueRef = ptr::null_mut();
+        match access {
+            MacOsAccess::TrustedApplications(paths) => {
+                let mut applications = vec![trusted_application(ptr::null())?];
+                for path in paths {
+                    let path = CString::new(path.as_os_str().as_bytes())
+                        .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
+                    applications.push(trusted_application(path.as_ptr())?);
+                }
+                let applications = CFArray::from_CFTypes(&applications);
+                check(SecAccessCreate(
+                    descriptor.as_concrete_TypeRef(),
+                    applications.as_concrete_TypeRef(),
+                    &mut created,
+                ))?;
+                Ok(CFType::wrap_under_create_rule(created as CFTypeRef))
+            }
+            MacOsAccess::AlwaysAllow => {
+                check(SecAccessCreate(
+                    descriptor.as_concrete_TypeRef(),
+                    ptr::null(),
+                    &mut created,
+                ))?;
+                let access = CFType::wrap_under_create_rule(created as CFTypeRef);
+                let acls: CFArray<CFType> = CFArray::wrap_under_create_rule(
+                    SecAccessCopyMatchingACLList(created, kSecACLAuthorizationDecrypt as CFTypeRef),
+                );
+                for acl in acls.get_all_values() {
+                    let acl = acl as OpaqueRef;
+                    let mut applications: CFArrayRef = ptr::null();
+                    let mut description: CFStringRef = ptr::null();
+                    let mut prompt_selector = 0u16;
+                    check(SecACLCopyContents(
+                        acl,
+                        &mut applications,
+                        &mut description,
+                        &mut prompt_selector,
+                    ))?;
+                    if !applications.is_null() {
+                        drop(CFArray::<CFType>::wrap_under_create_rule(applications));
+                    }
+                    let description = CFString::wrap_under_create_rule(description);
+                    // A null application list lets any application decrypt the item.
+                    check(SecACLSetContents(
+                        acl,
+                        ptr::null(),
+                        description.as_concrete_TypeRef(),
+                        0,
+                    ))?;
+                }
+                Ok(access)
+            }
+        }
+    }
+
+    /// Wraps the application at `path`, or the running executable when `path` is null.
+    unsafe fn trusted_application(path: *const c_char) -> keyring::Result<CFType> {
+        let mut application: OpaqueRef = ptr::null_mut();
+        check(SecTrustedApplicationCreateFromPath(path, &mut application))?;
+        Ok(CFType::wrap_under_create_rule(application as CFTypeRef))
+    }
+
+    fn check(status: OSStatus) -> keyring::Result<()> {
+        match status {
+            0 => Ok(()),
+            status => Err(keyring::Error::PlatformFailure(Box::new(
+                std::io::Error::other(format!("keychain error (OSStatus {})", status)),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::Vault;
+
+    #[test]
+    fn test_options_and_other_backends() {
+        let options = MacOsOptions::new()
+            .with_label("Billing API token")
+            .with_access(MacOsAccess::AlwaysAllow);
+        assert_eq!(options.label(), Some("Billing API token"));
+        assert_eq!(options.comment(), None);
+        assert_eq!(options.access(), Some(&MacOsAccess::AlwaysAllow));
+
+        let vault = Vault::ephemeral_for_tests().with_macos_options(options);
+        let mut manager = vault.key("token");
+        manager.store("secret").unwrap();
+        assert_eq!(manager.read_key().unwrap(), "secret");
+    }
+}
@@ -9,18 +9,99 @@ struct MyStruct {
     age: u32,
 }
 
+/// `key_vaulter agent [--socket PATH] [--file PATH] [--acl PATH] [--idle-minutes N]
+/// [--json]`: serves secrets from memory to other processes of the same user, so they
+/// don't each trigger a keychain unlock prompt. `--acl` limits each client executable to
+/// the keys listed in a JSON file (see `AgentAcl`); clients are only identified on Linux,
+/// Android and Windows, so elsewhere (macOS) it refuses every request. `--idle-minutes`
+/// forgets the values after N minutes without requests. With `--json`, status and errors
+/// are printed to stdout as one JSON object per line, e.g.
+/// `{"status":"listening","socket":"..."}` or `{"error":"..."}`. On Windows, `--socket`
+/// takes a pipe name like `\\.\pipe\my-agent`.
+#[cfg(feature = "agent")]
+fn run_agent(args: &[String], json: bool) -> Result<(), String> {
+    use key_vaulter::agent::{Agent, AgentAcl};
+    use key_vaulter::backend::Backend;
+    use key_vaulter::file_store::EncryptedFileStore;
+
+    let mut socket = Agent::default_socket_path();
+    let mut backend = None;
+    let mut acl = None;
+    let mut idle = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--socket" => socket = value()?.into(),
+            "--file" => backend = Some(Backend::EncryptedFile(EncryptedFileStore::new(value()?))),
+            "--acl" => acl = Some(AgentAcl::load(value()?).map_err(|e| e.to_string())?),
+            "--idle-minutes" => {
+                let minutes: u64 = value()?
+                    .parse()
+                    .map_err(|_| "--idle-minutes expects a whole number".to_string())?;
+                let secs = minutes
+                    .checked_mul(60)
+                    .ok_or_else(|| format!("--idle-minutes {} is too large", minutes))?;
+                idle = Some(std::time::Duration::from_secs(secs));
+            }
+            "--json" => {}
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+    let backend = backend
+        .or_else(Backend::auto)
+        .ok_or("neither the keyring nor the encrypted file is available; pass --file")?;
+    let mut agent = Agent::bind(&socket, backend).map_err(|e| e.to_string())?;
+    if let Some(acl) = acl {
+        agent = agent.with_acl(acl);
+    }
+    if let Some(idle) = idle {
+        agent = agent.with_idle_timeout(idle);
+    }
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({"status": "listening", "socket": agent.socket_path()})
+        );
+    } else {
+        println!(
+            "key_vaulter agent listening on {}",
+            agent.socket_path().display()
+        );
+    }
+    agent.serve().map_err(|e| e.to_string())
+}
+
 fn main() {
+    #[cfg(feature = "agent")]
+    {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if args.first().map(String::as_str) == Some("agent") {
+            let json = args.iter().any(|arg| arg == "--json");
+            if let Err(e) = run_agent(&args[1..], json) {
+                if json {
+                    println!("{}", serde_json::json!({ "error": e }));
+                } else {
+                    eprintln!("key_vaulter agent: {}", e);
+                }
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
     let mut manager: StructKeyManager<MyStruct> =
         StructKeyManager::new("my_system", "user_profile");
 
-    match manager.read_key() {
-        Ok(_) => {
-            manager.delete_key().unwrap();
-        }
-        Err(_) => {}
+    if manager.read_key().is_ok() {
+        manager.delete().unwrap();
     }
 
-    match manager.read_or_request_key() {
+    match manager.read_or_request_key(false) {
         Ok(value) => {
             println!(
                 "Successfully retrieved the struct from keyring: {:?}",
@@ -32,5 +113,5 @@ fn main() {
         }
     }
 
-    manager.delete_key().unwrap();
+    manager.delete().unwrap();
 }
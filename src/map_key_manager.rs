@@ -0,0 +1,134 @@
+use crate::backend::Backend;
+use crate::error::{ErrorKind, Result};
+use crate::struct_key_manager::StructKeyManager;
+use crate::vault::Vault;
+use std::collections::BTreeMap;
+
+type SecretMap = BTreeMap<String, String>;
+
+/// A dynamic set of named secrets kept in a single entry, e.g. per-tenant API keys.
+///
+/// The map is stored as a JSON object and read back on every call, so changes made by
+/// other processes are seen. A missing entry behaves like an empty map, and removing the
+/// last secret deletes the entry. Updates from several threads hold the key (see
+/// `KeyManager::lock`), so none of them is lost.
+#[derive(Debug)]
+pub struct MapKeyManager {
+    inner: StructKeyManager<SecretMap>,
+}
+
+impl MapKeyManager {
+    /// Creates a manager for the map stored under `key_name`.
+    pub fn new(system_name: &str, key_name: &str) -> Self {
+        MapKeyManager {
+            inner: StructKeyManager::new(system_name, key_name),
+        }
+    }
+
+    /// Uses the given backend instead of the default OS keyring.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.inner = self.inner.with_backend(backend);
+        self
+    }
+
+    fn load(&mut self) -> Result<SecretMap> {
+        match self.inner.read_key() {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(SecretMap::new()),
+            result => result,
+        }
+    }
+
+    fn save(&mut self, map: &SecretMap) -> Result<()> {
+        if map.is_empty() {
+            self.inner.delete().map(drop)
+        } else {
+            self.inner.store(map).map(drop)
+        }
+    }
+
+    /// Returns the secret stored under `name`, if any.
+    pub fn get(&mut self, name: &str) -> Result<Option<String>> {
+        Ok(self.load()?.remove(name))
+    }
+
+    /// Stores `value` under `name`, replacing any previous value.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let _lock = self.inner.inner().lock();
+        let mut map = self.load()?;
+        map.insert(name.to_string(), value.to_string());
+        self.save(&map)
+    }
+
+    /// Removes `name` and returns its previous value.
+    pub fn remove(&mut self, name: &str) -> Result<Option<String>> {
+        let _lock = self.inner.inner().lock();
+        let mut map = self.load()?;
+        let previous = map.remove(name);
+        if previous.is_some() {
+            self.save(&map)?;
+        }
+        Ok(previous)
+    }
+
+    /// The names of the stored secrets, sorted.
+    pub fn keys(&mut self) -> Result<Vec<String>> {
+        Ok(self.load()?.into_keys().collect())
+    }
+}
+
+impl Vault {
+    /// Returns a manager for a map of secrets stored under `key_name` in this vault.
+    pub fn map(&self, key_name: &str) -> MapKeyManager {
+        MapKeyManager {
+            inner: self.structured(key_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_not_stored, assert_stored};
+
+    #[test]
+    fn test_map_operations() {
+        let vault = Vault::ephemeral_for_tests();
+        let mut tenants = vault.map("tenant_keys");
+        assert!(tenants.keys().unwrap().is_empty());
+        tenants.set("globex", "key-2").unwrap();
+        tenants.set("acme", "key-1").unwrap();
+        assert_eq!(tenants.get("acme").unwrap().as_deref(), Some("key-1"));
+        assert_eq!(tenants.get("initech").unwrap(), None);
+        assert_eq!(tenants.keys().unwrap(), vec!["acme", "globex"]);
+        assert_stored(
+            &vault,
+            "tenant_keys",
+            r#"{"acme":"key-1","globex":"key-2"}"#,
+        );
+
+        assert_eq!(tenants.remove("acme").unwrap().as_deref(), Some("key-1"));
+        assert_eq!(tenants.remove("acme").unwrap(), None);
+        tenants.remove("globex").unwrap();
+        assert_not_stored(&vault, "tenant_keys");
+    }
+
+    #[test]
+    fn test_concurrent_sets_are_not_lost() {
+        let vault = Vault::ephemeral_for_tests();
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let vault = vault.clone();
+                std::thread::spawn(move || {
+                    vault
+                        .map("tenant_keys")
+                        .set(&format!("tenant{}", i), "key")
+                        .unwrap()
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(vault.map("tenant_keys").keys().unwrap().len(), 8);
+    }
+}
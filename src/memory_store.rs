@@ -0,0 +1,104 @@
+use keyring::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+type Entries = BTreeMap<(String, String), Zeroizing<String>>;
+
+/// A process-local store keeping values in memory only.
+///
+/// Clones share the same entries; they are zeroized once the last clone is dropped.
+/// Meant for tests and for secrets that must never reach the disk.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("entries", &self.lock().len())
+            .finish()
+    }
+}
+
+/// Two stores are equal when they share the same entries.
+impl PartialEq for MemoryStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.entries, &other.entries)
+    }
+}
+
+impl Eq for MemoryStore {}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Tells stores apart the way `PartialEq` does: the same for clones sharing entries.
+    pub(crate) fn id(&self) -> usize {
+        Arc::as_ptr(&self.entries) as usize
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        self.lock()
+            .get(&(system_name.to_string(), key_name.to_string()))
+            .map(|value| value.to_string())
+            .ok_or(keyring::Error::NoEntry)
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        self.lock().insert(
+            (system_name.to_string(), key_name.to_string()),
+            Zeroizing::new(value.to_string()),
+        );
+        Ok(())
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        self.lock()
+            .remove(&(system_name.to_string(), key_name.to_string()))
+            .map(|_| ())
+            .ok_or(keyring::Error::NoEntry)
+    }
+
+    /// Lists the key names stored for `system_name`, sorted.
+    pub fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .lock()
+            .keys()
+            .filter(|(system, _)| system == system_name)
+            .map(|(_, key)| key.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_entries() {
+        let store = MemoryStore::new();
+        let clone = store.clone();
+        store.set("memory_service", "b", "2").unwrap();
+        store.set("memory_service", "a", "1").unwrap();
+        store.set("other_service", "c", "3").unwrap();
+        assert_eq!(clone.get("memory_service", "a").unwrap(), "1");
+        assert_eq!(clone.list("memory_service").unwrap(), vec!["a", "b"]);
+        clone.delete("memory_service", "a").unwrap();
+        assert!(matches!(
+            store.get("memory_service", "a"),
+            Err(keyring::Error::NoEntry)
+        ));
+        assert_eq!(store, clone);
+        assert_ne!(store, MemoryStore::new());
+    }
+}
@@ -0,0 +1,157 @@
+use sha2::{Digest, Sha256};
+
+/// Longest system or key name accepted by [`validate_name`].
+///
+/// Windows limits the credential target name (which combines both names) to a few hundred
+/// characters in some APIs; staying under this keeps entries usable everywhere.
+pub const MAX_NAME_LEN: usize = 128;
+
+/// Characters kept unchanged by [`sanitize_name`]. They are accepted by every supported
+/// platform and survive being used as environment variable or file names.
+fn is_portable(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Checks that `name` can be used as a system or key name on every supported platform.
+///
+/// Returns a human-readable reason when the name is rejected.
+pub fn validate_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(format!(
+            "name must be at most {} characters long",
+            MAX_NAME_LEN
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("name must not contain control characters".to_string());
+    }
+    if name.trim() != name {
+        return Err("name must not start or end with whitespace".to_string());
+    }
+    Ok(())
+}
+
+/// Maps `name` to a portable name made only of ASCII letters, digits, `_`, `-` and `.`.
+///
+/// The mapping is deterministic. When characters had to be replaced (or the name was
+/// truncated), a short hash of the original name is appended so that different inputs
+/// don't collapse into the same entry.
+pub fn sanitize_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| if is_portable(c) { c } else { '_' })
+        .collect();
+    if sanitized == name && !name.is_empty() && name.len() <= MAX_NAME_LEN {
+        return sanitized;
+    }
+    let digest = Sha256::digest(name.as_bytes());
+    let suffix: String = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    sanitized.truncate(MAX_NAME_LEN - suffix.len() - 1);
+    format!("{}-{}", sanitized, suffix)
+}
+
+/// Separates a key name from the purpose of an entry the crate keeps next to it, as in
+/// `<key>#history`. Field paths are dotted and may not contain it, so these entries can't
+/// collide with the `<key>.<field>` entries of `StructKeyManager::with_field_backend`.
+pub const INTERNAL_SEPARATOR: char = '#';
+
+/// The entry the crate keeps next to `key_name` for `purpose` (see [`INTERNAL_SEPARATOR`]).
+pub(crate) fn internal_key(key_name: &str, purpose: &str) -> String {
+    format!("{}{}{}", key_name, INTERNAL_SEPARATOR, purpose)
+}
+
+/// Whether `key_name` is an entry the crate keeps for itself rather than a key of the app.
+pub fn is_internal_key(key_name: &str) -> bool {
+    key_name.contains(INTERNAL_SEPARATOR)
+}
+
+/// Whether `key_name` is an internal entry of the vault itself, kept next to no key (as
+/// `#sync-...`, the state of `Vault::sync`).
+pub(crate) fn is_vault_entry(key_name: &str) -> bool {
+    key_name.starts_with(INTERNAL_SEPARATOR)
+}
+
+/// The key an internal entry belongs to, and its purpose; `None` for other keys.
+pub(crate) fn split_internal_key(key_name: &str) -> Option<(&str, &str)> {
+    key_name.split_once(INTERNAL_SEPARATOR)
+}
+
+/// A stable system name for the running program: the file name of its executable, without
+/// extension or the hash suffix cargo adds to test binaries, passed through
+/// [`sanitize_name`].
+///
+/// Library crates that must share entries with every binary using them should pass
+/// `env!("CARGO_PKG_NAME")` explicitly instead.
+pub fn current_app_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| app_name_from_path(&path))
+        .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string())
+}
+
+fn app_name_from_path(path: &std::path::Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let name = match stem.rsplit_once('-') {
+        Some((name, hash))
+            if !name.is_empty()
+                && hash.len() == 16
+                && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            name
+        }
+        _ => stem,
+    };
+    Some(sanitize_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("my_service").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name(" padded").is_err());
+        assert!(validate_name("line\nbreak").is_err());
+        assert!(validate_name(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("plain-name_1.0"), "plain-name_1.0");
+        let sanitized = sanitize_name("my key/é");
+        assert!(sanitized.starts_with("my_key__-"));
+        assert_eq!(sanitized, sanitize_name("my key/é"));
+        assert_ne!(sanitize_name("a/b"), sanitize_name("a:b"));
+        assert!(validate_name(&sanitize_name("")).is_ok());
+        assert!(sanitize_name(&"é".repeat(300)).len() <= MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn test_internal_keys() {
+        assert_eq!(internal_key("smtp", "history"), "smtp#history");
+        assert!(is_internal_key("smtp#history"));
+        assert!(!is_internal_key("smtp.history"));
+        assert_eq!(
+            split_internal_key("smtp#history"),
+            Some(("smtp", "history"))
+        );
+        assert_eq!(split_internal_key("smtp.history"), None);
+    }
+
+    #[test]
+    fn test_app_name_from_path() {
+        let name = |path: &str| app_name_from_path(std::path::Path::new(path)).unwrap();
+        assert_eq!(name("/usr/bin/my-tool"), "my-tool");
+        assert_eq!(name("/opt/my-tool.exe"), "my-tool");
+        assert_eq!(
+            name("target/debug/deps/my_tool-0123456789abcdef"),
+            "my_tool"
+        );
+        assert_eq!(current_app_name(), "key_vaulter");
+    }
+}
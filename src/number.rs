@@ -0,0 +1,101 @@
+use serde_json::{Number, Value};
+
+/// How numbers typed at the prompt are written (see `StructKeyManager::with_number_locale`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NumberLocale {
+    /// Accepts both styles. When both separators appear, the last one is the decimal
+    /// separator; a separator appearing several times, or followed by exactly three digits
+    /// in an integer field, groups thousands.
+    #[default]
+    Auto,
+    /// `1,234.56`, as in English.
+    DecimalPoint,
+    /// `1.234,56`, as in Portuguese, German and most of Europe.
+    DecimalComma,
+}
+
+impl NumberLocale {
+    /// Returns the thousands and decimal separators used in `input`.
+    fn separators(&self, input: &str, integer: bool) -> (Option<char>, char) {
+        match self {
+            NumberLocale::DecimalPoint => (Some(','), '.'),
+            NumberLocale::DecimalComma => (Some('.'), ','),
+            NumberLocale::Auto => {
+                let Some(position) = input.rfind(['.', ',']) else {
+                    return (None, '.');
+                };
+                let last = input[position..].chars().next().unwrap_or('.');
+                let other = if last == '.' { ',' } else { '.' };
+                let digits_after = input.len() - position - 1;
+                if input.contains(other) {
+                    (Some(other), last)
+                } else if input.matches(last).count() > 1 || (integer && digits_after == 3) {
+                    (Some(last), other)
+                } else {
+                    (None, last)
+                }
+            }
+        }
+    }
+}
+
+/// Parses a number typed at the prompt. Spaces, `_` and `'` are accepted as thousands
+/// separators in every locale. Returns `None` when `input` isn't a number, or isn't an
+/// integer although `integer` is set.
+pub(crate) fn parse_number(input: &str, locale: NumberLocale, integer: bool) -> Option<Value> {
+    let cleaned: String = input
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '\'' | '\u{a0}'))
+        .collect();
+    let (group, decimal) = locale.separators(&cleaned, integer);
+    let decimal_at = cleaned.find(decimal).unwrap_or(cleaned.len());
+    let mut normalized = String::with_capacity(cleaned.len());
+    for (index, c) in cleaned.char_indices() {
+        if Some(c) == group {
+            if index > decimal_at {
+                return None;
+            }
+        } else if c == decimal {
+            normalized.push('.');
+        } else {
+            normalized.push(c);
+        }
+    }
+
+    if integer {
+        normalized
+            .parse::<i64>()
+            .map(Number::from)
+            .or_else(|_| normalized.parse::<u64>().map(Number::from))
+            .ok()
+            .map(Value::Number)
+    } else {
+        let number = normalized.parse::<f64>().ok()?;
+        Number::from_f64(number).map(Value::Number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_number_in_both_styles() {
+        let auto = NumberLocale::Auto;
+        assert_eq!(parse_number("1.234,56", auto, false), Some(json!(1234.56)));
+        assert_eq!(parse_number("1,234.56", auto, false), Some(json!(1234.56)));
+        assert_eq!(parse_number("0,5", auto, false), Some(json!(0.5)));
+        assert_eq!(parse_number("1 000 000", auto, true), Some(json!(1000000)));
+        assert_eq!(parse_number("1.234", auto, true), Some(json!(1234)));
+        assert_eq!(parse_number("1.5", auto, true), None);
+        assert_eq!(parse_number("-42", auto, true), Some(json!(-42)));
+        assert_eq!(parse_number("abc", auto, false), None);
+
+        let comma = NumberLocale::DecimalComma;
+        assert_eq!(parse_number("1.234", comma, false), Some(json!(1234.0)));
+        assert_eq!(parse_number("1,5", comma, false), Some(json!(1.5)));
+        assert_eq!(parse_number("1,5.0", comma, false), None);
+    }
+}
@@ -0,0 +1,455 @@
+//! Working with a remote backend while it's unreachable: reads are served from an
+//! encrypted local cache and writes are journaled in it, to be replayed on reconnect.
+
+use crate::backend::{Backend, Capabilities};
+use crate::error::{Operation, Result};
+use crate::file_store::{lock_file, platform_error, sibling, EncryptedFileStore};
+use crate::hooks;
+use crate::timestamp::now_unix;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::{Mutex, MutexGuard};
+
+/// Entry of the cache file holding the queue, outside any system an app would use.
+const QUEUE_SYSTEM: &str = "key_vaulter.offline";
+const QUEUE_KEY: &str = "queue";
+
+/// Serializes the changes to the queue within the process; the `.queue.lock` sibling of
+/// the cache file does it across processes.
+static QUEUE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Held while the queue is loaded, changed and saved, so concurrent writers and replays
+/// don't drop each other's writes.
+struct QueueLock {
+    _file: fs::File,
+    _guard: MutexGuard<'static, ()>,
+}
+
+/// Whether a queued write stores or deletes its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PendingKind {
+    Store,
+    Delete,
+}
+
+/// A write made while the primary backend was unreachable, waiting to be replayed.
+/// Never carries the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingWrite {
+    pub system: String,
+    pub key: String,
+    pub kind: PendingKind,
+    /// When the write was queued, in seconds since the Unix epoch.
+    pub queued_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QueuedWrite {
+    system: String,
+    key: String,
+    /// `None` for a delete.
+    value: Option<String>,
+    queued_at: u64,
+}
+
+impl QueuedWrite {
+    fn pending(&self) -> PendingWrite {
+        PendingWrite {
+            system: self.system.clone(),
+            key: self.key.clone(),
+            kind: match self.value {
+                Some(_) => PendingKind::Store,
+                None => PendingKind::Delete,
+            },
+            queued_at: self.queued_at,
+        }
+    }
+}
+
+/// A backend (typically `Backend::Remote`) with an encrypted local cache to fall back on
+/// when it can't be reached.
+///
+/// Every value read or written is also kept in `cache`. When the primary backend fails
+/// with `keyring::Error::PlatformFailure` (server down, no network), reads are served
+/// from the cache and writes are applied to it and appended to a queue stored, encrypted,
+/// in the same file. The queue is replayed in order before the next operation that
+/// reaches the primary again, or explicitly with `replay`; until it is empty, new writes
+/// are queued behind it so they land in the order they were made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OfflineStore {
+    primary: Box<Backend>,
+    cache: EncryptedFileStore,
+}
+
+impl OfflineStore {
+    pub fn new(primary: Backend, cache: EncryptedFileStore) -> Self {
+        OfflineStore {
+            primary: Box::new(primary),
+            cache,
+        }
+    }
+
+    pub fn primary(&self) -> &Backend {
+        &self.primary
+    }
+
+    pub fn cache(&self) -> &EncryptedFileStore {
+        &self.cache
+    }
+
+    /// The writes waiting for the primary backend, oldest first.
+    pub fn pending_writes(&self) -> keyring::Result<Vec<PendingWrite>> {
+        Ok(self.queue()?.iter().map(QueuedWrite::pending).collect())
+    }
+
+    /// Replays the queued writes on the primary backend and returns how many were
+    /// applied. Stops, keeping the rest queued, at the first write that fails: when the
+    /// backend is still unreachable this returns `Ok` with the count so far, any other
+    /// failure (e.g. access denied) is returned.
+    pub fn replay(&self) -> keyring::Result<usize> {
+        let _lock = self.lock_queue()?;
+        self.replay_locked()
+    }
+
+    /// `replay`, with the queue lock already held.
+    fn replay_locked(&self) -> keyring::Result<usize> {
+        let mut queue = self.queue()?;
+        let mut replayed = 0;
+        let mut failure = None;
+        for write in &queue {
+            let result = match &write.value {
+                Some(value) => self.primary.set(&write.system, &write.key, value),
+                None => match self.primary.delete(&write.system, &write.key) {
+                    Err(keyring::Error::NoEntry) => Ok(()),
+                    result => result,
+                },
+            };
+            match result {
+                Ok(()) => replayed += 1,
+                Err(e) if is_unreachable(&e) => break,
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+        if replayed > 0 {
+            queue.drain(..replayed);
+            self.save_queue(&queue)?;
+            hooks::debug(&format!(
+                "replayed {} queued write(s) on backend '{}'",
+                replayed,
+                self.primary.name()
+            ));
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(replayed),
+        }
+    }
+
+    pub(crate) fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            persistent: true,
+            ..self.primary.capabilities()
+        }
+    }
+
+    /// Healthy as long as the cache is usable, since the primary may be down.
+    pub(crate) fn health_check(&self) -> keyring::Result<()> {
+        self.cache.health_check()
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> keyring::Result<String> {
+        let queue = {
+            let _lock = self.lock_queue()?;
+            self.replay_pending()?
+        };
+        if queue
+            .iter()
+            .any(|write| write.system == system_name && write.key == key_name)
+        {
+            return self.cache.get(system_name, key_name);
+        }
+        match self.primary.get(system_name, key_name) {
+            Ok(value) => {
+                self.update_cache(system_name, key_name, Some(&value));
+                Ok(value)
+            }
+            Err(keyring::Error::NoEntry) => {
+                self.update_cache(system_name, key_name, None);
+                Err(keyring::Error::NoEntry)
+            }
+            Err(e) if is_unreachable(&e) => {
+                hooks::warn(&format!(
+                    "backend '{}' is unreachable ({}), reading '{}/{}' from the offline cache",
+                    self.primary.name(),
+                    e,
+                    system_name,
+                    key_name
+                ));
+                self.cache.get(system_name, key_name)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn set(
+        &self,
+        system_name: &str,
+        key_name: &str,
+        value: &str,
+    ) -> keyring::Result<()> {
+        self.write(system_name, key_name, Some(value))
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> keyring::Result<()> {
+        self.write(system_name, key_name, None)
+    }
+
+    pub(crate) fn list(&self, system_name: &str) -> keyring::Result<Vec<String>> {
+        match self.primary.list(system_name) {
+            Err(e) if is_unreachable(&e) => self.cache.list(system_name),
+            result => result,
+        }
+    }
+
+    /// Writes to the primary, or queues the write, holding the queue lock throughout so
+    /// a write can't overtake one queued by another thread or process.
+    fn write(&self, system_name: &str, key_name: &str, value: Option<&str>) -> keyring::Result<()> {
+        let _lock = self.lock_queue()?;
+        let queue = self.replay_pending()?;
+        if queue.is_empty() {
+            let result = match value {
+                Some(value) => self.primary.set(system_name, key_name, value),
+                None => self.primary.delete(system_name, key_name),
+            };
+            match result {
+                Err(e) if is_unreachable(&e) => hooks::warn(&format!(
+                    "backend '{}' is unreachable ({}), queueing the write of '{}/{}'",
+                    self.primary.name(),
+                    e,
+                    system_name,
+                    key_name
+                )),
+                Ok(()) => {
+                    self.update_cache(system_name, key_name, value);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.enqueue(queue, system_name, key_name, value)
+    }
+
+    /// Applies a write to the cache and appends it to the queue.
+    fn enqueue(
+        &self,
+        mut queue: Vec<QueuedWrite>,
+        system_name: &str,
+        key_name: &str,
+        value: Option<&str>,
+    ) -> keyring::Result<()> {
+        match value {
+            Some(value) => self.cache.set(system_name, key_name, value)?,
+            None => match self.cache.delete(system_name, key_name) {
+                Ok(()) | Err(keyring::Error::NoEntry) => {}
+                Err(e) => return Err(e),
+            },
+        }
+        queue.push(QueuedWrite {
+            system: system_name.to_string(),
+            key: key_name.to_string(),
+            value: value.map(str::to_string),
+            queued_at: now_unix(),
+        });
+        self.save_queue(&queue)
+    }
+
+    /// Replays the queue if it isn't empty, and returns what is still queued. Called with
+    /// the queue lock held.
+    fn replay_pending(&self) -> keyring::Result<Vec<QueuedWrite>> {
+        if self.queue()?.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Err(e) = self.replay_locked() {
+            hooks::warn(&format!("could not replay the queued writes: {}", e));
+        }
+        self.queue()
+    }
+
+    /// Keeps the cache in step with the primary; a failure only costs offline reads.
+    fn update_cache(&self, system_name: &str, key_name: &str, value: Option<&str>) {
+        let result = match value {
+            Some(value) => self.cache.set(system_name, key_name, value),
+            None => match self.cache.delete(system_name, key_name) {
+                Err(keyring::Error::NoEntry) => Ok(()),
+                result => result,
+            },
+        };
+        if let Err(e) = result {
+            hooks::warn(&format!("could not update the offline cache: {}", e));
+        }
+    }
+
+    fn lock_queue(&self) -> keyring::Result<QueueLock> {
+        let guard = QUEUE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(QueueLock {
+            _file: lock_file(&sibling(self.cache.path(), ".queue.lock"))?,
+            _guard: guard,
+        })
+    }
+
+    fn queue(&self) -> keyring::Result<Vec<QueuedWrite>> {
+        match self.cache.get(QUEUE_SYSTEM, QUEUE_KEY) {
+            Ok(json) => serde_json::from_str(&json).map_err(platform_error),
+            Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save_queue(&self, queue: &[QueuedWrite]) -> keyring::Result<()> {
+        if queue.is_empty() {
+            return match self.cache.delete(QUEUE_SYSTEM, QUEUE_KEY) {
+                Err(keyring::Error::NoEntry) => Ok(()),
+                result => result,
+            };
+        }
+        let json = serde_json::to_string(queue).map_err(platform_error)?;
+        self.cache.set(QUEUE_SYSTEM, QUEUE_KEY, &json)
+    }
+}
+
+/// Whether `error` means the backend couldn't be reached, rather than a refused request.
+fn is_unreachable(error: &keyring::Error) -> bool {
+    matches!(error, keyring::Error::PlatformFailure(_))
+}
+
+impl Vault {
+    /// The writes of this vault's system queued while its `Backend::Offline` primary was
+    /// unreachable; always empty on other backends.
+    pub fn pending_writes(&self) -> Result<Vec<PendingWrite>> {
+        match self.backend() {
+            Backend::Offline(store) => Ok(store
+                .pending_writes()
+                .map_err(|e| self.error(Operation::Read, e))?
+                .into_iter()
+                .filter(|write| write.system == self.system_name())
+                .collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Replays every queued write of the `Backend::Offline` backend (see
+    /// `OfflineStore::replay`) and returns how many were applied.
+    pub fn replay_pending_writes(&self) -> Result<usize> {
+        match self.backend() {
+            Backend::Offline(store) => store.replay().map_err(|e| self.error(Operation::Store, e)),
+            _ => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::MemoryStore;
+    use crate::store::SecretStore;
+    use crate::test_utils::{assert_not_stored, assert_stored};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// A memory store that can be unplugged.
+    struct Flaky {
+        store: MemoryStore,
+        online: Arc<AtomicBool>,
+    }
+
+    impl Flaky {
+        fn check(&self) -> keyring::Result<()> {
+            if self.online.load(Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(platform_error(std::io::Error::other("connection refused")))
+            }
+        }
+    }
+
+    impl SecretStore for Flaky {
+        fn get(&self, system_name: &str, key_name: &str) -> keyring::Result<String> {
+            self.check()?;
+            self.store.get(system_name, key_name)
+        }
+
+        fn set(&self, system_name: &str, key_name: &str, value: &str) -> keyring::Result<()> {
+            self.check()?;
+            self.store.set(system_name, key_name, value)
+        }
+
+        fn delete(&self, system_name: &str, key_name: &str) -> keyring::Result<()> {
+            self.check()?;
+            self.store.delete(system_name, key_name)
+        }
+    }
+
+    #[test]
+    fn test_offline_reads_and_queued_writes() {
+        let online = Arc::new(AtomicBool::new(true));
+        let remote = MemoryStore::new();
+        let primary = Backend::custom(Flaky {
+            store: remote.clone(),
+            online: Arc::clone(&online),
+        });
+        let cache = EncryptedFileStore::new(
+            std::env::temp_dir()
+                .join("key_vaulter_tests")
+                .join(format!("offline_{}.json", std::process::id())),
+        )
+        .with_passphrase("cache passphrase");
+        let _ = std::fs::remove_file(cache.path());
+        let vault = Vault::ephemeral_for_tests()
+            .with_backend(Backend::Offline(OfflineStore::new(primary, cache.clone())));
+        let server = vault.clone().with_backend(Backend::Memory(remote));
+        vault.key("token").store("v1").unwrap();
+        vault.key("old").store("x").unwrap();
+
+        online.store(false, Ordering::SeqCst);
+        assert_stored(&vault, "token", "v1");
+        vault.key("token").store("v2").unwrap();
+        vault.key("old").delete().unwrap();
+        assert_stored(&vault, "token", "v2");
+        assert_not_stored(&vault, "old");
+        let pending = vault.pending_writes().unwrap();
+        assert_eq!(
+            pending
+                .iter()
+                .map(|write| (write.key.as_str(), write.kind))
+                .collect::<Vec<_>>(),
+            vec![("token", PendingKind::Store), ("old", PendingKind::Delete)]
+        );
+        assert_eq!(vault.replay_pending_writes().unwrap(), 0);
+        assert_stored(&server, "token", "v1");
+
+        online.store(true, Ordering::SeqCst);
+        assert_eq!(vault.replay_pending_writes().unwrap(), 2);
+        assert!(vault.pending_writes().unwrap().is_empty());
+        assert_stored(&server, "token", "v2");
+        assert_not_stored(&server, "old");
+
+        online.store(false, Ordering::SeqCst);
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let vault = vault.clone();
+                std::thread::spawn(move || vault.key(&format!("key{}", i)).store("v").unwrap())
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(vault.pending_writes().unwrap().len(), 8);
+        online.store(true, Ordering::SeqCst);
+        assert_eq!(vault.replay_pending_writes().unwrap(), 8);
+        let _ = std::fs::remove_file(cache.path());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::error::{Operation, Result};
+use crate::naming::{is_vault_entry, INTERNAL_SEPARATOR};
+use crate::vault::Vault;
+use std::collections::BTreeSet;
+
+/// How `Vault::clean_orphans` picks the orphans to delete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanCleanup {
+    /// Deletes every orphan.
+    All,
+    /// Asks through the prompter before deleting each orphan.
+    Confirm,
+}
+
+impl Vault {
+    /// Lists the stored keys that aren't in `known_keys`, e.g. leftovers of settings an
+    /// older version of the app used. Keys derived from a known key, like
+    /// `<key>#history` or `<key>.<field>` (see `StructKeyManager::with_prompt_history` and
+    /// `with_field_backend`), count as known, and so do the entries the vault keeps for
+    /// itself, like the state of `sync`. Requires a backend that supports listing.
+    pub fn find_orphans<I, K>(&self, known_keys: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let known: BTreeSet<String> = known_keys
+            .into_iter()
+            .map(|key| key.as_ref().to_string())
+            .collect();
+        let is_known = |name: &str| {
+            is_vault_entry(name)
+                || known.iter().any(|key| {
+                    name.strip_prefix(key.as_str()).is_some_and(|rest| {
+                        rest.is_empty()
+                            || rest.starts_with('.')
+                            || rest.starts_with(INTERNAL_SEPARATOR)
+                    })
+                })
+        };
+        let mut orphans: Vec<String> = self
+            .backend()
+            .list(self.system_name())
+            .map_err(|e| self.error(Operation::Read, e))?
+            .into_iter()
+            .filter(|name| !is_known(name))
+            .collect();
+        orphans.sort();
+        Ok(orphans)
+    }
+
+    /// Deletes the orphans found by `find_orphans`, all of them or those confirmed one by
+    /// one, and returns the deleted keys.
+    pub fn clean_orphans<I, K>(&self, known_keys: I, cleanup: OrphanCleanup) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let mut deleted = Vec::new();
+        for key_name in self.find_orphans(known_keys)? {
+            let mut manager = self.key(&key_name);
+            if cleanup == OrphanCleanup::Confirm {
+                let answer = manager.prompt(&format!(
+                    "Key '{}' of system '{}' is not used anymore. Delete it? [y/N]: ",
+                    key_name,
+                    self.system_name()
+                ))?;
+                if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+                    continue;
+                }
+            }
+            manager.delete()?;
+            deleted.push(key_name);
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::ScriptedPrompter;
+
+    #[test]
+    fn test_find_and_clean_orphans() {
+        let prompter = ScriptedPrompter::new(["y", "y", ""]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        for key in [
+            "api_token",
+            "api_token#history",
+            "legacy_token#history",
+            "api_token.auth.token",
+            "legacy_token",
+            "old_url",
+            "smtp",
+        ] {
+            vault.key(key).store("value").unwrap();
+        }
+        let known = ["api_token", "smtp"];
+        assert_eq!(
+            vault.find_orphans(known).unwrap(),
+            vec!["legacy_token", "legacy_token#history", "old_url"]
+        );
+
+        let deleted = vault.clean_orphans(known, OrphanCleanup::Confirm).unwrap();
+        assert_eq!(deleted, vec!["legacy_token", "legacy_token#history"]);
+        assert!(prompter.prompts()[2].contains("'old_url'"));
+        assert_eq!(
+            vault.clean_orphans(known, OrphanCleanup::All).unwrap(),
+            vec!["old_url"]
+        );
+        assert!(vault.find_orphans(known).unwrap().is_empty());
+    }
+}
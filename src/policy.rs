@@ -0,0 +1,245 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The reason a value was rejected by a [`StorePolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    reason: String,
+}
+
+impl PolicyViolation {
+    pub fn new(reason: impl Into<String>) -> Self {
+        PolicyViolation {
+            reason: reason.into(),
+        }
+    }
+
+    /// Human-readable explanation, suitable to show to the user.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "policy violation: {}", self.reason)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// A check run on every value before it is stored. Implemented for closures.
+///
+/// A rejected value fails `store` with `ErrorKind::PolicyViolation` and is never
+/// written. `StructKeyManager` checks the serialized JSON.
+pub trait StorePolicy: Send + Sync {
+    fn check(&self, value: &str) -> Result<(), PolicyViolation>;
+}
+
+impl<F> StorePolicy for F
+where
+    F: Fn(&str) -> Result<(), PolicyViolation> + Send + Sync,
+{
+    fn check(&self, value: &str) -> Result<(), PolicyViolation> {
+        self(value)
+    }
+}
+
+/// Estimated entropy of `value` in bits: its length times the Shannon entropy of its
+/// character distribution. Repetitive values score low regardless of length.
+pub fn entropy_bits(value: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in value.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let length = value.chars().count() as f64;
+    let per_char: f64 = counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / length;
+            -p * p.log2()
+        })
+        .sum();
+    per_char * length
+}
+
+/// A ready-made policy covering the usual rules.
+///
+/// ```
+/// use key_vaulter::policy::ValuePolicy;
+///
+/// let policy = ValuePolicy::new()
+///     .reject_empty()
+///     .min_entropy_bits(40.0)
+///     .deny_pattern("(?i)changeme|password")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ValuePolicy {
+    reject_empty: bool,
+    min_entropy_bits: Option<f64>,
+    deny_patterns: Vec<Regex>,
+}
+
+impl ValuePolicy {
+    /// A policy accepting everything until rules are added.
+    pub fn new() -> Self {
+        ValuePolicy::default()
+    }
+
+    /// Rejects empty and whitespace-only values.
+    pub fn reject_empty(mut self) -> Self {
+        self.reject_empty = true;
+        self
+    }
+
+    /// Rejects values whose estimated entropy (see [`entropy_bits`]) is below `bits`.
+    pub fn min_entropy_bits(mut self, bits: f64) -> Self {
+        self.min_entropy_bits = Some(bits);
+        self
+    }
+
+    /// Rejects values matching the regular expression `pattern`, e.g. `(?i)changeme`.
+    pub fn deny_pattern(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.deny_patterns.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+}
+
+impl StorePolicy for ValuePolicy {
+    fn check(&self, value: &str) -> Result<(), PolicyViolation> {
+        if self.reject_empty && value.trim().is_empty() {
+            return Err(PolicyViolation::new("value must not be empty"));
+        }
+        if let Some(min) = self.min_entropy_bits {
+            let bits = entropy_bits(value);
+            if bits < min {
+                return Err(PolicyViolation::new(format!(
+                    "value is too weak ({:.0} bits of entropy, at least {:.0} required)",
+                    bits, min
+                )));
+            }
+        }
+        if let Some(pattern) = self.deny_patterns.iter().find(|p| p.is_match(value)) {
+            return Err(PolicyViolation::new(format!(
+                "value matches the deny-listed pattern '{}'",
+                pattern.as_str()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Rejects values that zxcvbn scores below `min_score` (0 = trivially guessable, 4 = very
+/// strong), with zxcvbn's warning and suggestions as the reason.
+#[cfg(feature = "strength")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrengthPolicy {
+    min_score: u8,
+}
+
+#[cfg(feature = "strength")]
+impl StrengthPolicy {
+    /// `min_score` is clamped to 0..=4; 3 is a reasonable default for passwords.
+    pub fn new(min_score: u8) -> Self {
+        StrengthPolicy {
+            min_score: min_score.min(4),
+        }
+    }
+}
+
+#[cfg(feature = "strength")]
+impl StorePolicy for StrengthPolicy {
+    fn check(&self, value: &str) -> Result<(), PolicyViolation> {
+        let estimate = zxcvbn::zxcvbn(value, &[]);
+        let score = u8::from(estimate.score());
+        if score >= self.min_score {
+            return Ok(());
+        }
+        let mut reason = format!("value is too weak (score {}/4", score);
+        if let Some(feedback) = estimate.feedback() {
+            if let Some(warning) = feedback.warning() {
+                reason.push_str(&format!(": {}", warning));
+            }
+            for suggestion in feedback.suggestions() {
+                reason.push_str(&format!(" {}", suggestion));
+            }
+        }
+        reason.push(')');
+        Err(PolicyViolation::new(reason))
+    }
+}
+
+/// The policies attached to a manager, checked in order.
+#[derive(Clone, Default)]
+pub(crate) struct Policies(Vec<Arc<dyn StorePolicy>>);
+
+impl fmt::Debug for Policies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Policies({})", self.0.len())
+    }
+}
+
+impl Policies {
+    pub(crate) fn push(&mut self, policy: Arc<dyn StorePolicy>) {
+        self.0.push(policy);
+    }
+
+    pub(crate) fn extend(&mut self, other: &Policies) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    pub(crate) fn check(&self, value: &str) -> Result<(), PolicyViolation> {
+        self.0.iter().try_for_each(|policy| policy.check(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::vault::Vault;
+
+    #[test]
+    fn test_value_policy_rules() {
+        let policy = ValuePolicy::new()
+            .reject_empty()
+            .min_entropy_bits(40.0)
+            .deny_pattern("(?i)changeme")
+            .unwrap();
+        assert!(policy.check("  ").is_err());
+        assert!(policy.check("aaaaaaaaaaaaaaaaaaaa").is_err());
+        assert!(policy.check("xX-CHANGEME-9f8e7d6c5b").is_err());
+        assert!(policy.check("q8#Lw2!vZr5@Tn0$").is_ok());
+        assert!(entropy_bits("abcd") > entropy_bits("aaaa"));
+    }
+
+    #[cfg(feature = "strength")]
+    #[test]
+    fn test_strength_policy() {
+        let policy = StrengthPolicy::new(3);
+        let violation = policy.check("password1").unwrap_err();
+        assert!(violation.reason().contains("too weak"));
+        assert!(policy.check("correct-horse-battery-staple-9174").is_ok());
+    }
+
+    #[test]
+    fn test_rejected_value_is_not_stored() {
+        let mut manager = Vault::ephemeral_for_tests()
+            .key("token")
+            .with_policy(|value: &str| {
+                if value == "changeme" {
+                    Err(PolicyViolation::new("default password"))
+                } else {
+                    Ok(())
+                }
+            });
+        let error = manager.store("changeme").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PolicyViolation);
+        assert!(error.to_string().contains("default password"));
+        assert_eq!(manager.read_key().unwrap_err().kind(), ErrorKind::NotFound);
+        manager.store("s3cure").unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// Asks the user for values in `request_key` and similar interactive flows.
+///
+/// The default, [`TerminalPrompter`], uses stdin and stderr. Implement it to prompt
+/// through a GUI, or use `test_utils::ScriptedPrompter` to feed canned answers in tests.
+pub trait Prompter: Send + Sync {
+    /// Shows `message` and returns the answer without its trailing line break.
+    fn prompt(&self, message: &str) -> io::Result<String>;
+
+    /// Same as `prompt`, with the earlier answers to the same question, most recent first
+    /// (see `StructKeyManager::with_prompt_history`). Prompters with line editing can offer
+    /// them on the arrow keys; by default they are ignored.
+    fn prompt_with_history(&self, message: &str, history: &[String]) -> io::Result<String> {
+        let _ = history;
+        self.prompt(message)
+    }
+
+    /// Shows `message` without expecting an answer, e.g. why the last answer was rejected.
+    /// Prints it to stderr by default.
+    fn notify(&self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// Where [`TerminalPrompter`] prints its messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PromptOutput {
+    /// Keeps stdout free for the program's own output, e.g. when it is piped.
+    #[default]
+    Stderr,
+    Stdout,
+}
+
+/// Prompts on the terminal: prints the message to stderr (see [`PromptOutput`]) and reads
+/// a line from stdin.
+///
+/// Without the `interactive` feature, nothing reads stdin: every prompt fails with
+/// `io::ErrorKind::Unsupported`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TerminalPrompter {
+    output: PromptOutput,
+}
+
+impl TerminalPrompter {
+    pub fn new() -> Self {
+        TerminalPrompter::default()
+    }
+
+    /// Prints the messages to `output` instead of stderr.
+    pub fn with_output(mut self, output: PromptOutput) -> Self {
+        self.output = output;
+        self
+    }
+}
+
+impl Prompter for TerminalPrompter {
+    #[cfg(feature = "interactive")]
+    fn prompt(&self, message: &str) -> io::Result<String> {
+        use std::io::Write;
+
+        match self.output {
+            PromptOutput::Stderr => {
+                eprint!("{}", message);
+                io::stderr().flush()?;
+            }
+            PromptOutput::Stdout => {
+                print!("{}", message);
+                io::stdout().flush()?;
+            }
+        }
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(input.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    #[cfg(not(feature = "interactive"))]
+    fn prompt(&self, _message: &str) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "interactive prompting is disabled (feature `interactive`)",
+        ))
+    }
+
+    fn notify(&self, message: &str) {
+        match self.output {
+            PromptOutput::Stderr => eprintln!("{}", message),
+            PromptOutput::Stdout => println!("{}", message),
+        }
+    }
+}
+
+/// A prompter shared by the managers of a vault.
+#[derive(Clone)]
+pub(crate) struct SharedPrompter(pub(crate) Arc<dyn Prompter>);
+
+impl fmt::Debug for SharedPrompter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Prompter")
+    }
+}
+
+/// Recent answers to the non-secret fields of a struct, most recent first, keyed by dotted
+/// field path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct PromptHistory(BTreeMap<String, Vec<String>>);
+
+impl PromptHistory {
+    pub(crate) fn recent(&self, field: &str) -> &[String] {
+        self.0.get(field).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Moves `answer` to the front of the answers of `field`, keeping at most `limit`.
+    pub(crate) fn record(&mut self, field: &str, answer: &str, limit: usize) {
+        let answers = self.0.entry(field.to_string()).or_default();
+        answers.retain(|previous| previous != answer);
+        answers.insert(0, answer.to_string());
+        answers.truncate(limit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_keeps_recent_distinct_answers() {
+        let mut history = PromptHistory::default();
+        for answer in ["db1", "db2", "db1", "db3"] {
+            history.record("host", answer, 2);
+        }
+        assert_eq!(history.recent("host"), ["db3", "db1"]);
+        assert!(history.recent("user").is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::{Cause, Operation, Result};
+use crate::key_manager::KeyManager;
+use crate::struct_key_manager::StructKeyManager;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+
+/// Renders `data` as a QR code made of Unicode half blocks, ready to print to a terminal.
+///
+/// Colors are inverted (light modules drawn as blocks) so the code scans on the usual
+/// dark terminal background.
+pub fn render_terminal_qr(data: &str) -> std::result::Result<String, qrcode::types::QrError> {
+    let code = QrCode::new(data.as_bytes())?;
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
+
+impl KeyManager {
+    /// Reads the key and renders its value as a terminal QR code (e.g. a TOTP
+    /// provisioning URI), so it can be scanned into a phone without touching a file.
+    pub fn render_qr(&mut self) -> Result<String> {
+        let value = self.read_key()?;
+        render_terminal_qr(&value)
+            .map_err(|e| self.error(Operation::Export, Cause::InvalidInput(e.to_string())))
+    }
+}
+
+impl<T> StructKeyManager<T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Default,
+{
+    /// Reads the struct, turns it into a QR payload with `to_payload` (e.g. a
+    /// `WIFI:T:WPA;S:...;P:...;;` string) and renders it as a terminal QR code.
+    pub fn render_qr_with<F>(&mut self, to_payload: F) -> Result<String>
+    where
+        F: FnOnce(&T) -> String,
+    {
+        let value = self.read_key()?;
+        render_terminal_qr(&to_payload(&value))
+            .map_err(|e| self.error(Operation::Export, Cause::InvalidInput(e.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_terminal_qr() {
+        let rendered =
+            render_terminal_qr("otpauth://totp/Example?secret=JBSWY3DPEHPK3PXP").unwrap();
+        assert!(rendered.lines().count() > 10);
+        assert!(!rendered.contains("JBSWY3DPEHPK3PXP"));
+    }
+
+    #[test]
+    fn test_render_terminal_qr_rejects_oversized_data() {
+        assert!(render_terminal_qr(&"x".repeat(10_000)).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Read counters of one key, as reported by [`RateLimiter::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadStats {
+    pub system: String,
+    pub key: String,
+    /// Reads let through since the limiter was created.
+    pub allowed: u64,
+    /// Reads rejected with `ErrorKind::RateLimited`.
+    pub rejected: u64,
+}
+
+#[derive(Default)]
+struct KeyCounter {
+    recent: VecDeque<Instant>,
+    allowed: u64,
+    rejected: u64,
+}
+
+/// Limits how often each key may be read, to blunt a bug or a compromised plugin hammering
+/// credential reads.
+///
+/// Clones share their counters, so one limiter attached to a `Vault` covers every manager
+/// it hands out. Limits apply per key, over a sliding window.
+#[derive(Clone)]
+pub struct RateLimiter {
+    max_reads: usize,
+    window: Duration,
+    counters: Arc<Mutex<BTreeMap<(String, String), KeyCounter>>>,
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("max_reads", &self.max_reads)
+            .field("window", &self.window)
+            .finish()
+    }
+}
+
+impl RateLimiter {
+    /// Allows at most `max_reads` reads of each key within any `window`.
+    pub fn new(max_reads: usize, window: Duration) -> Self {
+        RateLimiter {
+            max_reads,
+            window,
+            counters: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Allows at most `max_reads` reads of each key per minute.
+    pub fn per_minute(max_reads: usize) -> Self {
+        RateLimiter::new(max_reads, Duration::from_secs(60))
+    }
+
+    /// Counts a read of `system/key`, returning the reason when it exceeds the limit.
+    pub(crate) fn check(&self, system: &str, key: &str) -> Result<(), String> {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let counter = counters
+            .entry((system.to_string(), key.to_string()))
+            .or_default();
+        while let Some(&oldest) = counter.recent.front() {
+            if now.duration_since(oldest) < self.window {
+                break;
+            }
+            counter.recent.pop_front();
+        }
+        if counter.recent.len() >= self.max_reads {
+            counter.rejected += 1;
+            return Err(format!(
+                "more than {} reads within {}s",
+                self.max_reads,
+                self.window.as_secs()
+            ));
+        }
+        counter.recent.push_back(now);
+        counter.allowed += 1;
+        Ok(())
+    }
+
+    /// Counters of every key read through this limiter, sorted by system and key.
+    pub fn stats(&self) -> Vec<ReadStats> {
+        let counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        counters
+            .iter()
+            .map(|((system, key), counter)| ReadStats {
+                system: system.clone(),
+                key: key.clone(),
+                allowed: counter.allowed,
+                rejected: counter.rejected,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::vault::Vault;
+
+    #[test]
+    fn test_reads_over_the_limit_are_rejected() {
+        let limiter = RateLimiter::per_minute(2);
+        let vault = Vault::ephemeral_for_tests().with_rate_limit(limiter.clone());
+        vault.key("token").store("secret").unwrap();
+
+        let mut key = vault.key("token");
+        key.read_key().unwrap();
+        vault.key("token").read_key().unwrap();
+        assert_eq!(key.read_key().unwrap_err().kind(), ErrorKind::RateLimited);
+        vault.key("other").store("value").unwrap();
+        vault.key("other").read_key().unwrap();
+
+        let stats = limiter.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            (stats[1].key.as_str(), stats[1].allowed, stats[1].rejected),
+            ("token", 2, 1)
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use serde_json::Value;
+use std::fmt;
+
+/// Placeholder written in place of secret values.
+pub const REDACTED: &str = "***";
+
+/// Wraps a secret value so that `{:?}` and `{}` print `***` instead of its content.
+///
+/// The value is only reachable through `expose` or `into_inner`, which makes accidental
+/// logging of stored secrets stand out in review.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Redacted<T>(T);
+
+impl<T> Redacted<T> {
+    pub fn new(value: T) -> Self {
+        Redacted(value)
+    }
+
+    /// Gives access to the wrapped value.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Unwraps the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Redacted(value)
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Replaces the fields at the given paths with [`REDACTED`].
+///
+/// A path is a field name as it appears in the serialized JSON; nested fields are written
+/// with dots (`"database.password"`). Paths that don't exist are ignored.
+pub(crate) fn redact_fields<'a, I>(value: &mut Value, paths: I)
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    for path in paths {
+        let mut current = &mut *value;
+        let mut segments = path.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            let Value::Object(fields) = current else {
+                break;
+            };
+            let Some(field) = fields.get_mut(segment) else {
+                break;
+            };
+            if segments.peek().is_none() {
+                *field = Value::String(REDACTED.to_string());
+                break;
+            }
+            current = field;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redacted_formatting() {
+        let secret = Redacted::new("hunter2".to_string());
+        assert_eq!(format!("{:?}", secret), "***");
+        assert_eq!(format!("{}", secret), "***");
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(secret.into_inner(), "hunter2");
+    }
+
+    #[test]
+    fn test_redact_fields() {
+        let mut value = json!({
+            "user": "john",
+            "password": "hunter2",
+            "database": {"host": "db", "password": "secret"}
+        });
+        redact_fields(
+            &mut value,
+            ["password", "database.password", "missing.field"],
+        );
+        assert_eq!(
+            value,
+            json!({
+                "user": "john",
+                "password": "***",
+                "database": {"host": "db", "password": "***"}
+            })
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use crate::file_store::{
+    corrupt, derive_cipher, open_entry, platform_error, seal_entry, KdfParams,
+};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::XChaCha20Poly1305;
+use keyring::Result;
+use redb::{Database, ReadableTable, TableDefinition};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+const KDF_INFO: &[u8] = b"key_vaulter redb store v1";
+const SALT_LEN: usize = 16;
+
+const META: TableDefinition<&str, &[u8]> = TableDefinition::new("meta");
+const ENTRIES: TableDefinition<(&str, &str), &[u8]> = TableDefinition::new("entries");
+
+/// A single-file embedded database ([redb](https://crates.io/crates/redb)) holding values
+/// encrypted with XChaCha20-Poly1305 (feature `redb`).
+///
+/// Pure Rust, with no OS keyring and no C library involved, which suits portable tools
+/// run from a USB stick (see [`RedbStore::portable`]). Keys are derived like in
+/// [`EncryptedFileStore`](crate::file_store::EncryptedFileStore); set a passphrase when the
+/// file travels between machines, since the user-name fallback is easy to guess. Clones
+/// share the same database handle.
+#[derive(Clone)]
+pub struct RedbStore {
+    path: PathBuf,
+    database: Arc<Database>,
+    passphrase: Option<Zeroizing<String>>,
+}
+
+impl fmt::Debug for RedbStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedbStore")
+            .field("path", &self.path)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// Two stores are equal when they share the same database handle.
+impl PartialEq for RedbStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.database, &other.database)
+    }
+}
+
+impl Eq for RedbStore {}
+
+impl RedbStore {
+    /// Opens the database at `path`, creating it (and its directory) if needed. redb locks
+    /// the file, so a second process opening it fails until the first one closes it.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(platform_error)?;
+        }
+        let database = Database::create(&path).map_err(platform_error)?;
+        let transaction = database.begin_write().map_err(platform_error)?;
+        {
+            let mut meta = transaction.open_table(META).map_err(platform_error)?;
+            if meta.get("salt").map_err(platform_error)?.is_none() {
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                meta.insert("salt", salt.as_slice())
+                    .map_err(platform_error)?;
+                let kdf = KdfParams::DEFAULT.to_string();
+                meta.insert("kdf", kdf.as_bytes()).map_err(platform_error)?;
+            }
+            transaction.open_table(ENTRIES).map_err(platform_error)?;
+        }
+        transaction.commit().map_err(platform_error)?;
+        Ok(RedbStore {
+            path,
+            database: Arc::new(database),
+            passphrase: None,
+        })
+    }
+
+    /// Opens `vault.redb` next to the running executable, so the tool and its secrets can
+    /// be carried around together.
+    pub fn portable() -> Result<Self> {
+        let executable = std::env::current_exe().map_err(platform_error)?;
+        let directory = executable.parent().unwrap_or_else(|| Path::new("."));
+        RedbStore::open(directory.join("vault.redb"))
+    }
+
+    /// Derives the encryption key from the given passphrase.
+    pub fn with_passphrase(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(Zeroizing::new(passphrase.to_string()));
+        self
+    }
+
+    /// Path of the database file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn cipher(&self) -> Result<XChaCha20Poly1305> {
+        let transaction = self.database.begin_read().map_err(platform_error)?;
+        let meta = transaction.open_table(META).map_err(platform_error)?;
+        let salt = meta
+            .get("salt")
+            .map_err(platform_error)?
+            .ok_or(keyring::Error::NoStorageAccess("missing salt".into()))?;
+        let kdf = meta
+            .get("kdf")
+            .map_err(platform_error)?
+            .and_then(|kdf| {
+                std::str::from_utf8(kdf.value())
+                    .ok()
+                    .and_then(KdfParams::parse)
+            })
+            .ok_or_else(|| corrupt("missing or invalid key derivation parameters"))?;
+        derive_cipher(
+            self.passphrase.as_deref().map(String::as_str),
+            salt.value(),
+            KDF_INFO,
+            kdf,
+        )
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        let data = {
+            let transaction = self.database.begin_read().map_err(platform_error)?;
+            let entries = transaction.open_table(ENTRIES).map_err(platform_error)?;
+            let data = entries
+                .get((system_name, key_name))
+                .map_err(platform_error)?
+                .ok_or(keyring::Error::NoEntry)?;
+            data.value().to_vec()
+        };
+        open_entry(&self.cipher()?, system_name, key_name, &data, "redb store")
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        let data = seal_entry(&self.cipher()?, system_name, key_name, value)?;
+        let transaction = self.database.begin_write().map_err(platform_error)?;
+        {
+            let mut entries = transaction.open_table(ENTRIES).map_err(platform_error)?;
+            entries
+                .insert((system_name, key_name), data.as_slice())
+                .map_err(platform_error)?;
+        }
+        transaction.commit().map_err(platform_error)
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        let transaction = self.database.begin_write().map_err(platform_error)?;
+        let removed = {
+            let mut entries = transaction.open_table(ENTRIES).map_err(platform_error)?;
+            let removed = entries
+                .remove((system_name, key_name))
+                .map_err(platform_error)?;
+            removed.is_some()
+        };
+        if !removed {
+            return Err(keyring::Error::NoEntry);
+        }
+        transaction.commit().map_err(platform_error)
+    }
+
+    /// Lists the keys stored for `system_name`.
+    pub fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        let transaction = self.database.begin_read().map_err(platform_error)?;
+        let entries = transaction.open_table(ENTRIES).map_err(platform_error)?;
+        let mut keys = Vec::new();
+        for entry in entries.range((system_name, "")..).map_err(platform_error)? {
+            let (name, _) = entry.map_err(platform_error)?;
+            let (system, key) = name.value();
+            if system != system_name {
+                break;
+            }
+            keys.push(key.to_string());
+        }
+        Ok(keys)
+    }
+
+    /// Checks that the database can be read and the key derived.
+    pub(crate) fn health_check(&self) -> Result<()> {
+        self.cipher().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_list_and_passphrase() {
+        let path = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("redb-{}.redb", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = RedbStore::open(&path).unwrap().with_passphrase("usb");
+        store.set("system", "b", "2").unwrap();
+        store.set("system", "a", "very-secret-value").unwrap();
+        store.set("systemx", "c", "3").unwrap();
+        assert_eq!(store.get("system", "a").unwrap(), "very-secret-value");
+        assert_eq!(store.list("system").unwrap(), vec!["a", "b"]);
+
+        let content = std::fs::read(&path).unwrap();
+        assert!(!content
+            .windows(17)
+            .any(|window| window == b"very-secret-value"));
+        let other = store.clone().with_passphrase("other");
+        assert!(other.get("system", "a").is_err());
+
+        store.delete("system", "a").unwrap();
+        assert!(matches!(
+            store.delete("system", "a"),
+            Err(keyring::Error::NoEntry)
+        ));
+        drop((store, other));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,349 @@
+//! The process-wide registry of keys: every `KeyManager` of the same key (same backend,
+//! system and key name) shares one `KeySlot`, holding the last value seen by any of
+//! them, the lock their writes go through and the turn to prompt for the key. The cached
+//! values are forgotten by `lock_events::clear_caches`.
+
+use crate::backend::Backend;
+use crate::lock_events;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Once, OnceLock, Weak};
+use std::thread::{self, ThreadId};
+use zeroize::Zeroizing;
+
+type Slots = HashMap<(String, String), Vec<Weak<KeySlot>>>;
+
+/// What this process last saw of a key.
+enum Seen {
+    Nothing,
+    Missing,
+    Value(Zeroizing<String>),
+}
+
+fn slots() -> MutexGuard<'static, Slots> {
+    static SLOTS: OnceLock<Mutex<Slots>> = OnceLock::new();
+    SLOTS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Forgets the values cached by every live slot, or only by the slots of `backend`.
+pub(crate) fn forget_values(backend: Option<&Backend>) {
+    let live: Vec<Arc<KeySlot>> = slots()
+        .values()
+        .flatten()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for slot in live {
+        if backend.is_none_or(|backend| slot.backend == *backend) {
+            *slot.value_lock() = Seen::Nothing;
+        }
+    }
+}
+
+/// The turns to prompt for a key taken so far, numbered from 1, and the last of them that
+/// stored a value (0 for none).
+#[derive(Debug, Clone, Copy, Default)]
+struct Prompts {
+    turns: u64,
+    last_stored: u64,
+}
+
+/// What the managers of one key share. Dropped with the last of them.
+pub(crate) struct KeySlot {
+    backend: Backend,
+    system_name: String,
+    key_name: String,
+    value: Mutex<Seen>,
+    /// The thread holding the lock and how many times it took it.
+    owner: Mutex<(Option<ThreadId>, usize)>,
+    released: Condvar,
+    prompts: Mutex<Prompts>,
+}
+
+impl fmt::Debug for KeySlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeySlot")
+            .field("system_name", &self.system_name)
+            .field("key_name", &self.key_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeySlot {
+    /// The slot of `system_name`/`key_name` in `backend`, created if no manager of the
+    /// key is alive.
+    pub(crate) fn acquire(backend: &Backend, system_name: &str, key_name: &str) -> Arc<KeySlot> {
+        static PURGER: Once = Once::new();
+        PURGER.call_once(|| {
+            lock_events::register_cache(|| {
+                forget_values(None);
+                true
+            })
+        });
+        let mut slots = slots();
+        let id = (system_name.to_string(), key_name.to_string());
+        let same_key = slots.entry(id).or_default();
+        same_key.retain(|slot| slot.strong_count() > 0);
+        if let Some(slot) = same_key
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|slot| slot.backend == *backend)
+        {
+            return slot;
+        }
+        let slot = Arc::new(KeySlot {
+            backend: backend.clone(),
+            system_name: system_name.to_string(),
+            key_name: key_name.to_string(),
+            value: Mutex::new(Seen::Nothing),
+            owner: Mutex::new((None, 0)),
+            released: Condvar::new(),
+            prompts: Mutex::default(),
+        });
+        same_key.push(Arc::downgrade(&slot));
+        slots.retain(|_, same_key| same_key.iter().any(|slot| slot.strong_count() > 0));
+        slot
+    }
+
+    /// Whether this is the slot of `system_name`/`key_name` in `backend`.
+    pub(crate) fn is_for(&self, backend: &Backend, system_name: &str, key_name: &str) -> bool {
+        self.system_name == system_name && self.key_name == key_name && self.backend == *backend
+    }
+
+    pub(crate) fn value(&self) -> Option<String> {
+        match &*self.value_lock() {
+            Seen::Value(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Whether the key was last seen stored, `None` if nothing was seen yet.
+    pub(crate) fn is_stored(&self) -> Option<bool> {
+        match &*self.value_lock() {
+            Seen::Nothing => None,
+            Seen::Missing => Some(false),
+            Seen::Value(_) => Some(true),
+        }
+    }
+
+    /// Records `value` as read or stored, or `None` when the key was found missing.
+    pub(crate) fn set_value(&self, value: Option<&str>) {
+        *self.value_lock() = match value {
+            Some(value) => Seen::Value(Zeroizing::new(value.to_string())),
+            None => Seen::Missing,
+        };
+    }
+
+    fn value_lock(&self) -> MutexGuard<'_, Seen> {
+        self.value.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Waits until no other thread holds the key. The thread holding it can take it again.
+    pub(crate) fn lock(self: &Arc<Self>) -> KeyLock {
+        let me = thread::current().id();
+        let mut owner = self.owner.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            match owner.0 {
+                None => {
+                    *owner = (Some(me), 1);
+                    break;
+                }
+                Some(thread) if thread == me => {
+                    owner.1 += 1;
+                    break;
+                }
+                Some(_) => owner = self.released.wait(owner).unwrap_or_else(|e| e.into_inner()),
+            }
+        }
+        KeyLock {
+            slot: Arc::clone(self),
+        }
+    }
+
+    fn prompts(&self) -> MutexGuard<'_, Prompts> {
+        self.prompts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// How many turns to prompt for the key were taken so far.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub(crate) fn prompt_rounds(&self) -> u64 {
+        self.prompts().turns
+    }
+
+    /// Takes the key (see `lock`), so no other thread prompts for it or writes it
+    /// meanwhile, then the turn to prompt for it.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub(crate) fn prompt_turn(self: &Arc<Self>) -> PromptTurn {
+        let lock = self.lock();
+        let mut prompts = self.prompts();
+        prompts.turns += 1;
+        PromptTurn {
+            lock,
+            number: prompts.turns,
+            stored_before: prompts.last_stored,
+        }
+    }
+}
+
+/// The turn of a thread to prompt for a key, holding the key until dropped.
+pub(crate) struct PromptTurn {
+    lock: KeyLock,
+    number: u64,
+    stored_before: u64,
+}
+
+impl PromptTurn {
+    /// Whether a turn taken after `prompt_rounds` returned `rounds` stored a value: then
+    /// the key should be read again instead of prompted for.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub(crate) fn stored_since(&self, rounds: u64) -> bool {
+        self.stored_before > rounds
+    }
+
+    /// Records that this turn stored a value, for the threads waiting for it.
+    #[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+    pub(crate) fn stored(&self) {
+        self.lock.slot.prompts().last_stored = self.number;
+    }
+}
+
+/// Holds a key for the current thread, returned by `KeyManager::lock`; released when
+/// dropped. Stores and deletes of the key by any manager of this process, from other
+/// threads, wait for it.
+pub struct KeyLock {
+    slot: Arc<KeySlot>,
+}
+
+impl fmt::Debug for KeyLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyLock")
+            .field("system_name", &self.slot.system_name)
+            .field("key_name", &self.slot.key_name)
+            .finish()
+    }
+}
+
+impl Drop for KeyLock {
+    fn drop(&mut self) {
+        let mut owner = self.slot.owner.lock().unwrap_or_else(|e| e.into_inner());
+        owner.1 -= 1;
+        if owner.1 == 0 {
+            owner.0 = None;
+            self.slot.released.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vault::Vault;
+
+    #[test]
+    fn test_managers_of_a_key_share_its_slot() {
+        let vault = Vault::ephemeral_for_tests();
+        let mut writer = vault.key("db_password");
+        let mut reader = vault.key("db_password");
+        assert_eq!(reader.cached_value(), None);
+        writer.store("hunter2").unwrap();
+        assert_eq!(reader.cached_value().as_deref(), Some("hunter2"));
+        assert_eq!(vault.key("other").cached_value(), None);
+        let other_vault = Vault::ephemeral_for_tests();
+        assert_eq!(other_vault.key("db_password").cached_value(), None);
+
+        let lock = reader.lock();
+        reader.store("rotated").unwrap();
+        let waiting = std::thread::spawn(move || writer.delete().unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(reader.cached_value().as_deref(), Some("rotated"));
+        drop(lock);
+        waiting.join().unwrap();
+        assert_eq!(reader.cached_value(), None);
+
+        reader.store("again").unwrap();
+        crate::lock_events::clear_caches();
+        assert_eq!(reader.cached_value(), None);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_only_one_thread_prompts_for_a_missing_key() {
+        let prompter = crate::test_utils::ScriptedPrompter::new(["typed_value"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let vault = vault.clone();
+                std::thread::spawn(move || vault.key("token").read_or_request_key().unwrap())
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), "typed_value");
+        }
+        assert_eq!(prompter.prompts().len(), 1);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_prompting_while_holding_the_key() {
+        let prompter = crate::test_utils::ScriptedPrompter::new(["typed_value"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let (done, finished) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut holder = vault.key("token");
+            let lock = holder.lock();
+            let waiter = {
+                let vault = vault.clone();
+                std::thread::spawn(move || vault.key("token").read_or_request_key().unwrap())
+            };
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            let value = holder.read_or_request_key().unwrap();
+            drop(lock);
+            done.send((value, waiter.join().unwrap())).unwrap();
+        });
+        let values = finished
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .expect("prompting while holding the key deadlocked");
+        assert_eq!(
+            values,
+            ("typed_value".to_string(), "typed_value".to_string())
+        );
+        assert_eq!(prompter.prompts().len(), 1);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_waiters_prompt_again_after_a_failed_prompt() {
+        use crate::error::ErrorKind;
+        use crate::prompt::Prompter;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Fails the first prompt, slowly enough for another thread to wait for it.
+        struct FailingOnce(AtomicUsize);
+
+        impl Prompter for FailingOnce {
+            fn prompt(&self, _message: &str) -> std::io::Result<String> {
+                if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    return Err(std::io::ErrorKind::Interrupted.into());
+                }
+                Ok("typed_value".to_string())
+            }
+        }
+
+        let vault = Vault::ephemeral_for_tests().with_prompter(FailingOnce(AtomicUsize::new(0)));
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let vault = vault.clone();
+                std::thread::spawn(move || vault.key("token").read_or_request_key())
+            })
+            .collect();
+        let mut results: Vec<_> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        results.sort_by_key(Result::is_ok);
+        assert_ne!(results[0].as_ref().unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(results[1].as_deref().unwrap(), "typed_value");
+    }
+}
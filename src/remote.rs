@@ -0,0 +1,337 @@
+use crate::file_store::platform_error;
+use crate::hooks;
+use crate::lock_events;
+use keyring::Result;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize)]
+struct ValueBody {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct KeysBody {
+    keys: Vec<String>,
+}
+
+/// Values read from the server, keyed by (system, key), with the time they were fetched.
+type Cache = HashMap<(String, String), (String, Instant)>;
+
+/// Error for responses the protocol does not allow.
+#[derive(Debug)]
+struct UnexpectedStatus(StatusCode);
+
+impl fmt::Display for UnexpectedStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unexpected response from server: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnexpectedStatus {}
+
+/// Client for a shared secrets server speaking a small REST protocol:
+///
+/// - `GET /v1/{system}/{key}` returns `{"value": "..."}`, or 404 when there is no entry
+/// - `PUT /v1/{system}/{key}` with `{"value": "..."}` stores a value
+/// - `DELETE /v1/{system}/{key}` removes it
+/// - `GET /v1/{system}` returns `{"keys": [...]}`
+/// - `GET /v1/health` answers 200 when the server is up
+///
+/// Requests carry `Authorization: Bearer <token>` when a token is set. With
+/// `with_cache_ttl`, reads are served from an in-memory cache while fresh, and from the
+/// stale cached value (with a warning) when the server can't be reached.
+///
+/// Uses a blocking HTTP client: don't call it from inside an async runtime.
+#[derive(Clone)]
+pub struct RemoteStore {
+    base_url: String,
+    token: Option<String>,
+    client: Client,
+    cache_ttl: Option<Duration>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl fmt::Debug for RemoteStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteStore")
+            .field("base_url", &self.base_url)
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .field("cache_ttl", &self.cache_ttl)
+            .finish()
+    }
+}
+
+impl PartialEq for RemoteStore {
+    fn eq(&self, other: &Self) -> bool {
+        self.base_url == other.base_url
+            && self.token == other.token
+            && self.cache_ttl == other.cache_ttl
+    }
+}
+
+impl Eq for RemoteStore {}
+
+impl RemoteStore {
+    /// Creates a client for the server at `base_url`, e.g. `https://vault.example.com`.
+    /// Fails when the HTTP client can't be set up, e.g. without usable TLS roots.
+    pub fn new(base_url: &str) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(platform_error)?;
+        Ok(RemoteStore {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: None,
+            client,
+            cache_ttl: None,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Authenticates every request with `Authorization: Bearer <token>`.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Caches values read from the server for `ttl` (see the type documentation). The
+    /// cache is emptied by `lock_events::clear_caches`, e.g. when the machine locks.
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        let cache = Arc::downgrade(&self.cache);
+        lock_events::register_cache(move || match cache.upgrade() {
+            Some(cache) => {
+                cache.lock().unwrap_or_else(|e| e.into_inner()).clear();
+                true
+            }
+            None => false,
+        });
+        self
+    }
+
+    /// Base URL of the server.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(&self.base_url).map_err(platform_error)?;
+        url.path_segments_mut()
+            .map_err(|_| keyring::Error::Invalid("base_url".into(), "cannot be a base".into()))?
+            .pop_if_empty()
+            .push("v1")
+            .extend(segments);
+        Ok(url)
+    }
+
+    fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        let response = request.send().map_err(platform_error)?;
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            StatusCode::NOT_FOUND => Err(keyring::Error::NoEntry),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(
+                keyring::Error::NoStorageAccess(Box::new(UnexpectedStatus(response.status()))),
+            ),
+            status => Err(platform_error(UnexpectedStatus(status))),
+        }
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn fetch(&self, system_name: &str, key_name: &str) -> Result<String> {
+        let url = self.url(&[system_name, key_name])?;
+        let body: ValueBody = self
+            .send(self.client.get(url))?
+            .json()
+            .map_err(platform_error)?;
+        Ok(body.value)
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        let Some(ttl) = self.cache_ttl else {
+            return self.fetch(system_name, key_name);
+        };
+        let cache_key = (system_name.to_string(), key_name.to_string());
+        if let Some((value, fetched)) = self.cache().get(&cache_key) {
+            if fetched.elapsed() < ttl {
+                return Ok(value.clone());
+            }
+        }
+        match self.fetch(system_name, key_name) {
+            Ok(value) => {
+                self.cache()
+                    .insert(cache_key, (value.clone(), Instant::now()));
+                Ok(value)
+            }
+            Err(keyring::Error::PlatformFailure(e)) => match self.cache().get(&cache_key) {
+                Some((value, _)) => {
+                    hooks::warn(&format!(
+                        "remote vault unreachable ({}), using cached value of '{}'",
+                        e, key_name
+                    ));
+                    Ok(value.clone())
+                }
+                None => Err(keyring::Error::PlatformFailure(e)),
+            },
+            Err(e) => {
+                self.cache().remove(&cache_key);
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        let url = self.url(&[system_name, key_name])?;
+        self.send(self.client.put(url).json(&ValueBody {
+            value: value.to_string(),
+        }))?;
+        if self.cache_ttl.is_some() {
+            self.cache().insert(
+                (system_name.to_string(), key_name.to_string()),
+                (value.to_string(), Instant::now()),
+            );
+        }
+        Ok(())
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        let url = self.url(&[system_name, key_name])?;
+        self.cache()
+            .remove(&(system_name.to_string(), key_name.to_string()));
+        self.send(self.client.delete(url)).map(|_| ())
+    }
+
+    /// Lists the keys the server holds for `system_name`.
+    pub fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        let url = self.url(&[system_name])?;
+        let body: KeysBody = self
+            .send(self.client.get(url))?
+            .json()
+            .map_err(platform_error)?;
+        Ok(body.keys)
+    }
+
+    /// Checks that the server answers `GET /v1/health`.
+    pub(crate) fn health_check(&self) -> Result<()> {
+        let url = self.url(&["health"])?;
+        self.send(self.client.get(url)).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves the protocol from memory on a local port, answering `requests` requests.
+    fn serve(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut values: HashMap<String, String> = HashMap::new();
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let (mut length, mut authorized) = (0, false);
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim().to_lowercase();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    authorized |= header == "authorization: bearer team-token";
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let mut parts = request_line.split_whitespace();
+                let (method, path) = (parts.next().unwrap(), parts.next().unwrap().to_string());
+                let (status, response) = match (method, authorized) {
+                    (_, false) => ("401 Unauthorized", String::new()),
+                    ("GET", _) if path == "/v1/health" => ("200 OK", String::new()),
+                    ("GET", _) => match values.get(&path) {
+                        Some(value) => ("200 OK", format!(r#"{{"value":{:?}}}"#, value)),
+                        None => ("404 Not Found", String::new()),
+                    },
+                    ("PUT", _) => {
+                        let body: ValueBody = serde_json::from_slice(&body).unwrap();
+                        values.insert(path, body.value);
+                        ("204 No Content", String::new())
+                    }
+                    ("DELETE", _) => match values.remove(&path) {
+                        Some(_) => ("204 No Content", String::new()),
+                        None => ("404 Not Found", String::new()),
+                    },
+                    _ => ("405 Method Not Allowed", String::new()),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn test_store_read_and_delete() {
+        let store = RemoteStore::new(&serve(6))
+            .unwrap()
+            .with_token("team-token");
+        store.health_check().unwrap();
+        store.set("my system", "api/key", "secret").unwrap();
+        assert_eq!(store.get("my system", "api/key").unwrap(), "secret");
+        store.delete("my system", "api/key").unwrap();
+        assert!(matches!(
+            store.get("my system", "api/key"),
+            Err(keyring::Error::NoEntry)
+        ));
+        assert!(matches!(
+            store.delete("my system", "api/key"),
+            Err(keyring::Error::NoEntry)
+        ));
+    }
+
+    #[test]
+    fn test_missing_token_is_rejected() {
+        let store = RemoteStore::new(&serve(1)).unwrap();
+        assert!(matches!(
+            store.get("system", "key"),
+            Err(keyring::Error::NoStorageAccess(_))
+        ));
+    }
+
+    #[test]
+    fn test_cache_serves_reads_when_server_is_gone() {
+        // The server answers the store only; reads must come from the cache.
+        let store = RemoteStore::new(&serve(1))
+            .unwrap()
+            .with_token("team-token")
+            .with_cache_ttl(Duration::ZERO);
+        store.set("system", "key", "cached").unwrap();
+        assert_eq!(store.get("system", "key").unwrap(), "cached");
+    }
+}
@@ -0,0 +1,552 @@
+use crate::error::{ErrorKind, Operation, Result};
+use crate::hooks::{self, ChangeKind};
+use crate::key_manager::KeyManager;
+use crate::naming::{internal_key, split_internal_key};
+use crate::timestamp::{civil_from_days, now_unix};
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Longest gap searched for the next run of a schedule; Feb 29 comes back within 8 years.
+const SEARCH_DAYS: u64 = 366 * 9;
+
+/// A cron-like schedule: five fields `minute hour day-of-month month day-of-week`, in
+/// UTC, each `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a comma-separated
+/// list of those. Day of week 0 (or 7) is Sunday. As in cron, when both day fields are
+/// restricted a day matching either one runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// The reason an expression was rejected by `Schedule::parse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleError {
+    expression: String,
+    reason: String,
+}
+
+impl ScheduleError {
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schedule '{}': {}", self.expression, self.reason)
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+impl Schedule {
+    /// Parses a five-field expression, e.g. `"0 3 * * 1"` for every Monday at 03:00 UTC.
+    pub fn parse(expression: &str) -> std::result::Result<Schedule, ScheduleError> {
+        let error = |reason: String| ScheduleError {
+            expression: expression.to_string(),
+            reason,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(error(format!("expected 5 fields, found {}", fields.len())));
+        }
+        let weekdays = parse_field(fields[4], 0, 7).map_err(error)?;
+        Ok(Schedule {
+            minutes: parse_field(fields[0], 0, 59).map_err(error)?,
+            hours: parse_field(fields[1], 0, 23).map_err(error)?,
+            days: parse_field(fields[2], 1, 31).map_err(error)?,
+            months: parse_field(fields[3], 1, 12).map_err(error)?,
+            // Fold 7 onto Sunday.
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    /// The first run strictly after `unix_secs`, in seconds since the Unix epoch, or
+    /// `None` if the schedule never runs (e.g. February 30).
+    pub fn next_after(&self, unix_secs: u64) -> Option<u64> {
+        let first_day = unix_secs / 86_400;
+        (first_day..first_day + SEARCH_DAYS)
+            .filter(|&day| self.runs_on(day))
+            .find_map(|day| {
+                (0..24u64)
+                    .filter(|hour| self.hours & 1 << hour != 0)
+                    .flat_map(|hour| {
+                        (0..60u64)
+                            .filter(|minute| self.minutes & 1 << minute != 0)
+                            .map(move |minute| day * 86_400 + hour * 3600 + minute * 60)
+                    })
+                    .find(|&time| time > unix_secs)
+            })
+    }
+
+    fn runs_on(&self, day: u64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day as i64);
+        // 1970-01-01 was a Thursday.
+        let weekday = (day + 4) % 7;
+        let day_matches = self.days & 1 << day_of_month != 0;
+        let weekday_matches = self.weekdays & 1 << weekday != 0;
+        let matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day_matches || weekday_matches,
+            _ => day_matches && weekday_matches,
+        };
+        matches && self.months & 1 << month != 0
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(expression: &str) -> std::result::Result<Self, Self::Err> {
+        Schedule::parse(expression)
+    }
+}
+
+/// Parses one field into a bit mask of the values it allows.
+fn parse_field(field: &str, min: u64, max: u64) -> std::result::Result<u64, String> {
+    let number = |text: &str| match text.parse::<u64>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!(
+            "'{}' is not a number between {} and {}",
+            text, min, max
+        )),
+    };
+    let mut mask = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u64>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("'{}' is not a valid step", step)),
+            },
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("'{}' is an empty range", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+/// When a key must be rotated: after `max_age`, at the next run of `schedule` after the
+/// last rotation, or whichever comes first when both are set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RotationPolicy {
+    max_age: Option<Duration>,
+    schedule: Option<Schedule>,
+    grace_period: Option<Duration>,
+}
+
+impl RotationPolicy {
+    pub fn new() -> Self {
+        RotationPolicy::default()
+    }
+
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Makes `Vault::rotate_due` keep the replaced value valid for `grace_period` (see
+    /// `KeyManager::rotate_key`).
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = Some(grace_period);
+        self
+    }
+
+    /// When a value rotated at `rotated_at` (seconds since the Unix epoch) is due, `None`
+    /// if never.
+    pub fn due_at(&self, rotated_at: u64) -> Option<u64> {
+        let by_age = self
+            .max_age
+            .map(|max_age| rotated_at.saturating_add(max_age.as_secs()));
+        let by_schedule = self
+            .schedule
+            .as_ref()
+            .and_then(|schedule| schedule.next_after(rotated_at));
+        match (by_age, by_schedule) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Produces the new value of a key for `Vault::rotate_due`, e.g. by asking the provider
+/// for a new API key, given the key name and its current value, if any. Implemented for
+/// closures.
+pub trait Rotator: Send + Sync {
+    fn rotate(&self, key_name: &str, current: Option<&str>) -> Result<String>;
+}
+
+impl<F> Rotator for F
+where
+    F: Fn(&str, Option<&str>) -> Result<String> + Send + Sync,
+{
+    fn rotate(&self, key_name: &str, current: Option<&str>) -> Result<String> {
+        self(key_name, current)
+    }
+}
+
+/// The rotation policies and rotators configured on a vault.
+#[derive(Clone, Default)]
+pub(crate) struct Rotations {
+    policies: BTreeMap<String, RotationPolicy>,
+    rotators: BTreeMap<String, Arc<dyn Rotator>>,
+}
+
+impl fmt::Debug for Rotations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rotations")
+            .field("policies", &self.policies)
+            .field("rotators", &self.rotators.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Rotations {
+    pub(crate) fn tracks(&self, key_name: &str) -> bool {
+        self.policies.contains_key(key_name)
+    }
+}
+
+/// A key whose rotation is due, returned by `Vault::due_for_rotation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DueRotation {
+    pub key: String,
+    /// Last rotation, in seconds since the Unix epoch; `None` if none was ever recorded.
+    pub rotated_at: Option<u64>,
+    /// When the rotation became due; `None` if it was never rotated.
+    pub due_at: Option<u64>,
+}
+
+/// The key recording when `key_name` was last stored.
+fn marker_key(key_name: &str) -> String {
+    internal_key(key_name, "rotated_at")
+}
+
+/// The value replaced by `KeyManager::rotate_key`, kept under `<key_name>#previous`.
+#[derive(Serialize, Deserialize)]
+struct PreviousValue {
+    value: String,
+    expires_at: u64,
+}
+
+/// When the previous value kept in a `<key_name>#previous` entry stops being valid, for
+/// `Vault::gc`.
+pub(crate) fn previous_expiry(key_name: &str, value: &str) -> Option<u64> {
+    if !matches!(split_internal_key(key_name), Some((_, "previous"))) {
+        return None;
+    }
+    serde_json::from_str::<PreviousValue>(value)
+        .ok()
+        .map(|previous| previous.expires_at)
+}
+
+impl KeyManager {
+    /// Stores `value` and keeps the value it replaces valid for `grace`: `read_all_valid`
+    /// returns both until then, so a service can accept either token while its clients
+    /// switch to the new one. The replaced value is kept in `<key_name>#previous`; rotating
+    /// again within the grace period replaces it.
+    pub fn rotate_key(&mut self, value: &str, grace: Duration) -> Result<()> {
+        let current = match self.read_key() {
+            Ok(current) => Some(current),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(current) = current.filter(|current| current != value) {
+            let previous = PreviousValue {
+                value: current,
+                expires_at: now_unix().saturating_add(grace.as_secs()),
+            };
+            let json =
+                serde_json::to_string(&previous).map_err(|e| self.error(Operation::Store, e))?;
+            self.previous_manager().store(&json)?;
+        }
+        self.store_as(value, ChangeKind::Rotated).map(drop)
+    }
+
+    /// The current value followed by the value it replaced, while `rotate_key`'s grace
+    /// period lasts. An expired previous value is deleted.
+    pub fn read_all_valid(&mut self) -> Result<Vec<String>> {
+        let mut values = vec![self.read_key()?];
+        let mut manager = self.previous_manager();
+        let json = match manager.read_key() {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(values),
+            Err(e) => return Err(e),
+        };
+        let previous: PreviousValue =
+            serde_json::from_str(&json).map_err(|e| manager.error(Operation::Read, e))?;
+        if previous.expires_at <= now_unix() {
+            if let Err(e) = manager.delete() {
+                hooks::warn(&format!(
+                    "could not delete an expired previous value: {}",
+                    e
+                ));
+            }
+        } else if previous.value != values[0] {
+            values.push(previous.value);
+        }
+        Ok(values)
+    }
+
+    fn previous_manager(&self) -> KeyManager {
+        let key_name = internal_key(&self.key_name, "previous");
+        self.sibling(&key_name, self.backend().clone())
+    }
+
+    /// Records that the key was just rotated, for `Vault::due_for_rotation`.
+    pub(crate) fn record_rotation(&self) {
+        let result = self.backend().set(
+            &self.system_name,
+            &marker_key(&self.key_name),
+            &now_unix().to_string(),
+        );
+        if let Err(e) = result {
+            hooks::warn(&format!(
+                "could not record the rotation of '{}': {}",
+                self.key_name, e
+            ));
+        }
+    }
+}
+
+impl Vault {
+    /// Tracks the rotation of `key_name`: every store through this vault's managers
+    /// records the time in `<key_name>#rotated_at`, and `due_for_rotation` reports the key
+    /// once `policy` says so.
+    pub fn with_rotation_policy(mut self, key_name: &str, policy: RotationPolicy) -> Self {
+        self.rotations_mut()
+            .policies
+            .insert(key_name.to_string(), policy);
+        self
+    }
+
+    /// Lets `rotate_due` rotate `key_name` with `rotator` when its policy says so.
+    pub fn with_rotator<R>(mut self, key_name: &str, rotator: R) -> Self
+    where
+        R: Rotator + 'static,
+    {
+        self.rotations_mut()
+            .rotators
+            .insert(key_name.to_string(), Arc::new(rotator));
+        self
+    }
+
+    /// When `key_name` was last stored through a vault tracking its rotation, else when
+    /// the backend last wrote it (SQLite stores record it), in seconds since the Unix
+    /// epoch.
+    pub fn rotated_at(&self, key_name: &str) -> Result<Option<u64>> {
+        let marker = marker_key(key_name);
+        match self.backend().get(self.system_name(), &marker) {
+            Ok(value) => Ok(value.trim().parse().ok()),
+            Err(keyring::Error::NoEntry) => self
+                .backend()
+                .modified(self.system_name(), key_name)
+                .map_err(|e| self.error(Operation::Read, e)),
+            Err(e) => Err(self.key(&marker).error(Operation::Read, e)),
+        }
+    }
+
+    /// The keys with a rotation policy that are due now, in key order. A key whose last
+    /// rotation is unknown is due.
+    pub fn due_for_rotation(&self) -> Result<Vec<DueRotation>> {
+        let now = now_unix();
+        let mut due = Vec::new();
+        for (key, policy) in &self.rotations().policies {
+            let rotated_at = self.rotated_at(key)?;
+            let due_at = match rotated_at {
+                Some(rotated_at) => match policy.due_at(rotated_at) {
+                    Some(due_at) if due_at <= now => Some(due_at),
+                    _ => continue,
+                },
+                None => None,
+            };
+            due.push(DueRotation {
+                key: key.clone(),
+                rotated_at,
+                due_at,
+            });
+        }
+        Ok(due)
+    }
+
+    /// Rotates every due key that has a rotator and returns their names. Due keys without
+    /// a rotator are left to the app. Stops at the first failure.
+    pub fn rotate_due(&self) -> Result<Vec<String>> {
+        let mut rotated = Vec::new();
+        for due in self.due_for_rotation()? {
+            let Some(rotator) = self.rotations().rotators.get(&due.key) else {
+                continue;
+            };
+            let mut manager = self.key(&due.key);
+            let current = match manager.read_key() {
+                Ok(value) => Some(value),
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            let value = rotator.rotate(&due.key, current.as_deref())?;
+            match self.rotations().policies[&due.key].grace_period {
+                Some(grace) => manager.rotate_key(&value, grace)?,
+                None => {
+                    manager.store_as(&value, ChangeKind::Rotated)?;
+                }
+            }
+            hooks::debug(&format!("rotated '{}'", due.key));
+            rotated.push(due.key);
+        }
+        Ok(rotated)
+    }
+}
+
+#[cfg(feature = "async")]
+impl crate::async_vault::AsyncVault {
+    /// Runs `Vault::rotate_due` now and then every `interval` on the tokio runtime, until
+    /// the returned task is aborted. Failures go to the warning hook.
+    pub fn spawn_rotation(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let vault = self.vault().clone();
+        tokio::spawn(async move {
+            loop {
+                let task = vault.clone();
+                match tokio::task::spawn_blocking(move || task.rotate_due()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => hooks::warn(&format!("scheduled rotation failed: {}", e)),
+                    Err(e) => hooks::warn(&format!("scheduled rotation panicked: {}", e)),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{assert_not_stored, assert_stored};
+
+    #[test]
+    fn test_schedule() {
+        let mondays = Schedule::parse("30 3 * * 1").unwrap();
+        // 1970-01-01 was a Thursday: the next Monday is January 5th.
+        assert_eq!(mondays.next_after(0), Some(4 * 86_400 + 3 * 3600 + 1800));
+        let quarters: Schedule = "*/15 * * * *".parse().unwrap();
+        assert_eq!(quarters.next_after(60), Some(900));
+        assert_eq!(quarters.next_after(900), Some(1800));
+        let leap_day = Schedule::parse("0 0 29 2 *").unwrap();
+        // 1972-02-29.
+        assert_eq!(leap_day.next_after(0), Some(789 * 86_400));
+        assert_eq!(Schedule::parse("0 0 30 2 *").unwrap().next_after(0), None);
+
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("* * *").is_err());
+        assert!(Schedule::parse("5-1 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_rotate_due_keys() {
+        let vault = Vault::ephemeral_for_tests()
+            .with_rotation_policy(
+                "api_key",
+                RotationPolicy::new().with_max_age(Duration::from_secs(86_400)),
+            )
+            .with_rotation_policy(
+                "db_password",
+                RotationPolicy::new().with_schedule(Schedule::parse("0 3 * * *").unwrap()),
+            )
+            .with_rotator("api_key", |_: &str, current: Option<&str>| {
+                Ok(format!("rotated {}", current.unwrap_or_default()))
+            });
+        vault.key("api_key").store("v1").unwrap();
+        let due: Vec<String> = vault
+            .due_for_rotation()
+            .unwrap()
+            .into_iter()
+            .map(|due| due.key)
+            .collect();
+        assert_eq!(due, ["db_password"]);
+
+        // Pretend the key was last rotated at the epoch.
+        vault.key("api_key#rotated_at").store("0").unwrap();
+        assert_eq!(vault.due_for_rotation().unwrap()[0].due_at, Some(86_400));
+        assert_eq!(vault.rotate_due().unwrap(), ["api_key"]);
+        assert_stored(&vault, "api_key", "rotated v1");
+        assert!(vault.rotated_at("api_key").unwrap().unwrap() > 86_400);
+        assert_eq!(vault.due_for_rotation().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_read_all_valid_during_grace_period() {
+        let vault = Vault::ephemeral_for_tests();
+        let mut manager = vault.key("api_key");
+        manager.store("old").unwrap();
+        assert_eq!(manager.read_all_valid().unwrap(), ["old"]);
+
+        manager
+            .rotate_key("new", Duration::from_secs(3600))
+            .unwrap();
+        assert_eq!(manager.read_all_valid().unwrap(), ["new", "old"]);
+        assert_stored(&vault, "api_key", "new");
+
+        manager.rotate_key("newer", Duration::ZERO).unwrap();
+        assert_eq!(manager.read_all_valid().unwrap(), ["newer"]);
+        assert_not_stored(&vault, "api_key#previous");
+    }
+
+    #[test]
+    fn test_rotation_is_reported_once() {
+        use crate::hooks::{add_change_hook, ChangeEvent};
+        use std::sync::Mutex;
+
+        let vault = Vault::ephemeral_for_tests().with_rotation_policy(
+            "api_key",
+            RotationPolicy::new().with_max_age(Duration::ZERO),
+        );
+        let system = vault.system_name().to_string();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        add_change_hook(move |event: &ChangeEvent| {
+            if event.system == system {
+                sink.lock().unwrap().push((event.kind, event.key.clone()));
+            }
+        });
+        let mut manager = vault.key("api_key");
+        manager.store("old").unwrap();
+        manager
+            .rotate_key("new", Duration::from_secs(3600))
+            .unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                (ChangeKind::Stored, "api_key".to_string()),
+                (ChangeKind::Rotated, "api_key".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use schemars::schema::{InstanceType, RootSchema, Schema, SchemaObject, SingleOrVec};
+use serde_json::{Map, Value};
+
+/// Checks `value` against a schema generated by schemars, returning a message naming the
+/// first offending field, like `field 'port' expected integer, found string`.
+///
+/// Covers what `#[derive(JsonSchema)]` produces for plain data types: types, required and
+/// unknown properties, items, enum values, numeric bounds, string lengths and the
+/// `anyOf`/`oneOf`/`allOf` combinations used for `Option` and enums. String patterns and
+/// formats other than the integer widths (`uint16`, ...) are not checked.
+pub(crate) fn validate(root: &RootSchema, value: &Value) -> Result<(), String> {
+    Validator { root }.check_object(&root.schema, value, "")
+}
+
+struct Validator<'a> {
+    root: &'a RootSchema,
+}
+
+impl Validator<'_> {
+    fn check(&self, schema: &Schema, value: &Value, path: &str) -> Result<(), String> {
+        match schema {
+            Schema::Bool(true) => Ok(()),
+            Schema::Bool(false) => Err(format!("{} is not allowed", describe(path))),
+            Schema::Object(object) => self.check_object(object, value, path),
+        }
+    }
+
+    fn check_object(&self, schema: &SchemaObject, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = &schema.reference {
+            let name = reference.rsplit('/').next().unwrap_or(reference);
+            let target = self
+                .root
+                .definitions
+                .get(name)
+                .ok_or_else(|| format!("unknown schema reference '{}'", reference))?;
+            self.check(target, value, path)?;
+        }
+        if let Some(types) = &schema.instance_type {
+            let allowed: &[InstanceType] = match types {
+                SingleOrVec::Single(single) => std::slice::from_ref(single.as_ref()),
+                SingleOrVec::Vec(types) => types,
+            };
+            if !allowed.iter().any(|kind| has_type(value, kind)) {
+                let expected: Vec<&str> = allowed.iter().map(type_name).collect();
+                return Err(format!(
+                    "{} expected {}, found {}",
+                    describe(path),
+                    expected.join(" or "),
+                    value_type(value)
+                ));
+            }
+        }
+        if let Some(values) = &schema.enum_values {
+            if !values.contains(value) {
+                return Err(format!(
+                    "{} expected one of {}, found {}",
+                    describe(path),
+                    list_values(values),
+                    value
+                ));
+            }
+        }
+        if let Some(constant) = &schema.const_value {
+            if constant != value {
+                return Err(format!(
+                    "{} expected {}, found {}",
+                    describe(path),
+                    constant,
+                    value
+                ));
+            }
+        }
+        if let Some(subschemas) = &schema.subschemas {
+            for subschema in subschemas.all_of.iter().flatten() {
+                self.check(subschema, value, path)?;
+            }
+            for alternatives in [&subschemas.any_of, &subschemas.one_of]
+                .into_iter()
+                .flatten()
+            {
+                self.check_alternatives(alternatives, value, path)?;
+            }
+        }
+        match value {
+            Value::Number(number) => self.check_number(schema, number.as_f64(), path),
+            Value::String(text) => self.check_string(schema, text, path),
+            Value::Array(items) => self.check_array(schema, items, path),
+            Value::Object(fields) => self.check_fields(schema, fields, path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Passes when one alternative does. Otherwise reports the error of the only
+    /// alternative of the right type, if there is one, since it is the likely intent.
+    fn check_alternatives(
+        &self,
+        alternatives: &[Schema],
+        value: &Value,
+        path: &str,
+    ) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for alternative in alternatives {
+            match self.check(alternative, value, path) {
+                Ok(()) => return Ok(()),
+                Err(message) => errors.push(message),
+            }
+        }
+        let mismatch = format!("{} expected ", describe(path));
+        let mut relevant = errors
+            .into_iter()
+            .filter(|message| !message.starts_with(&mismatch) || !message.contains(", found "));
+        match (relevant.next(), relevant.next()) {
+            (Some(message), None) => Err(message),
+            _ => Err(format!(
+                "{} matches none of the allowed variants, found {}",
+                describe(path),
+                value
+            )),
+        }
+    }
+
+    fn check_number(
+        &self,
+        schema: &SchemaObject,
+        number: Option<f64>,
+        path: &str,
+    ) -> Result<(), String> {
+        let Some(number) = number else {
+            return Ok(());
+        };
+        let mut out_of_range = schema
+            .format
+            .as_deref()
+            .and_then(integer_range)
+            .is_some_and(|(min, max)| number < min || number > max);
+        if let Some(bounds) = &schema.number {
+            out_of_range |= bounds.minimum.is_some_and(|min| number < min)
+                || bounds.exclusive_minimum.is_some_and(|min| number <= min)
+                || bounds.maximum.is_some_and(|max| number > max)
+                || bounds.exclusive_maximum.is_some_and(|max| number >= max);
+        }
+        if out_of_range {
+            return Err(format!(
+                "{} is out of range, found {}",
+                describe(path),
+                number
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_string(&self, schema: &SchemaObject, text: &str, path: &str) -> Result<(), String> {
+        let Some(bounds) = &schema.string else {
+            return Ok(());
+        };
+        let length = text.chars().count() as u32;
+        if bounds.min_length.is_some_and(|min| length < min)
+            || bounds.max_length.is_some_and(|max| length > max)
+        {
+            return Err(format!(
+                "{} has an invalid length of {} characters",
+                describe(path),
+                length
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_array(
+        &self,
+        schema: &SchemaObject,
+        items: &[Value],
+        path: &str,
+    ) -> Result<(), String> {
+        let Some(array) = &schema.array else {
+            return Ok(());
+        };
+        for (index, item) in items.iter().enumerate() {
+            let item_schema = match &array.items {
+                Some(SingleOrVec::Single(schema)) => Some(schema.as_ref()),
+                Some(SingleOrVec::Vec(schemas)) => {
+                    schemas.get(index).or(array.additional_items.as_deref())
+                }
+                None => None,
+            };
+            if let Some(item_schema) = item_schema {
+                self.check(item_schema, item, &format!("{}[{}]", path, index))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_fields(
+        &self,
+        schema: &SchemaObject,
+        fields: &Map<String, Value>,
+        path: &str,
+    ) -> Result<(), String> {
+        let Some(object) = &schema.object else {
+            return Ok(());
+        };
+        for name in &object.required {
+            if !fields.contains_key(name) {
+                return Err(format!("{} is missing", describe(&join(path, name))));
+            }
+        }
+        for (name, field) in fields {
+            let field_path = join(path, name);
+            match object.properties.get(name) {
+                Some(field_schema) => self.check(field_schema, field, &field_path)?,
+                None => {
+                    if let Some(additional) = &object.additional_properties {
+                        if matches!(additional.as_ref(), Schema::Bool(false)) {
+                            return Err(format!("{} is not expected", describe(&field_path)));
+                        }
+                        self.check(additional, field, &field_path)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+fn describe(path: &str) -> String {
+    if path.is_empty() {
+        "value".to_string()
+    } else {
+        format!("field '{}'", path)
+    }
+}
+
+/// Bounds of the integer formats schemars emits for Rust's fixed-size integers.
+fn integer_range(format: &str) -> Option<(f64, f64)> {
+    let range = match format {
+        "uint8" => (0.0, u8::MAX as f64),
+        "uint16" => (0.0, u16::MAX as f64),
+        "uint32" => (0.0, u32::MAX as f64),
+        "int8" => (i8::MIN as f64, i8::MAX as f64),
+        "int16" => (i16::MIN as f64, i16::MAX as f64),
+        "int32" => (i32::MIN as f64, i32::MAX as f64),
+        _ => return None,
+    };
+    Some(range)
+}
+
+fn has_type(value: &Value, kind: &InstanceType) -> bool {
+    match kind {
+        InstanceType::Null => value.is_null(),
+        InstanceType::Boolean => value.is_boolean(),
+        InstanceType::Object => value.is_object(),
+        InstanceType::Array => value.is_array(),
+        InstanceType::Number => value.is_number(),
+        InstanceType::String => value.is_string(),
+        InstanceType::Integer => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+    }
+}
+
+fn type_name(kind: &InstanceType) -> &'static str {
+    match kind {
+        InstanceType::Null => "null",
+        InstanceType::Boolean => "boolean",
+        InstanceType::Object => "object",
+        InstanceType::Array => "array",
+        InstanceType::Number => "number",
+        InstanceType::String => "string",
+        InstanceType::Integer => "integer",
+    }
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn list_values(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(Value::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use schemars::{schema_for, JsonSchema};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    enum Mode {
+        Fast,
+        Safe,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize, JsonSchema)]
+    struct Settings {
+        server: Server,
+        mode: Mode,
+        mirrors: Vec<Server>,
+        timeout: Option<u32>,
+    }
+
+    #[test]
+    fn test_validate_reports_the_field() {
+        let schema = schema_for!(Settings);
+        let valid = json!({
+            "server": {"host": "db", "port": 5432},
+            "mode": "fast",
+            "mirrors": [],
+            "timeout": null
+        });
+        assert_eq!(validate(&schema, &valid), Ok(()));
+
+        let cases = [
+            (
+                "/server/port",
+                json!("5432"),
+                "field 'server.port' expected integer, found string",
+            ),
+            (
+                "/server/port",
+                json!(70000),
+                "field 'server.port' is out of range, found 70000",
+            ),
+            (
+                "/mode",
+                json!("slow"),
+                "field 'mode' expected one of \"fast\", \"safe\", found \"slow\"",
+            ),
+            (
+                "/mirrors",
+                json!([{"host": 1, "port": 1}]),
+                "field 'mirrors[0].host' expected string, found integer",
+            ),
+            (
+                "/timeout",
+                json!("soon"),
+                "field 'timeout' expected integer or null, found string",
+            ),
+        ];
+        for (pointer, field, message) in cases {
+            let mut invalid = valid.clone();
+            *invalid.pointer_mut(pointer).unwrap() = field;
+            assert_eq!(validate(&schema, &invalid), Err(message.to_string()));
+        }
+
+        let mut missing = valid.clone();
+        missing["server"].as_object_mut().unwrap().remove("host");
+        assert_eq!(
+            validate(&schema, &missing),
+            Err("field 'server.host' is missing".to_string())
+        );
+    }
+}
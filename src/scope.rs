@@ -0,0 +1,142 @@
+use crate::collection_key_manager::CollectionKeyManager;
+use crate::error::{Cause, Operation, Result};
+use crate::key_manager::KeyManager;
+use crate::map_key_manager::MapKeyManager;
+use crate::naming::validate_name;
+use crate::struct_key_manager::StructKeyManager;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+
+/// Separates the scope from the key name in the stored entry name.
+const SEPARATOR: char = '/';
+
+/// A view of a [`Vault`] limited to one scope, e.g. one tenant of a multi-tenant agent.
+///
+/// Every key is stored as `<scope>/<key>` in the parent vault, and `keys` and `purge`
+/// only see the entries of this scope. Scopes can't contain `/`, so one scope's keys
+/// can never be mistaken for another's.
+#[derive(Debug, Clone)]
+pub struct ScopedVault {
+    vault: Vault,
+    scope: String,
+}
+
+impl ScopedVault {
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    fn entry_name(&self, key_name: &str) -> String {
+        format!("{}{}{}", self.scope, SEPARATOR, key_name)
+    }
+
+    /// Returns a manager for a plain string key of this scope.
+    pub fn key(&self, key_name: &str) -> KeyManager {
+        self.vault.key(&self.entry_name(key_name))
+    }
+
+    /// Returns a manager for a struct stored under `key_name` in this scope.
+    pub fn structured<T>(&self, key_name: &str) -> StructKeyManager<T>
+    where
+        T: Serialize + for<'de> Deserialize<'de> + Default,
+    {
+        self.vault.structured(&self.entry_name(key_name))
+    }
+
+    /// Returns a manager for a map of secrets stored under `key_name` in this scope.
+    pub fn map(&self, key_name: &str) -> MapKeyManager {
+        self.vault.map(&self.entry_name(key_name))
+    }
+
+    /// Returns a manager for a list of structs stored under `key_name` in this scope.
+    pub fn collection<T>(&self, key_name: &str) -> CollectionKeyManager<T>
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        self.vault.collection(&self.entry_name(key_name))
+    }
+
+    /// The key names of this scope, without the scope prefix. Requires a backend that
+    /// supports listing.
+    pub fn keys(&self) -> Result<Vec<String>> {
+        let prefix = self.entry_name("");
+        let names = self
+            .vault
+            .backend()
+            .list(self.vault.system_name())
+            .map_err(|e| self.vault.error(Operation::Read, e))?;
+        Ok(names
+            .into_iter()
+            .filter_map(|name| name.strip_prefix(&prefix).map(str::to_string))
+            .collect())
+    }
+
+    /// Deletes every key of this scope and returns how many were deleted. Other scopes
+    /// and unscoped keys of the vault are left alone.
+    pub fn purge(&self) -> Result<usize> {
+        let keys = self.keys()?;
+        for key_name in &keys {
+            self.key(key_name).delete()?;
+        }
+        Ok(keys.len())
+    }
+}
+
+impl Vault {
+    /// Returns a view of this vault whose keys are namespaced under `scope`.
+    ///
+    /// ```no_run
+    /// use key_vaulter::Vault;
+    ///
+    /// let tenant = Vault::new("my_agent").scoped("tenant-42")?;
+    /// tenant.key("api_key").store("secret")?;
+    /// tenant.purge()?;
+    /// # Ok::<(), key_vaulter::Error>(())
+    /// ```
+    ///
+    /// Returns an error of kind `ErrorKind::InvalidName` when `scope` isn't a valid name
+    /// (see `naming::validate_name`) or contains `/`.
+    pub fn scoped(&self, scope: &str) -> Result<ScopedVault> {
+        validate_name(scope)
+            .and_then(|()| {
+                if scope.contains(SEPARATOR) {
+                    Err(format!("scope must not contain '{}'", SEPARATOR))
+                } else {
+                    Ok(())
+                }
+            })
+            .map_err(|reason| self.error(Operation::Validate, Cause::InvalidName(reason)))?;
+        Ok(ScopedVault {
+            vault: self.clone(),
+            scope: scope.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::test_utils::assert_stored;
+
+    #[test]
+    fn test_scopes_are_isolated() {
+        let vault = Vault::ephemeral_for_tests();
+        let acme = vault.scoped("acme").unwrap();
+        let globex = vault.scoped("globex").unwrap();
+        acme.key("api_key").store("acme-key").unwrap();
+        acme.key("token").store("acme-token").unwrap();
+        globex.key("api_key").store("globex-key").unwrap();
+        vault.key("shared").store("shared").unwrap();
+
+        assert_stored(&vault, "acme/api_key", "acme-key");
+        assert_eq!(acme.keys().unwrap(), vec!["api_key", "token"]);
+        assert_eq!(acme.purge().unwrap(), 2);
+        assert!(acme.keys().unwrap().is_empty());
+        assert_eq!(globex.key("api_key").read_key().unwrap(), "globex-key");
+        assert_stored(&vault, "shared", "shared");
+
+        let error = vault.scoped("acme/../globex").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidName);
+    }
+}
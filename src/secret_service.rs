@@ -0,0 +1,83 @@
+//! Direct access to the Secret Service (Linux and BSD) for what keyring doesn't offer:
+//! searching items by attribute and setting their label.
+
+use keyring::Result;
+use std::collections::HashMap;
+
+/// Whether the OS keyring is the Secret Service on this platform.
+pub(crate) const AVAILABLE: bool = cfg!(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd"
+));
+
+/// Attributes keyring uses to identify an item, which callers can't override.
+pub(crate) const RESERVED_ATTRIBUTES: [&str; 3] = ["target", "service", "username"];
+
+/// Returns the attributes of every item, locked or not, matching all of `attributes`.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+pub(crate) fn search(attributes: &[(&str, &str)]) -> Result<Vec<HashMap<String, String>>> {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let failure = |e: dbus_secret_service::Error| keyring::Error::PlatformFailure(Box::new(e));
+    let service = SecretService::connect(EncryptionType::Plain).map_err(failure)?;
+    let found = service
+        .search_items(attributes.iter().copied().collect())
+        .map_err(failure)?;
+    found
+        .unlocked
+        .iter()
+        .chain(found.locked.iter())
+        .map(|item| item.get_attributes().map_err(failure))
+        .collect()
+}
+
+/// Returns the attributes of every item, locked or not, matching all of `attributes`.
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+pub(crate) fn search(_attributes: &[(&str, &str)]) -> Result<Vec<HashMap<String, String>>> {
+    Err(keyring::Error::PlatformFailure(
+        "the Secret Service is not available on this platform".into(),
+    ))
+}
+
+/// Sets the label (the name shown by keyring browsers) of the unlocked items of
+/// `system_name` and `key_name`.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd"))]
+pub(crate) fn set_label(system_name: &str, key_name: &str, label: &str) -> Result<()> {
+    use dbus_secret_service::{EncryptionType, SecretService};
+
+    let failure = |e: dbus_secret_service::Error| keyring::Error::PlatformFailure(Box::new(e));
+    let service = SecretService::connect(EncryptionType::Plain).map_err(failure)?;
+    let found = service
+        .search_items(HashMap::from([
+            ("service", system_name),
+            ("username", key_name),
+        ]))
+        .map_err(failure)?;
+    for item in &found.unlocked {
+        item.set_label(label).map_err(failure)?;
+    }
+    Ok(())
+}
+
+/// Sets the label (the name shown by keyring browsers) of the unlocked items of
+/// `system_name` and `key_name`.
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+pub(crate) fn set_label(_system_name: &str, _key_name: &str, _label: &str) -> Result<()> {
+    Err(keyring::Error::PlatformFailure(
+        "the Secret Service is not available on this platform".into(),
+    ))
+}
+
+/// Returns the names of the keys of `system_name` whose items match all of `attributes`.
+pub(crate) fn find_keys(system_name: &str, attributes: &[(&str, &str)]) -> Result<Vec<String>> {
+    let mut query = vec![("service", system_name)];
+    query.extend_from_slice(attributes);
+    let mut keys: Vec<String> = search(&query)?
+        .into_iter()
+        .filter_map(|mut item| item.remove("username"))
+        .collect();
+    keys.sort();
+    keys.dedup();
+    Ok(keys)
+}
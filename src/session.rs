@@ -0,0 +1,320 @@
+use crate::backend::{Backend, Capabilities};
+use crate::error::{Operation, Result, StoreLocked};
+use crate::file_store::{self, EncryptedFileStore};
+use crate::key_manager::KeyManager;
+use crate::registry;
+use crate::store::{CustomStore, SecretStore};
+use crate::struct_key_manager::StructKeyManager;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// A passphrase-protected vault that hasn't been unlocked yet.
+///
+/// It offers no way to read or write secrets: call `unlock` to get an `UnlockedVault`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedVault {
+    system_name: String,
+    path: PathBuf,
+}
+
+impl LockedVault {
+    /// A vault for `system_name` stored in the encrypted file at `path`.
+    pub fn new(system_name: &str, path: impl Into<PathBuf>) -> Self {
+        LockedVault {
+            system_name: system_name.to_string(),
+            path: path.into(),
+        }
+    }
+
+    pub fn system_name(&self) -> &str {
+        &self.system_name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Unlocks the vault with the master passphrase.
+    ///
+    /// Fails with `ErrorKind::AccessDenied` when the passphrase doesn't open the existing
+    /// entries.
+    pub fn unlock(&self, passphrase: &str) -> Result<UnlockedVault> {
+        let store = EncryptedFileStore::new(&self.path).with_passphrase(passphrase);
+        let session = SessionStore {
+            session: Arc::new(Mutex::new(Session {
+                store: Some(store),
+                idle_timeout: None,
+                last_access: Instant::now(),
+            })),
+        };
+        let vault = Vault::new(&self.system_name).with_backend(Backend::custom(session.clone()));
+        if let Some(store) = session.session().store.as_ref() {
+            store
+                .verify_passphrase()
+                .map_err(|e| vault.error(Operation::Read, e))?;
+        }
+        Ok(UnlockedVault {
+            vault,
+            session,
+            locked: self.clone(),
+        })
+    }
+
+    /// Unlocks the vault with a passphrase obtained from `provider`, e.g. one kept in an
+    /// OS keychain item protected by biometrics (Touch ID, Windows Hello).
+    pub fn unlock_with<F>(&self, provider: F) -> Result<UnlockedVault>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        let passphrase = Zeroizing::new(provider()?);
+        self.unlock(&passphrase)
+    }
+}
+
+/// The state of an unlocked session.
+struct Session {
+    /// The encrypted file, with the passphrase; `None` once locked.
+    store: Option<EncryptedFileStore>,
+    idle_timeout: Option<Duration>,
+    last_access: Instant,
+}
+
+impl Session {
+    /// Drops the passphrase, zeroizing it, when the idle timeout has passed since the
+    /// last access. Returns whether the session is locked.
+    fn lock_if_idle(&mut self) -> bool {
+        if self
+            .idle_timeout
+            .is_some_and(|timeout| self.last_access.elapsed() >= timeout)
+        {
+            self.store = None;
+        }
+        self.store.is_none()
+    }
+}
+
+/// The encrypted file of an unlocked session, shared by every manager and vault handed
+/// out by it. `lock` empties it, after which they fail with `ErrorKind::Locked`.
+#[derive(Clone)]
+struct SessionStore {
+    session: Arc<Mutex<Session>>,
+}
+
+impl SessionStore {
+    fn session(&self) -> MutexGuard<'_, Session> {
+        self.session.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn with_store<R>(
+        &self,
+        f: impl FnOnce(&EncryptedFileStore) -> keyring::Result<R>,
+    ) -> keyring::Result<R> {
+        let mut session = self.session();
+        session.lock_if_idle();
+        let result = match session.store.as_ref() {
+            Some(store) => f(store),
+            None => Err(keyring::Error::NoStorageAccess(Box::new(StoreLocked(
+                "the vault is locked".to_string(),
+            )))),
+        };
+        // Counted from the end, as deriving the key of a first access may take a while.
+        session.last_access = Instant::now();
+        result
+    }
+}
+
+impl fmt::Debug for SessionStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionStore")
+            .field("locked", &self.session().store.is_none())
+            .finish()
+    }
+}
+
+/// Drops the passphrase of `session`, zeroizing it, and forgets the values cached by the
+/// managers of `backend` and the keys derived from passphrases.
+fn lock_session(session: &SessionStore, backend: &Backend) {
+    session.session().store = None;
+    registry::forget_values(Some(backend));
+    file_store::forget_derived_keys();
+}
+
+impl SecretStore for SessionStore {
+    fn get(&self, system_name: &str, key_name: &str) -> keyring::Result<String> {
+        self.with_store(|store| store.get(system_name, key_name))
+    }
+
+    fn set(&self, system_name: &str, key_name: &str, value: &str) -> keyring::Result<()> {
+        self.with_store(|store| store.set(system_name, key_name, value))
+    }
+
+    fn delete(&self, system_name: &str, key_name: &str) -> keyring::Result<()> {
+        self.with_store(|store| store.delete(system_name, key_name))
+    }
+
+    fn list(&self, system_name: &str) -> keyring::Result<Vec<String>> {
+        self.with_store(|store| store.list(system_name))
+    }
+
+    fn name(&self) -> &'static str {
+        "encrypted-file"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supports_listing: true,
+            max_value_size: None,
+            persistent: true,
+            requires_unlock: true,
+        }
+    }
+
+    fn health_check(&self) -> keyring::Result<()> {
+        self.with_store(EncryptedFileStore::health_check)
+    }
+}
+
+/// An unlocked vault. Reading and writing secrets is only possible through this type.
+///
+/// `lock` drops the passphrase (zeroizing its memory), forgets the values cached for the
+/// vault's keys and the keys derived from passphrases, and gives the `LockedVault` back.
+/// Managers and vaults handed out by `key`, `structured` and `vault` share the session's
+/// passphrase, so once it is locked they fail with `ErrorKind::Locked`. With
+/// `with_idle_timeout`, the session also locks itself once it hasn't been used for a
+/// while.
+#[derive(Debug)]
+pub struct UnlockedVault {
+    vault: Vault,
+    session: SessionStore,
+    locked: LockedVault,
+}
+
+impl UnlockedVault {
+    /// The underlying vault, for operations spanning several keys.
+    pub fn vault(&self) -> &Vault {
+        &self.vault
+    }
+
+    /// See `Vault::key`.
+    pub fn key(&self, key_name: &str) -> KeyManager {
+        self.vault.key(key_name)
+    }
+
+    /// See `Vault::structured`.
+    pub fn structured<T>(&self, key_name: &str) -> StructKeyManager<T>
+    where
+        T: Serialize + for<'de> Deserialize<'de> + Default,
+    {
+        self.vault.structured(key_name)
+    }
+
+    /// Locks the vault again, zeroizing the passphrase held by this session and forgetting
+    /// the values its managers cached (see `KeyManager::cached_value`) and the keys derived
+    /// from passphrases.
+    pub fn lock(self) -> LockedVault {
+        lock_session(&self.session, self.vault.backend());
+        self.locked
+    }
+
+    /// Locks the session, as `lock` does, once no manager has read or written a value
+    /// for `timeout`, so a long-running tool doesn't keep the passphrase in memory all
+    /// day. After that, every access fails with `ErrorKind::Locked` until the vault is
+    /// unlocked again.
+    pub fn with_idle_timeout(self, timeout: Duration) -> Self {
+        {
+            let mut session = self.session.session();
+            session.idle_timeout = Some(timeout);
+            session.last_access = Instant::now();
+        }
+        let Backend::Custom(store) = self.vault.backend() else {
+            unreachable!("a session's vault uses its session store");
+        };
+        let store = Arc::downgrade(store.inner());
+        let session = self.session.clone();
+        // Checks a few times per timeout, so the passphrase doesn't outlive it by much.
+        let interval = (timeout / 4).clamp(Duration::from_millis(10), Duration::from_secs(15));
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            // Stops once every manager and vault of the session is gone.
+            let Some(store) = store.upgrade() else {
+                break;
+            };
+            if session.session().lock_if_idle() {
+                lock_session(&session, &Backend::Custom(CustomStore::new(store)));
+                break;
+            }
+        });
+        self
+    }
+
+    /// Whether the session was locked by `with_idle_timeout`; if so, `lock` it and unlock
+    /// the returned `LockedVault` again.
+    pub fn is_locked(&self) -> bool {
+        self.session.session().lock_if_idle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join("key_vaulter_tests").join(format!(
+            "{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_unlock_and_lock() {
+        let locked = LockedVault::new("session_service", temp_path("session"));
+        let unlocked = locked.unlock("master").unwrap();
+        let mut token = unlocked.key("token");
+        token.store("secret").unwrap();
+        let vault = unlocked.vault().clone();
+        let locked = unlocked.lock();
+        assert_eq!(token.cached_value(), None);
+        assert_eq!(token.read_key().unwrap_err().kind(), ErrorKind::Locked);
+        assert_eq!(
+            vault.key("token").store("other").unwrap_err().kind(),
+            ErrorKind::Locked
+        );
+
+        let error = locked.unlock("wrong").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AccessDenied);
+
+        let unlocked = locked.unlock_with(|| Ok("master".to_string())).unwrap();
+        assert_eq!(unlocked.key("token").read_key().unwrap(), "secret");
+    }
+
+    #[test]
+    fn test_idle_session_locks_itself() {
+        let locked = LockedVault::new("session_service", temp_path("session_idle"));
+        let unlocked = locked
+            .unlock("master")
+            .unwrap()
+            .with_idle_timeout(Duration::from_millis(100));
+        let mut token = unlocked.key("token");
+        token.store("secret").unwrap();
+        assert_eq!(token.read_key().unwrap(), "secret");
+        assert!(!unlocked.is_locked());
+
+        thread::sleep(Duration::from_millis(300));
+        assert!(unlocked.is_locked());
+        assert_eq!(token.cached_value(), None);
+        assert_eq!(token.read_key().unwrap_err().kind(), ErrorKind::Locked);
+
+        let unlocked = unlocked.lock().unlock("master").unwrap();
+        assert_eq!(unlocked.key("token").read_key().unwrap(), "secret");
+    }
+}
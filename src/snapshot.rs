@@ -0,0 +1,135 @@
+use crate::error::{Operation, Result};
+use crate::naming::is_internal_key;
+use crate::timestamp::now_unix;
+use crate::vault::Vault;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+
+/// One key of a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub key: String,
+    /// `KeyManager::digest_of` the value, with the snapshot's salt.
+    pub digest: String,
+    /// When the backend last wrote the key, for backends recording it (SQLite stores).
+    pub modified: Option<u64>,
+}
+
+/// An immutable view of a vault taken by `Vault::snapshot`, for audit evidence: the key
+/// names, their metadata and salted digests of their values, never the values.
+///
+/// Serialize it (e.g. with `serde_json`) to archive it. Two snapshots taken with the same
+/// salt (`Vault::snapshot_with_salt`) show which values changed between them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    system: String,
+    backend: String,
+    taken_at: u64,
+    /// Hex-encoded.
+    salt: String,
+    entries: Vec<SnapshotEntry>,
+}
+
+impl Snapshot {
+    pub fn system(&self) -> &str {
+        &self.system
+    }
+
+    pub fn backend(&self) -> &str {
+        &self.backend
+    }
+
+    /// When the snapshot was taken, in seconds since the Unix epoch.
+    pub fn taken_at(&self) -> u64 {
+        self.taken_at
+    }
+
+    /// The salt of the digests, hex-encoded.
+    pub fn salt(&self) -> &str {
+        &self.salt
+    }
+
+    /// The keys, in key order.
+    pub fn entries(&self) -> &[SnapshotEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, key_name: &str) -> Option<&SnapshotEntry> {
+        self.entries.iter().find(|entry| entry.key == key_name)
+    }
+}
+
+impl Vault {
+    /// Takes a [`Snapshot`] of every key of this vault (not the entries the crate keeps for
+    /// itself, see `naming::is_internal_key`), with a random salt so the digests
+    /// can't be matched against guessed values. Values are hashed as by
+    /// `KeyManager::digest_of`, without approvers or prompts, and never kept. Requires a
+    /// backend that supports listing.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        self.snapshot_with_salt(&salt)
+    }
+
+    /// Same as `snapshot`, with a given salt, so snapshots taken at different times can
+    /// be compared. Keep the salt as private as the snapshots.
+    pub fn snapshot_with_salt(&self, salt: &[u8]) -> Result<Snapshot> {
+        let mut keys = self
+            .backend()
+            .list(self.system_name())
+            .map_err(|e| self.error(Operation::Read, e))?;
+        keys.retain(|key| !is_internal_key(key));
+        keys.sort();
+        let mut entries = Vec::new();
+        for key in keys {
+            let digest = match self.backend().digest(self.system_name(), &key, salt) {
+                Ok(digest) => digest,
+                Err(keyring::Error::NoEntry) => continue,
+                Err(e) => return Err(self.key(&key).error(Operation::Read, e)),
+            };
+            let modified = self
+                .backend()
+                .modified(self.system_name(), &key)
+                .map_err(|e| self.key(&key).error(Operation::Read, e))?;
+            entries.push(SnapshotEntry {
+                key,
+                digest,
+                modified,
+            });
+        }
+        Ok(Snapshot {
+            system: self.system_name().to_string(),
+            backend: self.backend().name().to_string(),
+            taken_at: now_unix(),
+            salt: salt.iter().map(|b| format!("{:02x}", b)).collect(),
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_never_holds_values() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("db_password").store("hunter2").unwrap();
+        vault.key("api_key").store("abc123").unwrap();
+
+        let first = vault.snapshot_with_salt(b"audit 2024").unwrap();
+        let keys: Vec<&str> = first.entries().iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["api_key", "db_password"]);
+        assert_eq!(first.backend(), "memory");
+        let json = serde_json::to_string(&first).unwrap();
+        assert!(!json.contains("hunter2") && !json.contains("abc123"));
+
+        vault.key("api_key").store("rotated").unwrap();
+        let second = vault.snapshot_with_salt(b"audit 2024").unwrap();
+        assert_ne!(first.get("api_key"), second.get("api_key"));
+        assert_eq!(first.get("db_password"), second.get("db_password"));
+        let unsalted = vault.snapshot().unwrap();
+        assert_ne!(unsalted.get("db_password"), second.get("db_password"));
+    }
+}
@@ -0,0 +1,578 @@
+use crate::age_file::{decrypt, encrypt_armored, parse_identities};
+use crate::error::{Cause, Operation, Result};
+use crate::timestamp::{format_rfc3339, now_unix};
+use crate::vault::Vault;
+use aes_gcm::aead::consts::U32;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::aes::Aes256;
+use aes_gcm::{AesGcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::Value as JsonValue;
+use serde_yaml::{Mapping, Value as YamlValue};
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// sops encrypts every value with AES-256-GCM using a 32-byte IV.
+type SopsCipher = AesGcm<Aes256, U32>;
+
+const SOPS_VERSION: &str = "3.8.1";
+const UNENCRYPTED_SUFFIX: &str = "_unencrypted";
+const TAG_LEN: usize = 16;
+
+/// A leaf value in a sops document, with its sops type name.
+enum Leaf {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl Leaf {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Leaf::Str(_) => "str",
+            Leaf::Int(_) => "int",
+            Leaf::Float(_) => "float",
+            Leaf::Bool(_) => "bool",
+        }
+    }
+
+    /// Plaintext bytes as sops encrypts and MACs them (Go's `strconv` formatting, and
+    /// booleans capitalized as `True`/`False`).
+    fn to_plaintext(&self) -> String {
+        match self {
+            Leaf::Str(s) => s.clone(),
+            Leaf::Int(i) => i.to_string(),
+            Leaf::Float(f) => f.to_string(),
+            Leaf::Bool(true) => "True".to_string(),
+            Leaf::Bool(false) => "False".to_string(),
+        }
+    }
+
+    fn parse(type_name: &str, plaintext: String) -> std::result::Result<Leaf, String> {
+        let invalid = |_| format!("invalid {} value", type_name);
+        match type_name {
+            "str" => Ok(Leaf::Str(plaintext)),
+            "int" => plaintext.parse().map(Leaf::Int).map_err(|_| invalid(())),
+            "float" => plaintext.parse().map(Leaf::Float).map_err(|_| invalid(())),
+            "bool" => match plaintext.to_lowercase().as_str() {
+                "true" => Ok(Leaf::Bool(true)),
+                "false" => Ok(Leaf::Bool(false)),
+                _ => Err(invalid(())),
+            },
+            other => Err(format!("unsupported value type '{}'", other)),
+        }
+    }
+
+    fn to_json(&self) -> JsonValue {
+        match self {
+            Leaf::Str(s) => JsonValue::String(s.clone()),
+            Leaf::Int(i) => JsonValue::from(*i),
+            Leaf::Float(f) => JsonValue::from(*f),
+            Leaf::Bool(b) => JsonValue::Bool(*b),
+        }
+    }
+}
+
+/// Additional authenticated data for a value: its path joined by `:`, plus a trailing `:`.
+fn path_aad(path: &[String]) -> String {
+    format!("{}:", path.join(":"))
+}
+
+fn encrypt_leaf(key: &[u8], leaf: &Leaf, aad: &str) -> std::result::Result<String, String> {
+    let cipher = SopsCipher::new_from_slice(key).map_err(|_| "invalid data key".to_string())?;
+    let mut iv = [0u8; 32];
+    OsRng.fill_bytes(&mut iv);
+    let sealed = cipher
+        .encrypt(
+            Nonce::<U32>::from_slice(&iv),
+            Payload {
+                msg: leaf.to_plaintext().as_bytes(),
+                aad: aad.as_bytes(),
+            },
+        )
+        .map_err(|_| "encryption failed".to_string())?;
+    let (data, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    Ok(format!(
+        "ENC[AES256_GCM,data:{},iv:{},tag:{},type:{}]",
+        BASE64.encode(data),
+        BASE64.encode(iv),
+        BASE64.encode(tag),
+        leaf.type_name()
+    ))
+}
+
+fn decrypt_leaf(key: &[u8], encrypted: &str, aad: &str) -> std::result::Result<Leaf, String> {
+    let inner = encrypted
+        .strip_prefix("ENC[AES256_GCM,")
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(|| "value is not in sops ENC[...] format".to_string())?;
+    let mut parts = BTreeMap::new();
+    for part in inner.split(',') {
+        if let Some((name, value)) = part.split_once(':') {
+            parts.insert(name, value);
+        }
+    }
+    let field = |name: &str| -> std::result::Result<Vec<u8>, String> {
+        let encoded = parts
+            .get(name)
+            .ok_or_else(|| format!("missing '{}' in encrypted value", name))?;
+        BASE64
+            .decode(encoded)
+            .map_err(|_| format!("invalid base64 in '{}'", name))
+    };
+    let (mut sealed, iv, tag) = (field("data")?, field("iv")?, field("tag")?);
+    if iv.len() != 32 {
+        return Err("unexpected IV length".to_string());
+    }
+    sealed.extend_from_slice(&tag);
+    let cipher = SopsCipher::new_from_slice(key).map_err(|_| "invalid data key".to_string())?;
+    let plaintext = cipher
+        .decrypt(
+            Nonce::<U32>::from_slice(&iv),
+            Payload {
+                msg: &sealed,
+                aad: aad.as_bytes(),
+            },
+        )
+        .map_err(|_| "failed to decrypt value (wrong data key or tampered file)".to_string())?;
+    let plaintext = String::from_utf8(plaintext).map_err(|_| "value is not UTF-8".to_string())?;
+    Leaf::parse(parts.get("type").copied().unwrap_or("str"), plaintext)
+}
+
+/// Converts a stored JSON value into a sops YAML value with encrypted leaves, feeding the
+/// plaintext of each leaf into the MAC in document order. `null`s are skipped.
+fn encrypt_tree(
+    key: &[u8],
+    value: &JsonValue,
+    path: &mut Vec<String>,
+    mac: &mut Sha512,
+) -> std::result::Result<Option<YamlValue>, String> {
+    let leaf = match value {
+        JsonValue::Null => return Ok(None),
+        JsonValue::Object(fields) => {
+            let mut mapping = Mapping::new();
+            for (name, field) in fields {
+                path.push(name.clone());
+                let encrypted = encrypt_tree(key, field, path, mac)?;
+                path.pop();
+                if let Some(encrypted) = encrypted {
+                    mapping.insert(YamlValue::String(name.clone()), encrypted);
+                }
+            }
+            return Ok(Some(YamlValue::Mapping(mapping)));
+        }
+        JsonValue::Array(items) => {
+            let mut sequence = Vec::new();
+            for item in items {
+                if let Some(encrypted) = encrypt_tree(key, item, path, mac)? {
+                    sequence.push(encrypted);
+                }
+            }
+            return Ok(Some(YamlValue::Sequence(sequence)));
+        }
+        JsonValue::String(s) => Leaf::Str(s.clone()),
+        JsonValue::Bool(b) => Leaf::Bool(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Leaf::Int(i),
+            None => Leaf::Float(n.as_f64().unwrap_or_default()),
+        },
+    };
+    mac.update(leaf.to_plaintext().as_bytes());
+    Ok(Some(YamlValue::String(encrypt_leaf(
+        key,
+        &leaf,
+        &path_aad(path),
+    )?)))
+}
+
+/// Decrypts a sops YAML value into JSON, feeding each plaintext leaf into the MAC.
+fn decrypt_tree(
+    key: &[u8],
+    value: &YamlValue,
+    path: &mut Vec<String>,
+    mac: &mut Sha512,
+) -> std::result::Result<JsonValue, String> {
+    let unencrypted = path.iter().any(|p| p.ends_with(UNENCRYPTED_SUFFIX));
+    let leaf = match value {
+        YamlValue::Mapping(mapping) => {
+            let mut fields = serde_json::Map::new();
+            for (name, field) in mapping {
+                let name = match name {
+                    YamlValue::String(s) => s.clone(),
+                    other => serde_yaml::to_string(other)
+                        .map_err(|e| e.to_string())?
+                        .trim()
+                        .to_string(),
+                };
+                path.push(name.clone());
+                let decrypted = decrypt_tree(key, field, path, mac)?;
+                path.pop();
+                fields.insert(name, decrypted);
+            }
+            return Ok(JsonValue::Object(fields));
+        }
+        YamlValue::Sequence(items) => {
+            let mut decrypted = Vec::new();
+            for item in items {
+                decrypted.push(decrypt_tree(key, item, path, mac)?);
+            }
+            return Ok(JsonValue::Array(decrypted));
+        }
+        YamlValue::String(s) if !unencrypted && s.starts_with("ENC[") => {
+            decrypt_leaf(key, s, &path_aad(path))?
+        }
+        YamlValue::String(s) => Leaf::Str(s.clone()),
+        YamlValue::Bool(b) => Leaf::Bool(*b),
+        YamlValue::Number(n) => match n.as_i64() {
+            Some(i) => Leaf::Int(i),
+            None => Leaf::Float(n.as_f64().unwrap_or_default()),
+        },
+        YamlValue::Null => return Ok(JsonValue::Null),
+        YamlValue::Tagged(tagged) => return decrypt_tree(key, &tagged.value, path, mac),
+    };
+    mac.update(leaf.to_plaintext().as_bytes());
+    Ok(leaf.to_json())
+}
+
+/// Where sops looks for age identities: `SOPS_AGE_KEY_FILE`, else `sops/age/keys.txt` in
+/// the user configuration directory.
+fn default_age_key_file() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SOPS_AGE_KEY_FILE") {
+        return Some(PathBuf::from(path));
+    }
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("sops").join("age").join("keys.txt"))
+}
+
+impl Vault {
+    /// Writes the given keys to a sops-encrypted YAML file readable by `sops -d`.
+    ///
+    /// Each key becomes a top-level entry; values holding a JSON object (as stored by
+    /// `StructKeyManager`) become nested mappings. The data key is encrypted to every age
+    /// recipient (`age1...`) given.
+    pub fn export_sops<P, I, K>(&self, path: P, keys: I, recipients: &[&str]) -> Result<()>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        let invalid = |reason: String| self.error(Operation::Export, Cause::InvalidInput(reason));
+        if recipients.is_empty() {
+            return Err(invalid(
+                "at least one age recipient is required".to_string(),
+            ));
+        }
+        let mut data_key = [0u8; 32];
+        OsRng.fill_bytes(&mut data_key);
+
+        let mut mac = Sha512::new();
+        let mut document = Mapping::new();
+        for key_name in keys {
+            let key_name = key_name.as_ref();
+            let raw = self.key(key_name).read_key()?;
+            let value = match serde_json::from_str::<JsonValue>(&raw) {
+                Ok(value @ JsonValue::Object(_)) => value,
+                _ => JsonValue::String(raw),
+            };
+            let mut path = vec![key_name.to_string()];
+            if let Some(encrypted) =
+                encrypt_tree(&data_key, &value, &mut path, &mut mac).map_err(invalid)?
+            {
+                document.insert(YamlValue::String(key_name.to_string()), encrypted);
+            }
+        }
+
+        let last_modified = format_rfc3339(now_unix());
+        let mac_hex: String = mac
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let encrypted_mac =
+            encrypt_leaf(&data_key, &Leaf::Str(mac_hex), &last_modified).map_err(invalid)?;
+
+        let mut age_entries = Vec::new();
+        for recipient in recipients {
+            let enc = encrypt_armored(&[recipient], &data_key).map_err(invalid)?;
+            let mut entry = Mapping::new();
+            entry.insert("recipient".into(), YamlValue::String(recipient.to_string()));
+            entry.insert("enc".into(), YamlValue::String(enc));
+            age_entries.push(YamlValue::Mapping(entry));
+        }
+        let mut metadata = Mapping::new();
+        for empty in ["kms", "gcp_kms", "azure_kv", "hc_vault"] {
+            metadata.insert(empty.into(), YamlValue::Sequence(Vec::new()));
+        }
+        metadata.insert("age".into(), YamlValue::Sequence(age_entries));
+        metadata.insert("lastmodified".into(), YamlValue::String(last_modified));
+        metadata.insert("mac".into(), YamlValue::String(encrypted_mac));
+        metadata.insert("pgp".into(), YamlValue::Sequence(Vec::new()));
+        metadata.insert(
+            "unencrypted_suffix".into(),
+            YamlValue::String(UNENCRYPTED_SUFFIX.to_string()),
+        );
+        metadata.insert(
+            "version".into(),
+            YamlValue::String(SOPS_VERSION.to_string()),
+        );
+        document.insert("sops".into(), YamlValue::Mapping(metadata));
+
+        let yaml = serde_yaml::to_string(&document).map_err(|e| invalid(e.to_string()))?;
+        fs::write(path, yaml).map_err(|e| self.error(Operation::Export, e))
+    }
+
+    /// Imports a sops-encrypted YAML file into this vault, using the age identities sops
+    /// itself would use (`SOPS_AGE_KEY`, `SOPS_AGE_KEY_FILE` or `sops/age/keys.txt` in the
+    /// user configuration directory).
+    ///
+    /// Returns the names of the imported keys. See `import_sops_with_identities`.
+    pub fn import_sops<P>(&self, path: P) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let identities = match std::env::var("SOPS_AGE_KEY") {
+            Ok(content) => content,
+            Err(_) => {
+                let key_file = default_age_key_file().ok_or_else(|| {
+                    self.error(
+                        Operation::Import,
+                        Cause::AccessDenied("no age identity file found".to_string()),
+                    )
+                })?;
+                let mut content = String::new();
+                fs::File::open(&key_file)
+                    .map(BufReader::new)
+                    .and_then(|mut reader| reader.read_to_string(&mut content))
+                    .map_err(|e| self.error(Operation::Import, e))?;
+                content
+            }
+        };
+        self.import_sops_with_identities(path, &identities)
+    }
+
+    /// Imports a sops-encrypted YAML file, decrypting its data key with the given age
+    /// identity file content (`AGE-SECRET-KEY-...` lines).
+    ///
+    /// Each top-level entry is stored as a key: scalars as plain strings, mappings as JSON
+    /// objects usable with `StructKeyManager`. The file's MAC is verified before anything
+    /// is stored.
+    pub fn import_sops_with_identities<P>(&self, path: P, identities: &str) -> Result<Vec<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let corrupt = |reason: String| self.error(Operation::Import, Cause::Corrupt(reason));
+        let content = fs::read_to_string(path).map_err(|e| self.error(Operation::Import, e))?;
+        let document: Mapping =
+            serde_yaml::from_str(&content).map_err(|e| corrupt(e.to_string()))?;
+        let metadata = document
+            .get("sops")
+            .and_then(YamlValue::as_mapping)
+            .ok_or_else(|| corrupt("missing sops metadata".to_string()))?;
+
+        let identities = parse_identities(identities)
+            .map_err(|reason| self.error(Operation::Import, Cause::InvalidInput(reason)))?;
+        let data_key = metadata
+            .get("age")
+            .and_then(YamlValue::as_sequence)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.get("enc").and_then(YamlValue::as_str))
+            .find_map(|enc| decrypt(&identities, enc.as_bytes()).ok())
+            .ok_or_else(|| {
+                self.error(
+                    Operation::Import,
+                    Cause::AccessDenied(
+                        "none of the age identities can decrypt the file".to_string(),
+                    ),
+                )
+            })?;
+
+        let mut mac = Sha512::new();
+        let mut values = Vec::new();
+        for (name, value) in &document {
+            let Some(name) = name.as_str() else {
+                continue;
+            };
+            if name == "sops" {
+                continue;
+            }
+            let mut path = vec![name.to_string()];
+            let decrypted = decrypt_tree(&data_key, value, &mut path, &mut mac).map_err(corrupt)?;
+            values.push((name.to_string(), decrypted));
+        }
+
+        let last_modified = metadata
+            .get("lastmodified")
+            .and_then(YamlValue::as_str)
+            .ok_or_else(|| corrupt("missing lastmodified".to_string()))?;
+        let stored_mac = metadata
+            .get("mac")
+            .and_then(YamlValue::as_str)
+            .ok_or_else(|| corrupt("missing mac".to_string()))?;
+        let expected = match decrypt_leaf(&data_key, stored_mac, last_modified).map_err(corrupt)? {
+            Leaf::Str(mac) => mac,
+            _ => return Err(corrupt("unexpected mac type".to_string())),
+        };
+        let actual: String = mac
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        if !expected.eq_ignore_ascii_case(&actual) {
+            return Err(corrupt("MAC mismatch, the file was modified".to_string()));
+        }
+
+        let mut imported = Vec::new();
+        for (name, value) in values {
+            let text = match value {
+                JsonValue::String(s) => s,
+                other => other.to_string(),
+            };
+            self.key(&name).store(&text)?;
+            imported.push(name);
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use age::secrecy::ExposeSecret;
+
+    fn temp_path(name: &str, extension: &str) -> PathBuf {
+        let path = std::env::temp_dir().join("key_vaulter_tests").join(format!(
+            "{}-{}.{}",
+            name,
+            std::process::id(),
+            extension
+        ));
+        let _ = fs::create_dir_all(path.parent().unwrap());
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_leaf_roundtrip_uses_path_as_aad() {
+        let key = [7u8; 32];
+        let path = vec!["db".to_string(), "port".to_string()];
+        let encrypted = encrypt_leaf(&key, &Leaf::Int(5432), &path_aad(&path)).unwrap();
+        assert!(encrypted.starts_with("ENC[AES256_GCM,data:"));
+        assert!(encrypted.ends_with(",type:int]"));
+        assert!(matches!(
+            decrypt_leaf(&key, &encrypted, "db:port:"),
+            Ok(Leaf::Int(5432))
+        ));
+        assert!(decrypt_leaf(&key, &encrypted, "db:other:").is_err());
+    }
+
+    #[test]
+    fn test_export_and_import_roundtrip() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let source = Vault::ephemeral_for_tests();
+        source.key("token").store("abc").unwrap();
+        source
+            .key("db")
+            .store(r#"{"user":"john","port":5432,"tls":true}"#)
+            .unwrap();
+        let file = temp_path("sops_export", "yaml");
+        source
+            .export_sops(&file, ["token", "db"], &[recipient.as_str()])
+            .unwrap();
+        let content = fs::read_to_string(&file).unwrap();
+        assert!(!content.contains("john"));
+        assert!(content.contains(&recipient));
+
+        let target = Vault::ephemeral_for_tests();
+        let imported = target
+            .import_sops_with_identities(&file, identity.to_string().expose_secret())
+            .unwrap();
+        assert_eq!(imported, vec!["token".to_string(), "db".to_string()]);
+        assert_eq!(target.resolve("token").unwrap(), "abc");
+        assert_eq!(target.resolve("db:port").unwrap(), "5432");
+        assert_eq!(target.resolve("db:tls").unwrap(), "true");
+    }
+
+    #[test]
+    fn test_tampered_file_is_rejected() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let source = Vault::ephemeral_for_tests();
+        source.key("a").store("1").unwrap();
+        source.key("b").store("2").unwrap();
+        let file = temp_path("sops_tamper", "yaml");
+        source
+            .export_sops(&file, ["a", "b"], &[recipient.as_str()])
+            .unwrap();
+        // Swapping two encrypted values breaks their path-bound authentication.
+        let mut document: Mapping =
+            serde_yaml::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        let a = document.get("a").unwrap().clone();
+        let b = document.get("b").unwrap().clone();
+        document.insert("a".into(), b);
+        document.insert("b".into(), a);
+        fs::write(&file, serde_yaml::to_string(&document).unwrap()).unwrap();
+        let error = source
+            .import_sops_with_identities(&file, identity.to_string().expose_secret())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Corrupt);
+    }
+
+    #[test]
+    fn test_booleans_are_capitalized_like_sops() {
+        let key = [7u8; 32];
+        let encrypted = encrypt_leaf(&key, &Leaf::Bool(true), "tls:").unwrap();
+        // Read back as a string to see the raw plaintext.
+        let raw = encrypted.replace(",type:bool]", ",type:str]");
+        assert!(matches!(decrypt_leaf(&key, &raw, "tls:"), Ok(Leaf::Str(s)) if s == "True"));
+
+        // A file as sops writes it: `False` encrypted and in the MAC.
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let leaf = encrypt_leaf(&key, &Leaf::Str("False".to_string()), "debug:")
+            .unwrap()
+            .replace(",type:str]", ",type:bool]");
+        let last_modified = "2024-01-01T00:00:00Z";
+        let mac: String = Sha512::digest(b"False")
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        let mac = encrypt_leaf(&key, &Leaf::Str(mac), last_modified).unwrap();
+        let enc = encrypt_armored(&[recipient.as_str()], &key).unwrap();
+        let file = temp_path("sops_bool", "yaml");
+        fs::write(
+            &file,
+            format!(
+                "debug: {}\nsops:\n  age:\n  - recipient: {}\n    enc: |\n{}  lastmodified: '{}'\n  mac: {}\n  version: {}\n",
+                leaf,
+                recipient,
+                enc.lines().map(|line| format!("      {}\n", line)).collect::<String>(),
+                last_modified,
+                mac,
+                SOPS_VERSION
+            ),
+        )
+        .unwrap();
+        let target = Vault::ephemeral_for_tests();
+        target
+            .import_sops_with_identities(&file, identity.to_string().expose_secret())
+            .unwrap();
+        assert_eq!(target.resolve("debug").unwrap(), "false");
+    }
+}
@@ -0,0 +1,401 @@
+use crate::file_store::{
+    corrupt, derive_cipher, open_entry, platform_error, seal_entry, KdfParams,
+};
+use crate::timestamp::now_unix;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use chacha20poly1305::XChaCha20Poly1305;
+use keyring::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use zeroize::Zeroizing;
+
+const KDF_INFO: &[u8] = b"key_vaulter sqlite store v1";
+const SALT_LEN: usize = 16;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (name TEXT PRIMARY KEY, value BLOB NOT NULL);
+    CREATE TABLE IF NOT EXISTS entries (
+        system TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value BLOB NOT NULL,
+        modified INTEGER NOT NULL,
+        PRIMARY KEY (system, key)
+    );
+    CREATE TABLE IF NOT EXISTS tags (
+        system TEXT NOT NULL,
+        key TEXT NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (system, key, tag),
+        FOREIGN KEY (system, key) REFERENCES entries (system, key) ON DELETE CASCADE
+    );
+    PRAGMA foreign_keys = ON;
+";
+
+/// What `SqliteStore::entries` reports about a key, never its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub key_name: String,
+    pub tags: Vec<String>,
+    /// Unix time of the last write.
+    pub modified: u64,
+}
+
+/// A local SQLite database holding values encrypted with XChaCha20-Poly1305 (feature
+/// `sqlite`).
+///
+/// Keys are derived like in [`EncryptedFileStore`](crate::file_store::EncryptedFileStore),
+/// from the passphrase or the current user name and a salt stored in the database. Names,
+/// tags and modification times stay in clear so they can be queried. Clones share the
+/// same connection.
+#[derive(Clone)]
+pub struct SqliteStore {
+    path: Option<PathBuf>,
+    connection: Arc<Mutex<Connection>>,
+    passphrase: Option<Zeroizing<String>>,
+}
+
+impl fmt::Debug for SqliteStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteStore")
+            .field("path", &self.path)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// Two stores are equal when they share the same connection.
+impl PartialEq for SqliteStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.connection, &other.connection)
+    }
+}
+
+impl Eq for SqliteStore {}
+
+impl SqliteStore {
+    /// Opens the database at `path`, creating it (and its directory) if needed.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(platform_error)?;
+        }
+        let connection = Connection::open(&path).map_err(platform_error)?;
+        SqliteStore::from_connection(Some(path), connection)
+    }
+
+    /// Opens a database living in memory only, e.g. for tests.
+    pub fn open_in_memory() -> Result<Self> {
+        let connection = Connection::open_in_memory().map_err(platform_error)?;
+        SqliteStore::from_connection(None, connection)
+    }
+
+    fn from_connection(path: Option<PathBuf>, connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA).map_err(platform_error)?;
+        Ok(SqliteStore {
+            path,
+            connection: Arc::new(Mutex::new(connection)),
+            passphrase: None,
+        })
+    }
+
+    /// Derives the encryption key from the given passphrase.
+    pub fn with_passphrase(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(Zeroizing::new(passphrase.to_string()));
+        self
+    }
+
+    /// Default location: `key_vaulter/vault.sqlite3` next to
+    /// [`EncryptedFileStore::default_path`](crate::file_store::EncryptedFileStore::default_path).
+    pub fn default_path() -> PathBuf {
+        crate::file_store::EncryptedFileStore::default_path().with_file_name("vault.sqlite3")
+    }
+
+    /// Path of the database, `None` when it lives in memory.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Loads the salt of the database, creating it (and recording the key derivation
+    /// parameters) on first use.
+    fn cipher(&self, connection: &Connection) -> Result<XChaCha20Poly1305> {
+        let salt: Option<Vec<u8>> = connection
+            .query_row("SELECT value FROM meta WHERE name = 'salt'", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(platform_error)?;
+        let salt = match salt {
+            Some(salt) => salt,
+            None => {
+                let mut salt = vec![0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                connection
+                    .execute(
+                        "INSERT INTO meta (name, value) VALUES ('salt', ?1)",
+                        params![salt],
+                    )
+                    .map_err(platform_error)?;
+                connection
+                    .execute(
+                        "INSERT INTO meta (name, value) VALUES ('kdf', ?1)",
+                        params![KdfParams::DEFAULT.to_string().into_bytes()],
+                    )
+                    .map_err(platform_error)?;
+                salt
+            }
+        };
+        let kdf: Option<Vec<u8>> = connection
+            .query_row("SELECT value FROM meta WHERE name = 'kdf'", [], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(platform_error)?;
+        let kdf = kdf
+            .and_then(|kdf| KdfParams::parse(std::str::from_utf8(&kdf).ok()?))
+            .ok_or_else(|| corrupt("missing or invalid key derivation parameters"))?;
+        derive_cipher(
+            self.passphrase.as_deref().map(String::as_str),
+            &salt,
+            KDF_INFO,
+            kdf,
+        )
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        let connection = self.lock();
+        let data: Vec<u8> = connection
+            .query_row(
+                "SELECT value FROM entries WHERE system = ?1 AND key = ?2",
+                params![system_name, key_name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(platform_error)?
+            .ok_or(keyring::Error::NoEntry)?;
+        open_entry(
+            &self.cipher(&connection)?,
+            system_name,
+            key_name,
+            &data,
+            "sqlite store",
+        )
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        self.apply(system_name, &[(key_name, Some(value))])
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        let deleted = self
+            .lock()
+            .execute(
+                "DELETE FROM entries WHERE system = ?1 AND key = ?2",
+                params![system_name, key_name],
+            )
+            .map_err(platform_error)?;
+        if deleted == 0 {
+            return Err(keyring::Error::NoEntry);
+        }
+        Ok(())
+    }
+
+    /// Applies several writes in one SQLite transaction: either all of them land or none.
+    /// A `None` value deletes the key; deleting a missing key is not an error here.
+    pub fn apply(&self, system_name: &str, writes: &[(&str, Option<&str>)]) -> Result<()> {
+        let mut connection = self.lock();
+        let cipher = self.cipher(&connection)?;
+        let transaction = connection.transaction().map_err(platform_error)?;
+        for (key_name, value) in writes {
+            match value {
+                Some(value) => {
+                    let data = seal_entry(&cipher, system_name, key_name, value)?;
+                    transaction
+                        .execute(
+                            "INSERT INTO entries (system, key, value, modified)
+                             VALUES (?1, ?2, ?3, ?4)
+                             ON CONFLICT (system, key)
+                             DO UPDATE SET value = excluded.value, modified = excluded.modified",
+                            params![system_name, key_name, data, now_unix() as i64],
+                        )
+                        .map_err(platform_error)?;
+                }
+                None => {
+                    transaction
+                        .execute(
+                            "DELETE FROM entries WHERE system = ?1 AND key = ?2",
+                            params![system_name, key_name],
+                        )
+                        .map_err(platform_error)?;
+                }
+            }
+        }
+        transaction.commit().map_err(platform_error)
+    }
+
+    /// Lists the keys stored for `system_name`.
+    pub fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        self.query_keys(
+            "SELECT key FROM entries WHERE system = ?1 ORDER BY key",
+            params![system_name],
+        )
+    }
+
+    /// Replaces the tags of an existing key.
+    pub fn set_tags(&self, system_name: &str, key_name: &str, tags: &[&str]) -> Result<()> {
+        let mut connection = self.lock();
+        let transaction = connection.transaction().map_err(platform_error)?;
+        let exists: bool = transaction
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM entries WHERE system = ?1 AND key = ?2)",
+                params![system_name, key_name],
+                |row| row.get(0),
+            )
+            .map_err(platform_error)?;
+        if !exists {
+            return Err(keyring::Error::NoEntry);
+        }
+        transaction
+            .execute(
+                "DELETE FROM tags WHERE system = ?1 AND key = ?2",
+                params![system_name, key_name],
+            )
+            .map_err(platform_error)?;
+        for tag in tags {
+            transaction
+                .execute(
+                    "INSERT OR IGNORE INTO tags (system, key, tag) VALUES (?1, ?2, ?3)",
+                    params![system_name, key_name, tag],
+                )
+                .map_err(platform_error)?;
+        }
+        transaction.commit().map_err(platform_error)
+    }
+
+    /// Lists the keys of `system_name` carrying `tag`.
+    pub fn find_by_tag(&self, system_name: &str, tag: &str) -> Result<Vec<String>> {
+        self.query_keys(
+            "SELECT key FROM tags WHERE system = ?1 AND tag = ?2 ORDER BY key",
+            params![system_name, tag],
+        )
+    }
+
+    /// Lists the keys of `system_name` written since `unix_secs`, e.g. to find what a
+    /// rotation touched.
+    pub fn modified_since(&self, system_name: &str, unix_secs: u64) -> Result<Vec<String>> {
+        self.query_keys(
+            "SELECT key FROM entries WHERE system = ?1 AND modified >= ?2 ORDER BY key",
+            params![system_name, unix_secs as i64],
+        )
+    }
+
+    /// Describes every key of `system_name`: its tags and when it was last written.
+    pub fn entries(&self, system_name: &str) -> Result<Vec<EntryInfo>> {
+        let connection = self.lock();
+        let mut statement = connection
+            .prepare(
+                "SELECT e.key, e.modified, group_concat(t.tag, char(31))
+                 FROM entries e
+                 LEFT JOIN tags t ON t.system = e.system AND t.key = e.key
+                 WHERE e.system = ?1
+                 GROUP BY e.key, e.modified
+                 ORDER BY e.key",
+            )
+            .map_err(platform_error)?;
+        let rows = statement
+            .query_map(params![system_name], |row| {
+                let tags: Option<String> = row.get(2)?;
+                let mut tags: Vec<String> = tags
+                    .map(|tags| tags.split('\u{1f}').map(str::to_string).collect())
+                    .unwrap_or_default();
+                tags.sort();
+                Ok(EntryInfo {
+                    key_name: row.get(0)?,
+                    modified: row.get::<_, i64>(1)? as u64,
+                    tags,
+                })
+            })
+            .map_err(platform_error)?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(platform_error)
+    }
+
+    fn query_keys(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<String>> {
+        let connection = self.lock();
+        let mut statement = connection.prepare(sql).map_err(platform_error)?;
+        let rows = statement
+            .query_map(params, |row| row.get(0))
+            .map_err(platform_error)?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(platform_error)
+    }
+
+    /// Checks that the database answers and the key can be derived.
+    pub(crate) fn health_check(&self) -> Result<()> {
+        let connection = self.lock();
+        self.cipher(&connection).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_tags_and_queries() {
+        let store = SqliteStore::open_in_memory()
+            .unwrap()
+            .with_passphrase("test passphrase");
+        store
+            .apply(
+                "system",
+                &[("id", Some("id-1")), ("secret", Some("s3cret"))],
+            )
+            .unwrap();
+        assert_eq!(store.get("system", "secret").unwrap(), "s3cret");
+        assert_eq!(store.list("system").unwrap(), vec!["id", "secret"]);
+
+        store
+            .set_tags("system", "secret", &["prod", "api"])
+            .unwrap();
+        assert_eq!(store.find_by_tag("system", "prod").unwrap(), vec!["secret"]);
+        assert!(matches!(
+            store.set_tags("system", "missing", &["prod"]),
+            Err(keyring::Error::NoEntry)
+        ));
+        let entries = store.entries("system").unwrap();
+        assert_eq!(entries[1].tags, vec!["api", "prod"]);
+        assert!(entries[0].tags.is_empty());
+        assert_eq!(store.modified_since("system", 0).unwrap().len(), 2);
+
+        store.delete("system", "secret").unwrap();
+        assert!(store.find_by_tag("system", "prod").unwrap().is_empty());
+        assert!(matches!(
+            store.delete("system", "secret"),
+            Err(keyring::Error::NoEntry)
+        ));
+    }
+
+    #[test]
+    fn test_value_is_encrypted() {
+        let path = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("sqlite-{}.sqlite3", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = SqliteStore::open(&path).unwrap();
+        store.set("system", "key", "very-secret-value").unwrap();
+        let content = std::fs::read(&path).unwrap();
+        assert!(!content
+            .windows(17)
+            .any(|window| window == b"very-secret-value"));
+        let other = SqliteStore::open(&path).unwrap().with_passphrase("other");
+        assert!(other.get("system", "key").is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
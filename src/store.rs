@@ -0,0 +1,178 @@
+use crate::backend::{listing_unsupported, Backend, Capabilities};
+use keyring::Result;
+use std::fmt;
+use std::sync::Arc;
+
+/// An object-safe secret store, for code that wants to depend on `Arc<dyn SecretStore>`
+/// rather than on a concrete backend.
+///
+/// `Backend` implements it, and any implementation can be used as a backend through
+/// `Backend::custom`, so managers and vaults work on top of it. Errors are plain
+/// `keyring::Error`s, which tests can construct (`keyring::Error::NoEntry` for a missing
+/// key). With the `mockall` feature, `MockSecretStore` is generated for it.
+#[cfg_attr(feature = "mockall", mockall::automock)]
+pub trait SecretStore: Send + Sync {
+    fn get(&self, system_name: &str, key_name: &str) -> Result<String>;
+
+    fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()>;
+
+    fn delete(&self, system_name: &str, key_name: &str) -> Result<()>;
+
+    /// See `Backend::list`. The default reports listing as unsupported.
+    fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        let _ = system_name;
+        Err(listing_unsupported(self.name()))
+    }
+
+    /// Short, stable name reported in errors and journals.
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    /// See `Backend::capabilities`. The default makes no promises beyond persistence.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supports_listing: false,
+            max_value_size: None,
+            persistent: true,
+            requires_unlock: false,
+        }
+    }
+
+    /// See `Backend::health_check`.
+    fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl SecretStore for Backend {
+    fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        Backend::get(self, system_name, key_name)
+    }
+
+    fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        Backend::set(self, system_name, key_name, value)
+    }
+
+    fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        Backend::delete(self, system_name, key_name)
+    }
+
+    fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        Backend::list(self, system_name)
+    }
+
+    fn name(&self) -> &'static str {
+        Backend::name(self)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Backend::capabilities(self)
+    }
+
+    fn health_check(&self) -> Result<()> {
+        Backend::health_check(self)
+    }
+}
+
+/// A [`SecretStore`] used as a backend (see `Backend::custom`).
+#[derive(Clone)]
+pub struct CustomStore {
+    store: Arc<dyn SecretStore>,
+}
+
+impl CustomStore {
+    pub fn new(store: Arc<dyn SecretStore>) -> Self {
+        CustomStore { store }
+    }
+
+    /// The wrapped store.
+    pub fn inner(&self) -> &Arc<dyn SecretStore> {
+        &self.store
+    }
+}
+
+impl fmt::Debug for CustomStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CustomStore")
+            .field(&self.store.name())
+            .finish()
+    }
+}
+
+/// Two custom stores are equal when they wrap the same instance.
+impl PartialEq for CustomStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.store, &other.store)
+    }
+}
+
+impl Eq for CustomStore {}
+
+impl Backend {
+    /// Uses `store` as the backend.
+    pub fn custom<S>(store: S) -> Backend
+    where
+        S: SecretStore + 'static,
+    {
+        Backend::Custom(CustomStore::new(Arc::new(store)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::memory_store::MemoryStore;
+    use crate::vault::Vault;
+
+    /// Refuses every write, like a read-only secrets mount.
+    struct ReadOnlyStore(MemoryStore);
+
+    impl SecretStore for ReadOnlyStore {
+        fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+            self.0.get(system_name, key_name)
+        }
+
+        fn set(&self, _: &str, _: &str, _: &str) -> Result<()> {
+            Err(keyring::Error::NoStorageAccess("read-only".into()))
+        }
+
+        fn delete(&self, _: &str, _: &str) -> Result<()> {
+            Err(keyring::Error::NoStorageAccess("read-only".into()))
+        }
+
+        fn name(&self) -> &'static str {
+            "read-only"
+        }
+    }
+
+    #[test]
+    fn test_custom_store_as_backend() {
+        let memory = MemoryStore::new();
+        memory.set("store_service", "token", "secret").unwrap();
+        let vault =
+            Vault::new("store_service").with_backend(Backend::custom(ReadOnlyStore(memory)));
+        assert_eq!(vault.key("token").read_key().unwrap(), "secret");
+        let error = vault.key("token").store("other").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AccessDenied);
+        assert_eq!(error.backend(), "read-only");
+
+        let store: Arc<dyn SecretStore> = Arc::new(Backend::Memory(MemoryStore::new()));
+        assert_eq!(store.name(), "memory");
+        assert!(store.capabilities().supports_listing);
+    }
+
+    #[cfg(feature = "mockall")]
+    #[test]
+    fn test_mock_secret_store() {
+        let mut mock = MockSecretStore::new();
+        mock.expect_get()
+            .withf(|system, key| system == "store_service" && key == "token")
+            .returning(|_, _| Ok("mocked".to_string()));
+        mock.expect_name().return_const("mock");
+        let mut manager = crate::key_manager::KeyManager::new("store_service", "token")
+            .with_backend(Backend::custom(mock));
+        assert_eq!(manager.read_key().unwrap(), "mocked");
+    }
+}
@@ -1,128 +1,1606 @@
-use crate::key_manager::KeyManager;
-use keyring::Result;
+use crate::ambiguity::AmbiguityStrategy;
+use crate::approval::Approver;
+use crate::backend::Backend;
+use crate::canonical::to_canonical_string;
+use crate::constraint::{check_all, Constraint};
+use crate::datetime::DateKind;
+use crate::dotenv::{env_segment, flatten_env};
+use crate::error::{Cause, Error, ErrorKind, Operation, Result};
+use crate::file_store::EncryptedFileStore;
+use crate::hooks::{self, PromptEventKind};
+use crate::journal::Journal;
+use crate::key_manager::{DeleteOutcome, KeyManager, StoreOutcome};
+use crate::macos_options::MacOsOptions;
+use crate::naming::{internal_key, INTERNAL_SEPARATOR};
+use crate::number::{parse_number, NumberLocale};
+use crate::policy::{PolicyViolation, StorePolicy};
+use crate::prompt::{PromptHistory, Prompter};
+use crate::rate_limit::RateLimiter;
+use crate::redact::{redact_fields, REDACTED};
+#[cfg(feature = "schemars")]
+use crate::schema;
+use crate::windows_options::WindowsOptions;
+#[cfg(feature = "schemars")]
+use schemars::{schema::RootSchema, JsonSchema};
 use serde::{Deserialize, Serialize};
-use std::io::{self, Write};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// A field of the same object and the predicate its value must pass, set with
+/// `StructKeyManager::with_condition`.
+#[derive(Clone)]
+struct Condition {
+    depends_on: String,
+    predicate: Arc<dyn Fn(&Value) -> bool + Send + Sync>,
+}
+
+impl fmt::Debug for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Condition")
+            .field("depends_on", &self.depends_on)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where `read_key` puts a raw value stored by another program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RawValues {
+    /// The raw value is `T` itself, a string.
+    Whole,
+    /// The raw value is the field at this dotted path.
+    Field(String),
+}
+
+impl RawValues {
+    fn wrap(&self, raw: String) -> Value {
+        match self {
+            RawValues::Whole => Value::String(raw),
+            RawValues::Field(field) => {
+                let mut value = Value::Object(Map::new());
+                insert_field(&mut value, field, Value::String(raw));
+                value
+            }
+        }
+    }
+}
+
+/// Computes the default value of a field at prompt time.
+type DefaultProvider = Arc<dyn Fn() -> Option<Value> + Send + Sync>;
 
 pub struct StructKeyManager<T> {
     key_manager: KeyManager,
+    canonical: bool,
+    secret_fields: BTreeSet<String>,
+    /// Checked on secret fields entered at the prompt; `true` forces re-entry.
+    prompt_policy: Option<(Arc<dyn StorePolicy>, bool)>,
+    /// Tag field paths of tagged enums, with their content field if adjacently tagged.
+    tagged_enums: BTreeMap<String, Option<String>>,
+    /// Date fields declared with `with_date_field`, besides those detected by default value.
+    date_fields: BTreeMap<String, DateKind>,
+    constraints: BTreeMap<String, Constraint>,
+    number_locale: NumberLocale,
+    /// UUID fields declared with `with_uuid_field`, besides those detected by default value.
+    #[cfg(feature = "uuid")]
+    uuid_fields: BTreeSet<String>,
+    /// Schema of `T` checked on read and store, set with `with_schema_validation`.
+    #[cfg(feature = "schemars")]
+    schema: Option<Arc<RootSchema>>,
+    /// Fields stored apart from the struct, each in its own backend.
+    field_backends: BTreeMap<String, Backend>,
+    /// Fields only asked when a field of the same object passes a predicate.
+    conditions: BTreeMap<String, Condition>,
+    /// How values that aren't the JSON of `T` are read, if accepted.
+    raw_values: Option<RawValues>,
+    /// Old and new paths of renamed fields, applied in order when reading.
+    renamed_fields: Vec<(String, String)>,
+    /// Defaults computed when prompting, by dotted path.
+    default_providers: BTreeMap<String, DefaultProvider>,
+    /// Candidate values offered for fields, by dotted path.
+    suggestions: BTreeMap<String, Vec<String>>,
+    /// How many answers per field `with_prompt_history` keeps, if enabled.
+    history_limit: Option<usize>,
+    history: Mutex<PromptHistory>,
     _marker: std::marker::PhantomData<T>,
 }
 
+impl<T> fmt::Debug for StructKeyManager<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StructKeyManager")
+            .field("key_manager", &self.key_manager)
+            .field("canonical", &self.canonical)
+            .field("secret_fields", &self.secret_fields)
+            .field("tagged_enums", &self.tagged_enums)
+            .field("constraints", &self.constraints)
+            .field("number_locale", &self.number_locale)
+            .field("field_backends", &self.field_backends)
+            .field("conditions", &self.conditions)
+            .field("suggestions", &self.suggestions)
+            .field("renamed_fields", &self.renamed_fields)
+            .field("raw_values", &self.raw_values)
+            .field(
+                "default_providers",
+                &self.default_providers.keys().collect::<Vec<_>>(),
+            )
+            .field("history_limit", &self.history_limit)
+            .field(
+                "prompt_policy",
+                &self.prompt_policy.as_ref().map(|(_, enforce)| enforce),
+            )
+            .finish()
+    }
+}
+
 impl<T> StructKeyManager<T>
 where
     T: Serialize + for<'de> Deserialize<'de> + Default,
 {
     /// Creates a new instance of StructKeyManager with the given system and key name.
     pub fn new(system_name: &str, key_name: &str) -> Self {
+        Self::from_key_manager(KeyManager::new(system_name, key_name))
+    }
+
+    /// Same as `new`, but rejects names that are not portable (see `KeyManager::try_new`).
+    pub fn try_new(system_name: &str, key_name: &str) -> Result<Self> {
+        Ok(Self::from_key_manager(KeyManager::try_new(
+            system_name,
+            key_name,
+        )?))
+    }
+
+    /// Same as `new`, with both names passed through `naming::sanitize_name`.
+    pub fn new_sanitized(system_name: &str, key_name: &str) -> Self {
+        Self::from_key_manager(KeyManager::new_sanitized(system_name, key_name))
+    }
+
+    /// Same as `new`, with the system name of `KeyManager::for_current_app`.
+    pub fn for_current_app(key_name: &str) -> Self {
+        Self::from_key_manager(KeyManager::for_current_app(key_name))
+    }
+
+    pub(crate) fn from_key_manager(key_manager: KeyManager) -> Self {
         StructKeyManager {
-            key_manager: KeyManager::new(system_name, key_name),
+            key_manager,
+            canonical: false,
+            secret_fields: BTreeSet::new(),
+            prompt_policy: None,
+            tagged_enums: BTreeMap::new(),
+            date_fields: BTreeMap::new(),
+            constraints: BTreeMap::new(),
+            number_locale: NumberLocale::default(),
+            #[cfg(feature = "uuid")]
+            uuid_fields: BTreeSet::new(),
+            #[cfg(feature = "schemars")]
+            schema: None,
+            field_backends: BTreeMap::new(),
+            conditions: BTreeMap::new(),
+            suggestions: BTreeMap::new(),
+            renamed_fields: Vec::new(),
+            raw_values: None,
+            default_providers: BTreeMap::new(),
+            history_limit: None,
+            history: Mutex::default(),
             _marker: std::marker::PhantomData,
         }
     }
 
+    /// Uses the given backend instead of the default OS keyring.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.key_manager = self.key_manager.with_backend(backend);
+        self
+    }
+
+    /// Records every read, store and delete in `journal`.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.key_manager = self.key_manager.with_journal(journal);
+        self
+    }
+
+    /// Escrows the serialized JSON of every store (see `KeyManager::with_escrow`).
+    #[cfg(feature = "age")]
+    pub fn with_escrow(mut self, escrow: crate::escrow::Escrow) -> Self {
+        self.key_manager = self.key_manager.with_escrow(escrow);
+        self
+    }
+
+    /// Checks the serialized JSON against `policy` before storing it.
+    pub fn with_policy<P>(mut self, policy: P) -> Self
+    where
+        P: StorePolicy + 'static,
+    {
+        self.key_manager = self.key_manager.with_policy(policy);
+        self
+    }
+
+    /// Pins the serialized struct (see `KeyManager::with_pin`).
+    pub fn with_pin(mut self, pin: &str) -> Self {
+        self.key_manager = self.key_manager.with_pin(pin);
+        self
+    }
+
+    /// The pin of the serialized struct as stored (see `KeyManager::current_pin`).
+    pub fn current_pin(&mut self) -> Result<String> {
+        self.key_manager.current_pin()
+    }
+
+    /// Requires an approval before every read (see `KeyManager::with_read_confirmation`).
+    pub fn with_read_confirmation(mut self) -> Self {
+        self.key_manager = self.key_manager.with_read_confirmation();
+        self
+    }
+
+    /// Requires `approver` to approve every read (see `KeyManager::with_approver`).
+    pub fn with_approver<A>(mut self, approver: A) -> Self
+    where
+        A: Approver + 'static,
+    {
+        self.key_manager = self.key_manager.with_approver(approver);
+        self
+    }
+
+    /// Counts every read against `limiter` (see `KeyManager::with_rate_limit`).
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.key_manager = self.key_manager.with_rate_limit(limiter);
+        self
+    }
+
+    /// See `KeyManager::with_ambiguity_strategy`.
+    pub fn with_ambiguity_strategy(mut self, strategy: AmbiguityStrategy) -> Self {
+        self.key_manager = self.key_manager.with_ambiguity_strategy(strategy);
+        self
+    }
+
+    /// See `KeyManager::with_windows_options`.
+    pub fn with_windows_options(mut self, options: WindowsOptions) -> Self {
+        self.key_manager = self.key_manager.with_windows_options(options);
+        self
+    }
+
+    /// See `KeyManager::with_attribute`.
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.key_manager = self.key_manager.with_attribute(name, value);
+        self
+    }
+
+    /// See `KeyManager::with_description`.
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.key_manager = self.key_manager.with_description(description);
+        self
+    }
+
+    /// See `KeyManager::with_owner_description`.
+    pub fn with_owner_description(mut self) -> Self {
+        self.key_manager = self.key_manager.with_owner_description();
+        self
+    }
+
+    /// See `KeyManager::with_macos_options`.
+    pub fn with_macos_options(mut self, options: MacOsOptions) -> Self {
+        self.key_manager = self.key_manager.with_macos_options(options);
+        self
+    }
+
+    /// Stores `field` (a dotted path, like secret fields) in `backend` instead of with the
+    /// rest of the struct, e.g. an API token in the OS keyring and the endpoint URL in an
+    /// `EncryptedFileStore`. The field is kept as JSON under the key `<key_name>.<field>`
+    /// of the same system, and put back in place by `read_key`. Fields containing
+    /// `naming::INTERNAL_SEPARATOR` are rejected with `ErrorKind::InvalidName` when used.
+    ///
+    /// A field missing from its backend is left out of the struct, so it takes its serde
+    /// default if it has one.
+    pub fn with_field_backend(mut self, field: &str, backend: Backend) -> Self {
+        self.field_backends.insert(field.to_string(), backend);
+        self
+    }
+
+    /// The manager of a field declared with `with_field_backend`.
+    fn field_manager(&self, field: &str, backend: &Backend) -> Result<KeyManager> {
+        if field.contains(INTERNAL_SEPARATOR) {
+            return Err(self.error(
+                Operation::Validate,
+                Cause::InvalidName(format!(
+                    "field '{}' must not contain '{}'",
+                    field, INTERNAL_SEPARATOR
+                )),
+            ));
+        }
+        let key_name = format!("{}.{}", self.key_manager.key_name, field);
+        Ok(self.key_manager.sibling(&key_name, backend.clone()))
+    }
+
+    /// Remembers the last `limit` answers to each non-secret field entered in `request_key`,
+    /// kept in the same backend under the key `<key_name>#history`. A field with no current
+    /// value offers its last answer as the default, and the prompter gets all of them (see
+    /// `Prompter::prompt_with_history`), so re-provisioning a machine doesn't mean retyping
+    /// hostnames and user names. The history is kept by `delete`.
+    pub fn with_prompt_history(mut self, limit: usize) -> Self {
+        self.history_limit = Some(limit);
+        self
+    }
+
+    fn history_manager(&self) -> KeyManager {
+        let key_name = internal_key(&self.key_manager.key_name, "history");
+        self.key_manager
+            .sibling(&key_name, self.key_manager.backend().clone())
+    }
+
+    fn history(&self) -> MutexGuard<'_, PromptHistory> {
+        self.history.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Loads the stored answers; a missing or unreadable history starts empty.
+    fn load_history(&self) {
+        if self.history_limit.is_none() {
+            return;
+        }
+        let mut manager = self.history_manager();
+        let loaded = match manager.read_key() {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| manager.error(Operation::Read, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(PromptHistory::default()),
+            Err(e) => Err(e),
+        };
+        *self.history() = loaded.unwrap_or_else(|e| {
+            hooks::warn(&format!("could not load the prompt history: {}", e));
+            PromptHistory::default()
+        });
+    }
+
+    /// Stores the answers; failing to do so doesn't fail the prompt.
+    fn save_history(&self) {
+        if self.history_limit.is_none() {
+            return;
+        }
+        let json = serde_json::to_string(&*self.history()).unwrap_or_default();
+        if let Err(e) = self.history_manager().store(&json) {
+            hooks::warn(&format!("could not store the prompt history: {}", e));
+        }
+    }
+
+    /// The earlier answers to a non-secret field, most recent first.
+    fn recent_answers(&self, field: &str) -> Vec<String> {
+        if self.is_secret_field(field) {
+            return Vec::new();
+        }
+        self.history().recent(field).to_vec()
+    }
+
+    /// Adds the value entered for a non-secret field to the history, when enabled.
+    fn remember_answer(&self, field: &str, value: &Value) {
+        let Some(limit) = self.history_limit else {
+            return;
+        };
+        if self.is_secret_field(field) {
+            return;
+        }
+        let answer = match value {
+            Value::String(text) if !text.is_empty() => text.clone(),
+            Value::Number(number) => number.to_string(),
+            _ => return,
+        };
+        self.history().record(field, &answer, limit);
+    }
+
+    /// Asks for the fields through `prompter` instead of the terminal.
+    pub fn with_prompter<P>(mut self, prompter: P) -> Self
+    where
+        P: Prompter + 'static,
+    {
+        self.key_manager = self.key_manager.with_prompter(prompter);
+        self
+    }
+
+    /// See `KeyManager::entry`.
+    pub fn entry(&self) -> Result<keyring::Entry> {
+        self.key_manager.entry()
+    }
+
+    /// Wraps a failure with the system, key and backend of the underlying manager.
+    pub(crate) fn error<C>(&self, operation: Operation, cause: C) -> Error
+    where
+        C: Into<Cause>,
+    {
+        self.key_manager.error(operation, cause)
+    }
+
+    /// Stores values as canonical JSON (sorted keys, fixed number formatting), so the same
+    /// struct always produces the same bytes. Needed for integrity tags, change detection
+    /// and clean diffs of exported files.
+    pub fn with_canonical_json(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Marks a field as secret, so it is hidden by `dump_redacted` and similar output.
+    ///
+    /// Use the field name as it appears in the JSON (after `serde(rename)`); nested fields
+    /// are written with dots, e.g. `"database.password"`.
+    pub fn with_secret_field(mut self, field: &str) -> Self {
+        self.secret_fields.insert(field.to_string());
+        self
+    }
+
+    /// Sets how numbers typed in `request_key` are written. The default, `NumberLocale::Auto`,
+    /// accepts both `1,234.56` and `1.234,56`; pick a locale to remove the ambiguity of
+    /// inputs like `1,234`.
+    pub fn with_number_locale(mut self, locale: NumberLocale) -> Self {
+        self.number_locale = locale;
+        self
+    }
+
+    /// Requires the numeric `field` (a dotted path) to lie between `min` and `max`,
+    /// inclusive. `request_key` asks again with a message like "port must be 1–65535", and
+    /// `store` rejects other values with `ErrorKind::InvalidInput`.
+    pub fn with_range<N>(mut self, field: &str, min: N, max: N) -> Self
+    where
+        N: Into<f64>,
+    {
+        let (min, max) = (min.into(), max.into());
+        self.constraints
+            .insert(field.to_string(), Constraint::Range { min, max });
+        self
+    }
+
+    /// Requires the string `field` (a dotted path) to be between `min` and `max` characters
+    /// long, inclusive, enforced like `with_range`.
+    pub fn with_length(mut self, field: &str, min: usize, max: usize) -> Self {
+        self.constraints
+            .insert(field.to_string(), Constraint::Length { min, max });
+        self
+    }
+
+    /// Declares the enum whose tag is the field `tag` (a dotted path, like secret fields),
+    /// so `request_key` asks for the variant before its fields. `content` is the content
+    /// field of an adjacently tagged enum (`#[serde(tag = "t", content = "c")]`), `None`
+    /// for an internally tagged one (`#[serde(tag = "type")]`).
+    ///
+    /// Keeping the default variant walks its fields one by one; choosing another variant
+    /// asks for its fields as one JSON object, since the default value doesn't describe
+    /// them.
+    pub fn with_tagged_enum(mut self, tag: &str, content: Option<&str>) -> Self {
+        self.tagged_enums
+            .insert(tag.to_string(), content.map(str::to_string));
+        self
+    }
+
+    /// Asks for `field` (a dotted path) in `request_key` only when `predicate` accepts the
+    /// value of `depends_on`, a field of the same object named without its path, e.g.
+    /// `with_condition("proxy.password", "use_proxy", |v| v == true)`. `depends_on` is
+    /// asked first; a field that isn't asked keeps its current value.
+    pub fn with_condition<F>(mut self, field: &str, depends_on: &str, predicate: F) -> Self
+    where
+        F: Fn(&Value) -> bool + Send + Sync + 'static,
+    {
+        self.conditions.insert(
+            field.to_string(),
+            Condition {
+                depends_on: depends_on.to_string(),
+                predicate: Arc::new(predicate),
+            },
+        );
+        self
+    }
+
+    /// The order to ask the fields of an object in: their JSON order, except that a
+    /// conditional field comes after the field it depends on.
+    fn prompt_order(&self, prefix: &str, fields: &Map<String, Value>) -> Vec<String> {
+        let mut order: Vec<String> = Vec::new();
+        for name in fields.keys() {
+            // The field, then what it depends on, and so on up the chain.
+            let mut chain = vec![name.clone()];
+            while let Some(condition) = chain
+                .last()
+                .and_then(|last| self.conditions.get(&join_path(prefix, last)))
+            {
+                let depends_on = &condition.depends_on;
+                if !fields.contains_key(depends_on) || chain.contains(depends_on) {
+                    break;
+                }
+                chain.push(depends_on.clone());
+            }
+            for name in chain.into_iter().rev() {
+                if !order.contains(&name) {
+                    order.push(name);
+                }
+            }
+        }
+        order
+    }
+
+    /// Computes the value offered for `field` (a dotted path) when prompting, instead of the
+    /// one of `T::default()`, e.g. `with_default_provider("username", || env::var("USER").ok())`.
+    /// `provider` runs each time the struct is prompted for, and only when nothing is
+    /// stored yet or the field is empty; returning `None` keeps the usual default.
+    pub fn with_default_provider<F, V>(mut self, field: &str, provider: F) -> Self
+    where
+        F: Fn() -> Option<V> + Send + Sync + 'static,
+        V: Into<Value>,
+    {
+        self.default_providers.insert(
+            field.to_string(),
+            Arc::new(move || provider().map(Into::into)),
+        );
+        self
+    }
+
+    /// Replaces the fields with a default provider by the provided value, when nothing was
+    /// stored or the field is empty.
+    fn apply_default_providers(&self, fields: &mut Value, stored: bool) {
+        for (field, provider) in &self.default_providers {
+            let current = field
+                .split('.')
+                .try_fold(&*fields, |value, segment| value.get(segment));
+            let empty = match current {
+                None | Some(Value::Null) => true,
+                Some(Value::String(text)) => text.is_empty(),
+                Some(_) => false,
+            };
+            if !stored || empty {
+                if let Some(value) = provider() {
+                    insert_field(fields, field, value);
+                }
+            }
+        }
+    }
+
+    /// Offers `candidates` for `field` (a dotted path), e.g. the known AWS regions.
+    /// `request_key` lists them with numbers; the answer may be a number from the list, the
+    /// start of a single candidate (completed to it) or any other text. A number that is
+    /// itself a candidate is taken as is.
+    pub fn with_suggestions<I, S>(mut self, field: &str, candidates: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.suggestions.insert(
+            field.to_string(),
+            candidates.into_iter().map(Into::into).collect(),
+        );
+        self
+    }
+
+    /// Declares `field` (a dotted path) as a date, for fields whose default value doesn't
+    /// show it, like `Option<NaiveDate>` (feature `chrono` or `time`). Fields whose default
+    /// is a date, like `NaiveDate` or `DateTime<Utc>`, are recognized without it.
+    ///
+    /// `request_key` accepts the formats listed in the `datetime` module and asks again on
+    /// anything else.
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    pub fn with_date_field(mut self, field: &str, kind: DateKind) -> Self {
+        self.date_fields.insert(field.to_string(), kind);
+        self
+    }
+
+    /// Declares `field` (a dotted path) as a UUID, for fields whose default value doesn't
+    /// show it, like `Option<Uuid>` (feature `uuid`). `Uuid` fields, which default to the
+    /// nil UUID, are recognized without it.
+    ///
+    /// `request_key` validates the entered UUID, and generates a random (v4) one when the
+    /// answer is empty.
+    #[cfg(feature = "uuid")]
+    pub fn with_uuid_field(mut self, field: &str) -> Self {
+        self.uuid_fields.insert(field.to_string());
+        self
+    }
+
+    /// Checks the stored JSON against the JSON Schema of `T` before deserializing it, and
+    /// values against it before storing them (feature `schemars`). Failures name the
+    /// field, like `field 'port' expected integer, found string`, where serde would only
+    /// report a position in the JSON; they are `ErrorKind::Corrupt` on read and
+    /// `ErrorKind::InvalidInput` on store.
+    #[cfg(feature = "schemars")]
+    pub fn with_schema_validation(mut self) -> Self
+    where
+        T: JsonSchema,
+    {
+        self.schema = Some(Arc::new(schemars::schema_for!(T)));
+        self
+    }
+
+    /// Checks each secret field entered in `request_key` against `policy` (e.g.
+    /// `policy::StrengthPolicy`). A weak value prints a warning; with `enforce`, the field
+    /// is asked again until the value passes.
+    pub fn with_prompt_policy<P>(mut self, policy: P, enforce: bool) -> Self
+    where
+        P: StorePolicy + 'static,
+    {
+        self.prompt_policy = Some((Arc::new(policy), enforce));
+        self
+    }
+
+    /// Checks a value typed for `field` against the prompt policy. Returns the violation
+    /// and whether it forces re-entry.
+    fn check_prompted(&self, field: &str, value: &str) -> Option<(PolicyViolation, bool)> {
+        let (policy, enforce) = self.prompt_policy.as_ref()?;
+        if !self.is_secret_field(field) {
+            return None;
+        }
+        policy.check(value).err().map(|v| (v, *enforce))
+    }
+
+    /// Returns true if the field was marked with `with_secret_field`.
+    pub fn is_secret_field(&self, field: &str) -> bool {
+        self.secret_fields.contains(field)
+    }
+
+    /// Reads the stored struct and returns it as pretty JSON with every secret field
+    /// replaced by `"***"`, suitable for bug reports and `--show-config` output.
+    pub fn dump_redacted(&mut self) -> Result<String> {
+        let mut value = self.read_value()?;
+        redact_fields(&mut value, self.secret_fields.iter().map(String::as_str));
+        serde_json::to_string_pretty(&value).map_err(|e| self.error(Operation::Read, e))
+    }
+
     /// Reads the value of a key from the keyring and deserializes it into a struct.
     pub fn read_key(&mut self) -> Result<T> {
+        let raw = self.key_manager.read_key()?;
+        let result = self.value_from(&raw, true).and_then(|json_value| {
+            // println!("{:#?}", json_value);
+            #[cfg(feature = "schemars")]
+            if let Some(schema) = &self.schema {
+                schema::validate(schema, &json_value)
+                    .map_err(|message| self.error(Operation::Read, Cause::Corrupt(message)))?;
+            }
+            serde_json::from_value(json_value).map_err(|e| self.error(Operation::Read, e))
+        });
+        match (result, &self.raw_values) {
+            (Err(e), Some(raw_values)) if e.kind() == ErrorKind::Corrupt => {
+                serde_json::from_value(raw_values.wrap(raw)).map_err(|_| e)
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Reads values stored by other programs under the same service and account, which
+    /// aren't the JSON of `T`, as the string field `field` (a dotted path) of an otherwise
+    /// default struct; the other fields need `#[serde(default)]`. Useful when adopting
+    /// key_vaulter over an existing install base that kept, say, a bare API token.
+    ///
+    /// The next `store` writes JSON, which the other program may not understand.
+    pub fn with_raw_value_field(mut self, field: &str) -> Self {
+        self.raw_values = Some(RawValues::Field(field.to_string()));
+        self
+    }
+
+    /// Same as `with_raw_value_field`, for a `T` that is a plain string: values that aren't
+    /// a JSON string, like `abc` or `12345`, are read as they are.
+    pub fn with_raw_values(mut self) -> Self {
+        self.raw_values = Some(RawValues::Whole);
+        self
+    }
+
+    /// The `KeyManager` of the entry holding the serialized struct, e.g. for its
+    /// `backend_info()`.
+    pub fn inner(&self) -> &KeyManager {
+        &self.key_manager
+    }
+
+    /// Returns the `KeyManager` of the entry holding the serialized struct, with its
+    /// backend, prompter and options.
+    pub fn into_inner(self) -> KeyManager {
+        self.key_manager
+    }
+
+    /// Reads the serialized string as stored, without deserializing it, renaming fields or
+    /// adding the fields kept in other backends (see `with_field_backend`).
+    pub fn read_raw(&mut self) -> Result<String> {
+        self.key_manager.read_key()
+    }
+
+    /// Stores `value` as is in place of the serialized struct, e.g. JSON written by a
+    /// non-Rust component sharing the entry. It isn't checked; a value `T` can't be
+    /// deserialized from makes `read_key` fail.
+    pub fn store_raw(&mut self, value: &str) -> Result<StoreOutcome> {
+        self.key_manager.store(value)
+    }
+
+    /// Reads the stored JSON, with the fields kept in other backends put back in place.
+    fn read_value(&mut self) -> Result<Value> {
         let json_value = self.key_manager.read_key()?;
-        // println!("{:#?}", json_value);
-        let struct_value: T = serde_json::from_str(&json_value)
-            .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
-        Ok(struct_value)
+        self.value_from(&json_value, true)
+    }
+
+    /// Parses the stored `json_value`, applies the renames and puts back the fields kept in
+    /// other backends, read through `read_key` when `gated` and straight from the backend
+    /// (see `KeyManager::read_stored`) otherwise.
+    fn value_from(&self, json_value: &str, gated: bool) -> Result<Value> {
+        let mut value: Value =
+            serde_json::from_str(json_value).map_err(|e| self.error(Operation::Read, e))?;
+        for (old, new) in &self.renamed_fields {
+            let exists = new
+                .split('.')
+                .try_fold(&value, |value, segment| value.get(segment))
+                .is_some();
+            if !exists {
+                if let Some(field_value) = take_field(&mut value, old) {
+                    insert_field(&mut value, new, field_value);
+                }
+            }
+        }
+        for (field, backend) in &self.field_backends {
+            let mut manager = self.field_manager(field, backend)?;
+            let field_value = if gated {
+                manager.read_key()
+            } else {
+                manager.read_stored()
+            };
+            let field_value = match field_value {
+                Ok(field_value) => field_value,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let field_value = serde_json::from_str(&field_value)
+                .map_err(|e| manager.error(Operation::Read, e))?;
+            insert_field(&mut value, field, field_value);
+        }
+        Ok(value)
     }
 
     /// Reads the value of the key, and if it does not exist, prompts the user and saves the new key value in the keyring.
+    /// Only a missing or corrupt value is prompted for; other errors are returned unchanged.
+    /// Like `KeyManager::read_or_request_key`, only one of several threads asking for the
+    /// same missing key prompts at a time, holding the key.
+    #[cfg(feature = "interactive")]
     pub fn read_or_request_key(&mut self, force: bool) -> Result<T> {
         if force {
             return self.request_key();
         }
-        match self.read_key() {
-            Ok(value) => Ok(value),
-            Err(_) => {
-                println!("The key was not found.");
-                self.request_key()
+        let rounds = self.key_manager.prompt_rounds();
+        match self.read_or_quarantine() {
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::Corrupt) => {
+                let turn = self.key_manager.prompt_turn();
+                if turn.stored_since(rounds) {
+                    return self.read_key();
+                }
+                hooks::debug(&format!(
+                    "'{}/{}' not read ({}), prompting",
+                    self.key_manager.system_name, self.key_manager.key_name, e
+                ));
+                if e.kind() == ErrorKind::Corrupt {
+                    self.notify_user("The stored value is corrupt.");
+                } else {
+                    self.notify_user("The key was not found.");
+                }
+                let result = self.prompt_and_store(None);
+                if result.is_ok() {
+                    turn.stored();
+                }
+                result
             }
+            result => result,
         }
     }
 
+    /// Same as `read_key`, `force` included: without the `interactive` feature, nothing is
+    /// prompted.
+    #[cfg(not(feature = "interactive"))]
+    pub fn read_or_request_key(&mut self, force: bool) -> Result<T> {
+        let _ = force;
+        self.read_key()
+    }
+
+    /// Reads the struct; a stored value that can't be deserialized is moved to
+    /// `<key_name>#corrupt` (see `recover_raw`) before the error is returned, so the next
+    /// prompt doesn't overwrite it.
+    #[cfg(feature = "interactive")]
+    fn read_or_quarantine(&mut self) -> Result<T> {
+        let error = match self.read_key() {
+            Err(e) if e.kind() == ErrorKind::Corrupt => e,
+            result => return result,
+        };
+        self.quarantine(&error)?;
+        Err(error)
+    }
+
+    /// The stored struct read straight from the backends, without the checks of
+    /// `read_key`, to start a prompt from.
+    #[cfg(feature = "interactive")]
+    fn read_stored(&self) -> Result<T> {
+        let json_value = self.key_manager.read_stored()?;
+        let result = self.value_from(&json_value, false).and_then(|value| {
+            serde_json::from_value(value).map_err(|e| self.error(Operation::Read, e))
+        });
+        match (result, &self.raw_values) {
+            (Err(e), Some(raw_values)) if e.kind() == ErrorKind::Corrupt => {
+                serde_json::from_value(raw_values.wrap(json_value)).map_err(|_| e)
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn corrupt_manager(&self) -> KeyManager {
+        let key_name = internal_key(&self.key_manager.key_name, "corrupt");
+        self.key_manager
+            .sibling(&key_name, self.key_manager.backend().clone())
+    }
+
+    /// Moves the stored value that failed with `error` aside, warning about it. Fails,
+    /// leaving the value in place, when it could not be copied, so that nothing prompts
+    /// over the only copy.
+    #[cfg(feature = "interactive")]
+    fn quarantine(&mut self, error: &Error) -> Result<()> {
+        let raw = self.key_manager.read_stored()?;
+        self.corrupt_manager().store(&raw)?;
+        self.key_manager.delete()?;
+        hooks::warn(&format!(
+            "{}; moved it to '{}'",
+            error,
+            self.corrupt_manager().key_name
+        ));
+        Ok(())
+    }
+
+    /// Returns the raw value moved aside by `read_or_request_key` or `request_key` when
+    /// the stored value could not be deserialized, e.g. after an incompatible change to
+    /// `T`, so the app can salvage what it can. Fails with `ErrorKind::NotFound` if
+    /// nothing was quarantined. The value stays under `<key_name>#corrupt` (which
+    /// `delete` keeps) until the next quarantine replaces it.
+    pub fn recover_raw(&mut self) -> Result<String> {
+        self.corrupt_manager().read_key()
+    }
+
     /// Prompts the user to input each field of the struct and saves the serialized struct as the key value in the keyring.
+    ///
+    /// Each field shows its current value, from the stored struct if there is one and
+    /// from `T::default()` otherwise, e.g. `'age' [30]`; pressing Enter keeps it. Secret
+    /// fields show `***` instead.
+    #[cfg(feature = "interactive")]
     pub fn request_key(&mut self) -> Result<T> {
+        let stored = match self.read_stored() {
+            Err(e) if e.kind() == ErrorKind::Corrupt => {
+                self.quarantine(&e)?;
+                None
+            }
+            result => result.ok(),
+        };
+        self.prompt_and_store(stored)
+    }
+
+    /// Prompts for each field starting from `stored`, the value already read if any, and
+    /// stores the answers.
+    #[cfg(feature = "interactive")]
+    fn prompt_and_store(&mut self, stored: Option<T>) -> Result<T> {
+        self.load_history();
+        let struct_value: T = self.prompt_struct(stored)?;
+
+        // Armazena a struct no keyring
+        if let Ok(json) = serde_json::to_string(&struct_value).map(zeroize::Zeroizing::new) {
+            self.key_manager.keep_draft(&json);
+        }
+        self.store(&struct_value)?;
+        self.key_manager.discard_draft();
+        self.save_history();
+        Ok(struct_value)
+    }
+
+    /// See `KeyManager::with_draft_persistence`; the whole struct is kept.
+    pub fn with_draft_persistence(mut self, ttl: Duration) -> Self {
+        self.key_manager = self.key_manager.with_draft_persistence(ttl);
+        self
+    }
+
+    /// See `KeyManager::with_draft_store`; the whole struct is kept.
+    pub fn with_draft_store(mut self, store: EncryptedFileStore, ttl: Duration) -> Self {
+        self.key_manager = self.key_manager.with_draft_store(store, ttl);
+        self
+    }
+
+    /// Whether `request_key` kept answers it didn't store, in memory or, with
+    /// `with_draft_persistence`, from an earlier run.
+    pub fn has_draft(&self) -> bool {
+        self.key_manager.has_draft()
+    }
+
+    /// Forgets the answers kept by `request_key`, including their persisted copy.
+    pub fn discard_draft(&mut self) {
+        self.key_manager.discard_draft();
+    }
+
+    /// Stores the answers kept by a `request_key` that failed to store them, e.g. once
+    /// the keyring is unlocked, instead of prompting for every field again. The answers
+    /// are kept until they are stored; fails with `ErrorKind::InvalidInput` if there are
+    /// none.
+    pub fn retry_store(&mut self) -> Result<StoreOutcome> {
+        let Some(draft) = self.key_manager.take_draft() else {
+            return Err(self.error(
+                Operation::Store,
+                Cause::InvalidInput("no answers to store".to_string()),
+            ));
+        };
+        let result = serde_json::from_str::<T>(&draft)
+            .map_err(|e| self.error(Operation::Store, e))
+            .and_then(|value| self.store(&value));
+        match result {
+            Ok(_) => {
+                self.key_manager.discard_draft();
+                self.save_history();
+            }
+            Err(_) => self.key_manager.keep_draft(&draft),
+        }
+        result
+    }
+
+    /// Reads the field `old` of the stored JSON as `new` (both dotted paths), for values
+    /// stored before the field was renamed. The stored value keeps the old name until the
+    /// next `store`. Renames apply in the order they were added, and are skipped when
+    /// the stored JSON already has `new`.
+    pub fn rename_field(mut self, old: &str, new: &str) -> Self {
+        self.renamed_fields.push((old.to_string(), new.to_string()));
+        self
+    }
+
+    /// Fills the struct from environment variables named after `prefix` and the fields,
+    /// prompts only for the fields without one, and stores the result: a one-call
+    /// migration from env-var-only configuration.
+    ///
+    /// Variables are named `PREFIX_FIELD`, with `__` between nesting levels like in
+    /// `Vault::export_dotenv` (`APP_DATABASE__PASSWORD` for `database.password`), names in
+    /// upper case and other characters as `_`; an empty `prefix` leaves just `FIELD`.
+    /// Numbers and booleans are parsed like prompted input and fail with
+    /// `ErrorKind::InvalidInput` when malformed. Fields found in neither the environment
+    /// nor the prompt keep their stored or default value.
+    ///
+    /// Fails without storing anything when the stored value can't be read for another
+    /// reason than being missing, e.g. a denied read or a corrupt value.
+    pub fn import_from_env(&mut self, prefix: &str) -> Result<T> {
+        self.import_from_env_with(prefix, |name| std::env::var(name).ok())
+    }
+
+    /// Same as `import_from_env`, with the variables looked up by `lookup` instead of in
+    /// the process environment, e.g. from a map parsed out of a `.env` file.
+    pub fn import_from_env_with<F>(&mut self, prefix: &str, lookup: F) -> Result<T>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let stored = match self.read_key() {
+            Ok(stored) => Some(stored),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.load_history();
+        let has_stored = stored.is_some();
+        let mut fields = serde_json::to_value(stored.unwrap_or_default())
+            .map_err(|e| self.error(Operation::Prompt, e))?;
+        self.apply_default_providers(&mut fields, has_stored);
+
+        let mut imported = BTreeSet::new();
+        self.import_env_fields(
+            &lookup,
+            &env_segment(prefix),
+            true,
+            "",
+            &mut fields,
+            &mut imported,
+        )?;
+        if let Value::Object(ref mut object) = fields {
+            self.prompt_fields("", object, &imported)?;
+        }
+        let value: T =
+            serde_json::from_value(fields).map_err(|e| self.error(Operation::Prompt, e))?;
+        self.store(&value)?;
+        self.save_history();
+        Ok(value)
+    }
+
+    /// Replaces the scalar fields of `value` that have an environment variable, adding
+    /// their paths to `imported`. `variable` is the variable name of the object itself.
+    fn import_env_fields(
+        &self,
+        lookup: &dyn Fn(&str) -> Option<String>,
+        variable: &str,
+        top_level: bool,
+        prefix: &str,
+        value: &mut Value,
+        imported: &mut BTreeSet<String>,
+    ) -> Result<()> {
+        let Value::Object(fields) = value else {
+            return Ok(());
+        };
+        for (name, field) in fields.iter_mut() {
+            let field_path = join_path(prefix, name);
+            let field_variable = if top_level {
+                prefixed_variable(variable, name)
+            } else {
+                format!("{}__{}", variable, env_segment(name))
+            };
+            if field.is_object() {
+                self.import_env_fields(
+                    lookup,
+                    &field_variable,
+                    false,
+                    &field_path,
+                    field,
+                    imported,
+                )?;
+                continue;
+            }
+            let Some(text) = lookup(&field_variable) else {
+                continue;
+            };
+            let parsed = match &*field {
+                Value::String(_) => Some(Value::String(text)),
+                Value::Number(number) => parse_number(&text, self.number_locale, !number.is_f64()),
+                Value::Bool(_) => parse_bool(&text).map(Value::Bool),
+                _ => Some(serde_json::from_str(&text).unwrap_or(Value::String(text))),
+            };
+            *field = parsed.ok_or_else(|| {
+                self.error(
+                    Operation::Prompt,
+                    Cause::InvalidInput(format!(
+                        "{} is not a valid value for field '{}'",
+                        field_variable, field_path
+                    )),
+                )
+            })?;
+            imported.insert(field_path);
+        }
+        Ok(())
+    }
+
+    /// Reads the stored struct as environment variables named like in `import_from_env`
+    /// (`PREFIX_FIELD`, `PREFIX_DATABASE__USER` for nested fields), ready for
+    /// `Command::envs` or `Vault::spawn_with_secrets`. Strings are passed as-is, other
+    /// values as JSON.
+    pub fn to_env_map(&mut self, prefix: &str) -> Result<HashMap<String, String>> {
+        let value = self.read_value()?;
+        let mut entries = Vec::new();
+        if let Value::Object(fields) = &value {
+            let prefix = env_segment(prefix);
+            for (name, field) in fields {
+                flatten_env(&prefixed_variable(&prefix, name), name, field, &mut entries);
+            }
+        }
+        Ok(entries
+            .into_iter()
+            .map(|(variable, _, value)| (variable, value))
+            .collect())
+    }
+
+    /// Prompts for each field of `U`, starting from `start` or the default value, without
+    /// storing it.
+    ///
+    /// Nested objects (nested structs, `serde(flatten)`ed structs, the content of tagged
+    /// enums) are walked field by field and prompted with dotted names.
+    pub(crate) fn prompt_struct<U>(&self, start: Option<U>) -> Result<U>
+    where
+        U: Serialize + for<'de> Deserialize<'de> + Default,
+    {
         // Converte a struct padrão para um objeto JSON
-        let mut struct_map = serde_json::to_value(T::default())
-            .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
+        let stored = start.is_some();
+        let mut struct_map = serde_json::to_value(start.unwrap_or_default())
+            .map_err(|e| self.error(Operation::Prompt, e))?;
+        self.apply_default_providers(&mut struct_map, stored);
 
         // Atualiza cada campo do JSON com o valor do usuário
-        if let serde_json::Value::Object(ref mut fields) = struct_map {
-            for (field_name, field_value) in fields.iter_mut() {
-                print!("Please enter the value for field '{}': ", field_name);
-                io::stdout()
-                    .flush()
-                    .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
-                let mut input = String::new();
-                io::stdout()
-                    .flush()
-                    .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
-                io::stdin()
-                    .read_line(&mut input)
-                    .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
-                let input = input.trim().to_string();
-
-                // Tenta determinar o tipo do campo e realizar a conversão apropriada
-                let new_value = if field_value.is_number() {
-                    match input.parse::<i64>() {
-                        Ok(num) => serde_json::Value::Number(num.into()),
-                        Err(_) => {
-                            eprintln!(
-                                "Invalid input for field '{}'. Expected a number.",
-                                field_name
-                            );
-                            continue; // Pede o valor novamente
+        if let Value::Object(ref mut fields) = struct_map {
+            self.prompt_fields("", fields, &BTreeSet::new())?;
+        }
+
+        // Converte o objeto JSON para a struct U
+        serde_json::from_value(struct_map).map_err(|e| self.error(Operation::Prompt, e))
+    }
+
+    /// Prompts for every field of one JSON object but those in `skip`; `prefix` is the
+    /// dotted path of the object itself.
+    fn prompt_fields(
+        &self,
+        prefix: &str,
+        fields: &mut Map<String, Value>,
+        skip: &BTreeSet<String>,
+    ) -> Result<()> {
+        let path = |name: &str| join_path(prefix, name);
+
+        // The tag of an enum is asked first, since it decides the shape of the rest.
+        let tag = fields
+            .keys()
+            .find(|name| self.tagged_enums.contains_key(&path(name)))
+            .cloned();
+        if let Some(tag) = tag.as_ref().filter(|tag| !skip.contains(&path(tag))) {
+            let tag_path = path(tag);
+            let previous = fields[tag].clone();
+            let variant = self.prompt_scalar(&tag_path, &previous)?;
+            fields.insert(tag.clone(), variant.clone());
+            if variant != previous {
+                // The default value only describes the default variant.
+                let content = self.prompt_json(&format!(
+                    "Please enter the fields of variant {} of '{}' as JSON: ",
+                    variant, tag_path
+                ))?;
+                match &self.tagged_enums[&tag_path] {
+                    Some(content_field) => match content {
+                        Value::Null => {
+                            fields.remove(content_field);
                         }
-                    }
-                } else if field_value.is_boolean() {
-                    match input.to_lowercase().as_str() {
-                        "true" => serde_json::Value::Bool(true),
-                        "false" => serde_json::Value::Bool(false),
-                        _ => {
-                            eprintln!(
-                                "Invalid input for field '{}'. Expected true or false.",
-                                field_name
-                            );
-                            continue; // Pede o valor novamente
+                        content => {
+                            fields.insert(content_field.clone(), content);
+                        }
+                    },
+                    None => {
+                        fields.retain(|name, _| name == tag);
+                        if let Value::Object(content) = content {
+                            fields.extend(content);
                         }
                     }
-                } else {
-                    serde_json::Value::String(input)
+                }
+                return Ok(());
+            }
+        }
+
+        for field_name in self.prompt_order(prefix, fields) {
+            if Some(&field_name) == tag.as_ref() {
+                continue;
+            }
+            let field_path = path(&field_name);
+            if skip.contains(&field_path) {
+                continue;
+            }
+            if let Some(condition) = self.conditions.get(&field_path) {
+                let depends_on = fields.get(&condition.depends_on).unwrap_or(&Value::Null);
+                if !(condition.predicate)(depends_on) {
+                    continue;
+                }
+            }
+            let Some(field_value) = fields.get_mut(&field_name) else {
+                continue;
+            };
+            match field_value {
+                Value::Object(nested) => self.prompt_fields(&field_path, nested, skip)?,
+                _ => *field_value = self.prompt_scalar(&field_path, field_value)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Prompts for one field until the input fits the type of `current`.
+    fn prompt_scalar(&self, field_name: &str, current: &Value) -> Result<Value> {
+        #[cfg(feature = "uuid")]
+        if self.is_uuid_field(field_name, current) {
+            return self.prompt_uuid(field_name, current);
+        }
+        if let Value::Bool(default) = current {
+            return self.prompt_bool(field_name, *default);
+        }
+        let default = self.default_label(field_name, current);
+        let history = self.recent_answers(field_name);
+        // Without a current value, the last answer is offered instead.
+        let remembered = history.first().filter(|_| default.is_none());
+        let mut message = match default.as_ref().or(remembered) {
+            Some(label) => format!(
+                "Please enter the value for field '{}' [{}]: ",
+                field_name, label
+            ),
+            None => format!("Please enter the value for field '{}': ", field_name),
+        };
+        let candidates = self.suggestions.get(field_name);
+        if let Some(candidates) = candidates.filter(|c| !c.is_empty()) {
+            let list: String = candidates
+                .iter()
+                .enumerate()
+                .map(|(index, candidate)| format!("  {}) {}\n", index + 1, candidate))
+                .collect();
+            message = format!("Suggestions for '{}':\n{}{}", field_name, list, message);
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            self.notify_prompt(PromptEventKind::Asked, field_name, attempt, None);
+            let mut input = self.prompt_with_history(&message, &history)?;
+            if let (true, Some(answer)) = (input.is_empty(), remembered) {
+                input = answer.clone();
+            }
+            if let Some(candidate) = candidates.and_then(|c| complete_suggestion(c, &input)) {
+                input = candidate;
+            }
+            // Enter keeps the current value, still subject to the constraints.
+            let keep = input.is_empty() && default.is_some();
+            if !keep {
+                if let Some((violation, enforce)) = self.check_prompted(field_name, &input) {
+                    self.notify_user(&format!(
+                        "Warning for field '{}': {}",
+                        field_name,
+                        violation.reason()
+                    ));
+                    if enforce {
+                        let reason = violation.reason();
+                        self.notify_prompt(
+                            PromptEventKind::Rejected,
+                            field_name,
+                            attempt,
+                            Some(reason),
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            // Tenta determinar o tipo do campo e realizar a conversão apropriada
+            let parsed = if keep {
+                Some(current.clone())
+            } else if current.is_number() {
+                match parse_number(&input, self.number_locale, !current.is_f64()) {
+                    Some(number) => Some(number),
+                    None => {
+                        self.notify_user(&format!(
+                            "Invalid input for field '{}'. Expected a number.",
+                            field_name
+                        ));
+                        let reason = "Expected a number";
+                        self.notify_prompt(
+                            PromptEventKind::Rejected,
+                            field_name,
+                            attempt,
+                            Some(reason),
+                        );
+                        None
+                    }
+                }
+            } else if let Some(kind) = self.date_kind(field_name, current) {
+                match kind.parse(&input) {
+                    Some(date) => Some(Value::String(date)),
+                    None => {
+                        let reason = format!("Expected {}", kind.describe());
+                        self.notify_user(&format!(
+                            "Invalid input for field '{}'. {}.",
+                            field_name, reason
+                        ));
+                        self.notify_prompt(
+                            PromptEventKind::Rejected,
+                            field_name,
+                            attempt,
+                            Some(&reason),
+                        );
+                        None
+                    }
+                }
+            } else {
+                Some(Value::String(input))
+            };
+
+            if let Some(value) = parsed {
+                let checked = match self.constraints.get(field_name) {
+                    Some(constraint) => constraint.check(field_name, &value),
+                    None => Ok(()),
                 };
+                match checked {
+                    Ok(()) => {
+                        self.remember_answer(field_name, &value);
+                        self.notify_prompt(PromptEventKind::Accepted, field_name, attempt, None);
+                        return Ok(value);
+                    }
+                    Err(message) => {
+                        self.notify_user(&format!("Invalid input: {}.", message));
+                        self.notify_prompt(
+                            PromptEventKind::Rejected,
+                            field_name,
+                            attempt,
+                            Some(&message),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// How the current value of a field is shown in its prompt, or `None` when there is
+    /// nothing worth keeping (an empty string or `null`).
+    fn default_label(&self, field_name: &str, current: &Value) -> Option<String> {
+        match current {
+            Value::String(text) if text.is_empty() => None,
+            Value::String(_) | Value::Number(_) if self.is_secret_field(field_name) => {
+                Some(REDACTED.to_string())
+            }
+            Value::String(text) => Some(text.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            _ => None,
+        }
+    }
 
-                // Atualiza o campo no mapa JSON
-                *field_value = new_value;
+    /// Asks a yes/no question showing the default, e.g. `[y/N]`; an empty answer keeps it.
+    fn prompt_bool(&self, field_name: &str, default: bool) -> Result<Value> {
+        let message = format!(
+            "Please enter the value for field '{}' [{}]: ",
+            field_name,
+            if default { "Y/n" } else { "y/N" }
+        );
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            self.notify_prompt(PromptEventKind::Asked, field_name, attempt, None);
+            let input = self.prompt(&message)?;
+            let value = if input.is_empty() {
+                Some(default)
+            } else {
+                parse_bool(&input)
+            };
+            match value {
+                Some(value) => {
+                    self.notify_prompt(PromptEventKind::Accepted, field_name, attempt, None);
+                    return Ok(Value::Bool(value));
+                }
+                None => {
+                    self.notify_user(&format!(
+                        "Invalid input for field '{}'. Expected y or n.",
+                        field_name
+                    ));
+                    let reason = "Expected y or n";
+                    self.notify_prompt(
+                        PromptEventKind::Rejected,
+                        field_name,
+                        attempt,
+                        Some(reason),
+                    );
+                }
             }
         }
+    }
 
-        // Converte o objeto JSON para a struct T
-        let struct_value: T = serde_json::from_value(struct_map)
-            .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
+    #[cfg(feature = "uuid")]
+    fn is_uuid_field(&self, field_name: &str, current: &Value) -> bool {
+        self.uuid_fields.contains(field_name)
+            || current
+                .as_str()
+                .is_some_and(|value| uuid::Uuid::try_parse(value).is_ok())
+    }
 
-        // Armazena a struct no keyring
-        self.store_key(&struct_value)?;
-        Ok(struct_value)
+    /// Prompts for a UUID until the input is valid. An empty answer keeps the current UUID,
+    /// or generates one when it is nil or missing.
+    #[cfg(feature = "uuid")]
+    fn prompt_uuid(&self, field_name: &str, current: &Value) -> Result<Value> {
+        let existing = current
+            .as_str()
+            .and_then(|value| uuid::Uuid::try_parse(value).ok())
+            .filter(|uuid| !uuid.is_nil());
+        let message = match existing {
+            Some(uuid) => format!(
+                "Please enter the value for field '{}' [{}]: ",
+                field_name, uuid
+            ),
+            None => format!(
+                "Please enter the value for field '{}' ([enter] to auto-generate): ",
+                field_name
+            ),
+        };
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            self.notify_prompt(PromptEventKind::Asked, field_name, attempt, None);
+            let input = self.prompt(&message)?;
+            let uuid = if input.is_empty() {
+                Some(existing.unwrap_or_else(uuid::Uuid::new_v4))
+            } else {
+                uuid::Uuid::try_parse(&input).ok()
+            };
+            match uuid {
+                Some(uuid) => {
+                    self.notify_prompt(PromptEventKind::Accepted, field_name, attempt, None);
+                    return Ok(Value::String(uuid.to_string()));
+                }
+                None => {
+                    self.notify_user(&format!(
+                        "Invalid input for field '{}'. Expected a UUID.",
+                        field_name
+                    ));
+                    let reason = "Expected a UUID";
+                    self.notify_prompt(
+                        PromptEventKind::Rejected,
+                        field_name,
+                        attempt,
+                        Some(reason),
+                    );
+                }
+            }
+        }
+    }
+
+    /// The date kind of a field, declared or recognized from its default value.
+    fn date_kind(&self, field_name: &str, current: &Value) -> Option<DateKind> {
+        self.date_fields
+            .get(field_name)
+            .copied()
+            .or_else(|| current.as_str().and_then(DateKind::detect))
+    }
+
+    /// Prompts until the input parses as JSON. An empty answer is `null`.
+    fn prompt_json(&self, message: &str) -> Result<Value> {
+        loop {
+            let input = self.prompt(message)?;
+            if input.is_empty() {
+                return Ok(Value::Null);
+            }
+            match serde_json::from_str(&input) {
+                Ok(value) => return Ok(value),
+                Err(e) => self.notify_user(&format!("Invalid JSON: {}", e)),
+            }
+        }
+    }
+
+    fn notify_prompt(
+        &self,
+        kind: PromptEventKind,
+        field_name: &str,
+        attempt: u32,
+        reason: Option<&str>,
+    ) {
+        self.key_manager
+            .notify_prompt(kind, Some(field_name), attempt, reason);
+    }
+
+    /// Shows `message` through the prompter and returns the trimmed answer.
+    pub(crate) fn prompt(&self, message: &str) -> Result<String> {
+        self.key_manager.prompt(message)
+    }
+
+    pub(crate) fn notify_user(&self, message: &str) {
+        self.key_manager.notify_user(message);
+    }
+
+    fn prompt_with_history(&self, message: &str, history: &[String]) -> Result<String> {
+        self.key_manager.prompt_with_history(message, history)
+    }
+
+    /// Serializes the struct and stores it as the key value in the keyring, and tells
+    /// whether the key was created or updated.
+    pub fn store(&mut self, value: &T) -> Result<StoreOutcome> {
+        if !self.constraints.is_empty() {
+            let fields =
+                serde_json::to_value(value).map_err(|e| self.error(Operation::Store, e))?;
+            check_all(&self.constraints, &fields)
+                .map_err(|message| self.error(Operation::Store, Cause::InvalidInput(message)))?;
+        }
+        #[cfg(feature = "schemars")]
+        if let Some(schema) = &self.schema {
+            let fields =
+                serde_json::to_value(value).map_err(|e| self.error(Operation::Store, e))?;
+            schema::validate(schema, &fields)
+                .map_err(|message| self.error(Operation::Store, Cause::InvalidInput(message)))?;
+        }
+        if self.field_backends.is_empty() {
+            let json_value = self.serialize(value)?;
+            return self.key_manager.store(&json_value);
+        }
+
+        // Fields kept elsewhere are written first, so the struct never refers to a field
+        // that failed to store.
+        let mut fields =
+            serde_json::to_value(value).map_err(|e| self.error(Operation::Store, e))?;
+        for (field, backend) in &self.field_backends {
+            let mut manager = self.field_manager(field, backend)?;
+            match take_field(&mut fields, field) {
+                Some(field_value) => manager.store(&self.serialize(&field_value)?).map(drop)?,
+                None => manager.delete().map(drop)?,
+            }
+        }
+        let json_value = self.serialize(&fields)?;
+        self.key_manager.store(&json_value)
     }
 
     /// Serializes the struct and stores it as the key value in the keyring.
+    #[deprecated(since = "0.2.0", note = "use `store`, which returns a `StoreOutcome`")]
     pub fn store_key(&mut self, value: &T) -> Result<()> {
-        let json_value = serde_json::to_string(value)
-            .map_err(|e| keyring::Error::PlatformFailure(Box::new(e)))?;
-        self.key_manager.store_key(&json_value)
+        self.store(value).map(drop)
+    }
+
+    /// Serializes a value the way it is written to the backend.
+    fn serialize<V: Serialize>(&self, value: &V) -> Result<String> {
+        let result = if self.canonical {
+            serde_json::to_value(value).map(|v| to_canonical_string(&v))
+        } else {
+            serde_json::to_string(value)
+        };
+        result.map_err(|e| self.error(Operation::Store, e))
+    }
+
+    /// Deletes the key value from the keyring, along with the fields kept in other
+    /// backends. Deleting a missing key is not an error.
+    pub fn delete(&mut self) -> Result<DeleteOutcome> {
+        let outcome = self.key_manager.delete()?;
+        for (field, backend) in &self.field_backends {
+            self.field_manager(field, backend)?.delete()?;
+        }
+        Ok(outcome)
     }
 
-    /// Deletes the key value from the keyring.
+    /// Deletes the key value from the keyring, along with the fields kept in other
+    /// backends; fails with `ErrorKind::NotFound` if it is missing.
+    #[deprecated(
+        since = "0.2.0",
+        note = "use `delete`, which returns a `DeleteOutcome`"
+    )]
     pub fn delete_key(&mut self) -> Result<()> {
-        self.key_manager.delete_key()
+        match self.delete()? {
+            DeleteOutcome::Deleted => Ok(()),
+            DeleteOutcome::NotFound => Err(self.error(Operation::Delete, keyring::Error::NoEntry)),
+        }
+    }
+}
+
+/// The variable of the top-level field `name`: `PREFIX_NAME`, or `NAME` without a prefix.
+fn prefixed_variable(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        env_segment(name)
+    } else {
+        format!("{}_{}", prefix, env_segment(name))
+    }
+}
+
+/// The dotted path of the field `name` of the object at `prefix`.
+fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// The candidate an answer designates: its number in the list, or the only candidate it
+/// starts (ignoring case). `None` for exact candidates and free text.
+fn complete_suggestion(candidates: &[String], input: &str) -> Option<String> {
+    if input.is_empty() || candidates.iter().any(|candidate| candidate == input) {
+        return None;
+    }
+    if let Ok(number) = input.parse::<usize>() {
+        return number
+            .checked_sub(1)
+            .and_then(|index| candidates.get(index))
+            .cloned();
+    }
+    let input = input.to_lowercase();
+    let mut matches = candidates
+        .iter()
+        .filter(|candidate| candidate.to_lowercase().starts_with(&input));
+    match (matches.next(), matches.next()) {
+        (Some(candidate), None) => Some(candidate.clone()),
+        _ => None,
+    }
+}
+
+/// Removes the field at the dotted `path` from `value` and returns it.
+fn take_field(value: &mut Value, path: &str) -> Option<Value> {
+    let (parent, name) = match path.rsplit_once('.') {
+        Some((parent, name)) => (
+            parent
+                .split('.')
+                .try_fold(value, |value, segment| value.get_mut(segment))?,
+            name,
+        ),
+        None => (value, path),
+    };
+    parent.as_object_mut()?.remove(name)
+}
+
+/// Sets the field at the dotted `path` of `value`, creating the objects on the way.
+fn insert_field(value: &mut Value, path: &str, field_value: Value) {
+    let mut current = value;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        let Value::Object(fields) = current else {
+            return;
+        };
+        if segments.peek().is_none() {
+            fields.insert(segment.to_string(), field_value);
+            return;
+        }
+        current = fields
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+/// Accepts `y`/`yes`/`true`/`1` and `n`/`no`/`false`/`0`, in any case.
+fn parse_bool(input: &str) -> Option<bool> {
+    match input.to_lowercase().as_str() {
+        "y" | "yes" | "true" | "1" => Some(true),
+        "n" | "no" | "false" | "0" => Some(false),
+        _ => None,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::ScriptedPrompter;
+    use crate::vault::Vault;
 
     #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
     struct TestStruct {
@@ -137,50 +1615,709 @@ mod tests {
         assert_eq!(manager.key_manager.key_name, "test_struct_key1");
     }
 
+    #[test]
+    fn test_canonical_serialization() {
+        #[derive(Serialize, Deserialize, Default)]
+        struct Unordered {
+            zeta: f64,
+            alpha: String,
+        }
+        let manager: StructKeyManager<Unordered> =
+            StructKeyManager::new("key_manager_service", "test_struct_key6")
+                .with_canonical_json(true);
+        let value = Unordered {
+            zeta: -0.0,
+            alpha: "a".to_string(),
+        };
+        assert_eq!(
+            manager.serialize(&value).unwrap(),
+            r#"{"alpha":"a","zeta":0.0}"#
+        );
+    }
+
+    #[test]
+    fn test_prompt_policy_applies_to_secret_fields() {
+        let manager: StructKeyManager<TestStruct> =
+            StructKeyManager::new("key_manager_service", "test_struct_key7")
+                .with_secret_field("field1")
+                .with_prompt_policy(crate::policy::ValuePolicy::new().reject_empty(), true);
+        let (violation, enforce) = manager.check_prompted("field1", "").unwrap();
+        assert!(violation.reason().contains("empty"));
+        assert!(enforce);
+        assert!(manager.check_prompted("field1", "value").is_none());
+        assert!(manager.check_prompted("field2", "").is_none());
+    }
+
     #[test]
     fn test_store_and_read_struct_key() {
         let mut manager: StructKeyManager<TestStruct> =
-            StructKeyManager::new("key_manager_service", "test_struct_key2");
-        match manager.read_key() {
-            Ok(_) => {
-                manager.delete_key().unwrap();
-            }
-            Err(_) => {}
+            Vault::ephemeral_for_tests().structured("test_struct_key2");
+        if manager.read_key().is_ok() {
+            manager.delete().unwrap();
         }
         let test_value = TestStruct {
             field1: "value1".to_string(),
             field2: 42,
         };
-        manager.store_key(&test_value).unwrap();
+        manager.store(&test_value).unwrap();
         let read_value = manager.read_key().unwrap();
         assert_eq!(read_value, test_value);
     }
 
+    #[cfg(feature = "interactive")]
     #[test]
     fn test_read_or_request_struct_key() {
-        let mut manager: StructKeyManager<TestStruct> =
-            StructKeyManager::new("key_manager_service", "test_struct_key3");
-        match manager.read_key() {
-            Ok(value) => {
-                assert_eq!(manager.read_or_request_key().unwrap(), value);
+        let vault =
+            Vault::ephemeral_for_tests().with_prompter(ScriptedPrompter::new(["value1", "42"]));
+        let mut manager: StructKeyManager<TestStruct> = vault.structured("test_struct_key3");
+        let expected = TestStruct {
+            field1: "value1".to_string(),
+            field2: 42,
+        };
+        assert_eq!(manager.read_or_request_key(false).unwrap(), expected);
+        crate::test_utils::assert_stored_json(&vault, "test_struct_key3", &expected);
+        assert_eq!(manager.read_or_request_key(false).unwrap(), expected);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_read_or_request_key_reads_once() {
+        let reads = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = std::sync::Arc::clone(&reads);
+        let vault =
+            Vault::ephemeral_for_tests().with_prompter(ScriptedPrompter::new(["value1", "42"]));
+        let mut manager: StructKeyManager<TestStruct> = vault
+            .structured("test_struct_reads_once")
+            .with_approver(move |_: &crate::hooks::ReadRequest| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                true
+            });
+        manager.read_or_request_key(false).unwrap();
+        assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_request_flattened_and_tagged_fields() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Limits {
+            retries: i32,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(tag = "kind", content = "settings")]
+        enum Auth {
+            Basic { user: String },
+            Token { token: String },
+        }
+
+        impl Default for Auth {
+            fn default() -> Self {
+                Auth::Basic {
+                    user: String::new(),
+                }
             }
-            Err(_) => {
-                // Test input is not automated in this example.
-                // To test this function, you would need to simulate stdin input.
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Connection {
+            #[serde(flatten)]
+            limits: Limits,
+            auth: Auth,
+        }
+
+        // Fields are asked in JSON key order: auth.kind, auth.settings.user, retries.
+        let vault = Vault::ephemeral_for_tests()
+            .with_prompter(ScriptedPrompter::new(["Basic", "admin", "3"]));
+        let mut manager: StructKeyManager<Connection> = vault
+            .structured("connection")
+            .with_tagged_enum("auth.kind", Some("settings"));
+        let connection = manager.request_key().unwrap();
+        assert_eq!(connection.limits.retries, 3);
+        assert_eq!(
+            connection.auth,
+            Auth::Basic {
+                user: "admin".to_string()
+            }
+        );
+
+        let prompter = ScriptedPrompter::new(["Token", r#"{"token":"t-1"}"#, "5"]);
+        let mut manager: StructKeyManager<Connection> = vault
+            .structured("connection")
+            .with_prompter(prompter.clone())
+            .with_tagged_enum("auth.kind", Some("settings"));
+        let connection = manager.request_key().unwrap();
+        assert_eq!(
+            connection.auth,
+            Auth::Token {
+                token: "t-1".to_string()
             }
+        );
+        assert!(prompter.prompts()[1].contains("variant \"Token\" of 'auth.kind'"));
+    }
+
+    #[cfg(all(feature = "uuid", feature = "interactive"))]
+    #[test]
+    fn test_request_uuid_fields() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Installation {
+            id: uuid::Uuid,
+            parent: Option<uuid::Uuid>,
         }
+
+        let prompter =
+            ScriptedPrompter::new(["", "not-a-uuid", "67E55044-10B1-426F-9247-BB680E5FE0C8"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<Installation> =
+            vault.structured("installation").with_uuid_field("parent");
+        let installation = manager.request_key().unwrap();
+        assert_eq!(installation.id.get_version_num(), 4);
+        assert_eq!(
+            installation.parent.unwrap().to_string(),
+            "67e55044-10b1-426f-9247-bb680e5fe0c8"
+        );
+        assert!(prompter.prompts()[0].contains("[enter] to auto-generate"));
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_schema_validation() {
+        #[derive(Serialize, Deserialize, schemars::JsonSchema, Debug, Default)]
+        struct Database {
+            host: String,
+            port: u16,
+        }
+
+        let vault = Vault::ephemeral_for_tests();
+        vault
+            .key("database")
+            .store(r#"{"host":"db","port":"5432"}"#)
+            .unwrap();
+        let mut manager: StructKeyManager<Database> =
+            vault.structured("database").with_schema_validation();
+        let error = manager.read_key().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Corrupt);
+        assert!(error
+            .to_string()
+            .contains("field 'port' expected integer, found string"));
+
+        let database = Database {
+            host: "db".into(),
+            port: 5432,
+        };
+        manager.store(&database).unwrap();
+        assert_eq!(manager.read_key().unwrap().port, 5432);
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_request_bool_fields() {
+        #[derive(Serialize, Deserialize, Debug)]
+        struct Flags {
+            verbose: bool,
+            verify_tls: bool,
+            telemetry: bool,
+        }
+
+        impl Default for Flags {
+            fn default() -> Self {
+                Flags {
+                    verbose: false,
+                    verify_tls: true,
+                    telemetry: false,
+                }
+            }
+        }
+
+        let prompter = ScriptedPrompter::new(["maybe", "YES", "", "0"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<Flags> = vault.structured("flags");
+        let flags = manager.request_key().unwrap();
+        assert!(flags.telemetry);
+        assert!(!flags.verbose);
+        assert!(!flags.verify_tls);
+        assert!(prompter.prompts()[0].ends_with("'telemetry' [y/N]: "));
+        assert!(prompter.prompts()[3].ends_with("'verify_tls' [Y/n]: "));
+        assert_eq!(
+            prompter.notices(),
+            vec!["Invalid input for field 'telemetry'. Expected y or n."]
+        );
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_range_and_length_constraints() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Server {
+            name: String,
+            port: u16,
+        }
+
+        let prompter = ScriptedPrompter::new(["db", "db-1", "0", "5432"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<Server> = vault
+            .structured("server")
+            .with_length("name", 3, 16)
+            .with_range("port", 1, 65535);
+        let server = manager.request_key().unwrap();
+        assert_eq!((server.name.as_str(), server.port), ("db-1", 5432));
+        assert!(prompter.is_exhausted());
+
+        let error = manager
+            .store(&Server {
+                name: "db-1".to_string(),
+                port: 0,
+            })
+            .unwrap_err();
+        assert_eq!(error.kind(), crate::error::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("port must be 1–65535"));
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_enter_keeps_current_values() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Profile {
+            name: String,
+            age: i32,
+            password: String,
+        }
+
+        impl Default for Profile {
+            fn default() -> Self {
+                Profile {
+                    name: String::new(),
+                    age: 30,
+                    password: String::new(),
+                }
+            }
+        }
+
+        let prompter = ScriptedPrompter::new(["", "ana", "s3cret", "", "", ""]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<Profile> =
+            vault.structured("profile").with_secret_field("password");
+        let first = manager.request_key().unwrap();
+        assert_eq!(first.age, 30);
+
+        // A second run offers the stored values.
+        assert_eq!(manager.request_key().unwrap(), first);
+        let prompts = prompter.prompts();
+        assert!(prompts[0].ends_with("'age' [30]: "));
+        assert!(prompts[1].ends_with("'name': "));
+        assert!(prompts[4].ends_with("'name' [ana]: "));
+        assert!(prompts[5].ends_with("'password' [***]: "));
+    }
+
+    #[test]
+    fn test_split_fields_across_backends() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Api {
+            endpoint_url: String,
+            auth: Auth,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Auth {
+            user: String,
+            token: String,
+        }
+
+        let vault = Vault::ephemeral_for_tests();
+        let tokens = Backend::Memory(crate::memory_store::MemoryStore::new());
+        let mut manager: StructKeyManager<Api> = vault
+            .structured("api")
+            .with_field_backend("auth.token", tokens.clone());
+        let api = Api {
+            endpoint_url: "https://example.com".to_string(),
+            auth: Auth {
+                user: "admin".to_string(),
+                token: "t-1".to_string(),
+            },
+        };
+        manager.store(&api).unwrap();
+        assert_eq!(manager.read_key().unwrap(), api);
+        assert_eq!(
+            tokens.get(vault.system_name(), "api.auth.token").unwrap(),
+            r#""t-1""#
+        );
+        let rest = vault.key("api").read_key().unwrap();
+        assert!(!rest.contains("t-1"));
+
+        manager.delete().unwrap();
+        assert!(tokens.get(vault.system_name(), "api.auth.token").is_err());
+    }
+
+    #[test]
+    fn test_field_backends_dont_collide_with_internal_entries() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Settings {
+            history: String,
+        }
+
+        let vault = Vault::ephemeral_for_tests();
+        let mut manager: StructKeyManager<Settings> = vault
+            .structured("settings")
+            .with_field_backend("history", vault.backend().clone())
+            .with_prompt_history(5);
+        let settings = Settings {
+            history: "kept".to_string(),
+        };
+        manager.store(&settings).unwrap();
+        manager.save_history();
+        assert_eq!(manager.read_key().unwrap(), settings);
+        assert_eq!(
+            vault.key("settings.history").read_key().unwrap(),
+            r#""kept""#
+        );
+        assert!(vault.key("settings#history").read_key().is_ok());
+
+        let mut reserved: StructKeyManager<Settings> = vault
+            .structured("reserved")
+            .with_field_backend("history#x", vault.backend().clone());
+        assert_eq!(
+            reserved.store(&settings).unwrap_err().kind(),
+            ErrorKind::InvalidName
+        );
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_prompt_history_offers_last_answers() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Host {
+            hostname: String,
+            password: String,
+        }
+
+        let prompter = ScriptedPrompter::new(["db1", "s3cret", "", ""]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<Host> = vault
+            .structured("host")
+            .with_secret_field("password")
+            .with_prompt_history(5);
+        manager.request_key().unwrap();
+        manager.delete().unwrap();
+
+        // After a reset, the hostname is offered again, the password is not.
+        let host = manager.request_key().unwrap();
+        assert_eq!(host.hostname, "db1");
+        assert!(host.password.is_empty());
+        let prompts = prompter.prompts();
+        assert!(prompts[2].ends_with("'hostname' [db1]: "));
+        assert!(prompts[3].ends_with("'password': "));
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_suggestions() {
+        let regions = ["us-east-1", "eu-west-1", "eu-central-1"].map(String::from);
+        assert_eq!(
+            complete_suggestion(&regions, "2").as_deref(),
+            Some("eu-west-1")
+        );
+        assert_eq!(
+            complete_suggestion(&regions, "EU-C").as_deref(),
+            Some("eu-central-1")
+        );
+        assert_eq!(complete_suggestion(&regions, "eu-"), None);
+        assert_eq!(complete_suggestion(&regions, "4"), None);
+        assert_eq!(complete_suggestion(&regions, "ap-south-1"), None);
+
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Aws {
+            region: String,
+        }
+        let prompter = ScriptedPrompter::new(["3"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<Aws> =
+            vault.structured("aws").with_suggestions("region", regions);
+        assert_eq!(manager.request_key().unwrap().region, "eu-central-1");
+        assert!(prompter.prompts()[0].starts_with("Suggestions for 'region':\n  1) us-east-1\n"));
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_conditional_fields() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Network {
+            host: String,
+            proxy_password: String,
+            use_proxy: bool,
+        }
+
+        // Fields are in JSON key order, except proxy_password which waits for use_proxy.
+        let prompter = ScriptedPrompter::new(["h1", "n", "h2", "y", "s3cret"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let manager = |key: &str| -> StructKeyManager<Network> {
+            vault
+                .structured(key)
+                .with_condition("proxy_password", "use_proxy", |v| v == true)
+        };
+        let direct = manager("direct").request_key().unwrap();
+        assert!(!direct.use_proxy && direct.proxy_password.is_empty());
+        let proxied = manager("proxied").request_key().unwrap();
+        assert_eq!(proxied.proxy_password, "s3cret");
+        assert!(prompter.prompts()[4].contains("'proxy_password'"));
+        assert!(prompter.is_exhausted());
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_default_providers() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Login {
+            hostname: String,
+            port: u16,
+        }
+
+        let prompter = ScriptedPrompter::new(["", "", "", ""]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<Login> = vault
+            .structured("login")
+            .with_default_provider("hostname", || Some("build-01"))
+            .with_default_provider("port", || Some(22));
+        let login = manager.request_key().unwrap();
+        assert_eq!((login.hostname.as_str(), login.port), ("build-01", 22));
+        assert!(prompter.prompts()[0].ends_with("'hostname' [build-01]: "));
+
+        // Stored values win over providers.
+        manager
+            .store(&Login {
+                hostname: "db".to_string(),
+                port: 5432,
+            })
+            .unwrap();
+        assert_eq!(manager.request_key().unwrap().port, 5432);
+    }
+
+    #[test]
+    fn test_import_from_env() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Database {
+            user: String,
+            password: String,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct App {
+            database: Database,
+            port: u16,
+            verbose: bool,
+        }
+
+        let mut env = HashMap::from([
+            ("KV_IMPORT_TEST_DATABASE__USER", "admin"),
+            ("KV_IMPORT_TEST_PORT", "8080"),
+            ("KV_IMPORT_TEST_VERBOSE", "yes"),
+        ]);
+        let prompter = ScriptedPrompter::new(["s3cret"]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        let mut manager: StructKeyManager<App> = vault.structured("app");
+        fn lookup<'a>(env: &'a HashMap<&str, &str>) -> impl Fn(&str) -> Option<String> + 'a {
+            |name| env.get(name).map(|value| value.to_string())
+        }
+
+        let app = manager
+            .import_from_env_with("kv_import_test", lookup(&env))
+            .unwrap();
+        assert_eq!(app.database.user, "admin");
+        assert_eq!(app.database.password, "s3cret");
+        assert_eq!(app.port, 8080);
+        assert!(app.verbose);
+        assert!(prompter.prompts()[0].contains("'database.password'"));
+        assert!(prompter.is_exhausted());
+
+        env.insert("KV_IMPORT_TEST_PORT", "eighty");
+        let error = manager
+            .import_from_env_with("kv_import_test", lookup(&env))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        env.insert("KV_IMPORT_TEST_PORT", "8080");
+        manager.store_raw("not json").unwrap();
+        let error = manager
+            .import_from_env_with("kv_import_test", lookup(&env))
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Corrupt);
+        assert_eq!(manager.read_raw().unwrap(), "not json");
+    }
+
+    #[test]
+    fn test_to_env_map() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Database {
+            user: String,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct App {
+            database: Database,
+            port: u16,
+        }
+
+        let mut manager: StructKeyManager<App> = Vault::ephemeral_for_tests().structured("app");
+        manager
+            .store(&App {
+                database: Database {
+                    user: "admin".to_string(),
+                },
+                port: 8080,
+            })
+            .unwrap();
+        let variables = manager.to_env_map("my-app").unwrap();
+        assert_eq!(variables.len(), 2);
+        assert_eq!(variables["MY_APP_DATABASE__USER"], "admin");
+        assert_eq!(variables["MY_APP_PORT"], "8080");
+        assert!(manager.to_env_map("").unwrap().contains_key("PORT"));
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_corrupt_value_is_quarantined() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Account {
+            user: String,
+        }
+
+        let vault = Vault::ephemeral_for_tests().with_prompter(ScriptedPrompter::new(["ana"]));
+        vault.key("account").store("{\"user\":").unwrap();
+        let mut manager: StructKeyManager<Account> = vault.structured("account");
+        assert_eq!(
+            manager.recover_raw().unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(manager.read_or_request_key(false).unwrap().user, "ana");
+        assert_eq!(manager.recover_raw().unwrap(), "{\"user\":");
+        assert_eq!(manager.read_key().unwrap().user, "ana");
+    }
+
+    #[cfg(feature = "interactive")]
+    #[test]
+    fn test_failed_quarantine_keeps_the_corrupt_value() {
+        use crate::memory_store::MemoryStore;
+        use crate::store::SecretStore;
+
+        /// Refuses to write the quarantine copies.
+        struct NoQuarantine(MemoryStore);
+
+        impl SecretStore for NoQuarantine {
+            fn get(&self, system_name: &str, key_name: &str) -> keyring::Result<String> {
+                self.0.get(system_name, key_name)
+            }
+
+            fn set(&self, system_name: &str, key_name: &str, value: &str) -> keyring::Result<()> {
+                if key_name.ends_with("#corrupt") {
+                    return Err(keyring::Error::NoStorageAccess("full".into()));
+                }
+                self.0.set(system_name, key_name, value)
+            }
+
+            fn delete(&self, system_name: &str, key_name: &str) -> keyring::Result<()> {
+                self.0.delete(system_name, key_name)
+            }
+        }
+
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Account {
+            user: String,
+        }
+
+        let vault = Vault::ephemeral_for_tests()
+            .with_backend(Backend::custom(NoQuarantine(MemoryStore::new())))
+            .with_prompter(ScriptedPrompter::new(["ana", "bia"]));
+        vault.key("account").store("{\"user\":").unwrap();
+        let mut manager: StructKeyManager<Account> = vault.structured("account");
+        let error = manager.read_or_request_key(false).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AccessDenied);
+        assert!(manager.request_key().is_err());
+        assert_eq!(vault.key("account").read_key().unwrap(), "{\"user\":");
+    }
+
+    #[test]
+    fn test_read_and_store_raw() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Account {
+            user: String,
+        }
+
+        let vault = Vault::ephemeral_for_tests();
+        let mut manager: StructKeyManager<Account> = vault.structured("account");
+        assert_eq!(
+            manager.store_raw(r#"{"user": "ana", "extra": 1}"#).unwrap(),
+            StoreOutcome::Created
+        );
+        assert_eq!(manager.read_key().unwrap().user, "ana");
+        assert_eq!(
+            manager.read_raw().unwrap(),
+            r#"{"user": "ana", "extra": 1}"#
+        );
+        assert_eq!(manager.inner().key_name, "account");
+        assert!(manager.into_inner().read_key().unwrap().contains("ana"));
+    }
+
+    #[test]
+    fn test_raw_values() {
+        #[derive(Serialize, Deserialize, Debug, Default)]
+        struct Api {
+            token: String,
+            #[serde(default)]
+            endpoint: String,
+        }
+
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("api").store("12345").unwrap();
+        let mut manager: StructKeyManager<Api> = vault.structured("api");
+        assert_eq!(manager.read_key().unwrap_err().kind(), ErrorKind::Corrupt);
+        let reads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&reads);
+        let mut manager = manager.with_raw_value_field("token").with_approver(
+            move |_: &crate::hooks::ReadRequest| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                true
+            },
+        );
+        assert_eq!(manager.read_key().unwrap().token, "12345");
+        assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        vault.key("plain").store("abc").unwrap();
+        let mut plain: StructKeyManager<String> = vault.structured("plain").with_raw_values();
+        assert_eq!(plain.read_key().unwrap(), "abc");
+        plain.store(&"def".to_string()).unwrap();
+        assert_eq!(plain.read_key().unwrap(), "def");
+    }
+
+    #[test]
+    fn test_renamed_fields() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+        struct Login {
+            username: String,
+            port: u16,
+        }
+
+        let vault = Vault::ephemeral_for_tests();
+        vault
+            .key("login")
+            .store(r#"{"user":"ana","connection":{"port":22}}"#)
+            .unwrap();
+        let mut manager: StructKeyManager<Login> = vault
+            .structured("login")
+            .rename_field("user", "username")
+            .rename_field("connection.port", "port");
+        let login = manager.read_key().unwrap();
+        assert_eq!((login.username.as_str(), login.port), ("ana", 22));
     }
 
     #[test]
     fn test_delete_struct_key() {
         let mut manager: StructKeyManager<TestStruct> =
-            StructKeyManager::new("key_manager_service", "test_struct_key4");
+            Vault::ephemeral_for_tests().structured("test_struct_key4");
         let test_value = TestStruct {
             field1: "value1".to_string(),
             field2: 42,
         };
-        manager.store_key(&test_value).unwrap();
-        manager.delete_key().unwrap();
+        manager.store(&test_value).unwrap();
+        manager.delete().unwrap();
         let result = manager.read_key();
         assert!(result.is_err());
     }
@@ -202,7 +2339,7 @@ mod tests {
         };
         println!("{:?}", read_value);
         println!("{:?}", test_value);
-        manager.delete_key().unwrap();
+        manager.delete().unwrap();
         assert_eq!(read_value, test_value);
     }
 }
@@ -0,0 +1,405 @@
+use crate::backend::Backend;
+use crate::error::{Cause, ErrorKind, Operation, Result};
+use crate::key_manager::KeyManager;
+use crate::naming::{internal_key, is_vault_entry};
+use crate::vault::Vault;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What the local backend remembers about one remote as of the last sync: the digest of
+/// each key, salted (see `KeyManager::digest_of`) so equal values can't be spotted across
+/// states and short values can't be looked up.
+#[derive(Serialize, Deserialize)]
+struct SyncState {
+    salt: String,
+    digests: BTreeMap<String, String>,
+}
+
+/// How `Vault::sync` settles a key changed on both sides since the last sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keeps the value written last, by the modification times the backends record
+    /// (SQLite stores do). Conflicts where a time is unknown are left unresolved.
+    NewestWins,
+    RemoteWins,
+    LocalWins,
+    /// Asks through the prompter which side to keep; an empty answer leaves the conflict
+    /// unresolved. Dry runs never ask.
+    Interactive,
+}
+
+/// What `Vault::sync` did, or would do, to a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAction {
+    /// The local value was copied to the remote backend.
+    Push,
+    /// The remote value was copied to the local backend.
+    Pull,
+    DeleteRemote,
+    DeleteLocal,
+    /// Both sides changed and the strategy didn't pick one; nothing was written.
+    Unresolved,
+}
+
+/// One key changed by a sync. Never carries the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncChange {
+    pub key: String,
+    pub action: SyncAction,
+    /// Whether both sides had changed since the last sync.
+    pub conflict: bool,
+}
+
+/// The keys changed by `Vault::sync`, in key order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub changes: Vec<SyncChange>,
+}
+
+impl SyncReport {
+    /// Whether both sides were already in sync.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The conflicts left for the next sync.
+    pub fn unresolved(&self) -> impl Iterator<Item = &SyncChange> {
+        self.changes
+            .iter()
+            .filter(|change| change.action == SyncAction::Unresolved)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Local,
+    Remote,
+}
+
+impl Vault {
+    /// Synchronizes the keys of this vault's system between its backend (e.g. the OS
+    /// keyring used as a local cache) and `remote`, both ways.
+    ///
+    /// Each key is compared with its state at the last sync with `remote`, kept in the
+    /// local backend (one state per remote): a key changed (or created, or deleted) on one side only is copied to the other, and
+    /// a key changed differently on both sides is settled by `strategy`. Writes go
+    /// through the vault's journal, policies and hooks. Both backends must support
+    /// listing.
+    pub fn sync(&self, remote: &Backend, strategy: ConflictStrategy) -> Result<SyncReport> {
+        self.run_sync(remote, strategy, false)
+    }
+
+    /// Reports what `sync` would change, without writing anything.
+    pub fn sync_dry_run(&self, remote: &Backend, strategy: ConflictStrategy) -> Result<SyncReport> {
+        self.run_sync(remote, strategy, true)
+    }
+
+    fn run_sync(
+        &self,
+        remote: &Backend,
+        strategy: ConflictStrategy,
+        dry_run: bool,
+    ) -> Result<SyncReport> {
+        let remote_vault = self.clone().with_backend(remote.clone());
+        let state_key = sync_state_key(remote);
+        let state = self.sync_state(&state_key)?;
+        let salt = BASE64.decode(&state.salt).map_err(|e| {
+            self.error(
+                Operation::Read,
+                Cause::Corrupt(format!("unreadable sync state: {}", e)),
+            )
+        })?;
+        let digest = |value: &str| KeyManager::digest_of(&salt, value);
+        let mut base = state.digests;
+        let mut keys: BTreeSet<String> = BTreeSet::new();
+        for vault in [self, &remote_vault] {
+            let listed = vault
+                .backend()
+                .list(self.system_name())
+                .map_err(|e| vault.error(Operation::Read, e))?;
+            keys.extend(listed.into_iter().filter(|key| !is_vault_entry(key)));
+        }
+        base.retain(|key, _| keys.contains(key));
+
+        let mut report = SyncReport::default();
+        for key in keys {
+            let mut local = self.key(&key);
+            let local_value = read_optional(&mut local)?;
+            let remote_value = read_optional(&mut remote_vault.key(&key))?;
+            let local_digest = local_value.as_deref().map(digest);
+            let remote_digest = remote_value.as_deref().map(digest);
+            if local_digest == remote_digest {
+                set_base(&mut base, &key, local_digest);
+                continue;
+            }
+            let previous = base.get(&key).cloned();
+            let local_changed = local_digest != previous;
+            let remote_changed = remote_digest != previous;
+            let conflict = local_changed && remote_changed;
+            let winner = if !conflict {
+                Some(if local_changed {
+                    Side::Local
+                } else {
+                    Side::Remote
+                })
+            } else {
+                self.settle(&key, &remote_vault, strategy, dry_run)?
+            };
+            let action = match (winner, &local_value, &remote_value) {
+                (None, _, _) => SyncAction::Unresolved,
+                (Some(Side::Local), Some(_), _) => SyncAction::Push,
+                (Some(Side::Local), None, _) => SyncAction::DeleteRemote,
+                (Some(Side::Remote), _, Some(_)) => SyncAction::Pull,
+                (Some(Side::Remote), _, None) => SyncAction::DeleteLocal,
+            };
+            if !dry_run {
+                match action {
+                    SyncAction::Push | SyncAction::DeleteRemote => {
+                        write(&mut remote_vault.key(&key), local_value.as_deref())?;
+                        set_base(&mut base, &key, local_digest);
+                    }
+                    SyncAction::Pull | SyncAction::DeleteLocal => {
+                        write(&mut local, remote_value.as_deref())?;
+                        set_base(&mut base, &key, remote_digest);
+                    }
+                    SyncAction::Unresolved => {}
+                }
+            }
+            report.changes.push(SyncChange {
+                key,
+                action,
+                conflict,
+            });
+        }
+        if !dry_run {
+            let state = SyncState {
+                salt: state.salt,
+                digests: base,
+            };
+            self.save_sync_state(&state_key, &state)?;
+        }
+        Ok(report)
+    }
+
+    /// Picks the side to keep for a key changed on both sides, `None` to leave it.
+    fn settle(
+        &self,
+        key: &str,
+        remote: &Vault,
+        strategy: ConflictStrategy,
+        dry_run: bool,
+    ) -> Result<Option<Side>> {
+        match strategy {
+            ConflictStrategy::LocalWins => Ok(Some(Side::Local)),
+            ConflictStrategy::RemoteWins => Ok(Some(Side::Remote)),
+            ConflictStrategy::NewestWins => {
+                let modified = |vault: &Vault| {
+                    vault
+                        .backend()
+                        .modified(self.system_name(), key)
+                        .map_err(|e| vault.error(Operation::Read, e))
+                };
+                Ok(match (modified(self)?, modified(remote)?) {
+                    (Some(local), Some(remote)) if local > remote => Some(Side::Local),
+                    (Some(local), Some(remote)) if remote > local => Some(Side::Remote),
+                    _ => None,
+                })
+            }
+            ConflictStrategy::Interactive if dry_run => Ok(None),
+            ConflictStrategy::Interactive => {
+                let manager = self.key(key);
+                loop {
+                    let answer = manager.prompt(&format!(
+                        "Key '{}' changed both locally and on {}. Keep the [l]ocal or the \
+                         [r]emote value? ",
+                        key,
+                        remote.backend().name()
+                    ))?;
+                    match answer.to_lowercase().as_str() {
+                        "l" | "local" => return Ok(Some(Side::Local)),
+                        "r" | "remote" => return Ok(Some(Side::Remote)),
+                        "" => return Ok(None),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// The state kept under `state_key`, or a fresh one with a new salt before the first
+    /// sync with that remote.
+    fn sync_state(&self, state_key: &str) -> Result<SyncState> {
+        match self.backend().get(self.system_name(), state_key) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                self.error(
+                    Operation::Read,
+                    Cause::Corrupt(format!("unreadable sync state: {}", e)),
+                )
+            }),
+            Err(keyring::Error::NoEntry) => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                Ok(SyncState {
+                    salt: BASE64.encode(salt),
+                    digests: BTreeMap::new(),
+                })
+            }
+            Err(e) => Err(self.error(Operation::Read, e)),
+        }
+    }
+
+    fn save_sync_state(&self, state_key: &str, state: &SyncState) -> Result<()> {
+        let json = serde_json::to_string(state).map_err(|e| self.error(Operation::Store, e))?;
+        self.backend()
+            .set(self.system_name(), state_key, &json)
+            .map_err(|e| self.error(Operation::Store, e))
+    }
+}
+
+/// The internal entry keeping the state of syncs with `remote`, e.g. `#sync-1f2e3d4c5b6a7988`:
+/// one per remote, so syncing with another remote starts from its own state.
+fn sync_state_key(remote: &Backend) -> String {
+    let id = KeyManager::digest_of(b"", &remote.identity());
+    internal_key("", &format!("sync-{}", &id[..16]))
+}
+
+fn read_optional(manager: &mut KeyManager) -> Result<Option<String>> {
+    match manager.read_key() {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stores `value`, or deletes the key when it is `None`.
+fn write(manager: &mut KeyManager, value: Option<&str>) -> Result<()> {
+    match value {
+        Some(value) => manager.store(value).map(drop),
+        None => manager.delete().map(drop),
+    }
+}
+
+fn set_base(base: &mut BTreeMap<String, String>, key: &str, digest: Option<String>) {
+    match digest {
+        Some(digest) => base.insert(key.to_string(), digest),
+        None => base.remove(key),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::MemoryStore;
+    use crate::test_utils::{assert_not_stored, assert_stored};
+
+    fn change(key: &str, action: SyncAction, conflict: bool) -> SyncChange {
+        SyncChange {
+            key: key.to_string(),
+            action,
+            conflict,
+        }
+    }
+
+    #[test]
+    fn test_two_way_sync() {
+        let local = Vault::ephemeral_for_tests();
+        let remote_backend = Backend::Memory(MemoryStore::new());
+        let remote = local.clone().with_backend(remote_backend.clone());
+        local.key("only_local").store("l").unwrap();
+        remote.key("only_remote").store("r").unwrap();
+        local.key("shared").store("same").unwrap();
+        remote.key("shared").store("same").unwrap();
+
+        let report = local
+            .sync(&remote_backend, ConflictStrategy::RemoteWins)
+            .unwrap();
+        assert_eq!(
+            report.changes,
+            vec![
+                change("only_local", SyncAction::Push, false),
+                change("only_remote", SyncAction::Pull, false),
+            ]
+        );
+        assert_stored(&remote, "only_local", "l");
+        assert_stored(&local, "only_remote", "r");
+
+        // One-sided changes win over the strategy; two-sided ones follow it.
+        local.key("only_local").delete().unwrap();
+        local.key("shared").store("local edit").unwrap();
+        remote.key("shared").store("remote edit").unwrap();
+        let preview = local
+            .sync_dry_run(&remote_backend, ConflictStrategy::LocalWins)
+            .unwrap();
+        assert_eq!(
+            preview.changes,
+            vec![
+                change("only_local", SyncAction::DeleteRemote, false),
+                change("shared", SyncAction::Push, true),
+            ]
+        );
+        assert_stored(&remote, "shared", "remote edit");
+
+        let report = local
+            .sync(&remote_backend, ConflictStrategy::NewestWins)
+            .unwrap();
+        assert_eq!(report.unresolved().count(), 1);
+        assert_not_stored(&remote, "only_local");
+
+        local
+            .sync(&remote_backend, ConflictStrategy::LocalWins)
+            .unwrap();
+        assert_stored(&remote, "shared", "local edit");
+        assert!(local
+            .sync(&remote_backend, ConflictStrategy::RemoteWins)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_sync_state_is_kept_per_remote() {
+        let local = Vault::ephemeral_for_tests();
+        let first = Backend::Memory(MemoryStore::new());
+        let second = Backend::Memory(MemoryStore::new());
+        local.key("token").store("t").unwrap();
+        local.sync(&first, ConflictStrategy::LocalWins).unwrap();
+        assert_eq!(
+            local
+                .sync(&second, ConflictStrategy::LocalWins)
+                .unwrap()
+                .changes,
+            vec![change("token", SyncAction::Push, false)]
+        );
+
+        // The states aren't app keys: cleaning orphans, gc and snapshots leave them out.
+        assert!(local
+            .clean_orphans(["token"], crate::orphans::OrphanCleanup::All)
+            .unwrap()
+            .is_empty());
+        local.gc(std::time::Duration::ZERO).unwrap();
+        assert_eq!(local.snapshot().unwrap().entries().len(), 1);
+        let states: Vec<String> = local
+            .backend()
+            .list(local.system_name())
+            .unwrap()
+            .into_iter()
+            .filter(|key| key.starts_with("#sync-"))
+            .map(|key| local.backend().get(local.system_name(), &key).unwrap())
+            .collect();
+        assert_eq!(states.len(), 2);
+        // Salted per state: the digests match neither the plain hash nor each other.
+        assert!(!states[0].contains(&KeyManager::pin_of("t")));
+        assert_ne!(
+            states[0].split("digests").nth(1),
+            states[1].split("digests").nth(1)
+        );
+        for remote in [&first, &second] {
+            assert!(local
+                .sync(remote, ConflictStrategy::RemoteWins)
+                .unwrap()
+                .is_empty());
+        }
+    }
+}
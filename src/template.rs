@@ -0,0 +1,167 @@
+use crate::error::{Cause, Operation, Result};
+use crate::file_store::sibling;
+use crate::vault::Vault;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+impl Vault {
+    /// Renders a template string, replacing each `{{ reference }}` placeholder.
+    ///
+    /// A placeholder is first looked up in `mapping` (alias → reference); otherwise it is
+    /// used as a reference directly. References are resolved with `Vault::resolve`, so
+    /// `{{ key }}` and `{{ key:field }}` both work. The result is only returned, never
+    /// written anywhere.
+    pub fn render_str(&self, template: &str, mapping: &HashMap<String, String>) -> Result<String> {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let after_open = &rest[start + 2..];
+            let Some(end) = after_open.find("}}") else {
+                let position = template.len() - rest.len() + start;
+                return Err(self.error(
+                    Operation::Export,
+                    Cause::InvalidInput(format!("unclosed placeholder at byte {}", position)),
+                ));
+            };
+            let placeholder = after_open[..end].trim();
+            let reference = mapping.get(placeholder).map(String::as_str);
+            output.push_str(&self.resolve(reference.unwrap_or(placeholder))?);
+            rest = &after_open[end + 2..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Reads the template at `template_path` and renders it like `render_str`.
+    pub fn render_template<P>(
+        &self,
+        template_path: P,
+        mapping: &HashMap<String, String>,
+    ) -> Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let template =
+            fs::read_to_string(template_path).map_err(|e| self.error(Operation::Export, e))?;
+        self.render_str(&template, mapping)
+    }
+
+    /// Renders the template at `template_path` and writes the result to `output_path`.
+    ///
+    /// On Unix the output file is created with mode `0600`, since it contains secrets.
+    pub fn render_template_to<P, Q>(
+        &self,
+        template_path: P,
+        output_path: Q,
+        mapping: &HashMap<String, String>,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let rendered = self.render_template(template_path, mapping)?;
+        write_private_file(output_path.as_ref(), rendered.as_bytes())
+            .map_err(|e| self.error(Operation::Export, e))
+    }
+}
+
+/// Writes `content` to `path`, readable only by the current user on Unix.
+///
+/// The content goes to a new file next to `path`, which then replaces it, so neither an
+/// existing file with looser permissions nor a symlink planted at `path` receives it.
+pub(crate) fn write_private_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let tmp_path = sibling(
+        path,
+        &format!(
+            ".{}-{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ),
+    );
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options
+        .open(&tmp_path)
+        .and_then(|mut tmp| tmp.write_all(content).and_then(|()| tmp.sync_all()))
+        .and_then(|()| fs::rename(&tmp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_render_str() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("token").store("abc").unwrap();
+        vault
+            .key("db")
+            .store(r#"{"host":"localhost","port":5432}"#)
+            .unwrap();
+        let mut mapping = HashMap::new();
+        mapping.insert("api".to_string(), "token".to_string());
+        let rendered = vault
+            .render_str(
+                "url=postgres://{{ db:host }}:{{db:port}}\ntoken={{ api }}\n",
+                &mapping,
+            )
+            .unwrap();
+        assert_eq!(rendered, "url=postgres://localhost:5432\ntoken=abc\n");
+    }
+
+    #[test]
+    fn test_unclosed_placeholder() {
+        let vault = Vault::ephemeral_for_tests();
+        let error = vault
+            .render_str("value={{ token", &HashMap::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_file_replaces_existing_files_and_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("private-file-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".env");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private_file(&path, b"secret").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "secret");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let target = dir.join("target");
+        fs::write(&target, "untouched").unwrap();
+        fs::remove_file(&path).unwrap();
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        write_private_file(&path, b"secret").unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "untouched");
+        assert!(!fs::symlink_metadata(&path)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,155 @@
+//! Helpers for testing code built on key_vaulter (feature `test-utils`).
+//!
+//! Combine them with `Vault::ephemeral_for_tests` to exercise interactive setup flows
+//! without a terminal or a real keychain:
+//!
+//! ```
+//! use key_vaulter::test_utils::{assert_stored, ScriptedPrompter};
+//! use key_vaulter::Vault;
+//!
+//! let prompter = ScriptedPrompter::new(["s3cret"]);
+//! let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+//! vault.key("token").read_or_request_key().unwrap();
+//! assert_stored(&vault, "token", "s3cret");
+//! assert!(prompter.is_exhausted());
+//! ```
+
+use crate::prompt::Prompter;
+use crate::vault::Vault;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct Script {
+    answers: VecDeque<String>,
+    prompts: Vec<String>,
+    notices: Vec<String>,
+}
+
+/// A [`Prompter`] answering prompts with canned answers, in order.
+///
+/// Clones share the script, so keep one to inspect the prompts after handing another to
+/// a manager. Once the answers run out, prompting fails with an I/O error of kind
+/// `UnexpectedEof`.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedPrompter {
+    script: Arc<Mutex<Script>>,
+}
+
+impl ScriptedPrompter {
+    pub fn new<I, S>(answers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ScriptedPrompter {
+            script: Arc::new(Mutex::new(Script {
+                answers: answers.into_iter().map(Into::into).collect(),
+                prompts: Vec::new(),
+                notices: Vec::new(),
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Script> {
+        self.script.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The messages shown so far, oldest first.
+    pub fn prompts(&self) -> Vec<String> {
+        self.lock().prompts.clone()
+    }
+
+    /// The messages shown without expecting an answer (see `Prompter::notify`), oldest
+    /// first.
+    pub fn notices(&self) -> Vec<String> {
+        self.lock().notices.clone()
+    }
+
+    /// Returns true once every answer has been consumed.
+    pub fn is_exhausted(&self) -> bool {
+        self.lock().answers.is_empty()
+    }
+}
+
+impl Prompter for ScriptedPrompter {
+    fn prompt(&self, message: &str) -> io::Result<String> {
+        let mut script = self.lock();
+        script.prompts.push(message.to_string());
+        script.answers.pop_front().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("no scripted answer left for prompt '{}'", message.trim()),
+            )
+        })
+    }
+
+    fn notify(&self, message: &str) {
+        self.lock().notices.push(message.to_string());
+    }
+}
+
+/// Panics unless `key_name` is stored in `vault` with the value `expected`.
+#[track_caller]
+pub fn assert_stored(vault: &Vault, key_name: &str, expected: &str) {
+    match vault.key(key_name).read_key() {
+        Ok(value) => assert!(
+            value == expected,
+            "key '{}' holds a different value than expected",
+            key_name
+        ),
+        Err(e) => panic!("key '{}' is not readable: {}", key_name, e),
+    }
+}
+
+/// Panics unless `key_name` holds JSON equal to `expected` serialized, ignoring field
+/// order and formatting.
+#[track_caller]
+pub fn assert_stored_json<T>(vault: &Vault, key_name: &str, expected: &T)
+where
+    T: Serialize,
+{
+    let expected = serde_json::to_value(expected).expect("expected value must serialize");
+    let stored = match vault.key(key_name).read_key() {
+        Ok(value) => value,
+        Err(e) => panic!("key '{}' is not readable: {}", key_name, e),
+    };
+    let stored: serde_json::Value = serde_json::from_str(&stored)
+        .unwrap_or_else(|e| panic!("key '{}' does not hold JSON: {}", key_name, e));
+    assert_eq!(
+        stored, expected,
+        "key '{}' holds a different payload",
+        key_name
+    );
+}
+
+/// Panics if `key_name` is stored in `vault`.
+#[track_caller]
+pub fn assert_not_stored(vault: &Vault, key_name: &str) {
+    if vault.key(key_name).read_key().is_ok() {
+        panic!("key '{}' is unexpectedly stored", key_name);
+    }
+}
+
+#[cfg(all(test, feature = "interactive"))]
+mod tests {
+    use super::*;
+    use crate::error::{ErrorKind, Operation};
+
+    #[test]
+    fn test_scripted_prompter() {
+        let prompter = ScriptedPrompter::new(["  padded  "]);
+        let vault = Vault::ephemeral_for_tests().with_prompter(prompter.clone());
+        assert_eq!(vault.key("token").request_key().unwrap(), "padded");
+        assert_stored(&vault, "token", "padded");
+        assert_not_stored(&vault, "other");
+
+        let error = vault.key("other").request_key().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Io);
+        assert_eq!(error.operation(), Operation::Prompt);
+        assert_eq!(prompter.prompts().len(), 2);
+        assert!(prompter.prompts()[0].contains("token"));
+    }
+}
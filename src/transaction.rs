@@ -0,0 +1,152 @@
+use crate::error::{Operation, Result};
+use crate::hooks;
+use crate::vault::Vault;
+use zeroize::Zeroizing;
+
+#[derive(Debug)]
+enum Staged {
+    Store(Zeroizing<String>),
+    Delete,
+}
+
+/// Writes staged by `Vault::transaction`. Nothing reaches the backend until the closure
+/// returns `Ok`.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    staged: Vec<(String, Staged)>,
+}
+
+impl Transaction {
+    /// Stages storing `value` under `key_name`.
+    pub fn store(&mut self, key_name: &str, value: &str) -> &mut Self {
+        self.staged.push((
+            key_name.to_string(),
+            Staged::Store(Zeroizing::new(value.to_string())),
+        ));
+        self
+    }
+
+    /// Stages deleting `key_name`.
+    pub fn delete(&mut self, key_name: &str) -> &mut Self {
+        self.staged.push((key_name.to_string(), Staged::Delete));
+        self
+    }
+}
+
+impl Vault {
+    /// Applies several writes as a unit, so related credentials (client id and secret)
+    /// never end up half-updated:
+    ///
+    /// ```no_run
+    /// use key_vaulter::Vault;
+    ///
+    /// Vault::new("my_service").transaction(|tx| {
+    ///     tx.store("client_id", "id-2").store("client_secret", "secret-2");
+    ///     Ok(())
+    /// })?;
+    /// # Ok::<(), key_vaulter::Error>(())
+    /// ```
+    ///
+    /// Writes are staged while the closure runs and applied in order once it returns `Ok`;
+    /// an `Err` discards them. When a write fails, the keys already written are restored to
+    /// their previous values (or deleted if they didn't exist) through their managers, so
+    /// cached values, hooks and the journal see the restore, and the write's error is
+    /// returned. A failed restore is reported through the warning hook.
+    pub fn transaction<F>(&self, build: F) -> Result<()>
+    where
+        F: FnOnce(&mut Transaction) -> Result<()>,
+    {
+        let mut transaction = Transaction::default();
+        build(&mut transaction)?;
+
+        let mut applied: Vec<(&str, Option<Zeroizing<String>>)> = Vec::new();
+        for (key_name, staged) in &transaction.staged {
+            let result = self.previous_value(key_name).and_then(|previous| {
+                let mut manager = self.key(key_name);
+                match staged {
+                    Staged::Store(value) => manager.store(value).map(drop)?,
+                    Staged::Delete => manager.delete().map(drop)?,
+                }
+                Ok(previous)
+            });
+            match result {
+                Ok(previous) => applied.push((key_name, previous)),
+                Err(e) => {
+                    self.roll_back(applied);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The current value of `key_name`, read straight from the backend.
+    fn previous_value(&self, key_name: &str) -> Result<Option<Zeroizing<String>>> {
+        match self.backend().get(self.system_name(), key_name) {
+            Ok(value) => Ok(Some(Zeroizing::new(value))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(self.key(key_name).error(Operation::Store, e)),
+        }
+    }
+
+    fn roll_back(&self, applied: Vec<(&str, Option<Zeroizing<String>>)>) {
+        for (key_name, previous) in applied.into_iter().rev() {
+            let mut manager = self.key(key_name);
+            let result = match &previous {
+                Some(value) => manager.store(value).map(drop),
+                None => manager.delete().map(drop),
+            };
+            if let Err(e) = result {
+                hooks::warn(&format!(
+                    "failed to roll back key '{}' of system '{}': {}",
+                    key_name,
+                    self.system_name(),
+                    e
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::policy::PolicyViolation;
+    use crate::test_utils::{assert_not_stored, assert_stored};
+
+    #[test]
+    fn test_failed_write_rolls_back() {
+        let vault = Vault::ephemeral_for_tests().with_policy(|value: &str| {
+            if value == "rejected" {
+                Err(PolicyViolation::new("rejected"))
+            } else {
+                Ok(())
+            }
+        });
+        let mut client_id = vault.key("client_id");
+        client_id.store("id-1").unwrap();
+
+        let error = vault
+            .transaction(|tx| {
+                tx.store("client_id", "id-2")
+                    .store("created", "x")
+                    .store("client_secret", "rejected");
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PolicyViolation);
+        assert_eq!(client_id.cached_value().as_deref(), Some("id-1"));
+        assert_stored(&vault, "client_id", "id-1");
+        assert_not_stored(&vault, "created");
+
+        vault
+            .transaction(|tx| {
+                tx.store("client_id", "id-2").store("client_secret", "s-2");
+                Ok(())
+            })
+            .unwrap();
+        assert_stored(&vault, "client_id", "id-2");
+        assert_stored(&vault, "client_secret", "s-2");
+    }
+}
@@ -0,0 +1,365 @@
+use crate::ambiguity::AmbiguityStrategy;
+use crate::backend::Backend;
+use crate::draft::DraftStore;
+use crate::error::{Cause, Error, Operation, Result};
+#[cfg(feature = "age")]
+use crate::escrow::Escrow;
+use crate::file_store::EncryptedFileStore;
+use crate::journal::{Journal, JournalEntry};
+use crate::key_manager::KeyManager;
+use crate::macos_options::MacOsOptions;
+use crate::memory_store::MemoryStore;
+use crate::naming::current_app_name;
+use crate::policy::{Policies, StorePolicy};
+use crate::prompt::{Prompter, SharedPrompter};
+use crate::rate_limit::RateLimiter;
+use crate::rotation::Rotations;
+use crate::struct_key_manager::StructKeyManager;
+use crate::windows_options::WindowsOptions;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::process::{Child, Command};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// All keys of one system (service) on one backend.
+///
+/// A `Vault` hands out `KeyManager`s and `StructKeyManager`s sharing its system name and
+/// backend, and offers operations spanning several keys.
+#[derive(Debug, Clone)]
+pub struct Vault {
+    system_name: String,
+    backend: Backend,
+    journal: Option<Journal>,
+    policies: Policies,
+    rate_limiter: Option<RateLimiter>,
+    prompter: Option<SharedPrompter>,
+    ambiguity: AmbiguityStrategy,
+    windows: Option<WindowsOptions>,
+    macos: Option<MacOsOptions>,
+    owner_description: bool,
+    rotations: Rotations,
+    drafts: Option<DraftStore>,
+    #[cfg(feature = "age")]
+    escrow: Option<Escrow>,
+}
+
+impl Vault {
+    /// Creates a vault for `system_name` on the default OS keyring.
+    pub fn new(system_name: &str) -> Self {
+        Vault {
+            system_name: system_name.to_string(),
+            backend: Backend::default(),
+            journal: None,
+            policies: Policies::default(),
+            rate_limiter: None,
+            prompter: None,
+            ambiguity: AmbiguityStrategy::default(),
+            windows: None,
+            macos: None,
+            owner_description: false,
+            rotations: Rotations::default(),
+            drafts: None,
+            #[cfg(feature = "age")]
+            escrow: None,
+        }
+    }
+
+    /// Creates a vault named after the running executable (see `naming::current_app_name`).
+    pub fn for_current_app() -> Self {
+        Vault::new(&current_app_name())
+    }
+
+    /// Creates a vault on a fresh in-memory backend under a random system name, so tests
+    /// never touch the real keychain or collide with each other. Everything stored is
+    /// wiped when the vault and the managers it handed out are dropped.
+    pub fn ephemeral_for_tests() -> Self {
+        let mut suffix = [0u8; 8];
+        OsRng.fill_bytes(&mut suffix);
+        let suffix: String = suffix.iter().map(|b| format!("{:02x}", b)).collect();
+        Vault::new(&format!("key_vaulter_test_{}", suffix))
+            .with_backend(Backend::Memory(MemoryStore::new()))
+    }
+
+    /// Uses the given backend instead of the default OS keyring.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Escrows every value stored through this vault's managers for organization
+    /// recovery (see `KeyManager::with_escrow`; feature `age`).
+    #[cfg(feature = "age")]
+    pub fn with_escrow(mut self, escrow: Escrow) -> Self {
+        self.escrow = Some(escrow);
+        self
+    }
+
+    /// Persists the drafts of the managers handed out by this vault for at most `ttl` (see
+    /// `KeyManager::with_draft_persistence`).
+    pub fn with_draft_persistence(mut self, ttl: Duration) -> Self {
+        self.drafts = Some(DraftStore::new(ttl));
+        self
+    }
+
+    /// Like `with_draft_persistence`, with the drafts kept in `store` (see
+    /// `KeyManager::with_draft_store`).
+    pub fn with_draft_store(mut self, store: EncryptedFileStore, ttl: Duration) -> Self {
+        self.drafts = Some(DraftStore::in_store(store, ttl));
+        self
+    }
+
+    /// Records every operation of the managers handed out by this vault in `journal`.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Checks every value stored through this vault against `policy`.
+    pub fn with_policy<P>(mut self, policy: P) -> Self
+    where
+        P: StorePolicy + 'static,
+    {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Limits the reads of every key of this vault. The limiter's counters are shared by
+    /// all managers handed out, so `limiter.stats()` covers the whole vault.
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Asks for values through `prompter` in every manager handed out by this vault.
+    pub fn with_prompter<P>(mut self, prompter: P) -> Self
+    where
+        P: Prompter + 'static,
+    {
+        self.prompter = Some(SharedPrompter(Arc::new(prompter)));
+        self
+    }
+
+    /// Applies `strategy` to every manager handed out by this vault (see
+    /// `KeyManager::with_ambiguity_strategy`).
+    pub fn with_ambiguity_strategy(mut self, strategy: AmbiguityStrategy) -> Self {
+        self.ambiguity = strategy;
+        self
+    }
+
+    /// Applies `options` to every manager handed out by this vault (see
+    /// `KeyManager::with_windows_options`).
+    pub fn with_windows_options(mut self, options: WindowsOptions) -> Self {
+        self.windows = Some(options);
+        self
+    }
+
+    /// Applies `options` to every manager handed out by this vault (see
+    /// `KeyManager::with_macos_options`).
+    pub fn with_macos_options(mut self, options: MacOsOptions) -> Self {
+        self.macos = Some(options);
+        self
+    }
+
+    /// Describes the entries stored through this vault with the owning app (see
+    /// `KeyManager::with_owner_description`).
+    pub fn with_owner_description(mut self) -> Self {
+        self.owner_description = true;
+        self
+    }
+
+    pub fn system_name(&self) -> &str {
+        &self.system_name
+    }
+
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    pub(crate) fn rotations(&self) -> &Rotations {
+        &self.rotations
+    }
+
+    pub(crate) fn rotations_mut(&mut self) -> &mut Rotations {
+        &mut self.rotations
+    }
+
+    /// Returns a manager for a plain string key of this vault.
+    pub fn key(&self, key_name: &str) -> KeyManager {
+        let manager = KeyManager::new(&self.system_name, key_name)
+            .with_backend(self.backend.clone())
+            .with_policies(&self.policies)
+            .with_ambiguity_strategy(self.ambiguity);
+        let manager = match &self.rate_limiter {
+            Some(limiter) => manager.with_rate_limit(limiter.clone()),
+            None => manager,
+        };
+        let manager = match &self.windows {
+            Some(options) => manager.with_windows_options(options.clone()),
+            None => manager,
+        };
+        let manager = match &self.macos {
+            Some(options) => manager.with_macos_options(options.clone()),
+            None => manager,
+        };
+        let manager = match &self.prompter {
+            Some(prompter) => manager.with_shared_prompter(prompter.clone()),
+            None => manager,
+        };
+        let manager = if self.owner_description {
+            manager.with_owner_description()
+        } else {
+            manager
+        };
+        let manager = if self.rotations.tracks(key_name) {
+            manager.with_rotation_tracking()
+        } else {
+            manager
+        };
+        let manager = match &self.drafts {
+            Some(drafts) => manager.with_drafts(drafts.clone()),
+            None => manager,
+        };
+        #[cfg(feature = "age")]
+        let manager = match &self.escrow {
+            Some(escrow) => manager.with_escrow(escrow.clone()),
+            None => manager,
+        };
+        match &self.journal {
+            Some(journal) => manager.with_journal(journal.clone()),
+            None => manager,
+        }
+    }
+
+    /// Returns a manager for a struct stored under `key_name` in this vault.
+    pub fn structured<T>(&self, key_name: &str) -> StructKeyManager<T>
+    where
+        T: Serialize + for<'de> Deserialize<'de> + Default,
+    {
+        StructKeyManager::from_key_manager(self.key(key_name))
+    }
+
+    /// Reads back the journal attached with `with_journal`, oldest entry first, e.g. to
+    /// show when a credential was last rotated.
+    pub fn journal(&self) -> Result<Vec<JournalEntry>> {
+        let journal = self.journal.as_ref().ok_or_else(|| {
+            self.error(
+                Operation::Read,
+                Cause::InvalidInput("no journal is attached to this vault".to_string()),
+            )
+        })?;
+        journal
+            .entries()
+            .map_err(|e| self.error(Operation::Read, e))
+    }
+
+    /// Wraps a failure that isn't tied to a single key.
+    pub(crate) fn error<C>(&self, operation: Operation, cause: C) -> Error
+    where
+        C: Into<Cause>,
+    {
+        Error::new(&self.system_name, "", operation, self.backend.name(), cause)
+    }
+
+    /// The names of the keys stored with the attribute `name` set to `value` (see
+    /// `KeyManager::with_attribute`), searched natively in the Secret Service on Linux and
+    /// BSD. Fails on other platforms and backends.
+    pub fn find_by_attribute(&self, name: &str, value: &str) -> Result<Vec<String>> {
+        self.backend
+            .find_by_attribute(&self.system_name, name, value)
+            .map_err(|e| self.error(Operation::Read, e))
+    }
+
+    /// Resolves a reference to a stored value.
+    ///
+    /// `"key"` returns the value of `key` as stored. `"key:field"` reads `key` as JSON (as
+    /// written by `StructKeyManager`) and returns `field`; nested fields are written with
+    /// dots (`"key:database.password"`). String fields are returned as-is, other values as
+    /// JSON.
+    pub fn resolve(&self, reference: &str) -> Result<String> {
+        let (key_name, field) = match reference.split_once(':') {
+            Some((key_name, field)) => (key_name.trim(), Some(field.trim())),
+            None => (reference.trim(), None),
+        };
+        let mut manager = self.key(key_name);
+        let raw = manager.read_key()?;
+        let Some(field) = field else {
+            return Ok(raw);
+        };
+        let value: Value =
+            serde_json::from_str(&raw).map_err(|e| manager.error(Operation::Read, e))?;
+        let mut current = &value;
+        for segment in field.split('.') {
+            current = current.get(segment).ok_or_else(|| {
+                manager.error(Operation::Read, Cause::FieldNotFound(field.to_string()))
+            })?;
+        }
+        Ok(match current {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+    }
+
+    /// Spawns `command` with selected keys injected as environment variables of the child
+    /// only, like `op run` or `doppler run`. Nothing is written to disk and the current
+    /// process environment is left untouched.
+    ///
+    /// `mapping` pairs environment variable names with references accepted by `resolve`.
+    /// All references are resolved before the child is started.
+    pub fn spawn_with_secrets<I, K, R>(&self, mut command: Command, mapping: I) -> Result<Child>
+    where
+        I: IntoIterator<Item = (K, R)>,
+        K: AsRef<str>,
+        R: AsRef<str>,
+    {
+        for (variable, reference) in mapping {
+            command.env(variable.as_ref(), self.resolve(reference.as_ref())?);
+        }
+        command
+            .spawn()
+            .map_err(|e| self.error(Operation::Export, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_resolve_plain_and_field_references() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("token").store("abc").unwrap();
+        vault
+            .key("db")
+            .store(r#"{"user":"john","port":5432,"nested":{"password":"pw"}}"#)
+            .unwrap();
+        assert_eq!(vault.resolve("token").unwrap(), "abc");
+        assert_eq!(vault.resolve("db:user").unwrap(), "john");
+        assert_eq!(vault.resolve("db:port").unwrap(), "5432");
+        assert_eq!(vault.resolve("db:nested.password").unwrap(), "pw");
+        assert_eq!(
+            vault.resolve("db:missing").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spawn_with_secrets() {
+        let vault = Vault::ephemeral_for_tests();
+        vault.key("token").store("abc").unwrap();
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "printf %s \"$API_TOKEN\""])
+            .stdout(std::process::Stdio::piped());
+        let child = vault
+            .spawn_with_secrets(command, [("API_TOKEN", "token")])
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "abc");
+        assert!(std::env::var("API_TOKEN").is_err());
+    }
+}
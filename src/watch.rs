@@ -0,0 +1,206 @@
+use crate::hooks;
+use crate::key_manager::KeyManager;
+use crate::redact::Redacted;
+use sha2::{Digest, Sha256};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A change of a watched key, reported by `KeyManager::watch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyChange {
+    /// The key did not exist and now holds a value.
+    Created(Redacted<String>),
+    /// The stored value changed (e.g. a rotated credential).
+    Updated(Redacted<String>),
+    /// The key was deleted.
+    Deleted,
+}
+
+/// Handle of a running watch. The watch stops when the handle is dropped or `stop` is
+/// called.
+#[derive(Debug)]
+pub struct Watcher {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Stops the watch and waits for a callback in progress to return.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Fingerprint of a value, so the watcher doesn't keep the previous secret in memory.
+fn fingerprint(value: &str) -> [u8; 32] {
+    Sha256::digest(value.as_bytes()).into()
+}
+
+impl KeyManager {
+    /// Polls the backend every `interval` on a background thread and calls `callback`
+    /// whenever the stored value is created, changed or deleted, so long-running services
+    /// can pick up rotated credentials without restarting.
+    ///
+    /// The value present when the watch starts is not reported. Read failures other than a
+    /// missing entry are sent to the warning hook once and the previous state is kept.
+    ///
+    /// Changes are detected by polling the backend directly, but a created or updated value
+    /// is only passed to `callback` after a `read_key` of it: it goes through the read
+    /// confirmation, approvers, rate limiter and pin of this manager and is journaled. A
+    /// refused read is sent to the warning hook and the change is not reported.
+    pub fn watch<F>(&self, interval: Duration, mut callback: F) -> Watcher
+    where
+        F: FnMut(KeyChange) + Send + 'static,
+    {
+        let mut reader = self.reader();
+        let backend = self.backend().clone();
+        let system_name = self.system_name.clone();
+        let key_name = self.key_name.clone();
+        let read = move || match backend.get(&system_name, &key_name) {
+            Ok(value) => Ok(Some(fingerprint(&value))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        };
+        let mut last = read().ok();
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let key_name = self.key_name.clone();
+        let thread = thread::spawn(move || {
+            let mut failing = false;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let digest = match read() {
+                    Ok(digest) => digest,
+                    Err(e) => {
+                        if !failing {
+                            hooks::warn(&format!("failed to watch key '{}': {}", key_name, e));
+                        }
+                        failing = true;
+                        continue;
+                    }
+                };
+                failing = false;
+                // `last` is `None` until the first successful read: nothing to compare yet.
+                let Some(previous) = last.replace(digest) else {
+                    continue;
+                };
+                if previous == digest {
+                    continue;
+                }
+                let change = if digest.is_none() {
+                    KeyChange::Deleted
+                } else {
+                    match reader.read_key() {
+                        Ok(value) => {
+                            last = Some(Some(fingerprint(&value)));
+                            let value = Redacted::new(value);
+                            if previous.is_none() {
+                                KeyChange::Created(value)
+                            } else {
+                                KeyChange::Updated(value)
+                            }
+                        }
+                        Err(e) => {
+                            hooks::warn(&format!(
+                                "failed to read watched key '{}': {}",
+                                key_name, e
+                            ));
+                            continue;
+                        }
+                    }
+                };
+                callback(change);
+            }
+        });
+        Watcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hooks::ReadRequest;
+    use crate::vault::Vault;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    fn wait_for(events: &Arc<Mutex<Vec<KeyChange>>>, count: usize) {
+        for _ in 0..200 {
+            if events.lock().unwrap().len() >= count {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_watch_reports_changes() {
+        let mut manager = Vault::ephemeral_for_tests().key("rotating");
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let watcher = manager.watch(Duration::from_millis(10), move |change| {
+            sink.lock().unwrap().push(change)
+        });
+        manager.store("v1").unwrap();
+        wait_for(&events, 1);
+        manager.store("v2").unwrap();
+        wait_for(&events, 2);
+        manager.delete().unwrap();
+        wait_for(&events, 3);
+        watcher.stop();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                KeyChange::Created(Redacted::new("v1".to_string())),
+                KeyChange::Updated(Redacted::new("v2".to_string())),
+                KeyChange::Deleted,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_watch_reads_through_approvers() {
+        let approve = Arc::new(AtomicBool::new(false));
+        let approval = Arc::clone(&approve);
+        let mut manager = Vault::ephemeral_for_tests()
+            .key("approved")
+            .with_approver(move |_: &ReadRequest| approval.load(Ordering::SeqCst));
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        let watcher = manager.watch(Duration::from_millis(10), move |change| {
+            sink.lock().unwrap().push(change)
+        });
+        manager.store("v1").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(events.lock().unwrap().is_empty());
+
+        approve.store(true, Ordering::SeqCst);
+        manager.store("v2").unwrap();
+        wait_for(&events, 1);
+        watcher.stop();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![KeyChange::Updated(Redacted::new("v2".to_string()))]
+        );
+    }
+}
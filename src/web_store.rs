@@ -0,0 +1,326 @@
+//! A browser backend for WASM builds (feature `wasm`): values are encrypted with the Web
+//! Crypto API and kept in IndexedDB.
+//!
+//! IndexedDB and Web Crypto are asynchronous while `SecretStore` is not, so
+//! [`WebStore::open`] decrypts every record into memory once, reads are served from memory,
+//! and writes are encrypted and persisted in the background. Write failures are reported
+//! through the warning hook (see [`hooks::set_warning_hook`]).
+//!
+//! The AES-GCM session key is generated by the browser on first use and stored in the same
+//! database as a non-extractable `CryptoKey`, so page scripts can use it but never read it.
+
+use crate::hooks;
+use js_sys::{Array, Promise, Reflect, Uint8Array};
+use keyring::Result;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    AesGcmParams, AesKeyGenParams, Crypto, CryptoKey, IdbDatabase, IdbFactory, IdbObjectStore,
+    IdbOpenDbRequest, IdbRequest, IdbTransactionMode,
+};
+use zeroize::Zeroizing;
+
+type JsResult<T> = std::result::Result<T, JsValue>;
+
+type Entries = BTreeMap<(String, String), Zeroizing<String>>;
+
+/// A record to write, or to delete when the value is `None`.
+type Write = (String, String, Option<Zeroizing<String>>);
+
+/// Writes waiting to be persisted, in order, and whether a task is draining them.
+#[derive(Default)]
+struct Queue {
+    writes: VecDeque<Write>,
+    draining: bool,
+}
+
+const VERSION: u32 = 1;
+const KEYS: &str = "keys";
+const SECRETS: &str = "secrets";
+const SESSION_KEY: &str = "session";
+const IV_LEN: usize = 12;
+
+/// A store persisting encrypted values in the browser's IndexedDB.
+///
+/// Clones share the same entries. Every store opened on the same database sees the values
+/// persisted before it was opened.
+#[derive(Clone)]
+pub struct WebStore {
+    database: String,
+    entries: Arc<Mutex<Entries>>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+impl fmt::Debug for WebStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebStore")
+            .field("database", &self.database)
+            .field("entries", &self.lock().len())
+            .finish()
+    }
+}
+
+/// Two stores are equal when they share the same entries.
+impl PartialEq for WebStore {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.entries, &other.entries)
+    }
+}
+
+impl Eq for WebStore {}
+
+impl WebStore {
+    /// Opens (creating it if needed) the IndexedDB database `database` and decrypts its
+    /// values. Works in windows and workers.
+    pub async fn open(database: &str) -> Result<WebStore> {
+        let db = open_database(database).await.map_err(failure)?;
+        let key = session_key(&db).await.map_err(failure)?;
+        let store = object_store(&db, SECRETS, IdbTransactionMode::Readonly).map_err(failure)?;
+        let names: Array = request(store.get_all_keys().map_err(failure)?)
+            .await
+            .map_err(failure)?
+            .unchecked_into();
+        let records: Array = request(store.get_all().map_err(failure)?)
+            .await
+            .map_err(failure)?
+            .unchecked_into();
+
+        let mut entries = Entries::new();
+        for (name, record) in names.iter().zip(records.iter()) {
+            let name: Array = name.unchecked_into();
+            let (Some(system_name), Some(key_name)) =
+                (name.get(0).as_string(), name.get(1).as_string())
+            else {
+                continue;
+            };
+            let value = decrypt(&key, &record.unchecked_into())
+                .await
+                .map_err(failure)?;
+            entries.insert((system_name, key_name), value);
+        }
+        Ok(WebStore {
+            database: database.to_string(),
+            entries: Arc::new(Mutex::new(entries)),
+            queue: Arc::default(),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        lock(&self.entries)
+    }
+
+    pub(crate) fn get(&self, system_name: &str, key_name: &str) -> Result<String> {
+        self.lock()
+            .get(&(system_name.to_string(), key_name.to_string()))
+            .map(|value| value.to_string())
+            .ok_or(keyring::Error::NoEntry)
+    }
+
+    pub(crate) fn set(&self, system_name: &str, key_name: &str, value: &str) -> Result<()> {
+        self.lock().insert(
+            (system_name.to_string(), key_name.to_string()),
+            Zeroizing::new(value.to_string()),
+        );
+        self.persist(
+            system_name,
+            key_name,
+            Some(Zeroizing::new(value.to_string())),
+        );
+        Ok(())
+    }
+
+    pub(crate) fn delete(&self, system_name: &str, key_name: &str) -> Result<()> {
+        self.lock()
+            .remove(&(system_name.to_string(), key_name.to_string()))
+            .ok_or(keyring::Error::NoEntry)?;
+        self.persist(system_name, key_name, None);
+        Ok(())
+    }
+
+    pub(crate) fn list(&self, system_name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .lock()
+            .keys()
+            .filter(|(system, _)| system == system_name)
+            .map(|(_, key)| key.clone())
+            .collect())
+    }
+
+    /// Writes (or deletes, when `value` is `None`) the record in the background. Writes
+    /// are persisted one at a time, in the order they were made.
+    fn persist(&self, system_name: &str, key_name: &str, value: Option<Zeroizing<String>>) {
+        {
+            let mut pending = lock(&self.queue);
+            pending
+                .writes
+                .push_back((system_name.to_string(), key_name.to_string(), value));
+            if pending.draining {
+                return;
+            }
+            pending.draining = true;
+        }
+        let database = self.database.clone();
+        let queue = Arc::clone(&self.queue);
+        spawn_local(async move {
+            loop {
+                let next = {
+                    let mut queue = lock(&queue);
+                    let next = queue.writes.pop_front();
+                    queue.draining = next.is_some();
+                    next
+                };
+                let Some((system_name, key_name, value)) = next else {
+                    break;
+                };
+                let name = Array::of2(&system_name.into(), &key_name.into());
+                if let Err(e) = write(&database, &name, value.as_deref().map(|v| v.as_str())).await
+                {
+                    hooks::warn(&format!(
+                        "could not persist a value to IndexedDB '{}': {}",
+                        database,
+                        failure(e)
+                    ));
+                }
+            }
+        });
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+async fn write(database: &str, name: &JsValue, value: Option<&str>) -> JsResult<()> {
+    let db = open_database(database).await?;
+    let record = match value {
+        Some(value) => Some(encrypt(&session_key(&db).await?, value).await?),
+        None => None,
+    };
+    let store = object_store(&db, SECRETS, IdbTransactionMode::Readwrite)?;
+    match record {
+        Some(record) => request(store.put_with_key(&record, name)?).await?,
+        None => request(store.delete(name)?).await?,
+    };
+    Ok(())
+}
+
+/// Returns the global object's `name` property, e.g. `indexedDB` or `crypto`, which exists
+/// in both windows and workers.
+fn global<T: JsCast>(name: &str) -> JsResult<T> {
+    let value = Reflect::get(&js_sys::global(), &name.into())?;
+    if value.is_undefined() {
+        return Err(format!("{} is not available", name).into());
+    }
+    Ok(value.unchecked_into())
+}
+
+/// Waits for an IndexedDB request and returns its result.
+async fn request(request: IdbRequest) -> JsResult<JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    JsFuture::from(promise).await?;
+    request.result()
+}
+
+async fn open_database(database: &str) -> JsResult<IdbDatabase> {
+    let factory: IdbFactory = global("indexedDB")?;
+    let opening: IdbOpenDbRequest = factory.open_with_u32(database, VERSION)?;
+    let upgrading = opening.clone();
+    let upgrade = Closure::<dyn FnMut()>::new(move || {
+        if let Ok(db) = upgrading.result() {
+            let db: IdbDatabase = db.unchecked_into();
+            for name in [KEYS, SECRETS] {
+                if !db.object_store_names().contains(name) {
+                    let _ = db.create_object_store(name);
+                }
+            }
+        }
+    });
+    opening.set_onupgradeneeded(Some(upgrade.as_ref().unchecked_ref()));
+    let db = request(opening.unchecked_into()).await?;
+    Ok(db.unchecked_into())
+}
+
+fn object_store(
+    db: &IdbDatabase,
+    name: &str,
+    mode: IdbTransactionMode,
+) -> JsResult<IdbObjectStore> {
+    db.transaction_with_str_and_mode(name, mode)?
+        .object_store(name)
+}
+
+/// Loads the session key of the database, generating it on first use.
+async fn session_key(db: &IdbDatabase) -> JsResult<CryptoKey> {
+    let store = object_store(db, KEYS, IdbTransactionMode::Readonly)?;
+    let stored = request(store.get(&SESSION_KEY.into())?).await?;
+    if !stored.is_undefined() {
+        return Ok(stored.unchecked_into());
+    }
+
+    let crypto: Crypto = global("crypto")?;
+    let usages = Array::of2(&"encrypt".into(), &"decrypt".into());
+    let generating = crypto.subtle().generate_key_with_object(
+        &AesKeyGenParams::new("AES-GCM", 256),
+        false,
+        &usages,
+    )?;
+    let key = JsFuture::from(generating).await?;
+    let store = object_store(db, KEYS, IdbTransactionMode::Readwrite)?;
+    request(store.put_with_key(&key, &SESSION_KEY.into())?).await?;
+    Ok(key.unchecked_into())
+}
+
+/// Encrypts `value` into a record holding the IV followed by the ciphertext.
+async fn encrypt(key: &CryptoKey, value: &str) -> JsResult<Uint8Array> {
+    let crypto: Crypto = global("crypto")?;
+    let mut iv = [0u8; IV_LEN];
+    crypto.get_random_values_with_u8_array(&mut iv)?;
+    let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(&iv[..]));
+    let sealed = JsFuture::from(crypto.subtle().encrypt_with_object_and_u8_array(
+        &params,
+        key,
+        value.as_bytes(),
+    )?)
+    .await?;
+    let sealed = Uint8Array::new(&sealed);
+    let record = Uint8Array::new_with_length((IV_LEN as u32) + sealed.length());
+    record.set(&Uint8Array::from(&iv[..]), 0);
+    record.set(&sealed, IV_LEN as u32);
+    Ok(record)
+}
+
+async fn decrypt(key: &CryptoKey, record: &Uint8Array) -> JsResult<Zeroizing<String>> {
+    let crypto: Crypto = global("crypto")?;
+    let record = record.to_vec();
+    if record.len() < IV_LEN {
+        return Err("truncated record".into());
+    }
+    let (iv, sealed) = record.split_at(IV_LEN);
+    let params = AesGcmParams::new("AES-GCM", &Uint8Array::from(iv));
+    let opened = JsFuture::from(
+        crypto
+            .subtle()
+            .decrypt_with_object_and_u8_array(&params, key, sealed)?,
+    )
+    .await?;
+    let bytes = Zeroizing::new(Uint8Array::new(&opened).to_vec());
+    String::from_utf8(bytes.to_vec())
+        .map(Zeroizing::new)
+        .map_err(|e| e.to_string().into())
+}
+
+fn failure(error: JsValue) -> keyring::Error {
+    let message = error
+        .dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| error.as_string())
+        .unwrap_or_else(|| format!("{:?}", error));
+    keyring::Error::PlatformFailure(message.into())
+}
@@ -0,0 +1,165 @@
+use keyring::Entry;
+use std::collections::HashMap;
+
+/// Where Windows Credential Manager keeps a credential, and whether it roams.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WindowsPersistence {
+    /// Lives only as long as the logon session.
+    Session,
+    /// Persists on this computer, but doesn't roam with the user's profile.
+    LocalMachine,
+    /// Persists and roams with the user's profile in Active Directory domains; keyring's
+    /// own default.
+    #[default]
+    Enterprise,
+}
+
+/// Windows Credential Manager options applied to every value a manager stores in the OS
+/// keyring (see `KeyManager::with_windows_options`). Ignored on other platforms and
+/// backends.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowsOptions {
+    comment: Option<String>,
+    target_alias: Option<String>,
+    persistence: WindowsPersistence,
+}
+
+impl WindowsOptions {
+    pub fn new() -> Self {
+        WindowsOptions::default()
+    }
+
+    /// Sets the description shown for the credential in Credential Manager.
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Sets the target alias of the credential.
+    pub fn with_target_alias(mut self, alias: &str) -> Self {
+        self.target_alias = Some(alias.to_string());
+        self
+    }
+
+    pub fn with_persistence(mut self, persistence: WindowsPersistence) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn target_alias(&self) -> Option<&str> {
+        self.target_alias.as_deref()
+    }
+
+    pub fn persistence(&self) -> WindowsPersistence {
+        self.persistence
+    }
+
+    /// The keyring attributes carrying the comment and the alias.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn attributes(&self) -> HashMap<&str, &str> {
+        let mut attributes = HashMap::new();
+        if let Some(comment) = &self.comment {
+            attributes.insert("comment", comment.as_str());
+        }
+        if let Some(alias) = &self.target_alias {
+            attributes.insert("target_alias", alias.as_str());
+        }
+        attributes
+    }
+
+    /// Updates the credential behind `entry`, which must have just been stored.
+    #[cfg(windows)]
+    pub(crate) fn apply(&self, entry: &Entry) -> keyring::Result<()> {
+        let attributes = self.attributes();
+        if !attributes.is_empty() {
+            entry.update_attributes(&attributes)?;
+        }
+        if self.persistence != WindowsPersistence::Enterprise {
+            if let Some(credential) = entry
+                .get_credential()
+                .downcast_ref::<keyring::windows::WinCredential>()
+            {
+                imp::set_persistence(&credential.target_name, self.persistence)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the credential behind `entry`, which must have just been stored.
+    #[cfg(not(windows))]
+    pub(crate) fn apply(&self, _entry: &Entry) -> keyring::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::WindowsPersistence;
+    use std::ffi::OsStr;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Security::Credentials::{
+        CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_ENTERPRISE,
+        CRED_PERSIST_LOCAL_MACHINE, CRED_PERSIST_SESSION, CRED_TYPE_GENERIC,
+    };
+
+    /// Rewrites the generic credential `target_name` with another persistence.
+    pub(super) fn set_persistence(
+        target_name: &str,
+        persistence: WindowsPersistence,
+    ) -> keyring::Result<()> {
+        let target: Vec<u16> = OsStr::new(target_name)
+            .encode_wide()
+            .chain(once(0))
+            .collect();
+        let mut stored: *mut CREDENTIALW = std::ptr::null_mut();
+        // SAFETY: `target` is NUL-terminated and `stored` is only read after a successful
+        // call, then released with `CredFree`.
+        unsafe {
+            if CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut stored) == 0 {
+                return Err(platform_failure());
+            }
+            let mut credential: CREDENTIALW = *stored;
+            credential.Persist = match persistence {
+                WindowsPersistence::Session => CRED_PERSIST_SESSION,
+                WindowsPersistence::LocalMachine => CRED_PERSIST_LOCAL_MACHINE,
+                WindowsPersistence::Enterprise => CRED_PERSIST_ENTERPRISE,
+            };
+            let failure = (CredWriteW(&credential, 0) == 0).then(platform_failure);
+            CredFree(stored as *const _);
+            failure.map_or(Ok(()), Err)
+        }
+    }
+
+    fn platform_failure() -> keyring::Error {
+        keyring::Error::PlatformFailure(Box::new(std::io::Error::last_os_error()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::Vault;
+
+    #[test]
+    fn test_options_and_other_backends() {
+        let options = WindowsOptions::new()
+            .with_comment("API token of the billing service")
+            .with_persistence(WindowsPersistence::LocalMachine);
+        assert_eq!(options.comment(), Some("API token of the billing service"));
+        assert_eq!(options.target_alias(), None);
+        assert_eq!(
+            options.attributes(),
+            HashMap::from([("comment", "API token of the billing service")])
+        );
+
+        let vault = Vault::ephemeral_for_tests().with_windows_options(options);
+        let mut manager = vault.key("token");
+        manager.store("secret").unwrap();
+        assert_eq!(manager.read_key().unwrap(), "secret");
+    }
+}
@@ -0,0 +1,356 @@
+use crate::error::Result;
+use crate::struct_key_manager::StructKeyManager;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One stage of a [`SetupWizard`]; implemented by `StructKeyManager`.
+pub trait SetupStep {
+    /// Returns true when the step's value is already stored.
+    fn is_configured(&mut self) -> bool;
+
+    /// Prompts for the value and stores it.
+    fn configure(&mut self) -> Result<()>;
+
+    /// The stored value with secrets hidden, for the final summary.
+    fn summary(&mut self) -> Result<String>;
+
+    /// Stores the answers of a `configure` that failed to store them, without prompting
+    /// again. Calls `configure` again by default.
+    fn retry_store(&mut self) -> Result<()> {
+        self.configure()
+    }
+
+    /// Returns true when answers were typed for this step but not stored, e.g. by a run
+    /// that crashed. `run` stores them instead of prompting again.
+    fn has_draft(&mut self) -> bool {
+        false
+    }
+
+    /// Forgets the answers typed for this step but not stored.
+    fn discard_draft(&mut self) {}
+}
+
+impl<T> SetupStep for StructKeyManager<T>
+where
+    T: Serialize + for<'de> Deserialize<'de> + Default,
+{
+    fn is_configured(&mut self) -> bool {
+        self.read_key().is_ok()
+    }
+
+    fn configure(&mut self) -> Result<()> {
+        self.request_key().map(|_| ())
+    }
+
+    fn summary(&mut self) -> Result<String> {
+        self.dump_redacted()
+    }
+
+    fn retry_store(&mut self) -> Result<()> {
+        if StructKeyManager::has_draft(self) {
+            StructKeyManager::retry_store(self).map(drop)
+        } else {
+            self.configure()
+        }
+    }
+
+    fn has_draft(&mut self) -> bool {
+        StructKeyManager::has_draft(self)
+    }
+
+    fn discard_draft(&mut self) {
+        StructKeyManager::discard_draft(self)
+    }
+}
+
+/// What happened to a step during `SetupWizard::run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    /// The step was prompted for and stored.
+    Configured,
+    /// The step was stored by an earlier run and skipped.
+    AlreadyConfigured,
+}
+
+/// The outcome of one step, as shown in the final summary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepSummary {
+    pub title: String,
+    pub status: StepStatus,
+    /// The stored value as pretty JSON, secret fields replaced by `"***"`.
+    pub value: String,
+}
+
+/// The summary returned by `SetupWizard::run`, one entry per step in order. Its `Display`
+/// lists every step with its redacted value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetupSummary {
+    pub steps: Vec<StepSummary>,
+}
+
+impl fmt::Display for SetupSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            let status = match step.status {
+                StepStatus::Configured => "configured",
+                StepStatus::AlreadyConfigured => "already configured",
+            };
+            writeln!(f, "{} ({}):", step.title, status)?;
+            for line in step.value.lines() {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Chains several `StructKeyManager`s into a first-run setup, e.g. account, then database,
+/// then SMTP.
+///
+/// `run` announces each step with its position (`Step 2/3: Database`, on stderr like the
+/// prompts), skips the steps whose value is already stored and stops at the first failure.
+/// Since every completed step is stored right away, running the wizard again after a
+/// failure resumes where it stopped. When the failure was storing a step (e.g. the keyring
+/// denied access), `retry_store` stores the answers already typed for it and goes on.
+/// With `with_draft_persistence` on the steps (or their vault), answers typed by a run
+/// that crashed before storing them are stored by the next `run` without prompting;
+/// `discard_draft` starts over instead.
+///
+/// ```no_run
+/// # use key_vaulter::wizard::SetupWizard;
+/// # use key_vaulter::Vault;
+/// # #[derive(serde::Serialize, serde::Deserialize, Default)]
+/// # struct Account { user: String }
+/// # #[derive(serde::Serialize, serde::Deserialize, Default)]
+/// # struct Database { url: String }
+/// let vault = Vault::new("my_app");
+/// let summary = SetupWizard::new()
+///     .step("Account", vault.structured::<Account>("account"))
+///     .step("Database", vault.structured::<Database>("database"))
+///     .run()?;
+/// println!("{}", summary);
+/// # Ok::<(), key_vaulter::Error>(())
+/// ```
+#[derive(Default)]
+pub struct SetupWizard {
+    steps: Vec<(String, Box<dyn SetupStep>)>,
+    reconfigure: bool,
+    /// The step that failed during the last run, and the summary of the steps before it.
+    failed: Option<(usize, SetupSummary)>,
+}
+
+impl fmt::Debug for SetupWizard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetupWizard")
+            .field(
+                "steps",
+                &self
+                    .steps
+                    .iter()
+                    .map(|(title, _)| title)
+                    .collect::<Vec<_>>(),
+            )
+            .field("reconfigure", &self.reconfigure)
+            .field("failed", &self.failed.as_ref().map(|(index, _)| index))
+            .finish()
+    }
+}
+
+impl SetupWizard {
+    pub fn new() -> Self {
+        SetupWizard::default()
+    }
+
+    /// Adds a step shown as `title`, run after the steps added before it.
+    pub fn step<S>(mut self, title: &str, step: S) -> Self
+    where
+        S: SetupStep + 'static,
+    {
+        self.steps.push((title.to_string(), Box::new(step)));
+        self
+    }
+
+    /// Prompts for every step, including those already stored (their values are offered
+    /// as defaults), instead of skipping them.
+    pub fn reconfigure(mut self) -> Self {
+        self.reconfigure = true;
+        self
+    }
+
+    /// Runs the steps in order and returns the summary. Fails with the error of the first
+    /// step that fails; the steps before it stay stored.
+    pub fn run(&mut self) -> Result<SetupSummary> {
+        self.run_from(0, SetupSummary::default())
+    }
+
+    /// Stores the answers typed for the step that failed during the last `run` (or
+    /// `retry_store`), then runs the steps after it and returns the whole summary. Same as
+    /// `run` when no step failed.
+    pub fn retry_store(&mut self) -> Result<SetupSummary> {
+        let Some((index, mut summary)) = self.failed.take() else {
+            return self.run();
+        };
+        let (title, step) = &mut self.steps[index];
+        if let Err(e) = step.retry_store() {
+            self.failed = Some((index, summary));
+            return Err(e);
+        }
+        summary.steps.push(StepSummary {
+            title: title.clone(),
+            status: StepStatus::Configured,
+            value: step.summary()?,
+        });
+        self.run_from(index + 1, summary)
+    }
+
+    /// Forgets the answers typed but not stored for every step, so the next `run`
+    /// prompts for them again.
+    pub fn discard_draft(&mut self) {
+        self.failed = None;
+        for (_, step) in &mut self.steps {
+            step.discard_draft();
+        }
+    }
+
+    fn run_from(&mut self, start: usize, mut summary: SetupSummary) -> Result<SetupSummary> {
+        self.failed = None;
+        let total = self.steps.len();
+        for (index, (title, step)) in self.steps.iter_mut().enumerate().skip(start) {
+            let status = if !self.reconfigure && step.is_configured() {
+                eprintln!(
+                    "Step {}/{}: {} (already configured)",
+                    index + 1,
+                    total,
+                    title
+                );
+                StepStatus::AlreadyConfigured
+            } else {
+                eprintln!("Step {}/{}: {}", index + 1, total, title);
+                let result = if step.has_draft() {
+                    step.retry_store()
+                } else {
+                    step.configure()
+                };
+                if let Err(e) = result {
+                    self.failed = Some((index, summary));
+                    return Err(e);
+                }
+                StepStatus::Configured
+            };
+            summary.steps.push(StepSummary {
+                title: title.clone(),
+                status,
+                value: step.summary()?,
+            });
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::ScriptedPrompter;
+    use crate::vault::Vault;
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Account {
+        user: String,
+    }
+
+    #[derive(Serialize, Deserialize, Default)]
+    struct Smtp {
+        host: String,
+        password: String,
+    }
+
+    #[test]
+    fn test_wizard_resumes_after_failure() {
+        let vault = Vault::ephemeral_for_tests().with_prompter(ScriptedPrompter::new(["ana"]));
+        let wizard = |vault: &Vault| {
+            SetupWizard::new()
+                .step("Account", vault.structured::<Account>("account"))
+                .step(
+                    "SMTP",
+                    vault
+                        .structured::<Smtp>("smtp")
+                        .with_secret_field("password"),
+                )
+        };
+        // The answers run out during the second step.
+        assert!(wizard(&vault).run().is_err());
+
+        let vault = vault.with_prompter(ScriptedPrompter::new(["mail.local", "s3cret"]));
+        let summary = wizard(&vault).run().unwrap();
+        assert_eq!(summary.steps[0].status, StepStatus::AlreadyConfigured);
+        assert_eq!(summary.steps[1].status, StepStatus::Configured);
+        let text = summary.to_string();
+        assert!(text.contains("SMTP (configured):"));
+        assert!(text.contains("\"password\": \"***\""));
+        assert!(!text.contains("s3cret"));
+    }
+
+    #[test]
+    fn test_retry_store_keeps_typed_answers() {
+        use crate::backend::Backend;
+        use crate::file_store::EncryptedFileStore;
+        let blocker = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("wizard-retry-{}", std::process::id()));
+        std::fs::create_dir_all(blocker.parent().unwrap()).unwrap();
+        std::fs::write(&blocker, "").unwrap();
+        let vault = Vault::ephemeral_for_tests();
+        let mut wizard = SetupWizard::new()
+            .step(
+                "Account",
+                vault
+                    .structured::<Account>("account")
+                    .with_prompter(ScriptedPrompter::new(["ana"])),
+            )
+            .step(
+                "SMTP",
+                vault
+                    .structured::<Smtp>("smtp")
+                    .with_backend(Backend::EncryptedFile(
+                        EncryptedFileStore::new(blocker.join("vault.json"))
+                            .with_machine_secret(crate::machine::test_secret_path()),
+                    ))
+                    .with_prompter(ScriptedPrompter::new(["mail.local", "s3cret"])),
+            );
+        assert!(wizard.run().is_err());
+        assert!(wizard.retry_store().is_err());
+
+        std::fs::remove_file(&blocker).unwrap();
+        let summary = wizard.retry_store().unwrap();
+        assert_eq!(summary.steps.len(), 2);
+        assert_eq!(summary.steps[0].status, StepStatus::Configured);
+        assert!(summary.to_string().contains("mail.local"));
+        let _ = std::fs::remove_dir_all(&blocker);
+    }
+
+    #[test]
+    fn test_run_resumes_persisted_drafts() {
+        use crate::file_store::EncryptedFileStore;
+        use std::time::Duration;
+        let drafts = std::env::temp_dir()
+            .join("key_vaulter_tests")
+            .join(format!("wizard-drafts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&drafts);
+        let vault = Vault::ephemeral_for_tests().with_draft_store(
+            EncryptedFileStore::new(drafts.join("drafts.json"))
+                .with_machine_secret(crate::machine::test_secret_path()),
+            Duration::from_secs(600),
+        );
+        // Answers typed by a run that crashed before storing them.
+        vault.key("account").keep_draft(r#"{"user":"ana"}"#);
+
+        let prompter = ScriptedPrompter::new(Vec::<String>::new());
+        let vault = vault.with_prompter(prompter.clone());
+        let mut wizard = SetupWizard::new().step("Account", vault.structured::<Account>("account"));
+        let summary = wizard.run().unwrap();
+        assert!(summary.to_string().contains("ana"));
+        assert!(prompter.prompts().is_empty());
+        assert!(!vault.structured::<Account>("account").has_draft());
+        let _ = std::fs::remove_dir_all(&drafts);
+    }
+}